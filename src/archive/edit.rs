@@ -0,0 +1,243 @@
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::archive::index::write_chunk_index;
+use crate::archive::reader::{validate_relative_path, ArchiveReader};
+use crate::archive::writer::{count_top_level_entries, write_file_table, EntryKind};
+use crate::fsutil::temp::staging_path;
+use crate::util::errors::AppError;
+use crate::util::header::patch_u64;
+
+/// Renames files inside an archive without touching its chunk data.
+///
+/// The file table sits at the end of the archive, before the chunk index, so both are rewritten
+/// in place with the new paths and the archive is truncated or extended to fit. Renames are applied in order, so a
+/// batch may move a file and then reuse its old path.
+///
+/// # Arguments
+/// * `archive_path` - The archive to edit.
+/// * `renames` - `(old path, new path)` pairs, as shown by `list`.
+/// * `password` - Password of an encrypted archive, whose file table is sealed again with it.
+///
+/// # Errors
+/// Returns `AppError::FileNotFoundInArchive` if an old path is not in the archive,
+/// `AppError::FileExistsInArchive` if a new path is already taken, `AppError::UnsafePath` if a
+/// new path is absolute or contains `..`, `AppError::Unsupported` if the archive is signed, or
+/// an error if the archive cannot be read or written. Nothing is written unless every rename is
+/// valid.
+///
+/// # Example
+///
+/// ```no_run
+/// use squishrs::archive::rename_entries;
+/// use std::path::Path;
+///
+/// let renames = [("docs/old.txt".to_string(), "docs/new.txt".to_string())];
+/// rename_entries(Path::new("backup.squish"), &renames, None).expect("Failed to rename");
+/// ```
+pub fn rename_entries(
+    archive_path: &Path,
+    renames: &[(String, String)],
+    password: Option<&str>,
+) -> Result<(), AppError> {
+    let mut reader = open_for_edit(archive_path, password)?;
+    let mut files = reader.file_table()?;
+    let key = reader.key().cloned();
+    let file_table_position = reader.file_table_position();
+    let top_level_count_position = reader.top_level_count_position();
+    let archive_size_position = reader.archive_size_position();
+    let layout = reader.file_table_layout();
+    let chunk_index = reader.chunk_index_entries();
+    drop(reader);
+
+    let mut paths: HashSet<String> = files.iter().map(|entry| entry.path.clone()).collect();
+    for (old_path, new_path) in renames {
+        if new_path.is_empty() {
+            return Err(AppError::UnsafePath(new_path.into()));
+        }
+        validate_relative_path(Path::new(new_path))?;
+
+        let entry = files
+            .iter_mut()
+            .find(|entry| &entry.path == old_path)
+            .ok_or_else(|| AppError::FileNotFoundInArchive(old_path.clone()))?;
+        if paths.contains(new_path) {
+            return Err(AppError::FileExistsInArchive(new_path.clone()));
+        }
+
+        entry.path = new_path.clone();
+        paths.remove(old_path);
+        paths.insert(new_path.clone());
+
+        // Hard links name the file they link to, so they follow it
+        for entry in &mut files {
+            if let EntryKind::HardLink(primary) = &mut entry.kind {
+                if primary == old_path {
+                    *primary = new_path.clone();
+                }
+            }
+        }
+    }
+
+    let file = OpenOptions::new()
+        .write(true)
+        .open(archive_path)
+        .map_err(|e| AppError::CreateFileError(archive_path.to_path_buf(), e))?;
+    let mut writer = BufWriter::new(file);
+
+    if let Some(position) = top_level_count_position {
+        patch_u64(&mut writer, position, count_top_level_entries(&files))?;
+    }
+
+    writer
+        .seek(SeekFrom::Start(file_table_position))
+        .map_err(AppError::WriterError)?;
+    write_file_table(&mut writer, &files, layout, key.as_ref())?;
+    // Chunks do not move, so the chunk index only follows the file table to its new end
+    if let Some(entries) = chunk_index {
+        let index_position = writer.stream_position().map_err(AppError::WriterError)?;
+        write_chunk_index(&mut writer, &entries, index_position)?;
+    }
+    writer.flush().map_err(AppError::FlushError)?;
+
+    // A shorter path leaves stale bytes after the new end of the archive
+    let end = writer.stream_position().map_err(AppError::WriterError)?;
+    writer
+        .get_ref()
+        .set_len(end)
+        .map_err(AppError::WriterError)?;
+    if let Some(position) = archive_size_position {
+        patch_u64(&mut writer, position, end)?;
+        writer.flush().map_err(AppError::FlushError)?;
+    }
+
+    Ok(())
+}
+
+/// Removes files from an archive and drops the chunks no other file still references.
+///
+/// Chunks carry no reference counts, so the live set is rebuilt from the entries that remain.
+/// The archive is rewritten next to itself with only those chunks and the shorter file table,
+/// then renamed over the original, so a failure part way leaves the archive as it was. Chunk
+/// data is copied as-is and nothing is recompressed.
+///
+/// A hard link to a removed file takes over its content, and any other links to it follow.
+///
+/// # Arguments
+/// * `archive_path` - The archive to edit.
+/// * `paths` - Paths of the entries to remove, as shown by `list`.
+/// * `password` - Password of an encrypted archive, whose file table is sealed again with it.
+///
+/// # Returns
+/// The number of chunks dropped because no remaining file references them.
+///
+/// # Errors
+/// Returns `AppError::FileNotFoundInArchive` if a path is not in the archive,
+/// `AppError::Unsupported` if the archive is signed, or an error if the archive cannot be read
+/// or written. Nothing is written unless every path is found.
+///
+/// # Example
+///
+/// ```no_run
+/// use squishrs::archive::remove_entries;
+/// use std::path::Path;
+///
+/// let dropped = remove_entries(Path::new("backup.squish"), &["logs/old.log".to_string()], None)
+///     .expect("Failed to remove");
+/// println!("Dropped {dropped} chunks");
+/// ```
+pub fn remove_entries(
+    archive_path: &Path,
+    paths: &[String],
+    password: Option<&str>,
+) -> Result<u64, AppError> {
+    let mut reader = open_for_edit(archive_path, password)?;
+    let mut files = reader.file_table()?;
+
+    for path in paths {
+        let index = files
+            .iter()
+            .position(|entry| &entry.path == path)
+            .ok_or_else(|| AppError::FileNotFoundInArchive(path.clone()))?;
+        let removed = files.remove(index);
+
+        // The first remaining link to the removed file becomes the one holding its content
+        let mut successor: Option<String> = None;
+        for entry in &mut files {
+            if entry.kind != EntryKind::HardLink(removed.path.clone()) {
+                continue;
+            }
+            match &successor {
+                Some(primary) => entry.kind = EntryKind::HardLink(primary.clone()),
+                None => {
+                    successor = Some(entry.path.clone());
+                    entry.kind = EntryKind::Regular;
+                    entry.original_size = removed.original_size;
+                    entry.chunk_hashes = removed.chunk_hashes.clone();
+                }
+            }
+        }
+    }
+
+    let staging = staging_path(archive_path, None, ".remove.tmp");
+    let rewritten = File::create(&staging)
+        .map_err(|e| AppError::CreateFileError(staging.clone(), e))
+        .and_then(|file| reader.rewrite_into(&mut BufWriter::new(file), Some(&files)));
+    let dropped = match rewritten {
+        Ok(dropped) => dropped,
+        Err(e) => {
+            let _ = fs::remove_file(&staging);
+            return Err(e);
+        }
+    };
+    drop(reader);
+
+    fs::rename(&staging, archive_path).map_err(AppError::WriterError)?;
+    Ok(dropped)
+}
+
+/// Opens the archive at `archive_path`, unlocking it with `password` when one is given.
+///
+/// Signed archives are refused, as the edited archive could not be signed again.
+fn open_for_edit(archive_path: &Path, password: Option<&str>) -> Result<ArchiveReader, AppError> {
+    let mut reader = ArchiveReader::new(archive_path)?;
+    reader.check_unsigned()?;
+    if let Some(password) = password {
+        reader.unlock(password)?;
+    }
+    Ok(reader)
+}
+
+/// Parses a batch of renames for `mv --from-file`.
+///
+/// Each line holds an old and a new path separated by a tab. Blank lines and lines starting
+/// with `#` are ignored.
+///
+/// # Errors
+/// Returns `AppError::Other` naming the first line that is not a tab-separated pair.
+///
+/// # Example
+///
+/// ```
+/// use squishrs::archive::parse_rename_map;
+///
+/// let renames = parse_rename_map("# moves\na.txt\tb.txt\n").unwrap();
+/// assert_eq!(renames, vec![("a.txt".to_string(), "b.txt".to_string())]);
+/// ```
+pub fn parse_rename_map(text: &str) -> Result<Vec<(String, String)>, AppError> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|(index, line)| match line.split_once('\t') {
+            Some((old_path, new_path)) if !new_path.contains('\t') => {
+                Ok((old_path.to_string(), new_path.to_string()))
+            }
+            _ => Err(AppError::Other(format!(
+                "line {} of the rename map is not `<old path><TAB><new path>`",
+                index + 1
+            ))),
+        })
+        .collect()
+}
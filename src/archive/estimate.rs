@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use indicatif::ProgressBar;
+use rayon::prelude::*;
+
+use crate::archive::index::{INDEX_ENTRY_SIZE, INDEX_TRAILER_SIZE};
+use crate::archive::pool::read_pool_hashes;
+use crate::archive::writer::{
+    archive_path, write_file_table, write_preamble, DedupScope, EntryKind, FileMetadata,
+    PackOptions, WRITTEN_LAYOUT,
+};
+use crate::fsutil::directory::find_hard_links;
+use crate::util::chunk::{
+    check_chunk_size, check_compression_level, chunk_seed, ChunkHash, ChunkStore, Chunker,
+};
+use crate::util::crypto::{Encryption, NONCE_LEN, TAG_LEN};
+use crate::util::errors::AppError;
+use crate::util::signing::FOOTER_SIZE;
+
+/// Size of a chunk table entry's header: the hash, the original size and the stored size.
+const CHUNK_ENTRY_HEADER_SIZE: u64 = 16 + 8 + 8;
+
+/// Bytes encryption adds to each sealed chunk and to the sealed file table: the nonce and the tag.
+const SEALED_OVERHEAD: u64 = (NONCE_LEN + TAG_LEN) as u64;
+
+/// What packing a set of files would produce, see [`estimate_pack`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PackEstimate {
+    /// Projected size of the archive in bytes, metadata included
+    pub archive_size: u64,
+    /// Total size of the files that would be packed
+    pub original_size: u64,
+    /// Chunks that would be stored in the archive after deduplication
+    pub unique_chunks: u64,
+    /// Entries the file table would hold
+    pub files: u64,
+}
+
+impl PackEstimate {
+    /// How much smaller than the input the archive would be, as a percentage.
+    ///
+    /// Negative when the archive would be larger, e.g. for incompressible input.
+    pub fn reduction_percentage(&self) -> f64 {
+        if self.original_size == 0 {
+            return 0.0;
+        }
+        (1.0 - self.archive_size as f64 / self.original_size as f64) * 100.0
+    }
+}
+
+/// Estimates the archive packing `files` from `input_dir` with `options` would produce,
+/// without writing anything.
+///
+/// Every file is chunked, deduplicated and compressed exactly as [`ArchiveWriter::pack`] would,
+/// so this takes about as long as a real pack; only the writing is skipped. The header, chunk
+/// table, file table and chunk index are sized from what would be written, which makes the
+/// estimate exact unless files change in the meantime.
+///
+/// [`ArchiveWriter::pack`]: crate::archive::ArchiveWriter::pack
+///
+/// # Arguments
+/// * `input_dir` - The directory `files` are inside, as for [`ArchiveWriter::with_options`].
+/// * `files` - The files and empty directories to pack.
+/// * `progress_bar` - Optional progress bar advanced once per file.
+/// * `options` - The settings the pack would use.
+///
+/// [`ArchiveWriter::with_options`]: crate::archive::ArchiveWriter::with_options
+///
+/// # Errors
+/// Returns an error if the options are invalid, or if a file cannot be read or is not inside
+/// `input_dir`.
+///
+/// # Example
+///
+/// ```no_run
+/// use squishrs::archive::{estimate_pack, PackOptions};
+/// use squishrs::fsutil::directory::walk_dir;
+/// use std::path::Path;
+///
+/// let input = Path::new("./data");
+/// let files = walk_dir(input).expect("Failed to walk directory");
+/// let estimate = estimate_pack(input, &files, None, &PackOptions::default())
+///     .expect("Failed to estimate");
+/// println!("About {} bytes, {:.1}% smaller", estimate.archive_size, estimate.reduction_percentage());
+/// ```
+pub fn estimate_pack(
+    input_dir: &Path,
+    files: &[PathBuf],
+    progress_bar: Option<&ProgressBar>,
+    options: &PackOptions,
+) -> Result<PackEstimate, AppError> {
+    check_compression_level(options.compression_level)?;
+    check_chunk_size(options.chunk_size as u64)?;
+
+    // Only the size of the encryption block matters, so no key is derived
+    let encryption = options
+        .password
+        .as_ref()
+        .map(|_| Encryption::generate(options.kdf));
+    let mut preamble = Cursor::new(Vec::new());
+    write_preamble(&mut preamble, options, encryption.as_ref())?;
+
+    // Chunks already in the pool are not stored, just as in a real pack
+    let chunk_store = ChunkStore::with_min_gain(options.min_gain_percent)
+        .with_level(options.compression_level)?
+        .with_compression(options.compression);
+    let pooled_chunks = match &options.chunk_pool {
+        Some(pool_path) => read_pool_hashes(pool_path)?,
+        None => Default::default(),
+    };
+    chunk_store.seed(pooled_chunks.iter().copied());
+
+    let relative_path = |file: &Path| -> Result<String, AppError> {
+        let relative = file.strip_prefix(input_dir).map_err(|_| {
+            AppError::Other(format!(
+                "`{}` is not inside `{}`",
+                file.display(),
+                input_dir.display()
+            ))
+        })?;
+        Ok(archive_path(relative))
+    };
+    let hard_links = if options.preserve_hardlinks {
+        find_hard_links(files)
+            .into_iter()
+            .map(|(link, primary)| Ok((link, relative_path(&primary)?)))
+            .collect::<Result<HashMap<_, _>, AppError>>()?
+    } else {
+        HashMap::new()
+    };
+
+    let stored_bytes = AtomicU64::new(0);
+    let files_metadata = files
+        .par_iter()
+        .map(|file| -> Result<FileMetadata, AppError> {
+            let path = relative_path(file)?;
+            let entry = |kind| FileMetadata {
+                path: path.clone(),
+                original_size: 0,
+                chunk_hashes: Vec::new(),
+                mode: None,
+                kind,
+                file_hash: None,
+            };
+
+            let metadata = fs::symlink_metadata(file)?;
+            let entry = if metadata.file_type().is_symlink() {
+                entry(EntryKind::Symlink(
+                    fs::read_link(file)?.to_string_lossy().to_string(),
+                ))
+            } else if let Some(primary) = hard_links.get(file) {
+                entry(EntryKind::HardLink(primary.clone()))
+            } else if metadata.is_dir() {
+                entry(EntryKind::Directory)
+            } else {
+                let scope = match options.dedup_scope {
+                    DedupScope::Global => chunk_seed(options.hash_seed, None),
+                    DedupScope::File => chunk_seed(options.hash_seed, Some(&path)),
+                };
+                let mut reader = BufReader::new(File::open(file)?);
+                let (chunk_hashes, original_size) =
+                    estimate_chunks(&mut reader, &chunk_store, scope, options, &stored_bytes)?;
+                FileMetadata {
+                    original_size,
+                    chunk_hashes,
+                    ..entry(EntryKind::Regular)
+                }
+            };
+
+            if let Some(progress_bar) = progress_bar {
+                progress_bar.inc(1);
+            }
+            Ok(entry)
+        })
+        .collect::<Result<Vec<_>, AppError>>()?;
+
+    let mut file_table = Vec::new();
+    write_file_table(&mut file_table, &files_metadata, WRITTEN_LAYOUT, None)?;
+
+    let unique_chunks = chunk_store.len() - pooled_chunks.len() as u64;
+    // Every chunk and the file table gain a nonce and tag, and the sealed table a length
+    let sealing = match encryption {
+        Some(_) => (unique_chunks + 1) * SEALED_OVERHEAD + 8,
+        None => 0,
+    };
+    let footer = match options.signing_key {
+        Some(_) => FOOTER_SIZE,
+        None => 0,
+    };
+    let archive_size = preamble.get_ref().len() as u64
+        + stored_bytes.load(Ordering::SeqCst)
+        + file_table.len() as u64
+        + unique_chunks * INDEX_ENTRY_SIZE
+        + INDEX_TRAILER_SIZE
+        + sealing
+        + footer;
+
+    Ok(PackEstimate {
+        archive_size,
+        original_size: files_metadata.iter().map(|entry| entry.original_size).sum(),
+        unique_chunks,
+        files: files_metadata.len() as u64,
+    })
+}
+
+/// Chunks `reader` into `chunk_store`, adding the size of every chunk entry that would be
+/// written to `stored_bytes`.
+///
+/// # Returns
+/// The chunk hashes in order and the number of bytes read.
+fn estimate_chunks<R: Read>(
+    reader: &mut R,
+    chunk_store: &ChunkStore,
+    scope: Option<u64>,
+    options: &PackOptions,
+    stored_bytes: &AtomicU64,
+) -> Result<(Vec<ChunkHash>, u64), AppError> {
+    let mut chunk_hashes = Vec::new();
+    let mut bytes_read = 0u64;
+
+    let mut chunker = Chunker::new(reader, options.chunk_size, options.chunking);
+    while let Some(slice) = chunker.next_chunk().map_err(AppError::ReaderError)? {
+        bytes_read += slice.len() as u64;
+        let result = chunk_store.insert_scoped(slice, scope)?;
+        if let Some(compressed) = result.compressed_data {
+            stored_bytes.fetch_add(
+                CHUNK_ENTRY_HEADER_SIZE + compressed.len() as u64,
+                Ordering::SeqCst,
+            );
+        }
+        chunk_hashes.push(result.hash);
+    }
+
+    Ok((chunk_hashes, bytes_read))
+}
@@ -0,0 +1,193 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::util::chunk::{decode_stored_size, ChunkHash};
+use crate::util::errors::AppError;
+
+/// Marker closing an archive that ends with a chunk index.
+const INDEX_MAGIC: [u8; 8] = *b"sqindex1";
+
+/// Size of one index entry: a hash, the data offset and the stored size.
+pub(crate) const INDEX_ENTRY_SIZE: u64 = 16 + 8 + 8;
+
+/// Size of the trailer: the index offset, the entry count and the magic marker.
+pub(crate) const INDEX_TRAILER_SIZE: u64 = 8 + 8 + INDEX_MAGIC.len() as u64;
+
+/// Where one chunk's stored data lives, as recorded in the chunk index.
+///
+/// The original size and raw flag stay in the chunk table; the index only says where to look.
+#[derive(Clone, Copy)]
+pub(crate) struct IndexEntry {
+    pub(crate) offset: u64,
+    pub(crate) stored_size: u64,
+}
+
+/// The chunk index found at the end of an archive.
+pub(crate) struct ChunkIndex {
+    /// Where the index begins, which is also where the file table ends
+    pub(crate) position: u64,
+    /// Every chunk in the chunk table, in table order
+    pub(crate) entries: Vec<(ChunkHash, IndexEntry)>,
+}
+
+impl ChunkIndex {
+    /// Offset of the `u32` file count, just past the last chunk in the table.
+    pub(crate) fn chunk_table_end(&self, chunk_table_offset: u64) -> u64 {
+        self.entries
+            .iter()
+            .map(|(_, entry)| entry.offset + entry.stored_size)
+            .max()
+            .unwrap_or(chunk_table_offset)
+    }
+}
+
+/// Walks `number_of_chunks` chunk table entries from the current position of `reader` and
+/// records where each one's data lives.
+///
+/// Only chunk headers are read; compressed data is skipped over with a seek.
+pub(crate) fn index_chunk_table<R: Read + Seek>(
+    reader: &mut R,
+    number_of_chunks: u64,
+) -> Result<Vec<(ChunkHash, IndexEntry)>, AppError> {
+    let mut sizes = [0u8; 16];
+    let mut entries = Vec::with_capacity(number_of_chunks as usize);
+
+    for _ in 0..number_of_chunks {
+        let mut hash = [0u8; 16];
+        reader
+            .read_exact(&mut hash)
+            .map_err(AppError::ReaderError)?;
+
+        // Original size, then stored size
+        reader
+            .read_exact(&mut sizes)
+            .map_err(AppError::ReaderError)?;
+        let (stored_size, _) =
+            decode_stored_size(u64::from_le_bytes(sizes[8..].try_into().unwrap()));
+
+        let offset = reader.stream_position().map_err(AppError::ReaderError)?;
+        entries.push((
+            hash,
+            IndexEntry {
+                offset,
+                stored_size,
+            },
+        ));
+
+        reader
+            .seek(SeekFrom::Current(stored_size as i64))
+            .map_err(AppError::ReaderError)?;
+    }
+
+    Ok(entries)
+}
+
+/// Writes a chunk index for `entries` followed by its trailer.
+///
+/// `position` is the offset in the archive at which the index starts, recorded in the trailer
+/// so a reader can find the index from the end of the file.
+pub(crate) fn write_chunk_index<W: Write>(
+    writer: &mut W,
+    entries: &[(ChunkHash, IndexEntry)],
+    position: u64,
+) -> Result<(), AppError> {
+    for (hash, entry) in entries {
+        writer.write_all(hash).map_err(AppError::WriterError)?;
+        writer
+            .write_all(&entry.offset.to_le_bytes())
+            .map_err(AppError::WriterError)?;
+        writer
+            .write_all(&entry.stored_size.to_le_bytes())
+            .map_err(AppError::WriterError)?;
+    }
+
+    writer
+        .write_all(&position.to_le_bytes())
+        .map_err(AppError::WriterError)?;
+    writer
+        .write_all(&(entries.len() as u64).to_le_bytes())
+        .map_err(AppError::WriterError)?;
+    writer
+        .write_all(&INDEX_MAGIC)
+        .map_err(AppError::WriterError)
+}
+
+/// Reads the chunk index at the end of an archive, if it has one.
+///
+/// Archives written before the index existed simply end with the file table, so a missing
+/// marker is not an error. An index that does not fit the chunk table it describes is ignored
+/// the same way, and the caller falls back to walking the table.
+///
+/// # Arguments
+/// * `reader` - The archive.
+/// * `archive_size` - Size of the archive in bytes.
+/// * `chunk_table_offset` - Offset of the first chunk table entry.
+/// * `number_of_chunks` - Chunk count from the archive header.
+///
+/// # Errors
+/// Returns an error only if reading fails.
+pub(crate) fn read_chunk_index<R: Read + Seek>(
+    reader: &mut R,
+    archive_size: u64,
+    chunk_table_offset: u64,
+    number_of_chunks: u64,
+) -> Result<Option<ChunkIndex>, AppError> {
+    // The file count alone takes 4 bytes, so a real index can never start earlier
+    if archive_size < chunk_table_offset + 4 + INDEX_TRAILER_SIZE {
+        return Ok(None);
+    }
+
+    let mut trailer = [0u8; INDEX_TRAILER_SIZE as usize];
+    reader
+        .seek(SeekFrom::Start(archive_size - INDEX_TRAILER_SIZE))
+        .map_err(AppError::ReaderError)?;
+    reader
+        .read_exact(&mut trailer)
+        .map_err(AppError::ReaderError)?;
+    if trailer[16..] != INDEX_MAGIC {
+        return Ok(None);
+    }
+
+    let position = u64::from_le_bytes(trailer[..8].try_into().unwrap());
+    let count = u64::from_le_bytes(trailer[8..16].try_into().unwrap());
+    let fits = count == number_of_chunks
+        && position >= chunk_table_offset + 4
+        && count
+            .checked_mul(INDEX_ENTRY_SIZE)
+            .and_then(|length| length.checked_add(position + INDEX_TRAILER_SIZE))
+            == Some(archive_size);
+    if !fits {
+        return Ok(None);
+    }
+
+    reader
+        .seek(SeekFrom::Start(position))
+        .map_err(AppError::ReaderError)?;
+    let mut entries = Vec::with_capacity(count as usize);
+    let mut buf = [0u8; INDEX_ENTRY_SIZE as usize];
+    for _ in 0..count {
+        reader.read_exact(&mut buf).map_err(AppError::ReaderError)?;
+
+        let hash: ChunkHash = buf[..16].try_into().unwrap();
+        let offset = u64::from_le_bytes(buf[16..24].try_into().unwrap());
+        let stored_size = u64::from_le_bytes(buf[24..].try_into().unwrap());
+
+        // Every chunk's data must lie inside the chunk table, before the file count
+        let inside = offset >= chunk_table_offset + 16 + 8 + 8
+            && offset
+                .checked_add(stored_size)
+                .is_some_and(|end| end + 4 <= position);
+        if !inside {
+            return Ok(None);
+        }
+
+        entries.push((
+            hash,
+            IndexEntry {
+                offset,
+                stored_size,
+            },
+        ));
+    }
+
+    Ok(Some(ChunkIndex { position, entries }))
+}
@@ -0,0 +1,158 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::archive::reader::{read_chunk_at, ChunkLocation};
+use crate::util::chunk::ChunkHash;
+use crate::util::crypto::ArchiveKey;
+use crate::util::errors::AppError;
+
+/// Decompressed chunks kept by [`LazyChunks`] for reuse; at `CHUNK_SIZE` each this caps the
+/// cache at 64 MiB.
+const LAZY_CHUNK_CACHE_CAPACITY: usize = 32;
+
+/// Least-recently-used cache of decompressed chunks.
+///
+/// Capacities are small, so eviction simply scans for the oldest entry.
+pub(crate) struct ChunkCache {
+    capacity: usize,
+    clock: u64,
+    entries: HashMap<ChunkHash, (u64, Vec<u8>)>,
+}
+
+impl ChunkCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            clock: 0,
+            entries: HashMap::with_capacity(capacity),
+        }
+    }
+
+    /// Returns a copy of the cached chunk, marking it as most recently used.
+    pub(crate) fn get(&mut self, hash: &ChunkHash) -> Option<Vec<u8>> {
+        self.clock += 1;
+        let clock = self.clock;
+        self.entries.get_mut(hash).map(|(last_used, data)| {
+            *last_used = clock;
+            data.clone()
+        })
+    }
+
+    /// Caches `data`, evicting the least recently used chunk when full.
+    pub(crate) fn insert(&mut self, hash: ChunkHash, data: Vec<u8>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&hash) {
+            if let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (last_used, _))| *last_used)
+                .map(|(hash, _)| *hash)
+            {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.clock += 1;
+        self.entries.insert(hash, (self.clock, data));
+    }
+}
+
+/// Index of where each chunk needed by an unpack is stored, read and decompressed on demand.
+///
+/// Only chunk headers are read up front, so memory stays at a handful of chunks however large
+/// the archive is. Chunks referenced more than once go through a small LRU cache so files
+/// sharing content do not decompress it again.
+pub(crate) struct LazyChunks {
+    /// Files chunks are read from: the archive itself, then its chunk pool if any
+    sources: Vec<PathBuf>,
+    locations: HashMap<ChunkHash, (usize, ChunkLocation)>,
+    /// Chunks referenced more than once, the only ones worth caching
+    shared: HashSet<ChunkHash>,
+    cache: Mutex<ChunkCache>,
+    /// Key of an encrypted archive; chunk pools are never encrypted, so only chunks from the
+    /// first source are decrypted
+    key: Option<ArchiveKey>,
+}
+
+/// Per-thread open handles onto the files of a [`LazyChunks`], opened on first use.
+#[derive(Default)]
+pub(crate) struct SourceReaders(Vec<Option<BufReader<File>>>);
+
+impl LazyChunks {
+    /// Creates an index over `sources`, where `shared` lists the chunks worth caching and `key`
+    /// decrypts the archive's own chunks.
+    pub(crate) fn new(
+        sources: Vec<PathBuf>,
+        shared: HashSet<ChunkHash>,
+        key: Option<ArchiveKey>,
+    ) -> Self {
+        Self {
+            sources,
+            locations: HashMap::new(),
+            shared,
+            cache: Mutex::new(ChunkCache::new(LAZY_CHUNK_CACHE_CAPACITY)),
+            key,
+        }
+    }
+
+    /// Records the chunks found in the source at `source`, keeping earlier sources' copies.
+    pub(crate) fn add_locations(
+        &mut self,
+        source: usize,
+        locations: HashMap<ChunkHash, ChunkLocation>,
+    ) {
+        for (hash, location) in locations {
+            self.locations.entry(hash).or_insert((source, location));
+        }
+    }
+
+    /// Reads and decompresses the chunk with `hash`, or `None` if no source stores it.
+    pub(crate) fn get(
+        &self,
+        hash: &ChunkHash,
+        readers: &mut SourceReaders,
+    ) -> Result<Option<Vec<u8>>, AppError> {
+        let Some((source, location)) = self.locations.get(hash) else {
+            return Ok(None);
+        };
+
+        let shared = self.shared.contains(hash);
+        if shared {
+            if let Some(data) = self.cache.lock().unwrap().get(hash) {
+                return Ok(Some(data));
+            }
+        }
+
+        let key = if *source == 0 {
+            self.key.as_ref()
+        } else {
+            None
+        };
+        let data = read_chunk_at(self.reader(*source, readers)?, location, key)?;
+        if shared {
+            self.cache.lock().unwrap().insert(*hash, data.clone());
+        }
+        Ok(Some(data))
+    }
+
+    fn reader<'r>(
+        &self,
+        source: usize,
+        readers: &'r mut SourceReaders,
+    ) -> Result<&'r mut BufReader<File>, AppError> {
+        if readers.0.len() <= source {
+            readers.0.resize_with(source + 1, || None);
+        }
+        let slot = &mut readers.0[source];
+        if slot.is_none() {
+            let path = &self.sources[source];
+            let file = File::open(path).map_err(|_| AppError::FileNotExist(path.clone()))?;
+            *slot = Some(BufReader::new(file));
+        }
+        Ok(slot.as_mut().expect("reader was just opened"))
+    }
+}
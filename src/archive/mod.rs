@@ -1,8 +1,21 @@
+pub mod edit;
+pub mod estimate;
+mod index;
+mod lazy;
+pub mod pool;
 pub mod reader;
 pub mod writer;
 
-pub use reader::ArchiveReader;
-pub use writer::ArchiveWriter;
+pub use edit::{parse_rename_map, remove_entries, rename_entries};
+pub use estimate::{estimate_pack, PackEstimate};
+pub use reader::{
+    check_compatibility, raw_fingerprint, unpack_sequential, ArchiveReader, CheckFailure,
+    ChunkInfo, ConflictPolicy, FileEntry, IntegrityReport, LayoutSection, PartialRecovery,
+    SharedChunk, SpaceReport, UnpackOptions, UnpackReport, VerifyReport,
+};
+pub use writer::{
+    ArchiveWriter, DedupScope, PackOptions, SizeMismatch, SkippedFile, DEFAULT_WRITER_QUEUE_CHUNKS,
+};
 
 #[cfg(test)]
 mod tests;
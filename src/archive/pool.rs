@@ -0,0 +1,93 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::archive::reader::{locate_chunk_entries, read_chunk_table, ChunkLocation};
+use crate::util::chunk::{decode_stored_size, ChunkHash};
+use crate::util::errors::AppError;
+use crate::util::header::verify_pool_header;
+
+/// Opens a chunk pool, checks its header and returns the reader positioned at the first chunk
+/// together with the number of chunks it holds.
+fn open_pool(pool_path: &Path) -> Result<(BufReader<File>, u64), AppError> {
+    let file =
+        File::open(pool_path).map_err(|_| AppError::FileNotExist(pool_path.to_path_buf()))?;
+    let mut reader = BufReader::new(file);
+    verify_pool_header(&mut reader)?;
+
+    let mut buf8 = [0u8; 8];
+    reader
+        .read_exact(&mut buf8)
+        .map_err(AppError::ReaderError)?;
+
+    Ok((reader, u64::from_le_bytes(buf8)))
+}
+
+/// Reads the hashes of every chunk in a pool without decompressing anything.
+///
+/// Used to seed a pack: chunks already in the pool are referenced but not stored again.
+///
+/// # Errors
+/// Returns an error if the pool cannot be opened, has an invalid header or is truncated.
+pub fn read_pool_hashes(pool_path: &Path) -> Result<HashSet<ChunkHash>, AppError> {
+    let (mut reader, number_of_chunks) = open_pool(pool_path)?;
+
+    let mut buf8 = [0u8; 8];
+    let mut hashes = HashSet::with_capacity(number_of_chunks as usize);
+    for _ in 0..number_of_chunks {
+        let mut hash = [0u8; 16];
+        reader
+            .read_exact(&mut hash)
+            .map_err(AppError::ReaderError)?;
+
+        // Skip the original size, then read the compressed size to skip the data
+        reader
+            .read_exact(&mut buf8)
+            .map_err(AppError::ReaderError)?;
+        reader
+            .read_exact(&mut buf8)
+            .map_err(AppError::ReaderError)?;
+        let (compressed_size, _) = decode_stored_size(u64::from_le_bytes(buf8));
+        reader
+            .seek(SeekFrom::Current(compressed_size as i64))
+            .map_err(AppError::ReaderError)?;
+
+        hashes.insert(hash);
+    }
+
+    Ok(hashes)
+}
+
+/// Finds where each chunk in `wanted` is stored in a pool, without decompressing anything.
+///
+/// # Errors
+/// Returns an error if the pool cannot be opened, has an invalid header or is truncated.
+pub(crate) fn locate_pool_chunks(
+    pool_path: &Path,
+    wanted: &HashSet<ChunkHash>,
+) -> Result<HashMap<ChunkHash, ChunkLocation>, AppError> {
+    let (mut reader, number_of_chunks) = open_pool(pool_path)?;
+    locate_chunk_entries(&mut reader, number_of_chunks, wanted)
+}
+
+/// Reads and decompresses every chunk in a pool, for unpacking archives packed against it.
+///
+/// `decompress_threads` bounds decompression parallelism as for the archive's own chunks.
+///
+/// # Errors
+/// Returns an error if the pool cannot be opened, has an invalid header, is truncated or a
+/// chunk fails to decompress.
+pub fn read_pool_chunks(
+    pool_path: &Path,
+    decompress_threads: Option<usize>,
+) -> Result<HashMap<ChunkHash, Vec<u8>>, AppError> {
+    let (mut reader, number_of_chunks) = open_pool(pool_path)?;
+    read_chunk_table(
+        &mut reader,
+        number_of_chunks,
+        None,
+        decompress_threads,
+        None,
+    )
+}
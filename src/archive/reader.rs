@@ -1,45 +1,329 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fs::{self, File};
-use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
-use std::path::{Path, PathBuf};
+use std::io::{BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
 
+use ed25519_dalek::VerifyingKey;
 use indicatif::ProgressBar;
 use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use xxhash_rust::xxh3::Xxh3;
 use zstd::bulk::decompress;
 
-use crate::util::chunk::ChunkHash;
+use crate::archive::index::{
+    read_chunk_index, write_chunk_index, IndexEntry, INDEX_ENTRY_SIZE, INDEX_TRAILER_SIZE,
+};
+use crate::archive::lazy::{LazyChunks, SourceReaders};
+use crate::archive::pool::{locate_pool_chunks, read_pool_chunks, read_pool_hashes};
+use crate::archive::writer::{
+    count_top_level_entries, write_file_table, write_sealed_file_table, EntryKind, FileMetadata,
+    FileTableLayout, ENTRY_DIRECTORY, ENTRY_HARDLINK, ENTRY_REGULAR, ENTRY_SYMLINK,
+};
+use crate::fsutil::alloc::preallocate;
+use crate::fsutil::directory::walk_dir;
+use crate::util::chunk::{
+    chunk_seed, decode_stored_size, hash_chunk_scoped, hash_chunks_scoped, hash_to_hex, ChunkHash,
+    Chunking, ContentHasher, CHUNK_SIZE, MAX_CHUNK_SIZE,
+};
+use crate::util::crypto::{ArchiveKey, Encryption, KdfParams};
 use crate::util::errors::AppError;
-use crate::util::header::{convert_timestamp_to_date, verify_header};
+use crate::util::header::{
+    convert_timestamp_to_date, read_timestamp, read_timestamp_fields, verify_header,
+    verify_header_strict, write_pool_header, write_timestamp_fields, Provenance, TimestampFields,
+    PREFIX,
+};
+use crate::util::interrupt::check_interrupted;
+use crate::util::signing::{read_footer, verify, KeyFingerprint, SignatureFooter};
 
 pub struct ArchiveReader {
     reader: BufReader<File>,
+    archive_path: PathBuf,
     archive_size: u64,
     squish_creation_time: String,
     number_of_chunks: u64,
+    top_level_entries: u64,
     squish_version: String,
     file_count: u32,
     chunk_table_offset: u64,
     file_table_offset: u64,
+    file_table_end: u64,
+    chunk_index: Option<HashMap<ChunkHash, IndexEntry>>,
+    provenance: Option<Provenance>,
+    hash_seed: u64,
+    chunk_size: usize,
+    chunking: Chunking,
+    layout: FileTableLayout,
+    /// Whether the header records the archive's total size, just before the chunk count
+    records_size: bool,
+    /// Whether the number of top-level entries follows the chunk count. Archives without it
+    /// have the count worked out from the file table when they are opened
+    records_top_level: bool,
+    /// How the key of an encrypted archive is derived, `None` when it is not encrypted
+    encryption: Option<Encryption>,
+    /// The key, once [`ArchiveReader::unlock`] has derived it
+    key: Option<ArchiveKey>,
+    /// The decrypted file table entries of an unlocked archive, read in place of the sealed
+    /// ones in the archive
+    file_table: Option<Cursor<Vec<u8>>>,
+    /// The footer of a signed archive, `None` when it is not signed
+    signature: Option<SignatureFooter>,
+}
+
+/// Optional settings for unpacking an archive.
+#[derive(Default, Clone)]
+pub struct UnpackOptions {
+    /// Relative directory prepended to every extracted path (e.g. `vendor/lib`)
+    pub prefix: Option<PathBuf>,
+    /// Reserve each file's full size on disk before writing its chunks
+    pub preallocate: bool,
+    /// Chunk pool the archive was packed against, supplying chunks it does not store itself
+    pub chunk_pool: Option<PathBuf>,
+    /// Write zeros in place of missing chunks instead of failing the file, for salvaging
+    /// damaged archives. Affected files are listed in the returned `UnpackReport`
+    pub zero_fill_missing: bool,
+    /// Decompress at most this many chunks at once; `None` uses the global thread pool. Memory
+    /// spent on in-flight chunks peaks at roughly threads × the archive's chunk size
+    pub decompress_threads: Option<usize>,
+    /// Reject archives from a different minor version instead of reading them. Only consulted
+    /// by [`unpack_sequential`]; seekable archives are checked when they are opened
+    pub strict_version: bool,
+    /// Only restore files of at least this many bytes
+    pub min_size: Option<u64>,
+    /// Only restore files of at most this many bytes
+    pub max_size: Option<u64>,
+    /// Decompress every needed chunk into memory before rebuilding files. Faster on archives
+    /// with heavy sharing, but needs memory for the whole unpacked chunk set; by default
+    /// chunks are read from disk as each file needs them. [`unpack_sequential`] cannot seek
+    /// back to chunks, so it always works in memory
+    pub in_memory: bool,
+    /// Rebuild every file without writing anything: each chunk must hash back to its recorded
+    /// hash and each file must come out at its recorded size. Files that do not are listed in
+    /// `UnpackReport::check_failures` rather than failing the unpack
+    pub check: bool,
+    /// Only restore the file stored at this path, as shown by `list`. Only its chunks are
+    /// located and decompressed, unless the archive is read sequentially
+    pub file: Option<String>,
+    /// Don't compare the length of each rebuilt file with the size recorded in the file table,
+    /// which otherwise fails the unpack with `AppError::SizeMismatch`
+    pub skip_size_check: bool,
+    /// Hash each rebuilt file and compare it with the content hash recorded when it was packed,
+    /// failing the unpack with `AppError::FileHashMismatch` if they differ. Archives and entries
+    /// without a recorded hash are not checked
+    pub verify: bool,
+    /// Restore every file whose chunks are intact and leave out the rest instead of failing the
+    /// unpack. Each chunk is re-hashed and content hashes compared as with `verify`; files that
+    /// fail are listed in `UnpackReport::lost_files`. Chunks are always read from disk as files
+    /// need them, and [`unpack_sequential`] does not support it
+    pub repair: bool,
+    /// What to do when a file, link or hard link is already present at an extracted path.
+    /// Directories are always merged into
+    pub on_conflict: ConflictPolicy,
+    /// Restore the setuid, setgid and sticky bits recorded for each file. Off by default, so
+    /// only the read, write and execute bits of an untrusted archive are applied
+    pub special_mode_bits: bool,
+    /// Password of an encrypted archive, needed by [`unpack_sequential`]; an [`ArchiveReader`] is
+    /// given its password with [`ArchiveReader::unlock`] instead
+    pub password: Option<String>,
+}
+
+/// What an unpack does with files already present at the paths it extracts to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Replace the existing file
+    #[default]
+    Overwrite,
+    /// Leave the existing file alone and do not extract the entry
+    Skip,
+    /// Extract next to the existing file as `name (1).ext`, counting up until the name is free
+    Rename,
+}
+
+impl UnpackOptions {
+    /// Whether `min_size` or `max_size` is set.
+    pub fn has_size_filter(&self) -> bool {
+        self.min_size.is_some() || self.max_size.is_some()
+    }
+
+    /// Whether a file of `size` bytes passes the size filter; bounds are inclusive.
+    pub fn size_matches(&self, size: u64) -> bool {
+        self.min_size.is_none_or(|min| size >= min) && self.max_size.is_none_or(|max| size <= max)
+    }
+}
+
+/// A file restored with zeros in place of chunks missing from the archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartialRecovery {
+    pub path: String,
+    pub missing_chunks: usize,
+    pub zero_filled_bytes: u64,
+}
+
+/// Outcome of [`ArchiveReader::verify_integrity`] on an intact archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntegrityReport {
+    /// Chunks decompressed and re-hashed
+    pub chunks_verified: u64,
+    /// Files whose chunk references were all found
+    pub files_checked: usize,
+}
+
+/// Outcome of an unpack.
+#[derive(Debug, Default)]
+pub struct UnpackReport {
+    /// Files that were only partially recovered; always empty unless `zero_fill_missing` is set
+    pub partially_recovered: Vec<PartialRecovery>,
+    /// Files restored
+    pub files_restored: usize,
+    /// Files left out because their size fell outside `min_size`..=`max_size`
+    pub excluded_by_size: usize,
+    /// Files that failed to rebuild correctly; always empty unless `check` is set
+    pub check_failures: Vec<CheckFailure>,
+    /// Files left out because their chunks are damaged; always empty unless `repair` is set
+    pub lost_files: Vec<CheckFailure>,
+    /// Entries not extracted because a file was already at their path; only with
+    /// `ConflictPolicy::Skip`
+    pub skipped_existing: usize,
+    /// Entries extracted under a new name because a file was already at their path; only with
+    /// `ConflictPolicy::Rename`
+    pub renamed_existing: usize,
+}
+
+/// A file that did not rebuild correctly during an unpack with `check` or `repair` set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckFailure {
+    pub path: String,
+    /// What went wrong, e.g. a missing chunk or a size mismatch
+    pub reason: String,
 }
 
 pub struct ArchiveSummary {
     pub unique_chunks: u64,
+    pub top_level_entries: u64,
     pub total_original_size: u64,
+    /// Original bytes of chunk references that repeat a chunk already referenced, i.e. what
+    /// deduplication saved before compression
+    pub duplicate_chunk_bytes: u64,
     pub archive_size: u64,
     pub compression_ratio: f64,
     pub squish_creation_date: String,
     pub squish_version: String,
+    /// Where the archive was made, when it was packed with provenance
+    pub provenance: Option<Provenance>,
     pub files: Vec<FileEntry>,
 }
 
+/// One entry of the file table, as listed by [`ArchiveReader::entries`].
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FileEntry {
     pub path: String,
     pub original_size: u64,
+    /// Number of chunks the entry references, counting repeats
+    pub chunk_count: u32,
+}
+
+/// Diagnostic details about a single entry of the chunk table, see [`ArchiveReader::chunk_info`].
+#[derive(Debug)]
+pub struct ChunkInfo {
+    pub index: u64,
+    pub hash: ChunkHash,
+    pub original_size: u64,
+    pub compressed_size: u64,
+    /// Whether the chunk is stored uncompressed because compression gained too little
+    pub stored_raw: bool,
+    /// Byte offset of the compressed data within the archive file
+    pub offset: u64,
+    /// Whether the compressed data decompresses successfully
+    pub decompresses: bool,
+    /// Whether the decompressed data hashes back to `hash`, within the archive's hash seed or,
+    /// for archives packed with a per-file dedup scope, the scope of a file referencing it
+    pub hash_matches: bool,
+}
+
+/// A chunk referenced by more than one file, see [`ArchiveReader::shared_chunks`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedChunk {
+    pub hash: ChunkHash,
+    /// Uncompressed size of the chunk
+    pub size: u64,
+    /// Paths of the files referencing the chunk, sorted
+    pub files: Vec<String>,
+}
+
+/// A contiguous byte range of the archive file, see [`ArchiveReader::layout`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayoutSection {
+    pub name: &'static str,
+    /// Byte offset of the section's first byte
+    pub offset: u64,
+    pub length: u64,
+}
+
+impl LayoutSection {
+    /// Offset one past the section's last byte
+    pub fn end(&self) -> u64 {
+        self.offset + self.length
+    }
+}
+
+/// How an archive's bytes split between chunks still in use and dead space,
+/// see [`ArchiveReader::space_report`].
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SpaceReport {
+    pub on_disk_bytes: u64,
+    /// Chunk table bytes (entry headers and data) of chunks referenced by the file table
+    pub live_chunk_bytes: u64,
+    /// Chunk table bytes of chunks no file references any more
+    pub orphan_chunk_bytes: u64,
+    pub orphan_count: u64,
+}
+
+/// Outcome of comparing an archive against a source directory with
+/// [`ArchiveReader::verify_against`]. All paths are relative, as stored in the archive.
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    /// Files whose on-disk chunk hashes match the archive exactly
+    pub matched: Vec<String>,
+    /// Files present in both places whose contents differ
+    pub mismatched: Vec<String>,
+    /// Files recorded in the archive but absent from the source directory
+    pub missing_from_source: Vec<String>,
+    /// Files in the source directory that the archive does not contain
+    pub missing_from_archive: Vec<String>,
+}
+
+impl VerifyReport {
+    /// Returns true when every file matches and nothing is missing on either side.
+    pub fn is_clean(&self) -> bool {
+        self.mismatched.is_empty()
+            && self.missing_from_source.is_empty()
+            && self.missing_from_archive.is_empty()
+    }
 }
 
-struct FileRebuildEntry {
-    relative_path: String,
+/// A single entry parsed from the file table.
+struct FileRecord {
+    path: String,
+    original_size: u64,
+    chunk_count: u32,
     chunk_hashes: Vec<ChunkHash>,
+    /// Unix permission mode, absent on archives that do not record one
+    mode: Option<u32>,
+    kind: EntryKind,
+    /// xxh3-128 hash of the file's content, absent on archives or entries that do not record one
+    file_hash: Option<ChunkHash>,
+}
+
+/// Where a chunk lives in the archive's chunk table.
+#[derive(Clone, Copy)]
+pub(crate) struct ChunkLocation {
+    /// The chunk's hash, which an encrypted chunk is authenticated with
+    hash: ChunkHash,
+    offset: u64,
+    original_size: u64,
+    compressed_size: u64,
+    stored_raw: bool,
 }
 
 impl ArchiveReader {
@@ -59,9 +343,32 @@ impl ArchiveReader {
         let mut buf8 = [0u8; 8];
         let mut buf16 = [0u8; 16];
 
-        // Get creation time
-        reader.read_exact(&mut buf8)?;
-        let squish_creation_time = convert_timestamp_to_date(u64::from_le_bytes(buf8))?;
+        // Get creation time, where the archive was made if it was recorded, the hash seed and
+        // which optional fields the file table carries
+        let fields = read_timestamp_fields(&mut reader)?;
+        let layout = FileTableLayout::of(&fields);
+        let TimestampFields {
+            timestamp,
+            provenance,
+            hash_seed,
+            chunk_size,
+            chunking,
+            archive_size: recorded_size,
+            top_level_count: records_top_level,
+            encryption,
+            signed,
+            ..
+        } = fields;
+        let squish_creation_time = convert_timestamp_to_date(timestamp)?;
+
+        // A copy cut short in transfer would otherwise fail part way through the chunk table
+        if let Some(recorded_size) = recorded_size {
+            if archive_size < recorded_size {
+                return Err(AppError::Archive(format!(
+                    "truncated archive: expected {recorded_size} bytes, found {archive_size}"
+                )));
+            }
+        }
 
         // Read the number of chunks
         reader
@@ -69,30 +376,69 @@ impl ArchiveReader {
             .map_err(AppError::ReaderError)?;
         let unique_chunk_count = u64::from_le_bytes(buf8);
 
-        let chunk_table_offset = reader.stream_position().map_err(AppError::ReaderError)?;
-
-        // Skip all chunks
-        for _ in 0..unique_chunk_count {
-            // Read chunk hash
-            reader
-                .read_exact(&mut buf16)
-                .map_err(AppError::ReaderError)?;
-
-            // original size
+        // Read the number of distinct top-level entries, if the archive records it
+        let recorded_top_level = if records_top_level {
             reader
                 .read_exact(&mut buf8)
                 .map_err(AppError::ReaderError)?;
+            Some(u64::from_le_bytes(buf8))
+        } else {
+            None
+        };
 
-            // compressed size
-            reader
-                .read_exact(&mut buf8)
-                .map_err(AppError::ReaderError)?;
-            let compressed_size = u64::from_le_bytes(buf8);
+        let chunk_table_offset = reader.stream_position().map_err(AppError::ReaderError)?;
 
-            // Skip over compressed data
-            reader
-                .seek(SeekFrom::Current(compressed_size as i64))
-                .map_err(AppError::ReaderError)?;
+        // The signature footer comes after everything else, chunk index included
+        let signature = if signed {
+            Some(read_footer(&mut reader, archive_size)?)
+        } else {
+            None
+        };
+        let body_end = signature
+            .as_ref()
+            .map_or(archive_size, |footer| footer.signed_length);
+
+        // With a chunk index the file table is found without walking the chunk table
+        let chunk_index = read_chunk_index(
+            &mut reader,
+            body_end,
+            chunk_table_offset,
+            unique_chunk_count,
+        )?;
+        match &chunk_index {
+            Some(index) => {
+                reader
+                    .seek(SeekFrom::Start(index.chunk_table_end(chunk_table_offset)))
+                    .map_err(AppError::ReaderError)?;
+            }
+            None => {
+                reader
+                    .seek(SeekFrom::Start(chunk_table_offset))
+                    .map_err(AppError::ReaderError)?;
+                // Skip all chunks
+                for _ in 0..unique_chunk_count {
+                    // Read chunk hash
+                    reader
+                        .read_exact(&mut buf16)
+                        .map_err(AppError::ReaderError)?;
+
+                    // original size
+                    reader
+                        .read_exact(&mut buf8)
+                        .map_err(AppError::ReaderError)?;
+
+                    // compressed size
+                    reader
+                        .read_exact(&mut buf8)
+                        .map_err(AppError::ReaderError)?;
+                    let (compressed_size, _) = decode_stored_size(u64::from_le_bytes(buf8));
+
+                    // Skip over compressed data
+                    reader
+                        .seek(SeekFrom::Current(compressed_size as i64))
+                        .map_err(AppError::ReaderError)?;
+                }
+            }
         }
 
         // Read number of files (u32)
@@ -105,18 +451,200 @@ impl ArchiveReader {
         // Get file table offset
         let file_table_offset = reader.stream_position().map_err(AppError::ReaderError)?;
 
+        let top_level_entries = match recorded_top_level {
+            Some(count) => count,
+            // The writer always records the count, and the entries cannot be read while locked
+            None if encryption.is_some() => {
+                return Err(AppError::Archive(
+                    "encrypted squish does not record its top-level entry count".into(),
+                ))
+            }
+            None => {
+                let mut top_level = HashSet::new();
+                for _ in 0..file_count {
+                    let record = read_file_record(&mut reader, layout)?;
+                    if let Some(first) = record.path.split('/').next() {
+                        top_level.insert(first.to_string());
+                    }
+                }
+                top_level.len() as u64
+            }
+        };
+
         Ok(Self {
             reader,
+            archive_path: archive_path.to_path_buf(),
             archive_size,
             squish_creation_time,
             number_of_chunks: unique_chunk_count,
+            top_level_entries,
             file_count,
             chunk_table_offset,
             file_table_offset,
+            file_table_end: chunk_index
+                .as_ref()
+                .map_or(body_end, |index| index.position),
+            chunk_index: chunk_index.map(|index| index.entries.into_iter().collect()),
             squish_version,
+            provenance,
+            hash_seed,
+            chunk_size: chunk_size.map_or(CHUNK_SIZE, |size| size as usize),
+            chunking,
+            layout,
+            records_size: recorded_size.is_some(),
+            records_top_level,
+            encryption,
+            key: None,
+            file_table: None,
+            signature,
         })
     }
 
+    /// Returns whether chunks and the file table are encrypted, see [`ArchiveReader::unlock`].
+    pub fn is_encrypted(&self) -> bool {
+        self.encryption.is_some()
+    }
+
+    /// Returns whether the archive ends with a signature footer, see
+    /// [`ArchiveReader::verify_signature`].
+    pub fn is_signed(&self) -> bool {
+        self.signature.is_some()
+    }
+
+    /// Returns the fingerprint of the public key a signed archive says it was signed with.
+    ///
+    /// Only [`ArchiveReader::verify_signature`] proves the key really signed it.
+    pub fn signer_fingerprint(&self) -> Option<&KeyFingerprint> {
+        self.signature.as_ref().map(|footer| &footer.fingerprint)
+    }
+
+    /// Checks that the archive was signed by the private half of `key` and has not changed
+    /// since.
+    ///
+    /// The footer records how many bytes the signature covers, so they are hashed in a single
+    /// pass from the start of the file. An encrypted archive does not need to be unlocked.
+    ///
+    /// # Errors
+    /// Returns `AppError::NotSigned` if the archive has no signature, or
+    /// `AppError::SignatureMismatch` if another key signed it or it was altered after signing.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use squishrs::archive::ArchiveReader;
+    /// use squishrs::util::signing::load_verifying_key;
+    /// use std::path::Path;
+    ///
+    /// let key = load_verifying_key(Path::new("release.pub.pem")).expect("Failed to read key");
+    /// let mut reader = ArchiveReader::new(Path::new("release.squish")).expect("Failed to read squish");
+    /// reader.verify_signature(&key).expect("Signature check failed");
+    /// ```
+    pub fn verify_signature(&mut self, key: &VerifyingKey) -> Result<(), AppError> {
+        let footer = self.signature.as_ref().ok_or(AppError::NotSigned)?;
+        self.reader.rewind().map_err(AppError::ReaderError)?;
+        verify(&mut self.reader, footer, key)
+    }
+
+    /// Derives the key of an encrypted archive from `password` and decrypts its file table, so
+    /// the archive can be read like any other. Does nothing on an unencrypted archive.
+    ///
+    /// Until an encrypted archive is unlocked, anything that reads its file table or chunks
+    /// fails with `AppError::PasswordRequired`.
+    ///
+    /// # Errors
+    /// Returns `AppError::DecryptionFailed` if the password is wrong or the file table was
+    /// altered.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use squishrs::archive::ArchiveReader;
+    /// use std::path::Path;
+    ///
+    /// let mut reader = ArchiveReader::new(Path::new("secret.squish")).expect("Failed to read squish");
+    /// reader.unlock("correct horse").expect("Wrong password");
+    /// println!("{} files", reader.entries().expect("Failed to list").len());
+    /// ```
+    pub fn unlock(&mut self, password: &str) -> Result<(), AppError> {
+        let Some(encryption) = &self.encryption else {
+            return Ok(());
+        };
+        let key = encryption.derive_key(password)?;
+
+        self.reader
+            .seek(SeekFrom::Start(self.file_table_offset))
+            .map_err(AppError::ReaderError)?;
+        let records = read_sealed_file_table(&mut self.reader, self.file_count, &key)?;
+        self.file_table = Some(Cursor::new(records));
+        self.key = Some(key);
+        Ok(())
+    }
+
+    /// Returns when the archive was packed, as the UTC date and time recorded in its header.
+    pub fn creation_time(&self) -> &str {
+        &self.squish_creation_time
+    }
+
+    /// Returns the number of unique chunks stored in the archive, read from the header.
+    pub fn chunk_count(&self) -> u64 {
+        self.number_of_chunks
+    }
+
+    /// Returns the number of entries in the file table, including directories and links.
+    ///
+    /// This is the count that precedes the file table, so no entries are read.
+    pub fn file_count(&self) -> u32 {
+        self.file_count
+    }
+
+    /// Returns the size of the archive file in bytes.
+    pub fn archive_size(&self) -> u64 {
+        self.archive_size
+    }
+
+    /// Returns the number of distinct top-level directories and files recorded at pack time.
+    ///
+    /// This is read from the header, so no file table scan is needed.
+    pub fn top_level_entries(&self) -> u64 {
+        self.top_level_entries
+    }
+
+    /// Returns the squishrs version that wrote the archive, as recorded in its header.
+    pub fn version(&self) -> &str {
+        &self.squish_version
+    }
+
+    /// Returns the hostname and tool version recorded at pack time, if any.
+    pub fn provenance(&self) -> Option<&Provenance> {
+        self.provenance.as_ref()
+    }
+
+    /// Returns the xxh3 seed chunk hashes were computed with; 0 unless packed with a seed.
+    pub fn hash_seed(&self) -> u64 {
+        self.hash_seed
+    }
+
+    /// Returns the number of bytes per chunk the archive was packed with; the average chunk
+    /// length when chunks are content-defined.
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Returns how files were split into chunks.
+    pub fn chunking(&self) -> Chunking {
+        self.chunking
+    }
+
+    /// Returns whether the file table records each file's Unix permission mode.
+    pub fn file_modes(&self) -> bool {
+        self.layout.modes
+    }
+
+    /// Returns the optional fields each file table entry carries.
+    pub(crate) fn file_table_layout(&self) -> FileTableLayout {
+        self.layout
+    }
+
     /// Returns a summary of the archive's contents, including total size, compression ratio,
     /// number of files, and file metadata.
     ///
@@ -129,7 +657,7 @@ impl ArchiveReader {
     ///
     /// * `Ok(ArchiveSummary)` — Contains a high-level overview of the archive's contents,
     ///   including all file paths, their original sizes, and archive statistics.
-    /// * `Err(Box<dyn std::error::Error>)` — Returned if the archive is malformed or an I/O
+    /// * `Err(AppError)` — Returned if the archive is malformed or an I/O
     ///   operation fails (e.g., seeking or reading from the file).
     ///
     /// # Errors
@@ -153,50 +681,27 @@ impl ArchiveReader {
     /// println!("Compression Ratio: {:.2}%", summary.compression_ratio);
     /// ```
     pub fn get_summary(&mut self) -> Result<ArchiveSummary, AppError> {
-        self.reader
-            .seek(SeekFrom::Start(self.file_table_offset))
-            .map_err(AppError::ReaderError)?;
-
-        let mut buf4 = [0u8; 4];
-        let mut buf8 = [0u8; 8];
+        self.seek_file_table()?;
 
         let mut files = Vec::with_capacity(self.file_count as usize);
         let mut total_orig_size = 0;
+        let mut seen = HashSet::new();
+        // How many times each chunk is referenced again after its first reference
+        let mut repeats: HashMap<ChunkHash, u64> = HashMap::new();
 
         for _ in 0..self.file_count {
-            // Read Path length
-            self.reader
-                .read_exact(&mut buf4)
-                .map_err(AppError::ReaderError)?;
-            let path_length = u32::from_le_bytes(buf4) as usize;
-
-            // Read Path
-            let mut path_bytes = vec![0u8; path_length];
-            self.reader
-                .read_exact(&mut path_bytes)
-                .map_err(AppError::ReaderError)?;
-            let path = String::from_utf8(path_bytes).map_err(|_| AppError::IllegalUTF8)?;
-
-            // Read original size
-            self.reader
-                .read_exact(&mut buf8)
-                .map_err(AppError::ReaderError)?;
-            let orig_size = u64::from_le_bytes(buf8);
-            total_orig_size += orig_size;
-
-            // Read number of chunks belonging to file
-            self.reader
-                .read_exact(&mut buf4)
-                .map_err(AppError::ReaderError)?;
-            let chunk_count = u32::from_le_bytes(buf4);
-
-            self.reader
-                .seek(SeekFrom::Current(chunk_count as i64 * 16))
-                .map_err(AppError::ReaderError)?;
+            let record = self.read_file_record(true)?;
+            total_orig_size += record.original_size;
+            for hash in &record.chunk_hashes {
+                if !seen.insert(*hash) {
+                    *repeats.entry(*hash).or_default() += 1;
+                }
+            }
 
             files.push(FileEntry {
-                path,
-                original_size: orig_size,
+                path: record.path,
+                original_size: record.original_size,
+                chunk_count: record.chunk_count,
             });
         }
 
@@ -207,17 +712,62 @@ impl ArchiveReader {
             0.0
         };
 
+        // Only repeated chunks need their size, so the rest of the chunk table is not read.
+        // Chunks held in a chunk pool are not in the archive and count for nothing
+        let locations = self.locate_chunks(&repeats.keys().copied().collect())?;
+        let duplicate_chunk_bytes = repeats
+            .iter()
+            .filter_map(|(hash, count)| Some(locations.get(hash)?.original_size * count))
+            .sum();
+
         Ok(ArchiveSummary {
             unique_chunks: self.number_of_chunks,
+            top_level_entries: self.top_level_entries,
             total_original_size: total_orig_size,
+            duplicate_chunk_bytes,
             archive_size: self.archive_size,
             compression_ratio,
             squish_creation_date: self.squish_creation_time.clone(),
             squish_version: self.squish_version.clone(),
+            provenance: self.provenance.clone(),
             files,
         })
     }
 
+    /// Lists every entry of the file table, in the order it was packed.
+    ///
+    /// Only the file table is read, and chunk hashes are skipped over, so this is much cheaper
+    /// than [`ArchiveReader::get_summary`] when only the entries are needed.
+    ///
+    /// # Errors
+    /// Returns an error if the file table is malformed or cannot be read.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use squishrs::archive::ArchiveReader;
+    /// use std::path::Path;
+    ///
+    /// let mut reader = ArchiveReader::new(Path::new("backup.squish")).expect("Failed to read squish");
+    /// for entry in reader.entries().expect("Failed to list entries") {
+    ///     println!("{} ({} bytes, {} chunks)", entry.path, entry.original_size, entry.chunk_count);
+    /// }
+    /// ```
+    pub fn entries(&mut self) -> Result<Vec<FileEntry>, AppError> {
+        self.seek_file_table()?;
+
+        (0..self.file_count)
+            .map(|_| {
+                let record = self.read_file_record(false)?;
+                Ok(FileEntry {
+                    path: record.path,
+                    original_size: record.original_size,
+                    chunk_count: record.chunk_count,
+                })
+            })
+            .collect()
+    }
+
     /// Unpacks the archive contents into the specified output directory.
     ///
     /// Reads all chunks, decompresses them, and reconstructs all files,
@@ -234,181 +784,2794 @@ impl ArchiveReader {
         output_dir: &Path,
         progress_bar: Option<&mut ProgressBar>,
     ) -> Result<(), AppError> {
-        // Read chunks here
-        let chunk_map = self.read_chunks(progress_bar.as_deref())?;
-
-        // Rebuild files from chunk_map
-        self.rebuild_files(&chunk_map, output_dir, progress_bar.as_deref())?;
-
-        Ok(())
+        self.unpack_with_options(output_dir, progress_bar, &UnpackOptions::default())
+            .map(|_| ())
     }
 
-    /// Reads and decompresses all chunks from the archive's chunk table into memory.
+    /// Extracts every file of a damaged archive whose chunks are intact into `output_dir`,
+    /// leaving out the files whose chunks are missing, unreadable or do not match their hash.
     ///
-    /// Seeks to the chunk table offset stored in the archive, then reads and decompresses
-    /// each chunk. Decompressed chunks are stored in a HashMap keyed by their 16-byte hash.
+    /// A shorthand for [`ArchiveReader::unpack_with_options`] with `UnpackOptions::repair` set.
     ///
-    /// # Arguments
-    /// * `pb` - Optional progress bar for tracking chunk reading progress.
+    /// # Returns
+    /// An `UnpackReport` whose `lost_files` lists the files left out and why.
+    ///
+    /// # Errors
+    /// Returns an error if the header or file table cannot be read, or an output file cannot be
+    /// written.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use squishrs::archive::ArchiveReader;
+    /// use std::path::Path;
+    ///
+    /// let mut reader = ArchiveReader::new(Path::new("damaged.squish")).expect("Failed to read squish");
+    /// let report = reader.repair(Path::new("out"), None).expect("Failed to repair");
+    /// for lost in &report.lost_files {
+    ///     println!("lost {}: {}", lost.path, lost.reason);
+    /// }
+    /// ```
+    pub fn repair(
+        &mut self,
+        output_dir: &Path,
+        progress_bar: Option<&mut ProgressBar>,
+    ) -> Result<UnpackReport, AppError> {
+        let options = UnpackOptions {
+            repair: true,
+            ..Default::default()
+        };
+        self.unpack_with_options(output_dir, progress_bar, &options)
+    }
+
+    /// Unpacks the archive contents into `output_dir` using the given `UnpackOptions`.
+    ///
+    /// Behaves like [`ArchiveReader::unpack`], but allows settings such as a path prefix to be
+    /// applied to every extracted file.
     ///
     /// # Returns
-    /// A `HashMap` where keys are chunk hashes (`[u8; 16]`) and values are decompressed chunk data (`Vec<u8>`).
+    /// An `UnpackReport` listing any files restored with zero-filled gaps.
     ///
     /// # Errors
-    /// Returns an error if any IO operation or decompression fails.
-    fn read_chunks(
+    /// Returns `AppError::UnsafePath` if the prefix is absolute or contains `..`, or an error if
+    /// reading, decompression, or writing fails.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use squishrs::archive::reader::{ArchiveReader, UnpackOptions};
+    /// use std::path::{Path, PathBuf};
+    ///
+    /// let mut reader = ArchiveReader::new(Path::new("deps.squish")).expect("Failed to read squish");
+    /// let options = UnpackOptions {
+    ///     prefix: Some(PathBuf::from("vendor/lib")),
+    ///     ..Default::default()
+    /// };
+    /// reader.unpack_with_options(Path::new("out"), None, &options).expect("Failed to unpack");
+    /// ```
+    pub fn unpack_with_options(
         &mut self,
-        progress_bar: Option<&ProgressBar>,
-    ) -> Result<HashMap<ChunkHash, Vec<u8>>, AppError> {
-        // Seek to chunk table offset
-        self.reader
-            .seek(std::io::SeekFrom::Start(self.chunk_table_offset))?;
+        output_dir: &Path,
+        progress_bar: Option<&mut ProgressBar>,
+        options: &UnpackOptions,
+    ) -> Result<UnpackReport, AppError> {
+        check_zero_fill(options, self.chunking)?;
 
-        let mut buf8 = [0u8; 8];
-        let mut chunk_map: HashMap<ChunkHash, Vec<u8>> = HashMap::new();
+        // Resolve the directory files are restored under
+        let target_dir = match &options.prefix {
+            Some(prefix) => output_dir.join(validate_relative_path(prefix)?),
+            None => output_dir.to_path_buf(),
+        };
 
-        // Setup progress bar if one is given
-        if let Some(progress_bar) = progress_bar {
-            progress_bar.set_length(self.number_of_chunks);
+        // The file table comes first so a size filter can rule out chunks before decompressing
+        self.seek_file_table()?;
+        let mut entries = Vec::with_capacity(self.file_count as usize);
+        for _ in 0..self.file_count {
+            entries.push(self.read_file_record(true)?);
         }
+        let entries = select_file(entries, options)?;
+        let (entries, excluded_by_size) = filter_by_size(entries, options);
 
-        // For each chunk, decompress and insert it corresponding hash into the hashmap
-        for _ in 0..self.number_of_chunks {
-            let mut hash = [0u8; 16];
-            self.reader
-                .read_exact(&mut hash)
-                .map_err(AppError::ReaderError)?;
-
-            // original size
-            self.reader
-                .read_exact(&mut buf8)
-                .map_err(AppError::ReaderError)?;
-            let orig_size = u64::from_le_bytes(buf8);
-            let orig_size_usize = orig_size
-                .try_into()
-                .map_err(|_| AppError::InvalidChunkSize(orig_size))?;
-
-            // compressed size
-            self.reader
-                .read_exact(&mut buf8)
-                .map_err(AppError::ReaderError)?;
-            let compressed_size = u64::from_le_bytes(buf8);
+        // Decompressing every chunk up front would fail on the first damaged one
+        if !options.in_memory || options.repair {
+            let lazy_chunks = self.index_chunks(&entries, options)?;
+            let mut report = write_files(
+                &entries,
+                ChunkSource::Lazy(&lazy_chunks),
+                &target_dir,
+                progress_bar.as_deref(),
+                options,
+                self.hash_seed,
+                self.chunk_size,
+            )?;
+            report.excluded_by_size = excluded_by_size;
+            return Ok(report);
+        }
 
-            let mut compressed_data = vec![0u8; compressed_size as usize];
+        let mut chunk_map = if options.has_size_filter() {
+            let wanted: HashSet<ChunkHash> = entries
+                .iter()
+                .flat_map(|entry| entry.chunk_hashes.iter().copied())
+                .collect();
+            let key = self.chunk_key()?;
             self.reader
-                .read_exact(&mut compressed_data)
+                .seek(SeekFrom::Start(self.chunk_table_offset))
                 .map_err(AppError::ReaderError)?;
-
-            let decompressed =
-                decompress(&compressed_data, orig_size_usize).map_err(AppError::ReaderError)?;
-
-            chunk_map.insert(hash, decompressed);
-
-            // Increment progress bar if it exists
-            if let Some(progress_bar) = progress_bar {
-                progress_bar.inc(1);
-            }
+            read_wanted_chunk_table(
+                &mut self.reader,
+                self.number_of_chunks,
+                &wanted,
+                progress_bar.as_deref(),
+                options.decompress_threads,
+                key.as_ref(),
+            )?
+        } else {
+            self.read_chunks(progress_bar.as_deref(), options.decompress_threads)?
+        };
+        if let Some(pool_path) = &options.chunk_pool {
+            chunk_map.extend(read_pool_chunks(pool_path, options.decompress_threads)?);
         }
 
-        Ok(chunk_map)
+        // Rebuild files from chunk_map
+        let mut report = write_files(
+            &entries,
+            ChunkSource::Memory(&chunk_map),
+            &target_dir,
+            progress_bar.as_deref(),
+            options,
+            self.hash_seed,
+            self.chunk_size,
+        )?;
+        report.excluded_by_size = excluded_by_size;
+        Ok(report)
     }
 
-    fn rebuild_files(
-        &mut self,
-        chunk_map: &HashMap<ChunkHash, Vec<u8>>,
-        output_dir: &Path,
-        progress_bar: Option<&ProgressBar>,
-    ) -> Result<(), AppError> {
-        // Move to the file table
-        self.reader
-            .seek(SeekFrom::Start(self.file_table_offset))
-            .map_err(AppError::ReaderError)?;
+    /// Streams the contents of a single archived file into `out`.
+    ///
+    /// Looks up `relative_path` in the file table, locates only the chunks that file references
+    /// and decompresses them one at a time, writing each straight to `out`. At most one chunk is
+    /// held in memory, so any `Write` sink (stdout, a pipe to a child process, a socket) can
+    /// receive arbitrarily large files.
+    ///
+    /// # Arguments
+    /// * `relative_path` - Path of the file as stored in the archive (e.g. `sub/file.txt`).
+    /// * `out` - Destination for the reconstructed bytes.
+    ///
+    /// # Errors
+    /// Returns `AppError::FileNotFoundInArchive` if no file matches `relative_path`, or an error
+    /// if reading, decompression, or writing to `out` fails.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use squishrs::archive::ArchiveReader;
+    /// use std::path::Path;
+    ///
+    /// let mut reader = ArchiveReader::new(Path::new("backup.squish")).expect("Failed to read squish");
+    /// let mut stdout = std::io::stdout().lock();
+    /// reader.extract_file("notes/todo.txt", &mut stdout).expect("Failed to extract file");
+    /// ```
+    pub fn extract_file<W: Write>(
+        &mut self,
+        relative_path: &str,
+        out: &mut W,
+    ) -> Result<(), AppError> {
+        let record = self
+            .find_file_record(relative_path)?
+            .ok_or_else(|| AppError::FileNotFoundInArchive(relative_path.to_string()))?;
+
+        let wanted: HashSet<ChunkHash> = record.chunk_hashes.iter().copied().collect();
+        let locations = self.locate_chunks(&wanted)?;
+
+        for hash in &record.chunk_hashes {
+            let location = locations
+                .get(hash)
+                .ok_or_else(|| AppError::MissingChunk(relative_path.into()))?;
+            let data = self.read_chunk_at(location)?;
+            out.write_all(&data).map_err(AppError::WriterError)?;
+        }
+        out.flush().map_err(AppError::WriterError)?;
+
+        Ok(())
+    }
+
+    /// Streams bytes `start..end` of a single archived file into `out`.
+    ///
+    /// With fixed chunking every chunk but a file's last holds exactly
+    /// [`ArchiveReader::chunk_size`] bytes, so the chunks overlapping the range follow from the
+    /// offsets alone; content-defined chunk lengths are read from the chunk table. Only the
+    /// overlapping chunks are decompressed, and only the bytes inside the range are written,
+    /// which makes previewing part of a huge file cheap.
+    ///
+    /// # Arguments
+    /// * `relative_path` - Path of the file as stored in the archive (e.g. `logs/app.log`).
+    /// * `start` - Offset of the first byte to write.
+    /// * `end` - Offset one past the last byte to write; `None` means the end of the file.
+    /// * `out` - Destination for the bytes.
+    ///
+    /// # Returns
+    /// The number of bytes written.
+    ///
+    /// # Errors
+    /// Returns `AppError::FileNotFoundInArchive` if no file matches `relative_path`,
+    /// `AppError::InvalidRange` if the range is reversed or ends past the file, or an error if
+    /// reading, decompression, or writing to `out` fails.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use squishrs::archive::ArchiveReader;
+    /// use std::path::Path;
+    ///
+    /// let mut reader = ArchiveReader::new(Path::new("logs.squish")).expect("Failed to read squish");
+    /// let mut stdout = std::io::stdout().lock();
+    /// reader
+    ///     .extract_range("app.log", 0, Some(65536), &mut stdout)
+    ///     .expect("Failed to extract range");
+    /// ```
+    pub fn extract_range<W: Write>(
+        &mut self,
+        relative_path: &str,
+        start: u64,
+        end: Option<u64>,
+        out: &mut W,
+    ) -> Result<u64, AppError> {
+        let record = self
+            .find_file_record(relative_path)?
+            .ok_or_else(|| AppError::FileNotFoundInArchive(relative_path.to_string()))?;
+
+        let end = end.unwrap_or(record.original_size);
+        if start > end || end > record.original_size {
+            return Err(AppError::InvalidRange(
+                start,
+                end,
+                relative_path.to_string(),
+                record.original_size,
+            ));
+        }
+        if start == end {
+            return Ok(0);
+        }
+
+        let chunk_starts = self.chunk_offsets(&record)?;
+        let first_chunk = chunk_starts.partition_point(|&offset| offset <= start) - 1;
+        let last_chunk = chunk_starts.partition_point(|&offset| offset < end) - 1;
+        let overlapping = &record.chunk_hashes[first_chunk..=last_chunk];
+
+        let wanted: HashSet<ChunkHash> = overlapping.iter().copied().collect();
+        let locations = self.locate_chunks(&wanted)?;
+
+        for (index, hash) in (first_chunk..).zip(overlapping) {
+            let location = locations
+                .get(hash)
+                .ok_or_else(|| AppError::MissingChunk(relative_path.into()))?;
+            let data = self.read_chunk_at(location)?;
+
+            // Trim the chunk to the part of it inside the range
+            let chunk_start = chunk_starts[index];
+            let from = start.saturating_sub(chunk_start) as usize;
+            let to = ((end - chunk_start) as usize).min(data.len());
+            out.write_all(&data[from..to])
+                .map_err(AppError::WriterError)?;
+        }
+        out.flush().map_err(AppError::WriterError)?;
+
+        Ok(end - start)
+    }
+
+    /// Measures how much of the archive is taken up by chunks no file references.
+    ///
+    /// Every chunk hash in the file table is collected, then the chunk table is walked and each
+    /// entry's size (header and data) is counted as live or orphaned. Orphans are dead space that
+    /// only rewriting the archive can reclaim.
+    ///
+    /// # Errors
+    /// Returns an error if the file table or chunk table cannot be read.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use squishrs::archive::ArchiveReader;
+    /// use std::path::Path;
+    ///
+    /// let mut reader = ArchiveReader::new(Path::new("backup.squish")).expect("Failed to read squish");
+    /// let report = reader.space_report().expect("Failed to measure space");
+    /// println!("{} orphaned chunks", report.orphan_count);
+    /// ```
+    pub fn space_report(&mut self) -> Result<SpaceReport, AppError> {
+        let referenced = self.referenced_hashes()?;
+
+        self.reader
+            .seek(SeekFrom::Start(self.chunk_table_offset))
+            .map_err(AppError::ReaderError)?;
+
+        let mut report = SpaceReport {
+            on_disk_bytes: self.archive_size,
+            ..Default::default()
+        };
+        for _ in 0..self.number_of_chunks {
+            let (hash, entry_size) = self.skip_chunk_entry()?;
+            if referenced.contains(&hash) {
+                report.live_chunk_bytes += entry_size;
+            } else {
+                report.orphan_chunk_bytes += entry_size;
+                report.orphan_count += 1;
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Lists the chunks shared between files, most widely shared first.
+    ///
+    /// Only the file table is read: every file's chunk list is inverted into chunk → files, and
+    /// chunks referenced by a single file are dropped. A chunk repeated within one file counts
+    /// that file once. Ties are broken by size, then hash, so the order is stable.
+    ///
+    /// # Arguments
+    /// * `limit` - Maximum number of chunks to return.
+    ///
+    /// # Errors
+    /// Returns an error if the file table cannot be read.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use squishrs::archive::ArchiveReader;
+    /// use std::path::Path;
+    ///
+    /// let mut reader = ArchiveReader::new(Path::new("backup.squish")).expect("Failed to read squish");
+    /// for chunk in reader.shared_chunks(10).expect("Failed to read file table") {
+    ///     println!("{} files: {}", chunk.files.len(), chunk.files.join(", "));
+    /// }
+    /// ```
+    pub fn shared_chunks(&mut self, limit: usize) -> Result<Vec<SharedChunk>, AppError> {
+        let mut owners: HashMap<ChunkHash, (u64, BTreeSet<String>)> = HashMap::new();
+
+        for FileMetadata {
+            path,
+            original_size,
+            chunk_hashes,
+            ..
+        } in self.file_table()?
+        {
+            let chunk_size = self.chunk_size as u64;
+            let chunk_count = chunk_hashes.len() as u64;
+            for (index, hash) in chunk_hashes.into_iter().enumerate() {
+                // Every fixed chunk but a file's last is exactly the archive's chunk size;
+                // content-defined sizes are looked up once the shared chunks are known
+                let size = if index as u64 + 1 < chunk_count {
+                    chunk_size
+                } else {
+                    original_size.saturating_sub((chunk_count - 1) * chunk_size)
+                };
+                owners
+                    .entry(hash)
+                    .or_insert_with(|| (size, BTreeSet::new()))
+                    .1
+                    .insert(path.clone());
+            }
+        }
+
+        let mut shared: Vec<SharedChunk> = owners
+            .into_iter()
+            .filter(|(_, (_, files))| files.len() > 1)
+            .map(|(hash, (size, files))| SharedChunk {
+                hash,
+                size,
+                files: files.into_iter().collect(),
+            })
+            .collect();
+
+        if self.chunking == Chunking::ContentDefined {
+            let wanted: HashSet<ChunkHash> = shared.iter().map(|chunk| chunk.hash).collect();
+            let locations = self.locate_chunks(&wanted)?;
+            for chunk in &mut shared {
+                if let Some(location) = locations.get(&chunk.hash) {
+                    chunk.size = location.original_size;
+                }
+            }
+        }
+
+        shared.sort_by(|a, b| {
+            b.files
+                .len()
+                .cmp(&a.files.len())
+                .then(b.size.cmp(&a.size))
+                .then(a.hash.cmp(&b.hash))
+        });
+        shared.truncate(limit);
+
+        Ok(shared)
+    }
+
+    /// Offset within `record`'s file at which each of its chunks starts.
+    ///
+    /// # Errors
+    /// Returns `AppError::MissingChunk` if a content-defined chunk is not in the chunk table, or
+    /// an error if the chunk table cannot be read.
+    fn chunk_offsets(&mut self, record: &FileRecord) -> Result<Vec<u64>, AppError> {
+        let chunk_count = record.chunk_hashes.len() as u64;
+        if self.chunking == Chunking::Fixed {
+            let chunk_size = self.chunk_size as u64;
+            return Ok((0..chunk_count).map(|index| index * chunk_size).collect());
+        }
+
+        let wanted: HashSet<ChunkHash> = record.chunk_hashes.iter().copied().collect();
+        let locations = self.locate_chunks(&wanted)?;
+        let mut offset = 0;
+        record
+            .chunk_hashes
+            .iter()
+            .map(|hash| {
+                let location = locations
+                    .get(hash)
+                    .ok_or_else(|| AppError::MissingChunk(record.path.clone().into()))?;
+                let start = offset;
+                offset += location.original_size;
+                Ok(start)
+            })
+            .collect()
+    }
+
+    /// Writes a copy of the archive to `out` that keeps only chunks referenced by the file table.
+    ///
+    /// Live chunk entries are copied verbatim, so nothing is recompressed and the sources are not
+    /// needed. The header, timestamp and file table are carried over unchanged and a chunk index
+    /// is rebuilt for the new offsets, which makes the copy of an archive without orphans
+    /// byte-for-byte identical to the original.
+    ///
+    /// # Returns
+    /// The number of orphaned chunks that were dropped.
+    ///
+    /// # Errors
+    /// Returns `AppError::Unsupported` for a signed archive, or an error if the archive cannot
+    /// be read or `out` cannot be written.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use squishrs::archive::ArchiveReader;
+    /// use std::fs::File;
+    /// use std::path::Path;
+    ///
+    /// let mut reader = ArchiveReader::new(Path::new("backup.squish")).expect("Failed to read squish");
+    /// let mut out = File::create("compact.squish").expect("Failed to create output");
+    /// let dropped = reader.compact_into(&mut out).expect("Failed to compact");
+    /// println!("Dropped {dropped} orphaned chunks");
+    /// ```
+    pub fn compact_into<W: Write>(&mut self, out: &mut W) -> Result<u64, AppError> {
+        self.rewrite_into(out, None)
+    }
+
+    /// Writes a copy of an unlocked encrypted archive to `out`, encrypted with a key derived
+    /// from `new_password` under a fresh salt and `kdf`.
+    ///
+    /// Every chunk and the file table are decrypted with the old key and sealed again under new
+    /// nonces. Nothing is decompressed, and sealing adds the same bytes whatever the key, so
+    /// chunk boundaries and offsets, the file table entries and the chunk index carry over
+    /// unchanged.
+    ///
+    /// # Returns
+    /// The number of chunks re-encrypted.
+    ///
+    /// # Errors
+    /// Returns `AppError::Unsupported` if the archive is not encrypted or is signed,
+    /// `AppError::PasswordRequired` if it has not been unlocked, `AppError::DecryptionFailed` if
+    /// a chunk was altered, or an error if the archive cannot be read or `out` cannot be written.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use squishrs::archive::ArchiveReader;
+    /// use squishrs::util::crypto::KdfParams;
+    /// use std::fs::File;
+    /// use std::path::Path;
+    ///
+    /// let mut reader = ArchiveReader::new(Path::new("backup.squish")).expect("Failed to read squish");
+    /// reader.unlock("old password").expect("Wrong password");
+    /// let mut out = File::create("rekeyed.squish").expect("Failed to create output");
+    /// reader
+    ///     .reencrypt_into(&mut out, "new password", KdfParams::default(), None)
+    ///     .expect("Failed to re-encrypt");
+    /// ```
+    pub fn reencrypt_into<W: Write>(
+        &mut self,
+        out: &mut W,
+        new_password: &str,
+        kdf: KdfParams,
+        progress_bar: Option<&ProgressBar>,
+    ) -> Result<u64, AppError> {
+        self.check_reencryptable()?;
+        let (Some(old_key), Some(file_table)) = (self.key.clone(), &self.file_table) else {
+            return Err(AppError::PasswordRequired);
+        };
+        let records = file_table.get_ref().clone();
+
+        let encryption = Encryption::generate(kdf);
+        let new_key = encryption.derive_key(new_password)?;
+
+        // Only the salt and costs change, and their block has a fixed length
+        let header_len = self.chunk_table_offset - self.counts_len();
+        let magic_len = PREFIX.len() + self.squish_version.len();
+        self.reader.rewind().map_err(AppError::ReaderError)?;
+        let mut old_header = Vec::with_capacity(header_len as usize);
+        (&mut self.reader)
+            .take(header_len)
+            .read_to_end(&mut old_header)
+            .map_err(AppError::ReaderError)?;
+        let mut fields = read_timestamp_fields(&mut &old_header[magic_len..])?;
+        fields.encryption = Some(encryption);
+        let mut header = old_header[..magic_len].to_vec();
+        write_timestamp_fields(&mut header, &fields).map_err(AppError::WriterError)?;
+        if header.len() != old_header.len() {
+            return Err(AppError::Archive(
+                "re-encrypting would change the header's length".into(),
+            ));
+        }
+        out.write_all(&header).map_err(AppError::WriterError)?;
+
+        // The chunk and top-level entry counts
+        let counts_len = self.counts_len();
+        std::io::copy(&mut (&mut self.reader).take(counts_len), out)
+            .map_err(AppError::WriterError)?;
+
+        let mut entry_header = [0u8; 16 + 8 + 8];
+        for _ in 0..self.number_of_chunks {
+            check_interrupted()?;
+            self.reader
+                .read_exact(&mut entry_header)
+                .map_err(AppError::ReaderError)?;
+            let hash: ChunkHash = entry_header[..16].try_into().unwrap();
+            let (stored_size, _) =
+                decode_stored_size(u64::from_le_bytes(entry_header[24..].try_into().unwrap()));
+
+            let mut sealed = Vec::new();
+            (&mut self.reader)
+                .take(stored_size)
+                .read_to_end(&mut sealed)
+                .map_err(AppError::ReaderError)?;
+            if (sealed.len() as u64) < stored_size {
+                return Err(AppError::Archive(format!(
+                    "truncated chunk `{}`",
+                    hash_to_hex(&hash)
+                )));
+            }
+            let resealed = new_key.seal(&old_key.open(&sealed, &hash)?, &hash)?;
+
+            out.write_all(&entry_header)
+                .map_err(AppError::WriterError)?;
+            out.write_all(&resealed).map_err(AppError::WriterError)?;
+            if let Some(progress_bar) = progress_bar {
+                progress_bar.inc(1);
+            }
+        }
+
+        out.write_all(&self.file_count.to_le_bytes())
+            .map_err(AppError::WriterError)?;
+        write_sealed_file_table(out, self.file_count, &records, &new_key)?;
+
+        // Chunk offsets did not move, so the chunk index is copied as it is
+        self.reader
+            .seek(SeekFrom::Start(self.file_table_end))
+            .map_err(AppError::ReaderError)?;
+        std::io::copy(
+            &mut (&mut self.reader).take(self.archive_size - self.file_table_end),
+            out,
+        )
+        .map_err(AppError::WriterError)?;
+        out.flush().map_err(AppError::WriterError)?;
+
+        Ok(self.number_of_chunks)
+    }
+
+    /// Refuses to re-encrypt an archive that is not encrypted, or is signed and would no longer
+    /// match its signature, see [`ArchiveReader::reencrypt_into`].
+    pub(crate) fn check_reencryptable(&self) -> Result<(), AppError> {
+        if self.encryption.is_none() {
+            return Err(AppError::Unsupported(
+                "re-encrypting a squish that is not encrypted".into(),
+            ));
+        }
+        self.check_unsigned()
+    }
+
+    /// Writes a copy of the archive to `out` with `files` as its file table, keeping only the
+    /// chunks those entries reference.
+    ///
+    /// Works like [`ArchiveReader::compact_into`], which passes `None` to carry the file table
+    /// over byte for byte.
+    ///
+    /// # Returns
+    /// The number of chunks that were dropped.
+    ///
+    /// # Errors
+    /// Returns `AppError::Unsupported` for a signed archive, whose signature the copy would
+    /// not match.
+    pub(crate) fn rewrite_into<W: Write>(
+        &mut self,
+        out: &mut W,
+        files: Option<&[FileMetadata]>,
+    ) -> Result<u64, AppError> {
+        self.check_unsigned()?;
+        let referenced = match files {
+            Some(files) => files
+                .iter()
+                .flat_map(|entry| entry.chunk_hashes.iter().copied())
+                .collect(),
+            None => self.referenced_hashes()?,
+        };
+        let top_level_entries = match files {
+            Some(files) => count_top_level_entries(files),
+            None => self.top_level_entries,
+        };
+
+        // First pass finds the live chunks so the chunk count can be written up front
+        self.reader
+            .seek(SeekFrom::Start(self.chunk_table_offset))
+            .map_err(AppError::ReaderError)?;
+        let mut live = Vec::with_capacity(self.number_of_chunks as usize);
+        for _ in 0..self.number_of_chunks {
+            let (hash, entry_size) = self.skip_chunk_entry()?;
+            live.push((hash, referenced.contains(&hash), entry_size));
+        }
+        let live_count = live.iter().filter(|(_, is_live, _)| *is_live).count() as u64;
+
+        // The new file table is built up front, since its length decides the archive size
+        let new_file_table = match files {
+            Some(files) => {
+                let mut file_table = Vec::new();
+                write_file_table(
+                    &mut file_table,
+                    files,
+                    self.file_table_layout(),
+                    self.key.as_ref(),
+                )?;
+                Some(file_table)
+            }
+            None => None,
+        };
+
+        // Header and timestamp sit before the chunk and top-level entry counts
+        let header_len = self.chunk_table_offset - self.counts_len();
+        self.reader.rewind().map_err(AppError::ReaderError)?;
+        let mut header = Vec::with_capacity(header_len as usize);
+        (&mut self.reader)
+            .take(header_len)
+            .read_to_end(&mut header)
+            .map_err(AppError::ReaderError)?;
+        if let Some(position) = self.archive_size_position() {
+            let live_bytes: u64 = live
+                .iter()
+                .filter(|(_, is_live, _)| *is_live)
+                .map(|(_, _, entry_size)| entry_size)
+                .sum();
+            let file_table_len = new_file_table.as_ref().map_or(
+                self.file_table_end - (self.file_table_offset - 4),
+                |file_table| file_table.len() as u64,
+            );
+            let index_len = if self.chunk_index.is_some() {
+                live_count * INDEX_ENTRY_SIZE + INDEX_TRAILER_SIZE
+            } else {
+                0
+            };
+            let archive_size = self.chunk_table_offset + live_bytes + file_table_len + index_len;
+            header[position as usize..position as usize + 8]
+                .copy_from_slice(&archive_size.to_le_bytes());
+        }
+        out.write_all(&header).map_err(AppError::WriterError)?;
+        out.write_all(&live_count.to_le_bytes())
+            .map_err(AppError::WriterError)?;
+        if self.records_top_level {
+            out.write_all(&top_level_entries.to_le_bytes())
+                .map_err(AppError::WriterError)?;
+        }
+
+        self.reader
+            .seek(SeekFrom::Start(self.chunk_table_offset))
+            .map_err(AppError::ReaderError)?;
+        // Live chunks move up, so the chunk index is rebuilt with their new offsets
+        let mut position = self.chunk_table_offset;
+        let mut index_entries = Vec::with_capacity(live_count as usize);
+        for (hash, is_live, entry_size) in &live {
+            if *is_live {
+                index_entries.push((
+                    *hash,
+                    IndexEntry {
+                        offset: position + 16 + 8 + 8,
+                        stored_size: entry_size - (16 + 8 + 8),
+                    },
+                ));
+                std::io::copy(&mut (&mut self.reader).take(*entry_size), out)
+                    .map_err(AppError::WriterError)?;
+                position += entry_size;
+            } else {
+                self.reader
+                    .seek(SeekFrom::Current(*entry_size as i64))
+                    .map_err(AppError::ReaderError)?;
+            }
+        }
+
+        let file_table_len = match new_file_table {
+            Some(file_table) => {
+                out.write_all(&file_table).map_err(AppError::WriterError)?;
+                file_table.len() as u64
+            }
+            None => {
+                // The file table runs from the u32 file count to the chunk index, if there is one
+                let file_table_len = self.file_table_end - (self.file_table_offset - 4);
+                self.reader
+                    .seek(SeekFrom::Start(self.file_table_offset - 4))
+                    .map_err(AppError::ReaderError)?;
+                std::io::copy(&mut (&mut self.reader).take(file_table_len), out)
+                    .map_err(AppError::WriterError)?;
+                file_table_len
+            }
+        };
+        if self.chunk_index.is_some() {
+            write_chunk_index(out, &index_entries, position + file_table_len)?;
+        }
+        out.flush().map_err(AppError::WriterError)?;
+
+        Ok(self.number_of_chunks - live_count)
+    }
+
+    /// Writes the archive's chunk table to `out` as a standalone chunk pool.
+    ///
+    /// The pool holds a pool header, the chunk count and the chunk entries exactly as stored in
+    /// the archive; nothing is decompressed. A pool can seed later packs through
+    /// `PackOptions::chunk_pool`, so shared chunks are not stored again.
+    ///
+    /// # Returns
+    /// The number of chunks exported.
+    ///
+    /// # Errors
+    /// Returns `AppError::Unsupported` for an encrypted archive, as pools are not encrypted, or
+    /// an error if the chunk table cannot be read or `out` cannot be written.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use squishrs::archive::ArchiveReader;
+    /// use std::fs::File;
+    /// use std::path::Path;
+    ///
+    /// let mut reader = ArchiveReader::new(Path::new("base.squish")).expect("Failed to read squish");
+    /// let mut pool = File::create("chunks.pack").expect("Failed to create pool");
+    /// reader.export_chunks(&mut pool).expect("Failed to export chunks");
+    /// ```
+    pub fn export_chunks<W: Write>(&mut self, out: &mut W) -> Result<u64, AppError> {
+        // Chunks are copied as stored, and pools have nowhere to record a key
+        if self.is_encrypted() {
+            return Err(AppError::Unsupported(
+                "exporting the chunks of an encrypted squish".to_string(),
+            ));
+        }
+        write_pool_header(out).map_err(AppError::WriterError)?;
+        out.write_all(&self.number_of_chunks.to_le_bytes())
+            .map_err(AppError::WriterError)?;
+
+        // The chunk table ends where the u32 file count begins
+        let chunk_table_len = self.file_table_offset - 4 - self.chunk_table_offset;
+        self.reader
+            .seek(SeekFrom::Start(self.chunk_table_offset))
+            .map_err(AppError::ReaderError)?;
+        std::io::copy(&mut (&mut self.reader).take(chunk_table_len), out)
+            .map_err(AppError::WriterError)?;
+        out.flush().map_err(AppError::WriterError)?;
+
+        Ok(self.number_of_chunks)
+    }
+
+    /// Compares the archive's file table against the files currently in `source_dir`.
+    ///
+    /// Every archived file is re-read from `source_dir`, split with the same chunking used when
+    /// packing and hashed; the resulting hash sequence must equal the one stored in the archive.
+    /// Nothing is decompressed, so this catches both a damaged file table and source files that
+    /// changed after packing without extracting anything.
+    ///
+    /// # Arguments
+    /// * `source_dir` - The directory the archive was packed from.
+    ///
+    /// # Errors
+    /// Returns an error if the file table cannot be read, `source_dir` cannot be walked, or a
+    /// source file exists but cannot be read.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use squishrs::archive::ArchiveReader;
+    /// use std::path::Path;
+    ///
+    /// let mut reader = ArchiveReader::new(Path::new("backup.squish")).expect("Failed to read squish");
+    /// let report = reader.verify_against(Path::new("backup")).expect("Failed to verify");
+    /// println!("{} files match", report.matched.len());
+    /// ```
+    pub fn verify_against(&mut self, source_dir: &Path) -> Result<VerifyReport, AppError> {
+        self.seek_file_table()?;
+
+        let mut records = Vec::with_capacity(self.file_count as usize);
+        for _ in 0..self.file_count {
+            records.push(self.read_file_record(true)?);
+        }
+
+        // Hard links are checked against the hashes of the file they link to
+        let hashes_by_path: HashMap<&str, &Vec<ChunkHash>> = records
+            .iter()
+            .map(|record| (record.path.as_str(), &record.chunk_hashes))
+            .collect();
+
+        // Re-chunk source files in parallel, recording whether each one is present and matches
+        let outcomes = records
+            .par_iter()
+            .map(|record| -> Result<Option<bool>, AppError> {
+                let source_path = source_dir.join(platform_path(&record.path));
+                let expected_hashes = match &record.kind {
+                    EntryKind::HardLink(primary) => hashes_by_path
+                        .get(primary.as_str())
+                        .copied()
+                        .unwrap_or(&record.chunk_hashes),
+                    _ => &record.chunk_hashes,
+                };
+
+                // Symlinks match when they still point at the recorded target
+                if let EntryKind::Symlink(target) = &record.kind {
+                    return Ok(fs::read_link(&source_path)
+                        .ok()
+                        .map(|link| link == Path::new(target)));
+                }
+                if record.kind == EntryKind::Directory {
+                    return Ok(source_path.is_dir().then_some(true));
+                }
+                if !source_path.is_file() {
+                    return Ok(None);
+                }
+
+                let open = || {
+                    File::open(&source_path)
+                        .map(BufReader::new)
+                        .map_err(|_| AppError::FileNotExist(source_path.clone()))
+                };
+                let hashes = hash_chunks_scoped(
+                    &mut open()?,
+                    self.chunk_size,
+                    self.chunking,
+                    chunk_seed(self.hash_seed, None),
+                )
+                .map_err(AppError::ReaderError)?;
+                if &hashes == expected_hashes {
+                    return Ok(Some(true));
+                }
+
+                // Archives packed with a per-file dedup scope seed each file's hashes by path
+                let scoped_hashes = hash_chunks_scoped(
+                    &mut open()?,
+                    self.chunk_size,
+                    self.chunking,
+                    chunk_seed(self.hash_seed, Some(&record.path)),
+                )
+                .map_err(AppError::ReaderError)?;
+                Ok(Some(&scoped_hashes == expected_hashes))
+            })
+            .collect::<Result<Vec<_>, AppError>>()?;
+
+        let mut report = VerifyReport::default();
+        let mut archived_paths = HashSet::with_capacity(records.len());
+
+        for (record, outcome) in records.into_iter().zip(outcomes) {
+            archived_paths.insert(PathBuf::from(&record.path));
+            match outcome {
+                Some(true) => report.matched.push(record.path),
+                Some(false) => report.mismatched.push(record.path),
+                None => report.missing_from_source.push(record.path),
+            }
+        }
+
+        for file_path in walk_dir(source_dir)? {
+            if let Ok(relative) = file_path.strip_prefix(source_dir) {
+                if !archived_paths.contains(relative) {
+                    report
+                        .missing_from_archive
+                        .push(relative.to_string_lossy().to_string());
+                }
+            }
+        }
+        report.missing_from_archive.sort();
+
+        Ok(report)
+    }
+
+    /// Checks the archive is intact without extracting anything.
+    ///
+    /// Every chunk is read, decompressed and re-hashed, and the hash must equal the one stored
+    /// in front of it; chunks of files packed with a per-file dedup scope are re-hashed with
+    /// their file's seed. Then every chunk the file table references must be in the chunk table,
+    /// or in `chunk_pool` for archives packed against one. Chunks are checked in parallel
+    /// batches, so memory stays at a few chunks per thread.
+    ///
+    /// # Arguments
+    /// * `chunk_pool` - Pool the archive was packed against, supplying chunks it does not store.
+    /// * `progress_bar` - Optional progress bar advanced once per chunk.
+    ///
+    /// # Errors
+    /// Returns `AppError::CorruptChunk` for the first chunk that fails to decompress or hash,
+    /// `AppError::UnknownChunk` for the first file referencing a chunk that is nowhere to be
+    /// found, or an error if the archive cannot be read.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use squishrs::archive::ArchiveReader;
+    /// use std::path::Path;
+    ///
+    /// let mut reader = ArchiveReader::new(Path::new("backup.squish")).expect("Failed to read squish");
+    /// let report = reader.verify_integrity(None, None).expect("Archive is damaged");
+    /// println!("{} chunks verified", report.chunks_verified);
+    /// ```
+    pub fn verify_integrity(
+        &mut self,
+        chunk_pool: Option<&Path>,
+        progress_bar: Option<&ProgressBar>,
+    ) -> Result<IntegrityReport, AppError> {
+        let hash_seed = self.hash_seed;
+        self.seek_file_table()?;
+        let mut records = Vec::with_capacity(self.file_count as usize);
+        for _ in 0..self.file_count {
+            records.push(self.read_file_record(true)?);
+        }
+
+        // Seed of the file each chunk belongs to, for chunks hashed within a per-file scope
+        let scopes: HashMap<ChunkHash, Option<u64>> = records
+            .iter()
+            .flat_map(|record| {
+                let seed = chunk_seed(self.hash_seed, Some(&record.path));
+                record.chunk_hashes.iter().map(move |hash| (*hash, seed))
+            })
+            .collect();
+
+        if let Some(progress_bar) = progress_bar {
+            progress_bar.set_length(self.number_of_chunks);
+        }
+
+        let key = self.chunk_key()?;
+        self.reader
+            .seek(SeekFrom::Start(self.chunk_table_offset))
+            .map_err(AppError::ReaderError)?;
+        let batch_size = rayon::current_num_threads() as u64;
+        let mut present = HashSet::with_capacity(self.number_of_chunks as usize);
+        let mut index = 0;
+        while index < self.number_of_chunks {
+            let batch_len = (self.number_of_chunks - index).min(batch_size);
+            let mut batch = Vec::with_capacity(batch_len as usize);
+            for offset in 0..batch_len {
+                batch.push((index + offset, read_stored_chunk(&mut self.reader)?));
+            }
+
+            batch
+                .into_par_iter()
+                .map(|(chunk_index, chunk)| {
+                    let stored_hash = chunk.hash;
+                    let corrupt = |reason: String| {
+                        AppError::CorruptChunk(hash_to_hex(&stored_hash), chunk_index, reason)
+                    };
+                    let (_, data) = chunk
+                        .decompress(key.as_ref())
+                        .map_err(|e| corrupt(format!("does not decompress ({e})")))?;
+
+                    let scope = scopes.get(&stored_hash).copied().flatten();
+                    if hash_chunk_scoped(&data, chunk_seed(hash_seed, None)) != stored_hash
+                        && hash_chunk_scoped(&data, scope) != stored_hash
+                    {
+                        return Err(corrupt("content does not match its hash".to_string()));
+                    }
+                    Ok(stored_hash)
+                })
+                .collect::<Result<Vec<_>, AppError>>()?
+                .into_iter()
+                .for_each(|hash| {
+                    present.insert(hash);
+                });
+
+            index += batch_len;
+            if let Some(progress_bar) = progress_bar {
+                progress_bar.inc(batch_len);
+            }
+        }
+
+        if let Some(pool_path) = chunk_pool {
+            present.extend(read_pool_hashes(pool_path)?);
+        }
+        for record in &records {
+            if let Some(missing) = record
+                .chunk_hashes
+                .iter()
+                .find(|hash| !present.contains(*hash))
+            {
+                return Err(AppError::UnknownChunk(
+                    record.path.clone(),
+                    hash_to_hex(missing),
+                ));
+            }
+        }
+
+        Ok(IntegrityReport {
+            chunks_verified: self.number_of_chunks,
+            files_checked: records.len(),
+        })
+    }
+
+    /// Computes a fingerprint of the archive's logical content.
+    ///
+    /// The file table is hashed in path order: each path, original size and chunk hash sequence.
+    /// The creation timestamp and the order of the chunk table are ignored, so two archives
+    /// holding the same files produce the same fingerprint even when packed at different times.
+    ///
+    /// # Errors
+    /// Returns an error if the file table cannot be read.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use squishrs::archive::ArchiveReader;
+    /// use squishrs::util::chunk::hash_to_hex;
+    /// use std::path::Path;
+    ///
+    /// let mut reader = ArchiveReader::new(Path::new("backup.squish")).expect("Failed to read squish");
+    /// let fingerprint = reader.content_fingerprint().expect("Failed to fingerprint");
+    /// println!("{}", hash_to_hex(&fingerprint));
+    /// ```
+    pub fn content_fingerprint(&mut self) -> Result<ChunkHash, AppError> {
+        self.seek_file_table()?;
+
+        let mut records = Vec::with_capacity(self.file_count as usize);
+        for _ in 0..self.file_count {
+            records.push(self.read_file_record(true)?);
+        }
+        records.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut hasher = Xxh3::new();
+        for record in &records {
+            // Length-prefix the path so adjacent fields cannot run into each other
+            hasher.update(&(record.path.len() as u64).to_le_bytes());
+            hasher.update(record.path.as_bytes());
+            hasher.update(&record.original_size.to_le_bytes());
+            hasher.update(&(record.chunk_hashes.len() as u64).to_le_bytes());
+            for hash in &record.chunk_hashes {
+                hasher.update(hash);
+            }
+        }
+
+        Ok(hasher.digest128().to_le_bytes())
+    }
+
+    /// Returns the byte range of each section of the archive, in file order.
+    ///
+    /// Everything is derived from the offsets found while opening the archive, so nothing is
+    /// read. The file table is followed by the chunk index when the archive has one, which runs
+    /// to the end of the file.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use squishrs::archive::reader::ArchiveReader;
+    /// use std::path::Path;
+    ///
+    /// let reader = ArchiveReader::new(Path::new("backup.squish")).expect("Failed to open");
+    /// for section in reader.layout() {
+    ///     println!("{} {}..{}", section.name, section.offset, section.end());
+    /// }
+    /// ```
+    pub fn layout(&self) -> Vec<LayoutSection> {
+        let header_len = (PREFIX.len() + self.squish_version.len()) as u64;
+        let size_len = if self.records_size { 8 } else { 0 };
+        // Whatever lies between the timestamp and the archive size is the provenance block
+        let top_level_len = self.counts_len() - 8;
+        let provenance_len =
+            self.chunk_table_offset - self.counts_len() - size_len - (header_len + 8);
+        let sections = [
+            ("header", header_len),
+            ("timestamp", 8),
+            ("provenance", provenance_len),
+            ("archive size", size_len),
+            ("chunk count", 8),
+            ("top-level entry count", top_level_len),
+            (
+                "chunk table",
+                self.file_table_offset - 4 - self.chunk_table_offset,
+            ),
+            ("file count", 4),
+            ("file table", self.file_table_end - self.file_table_offset),
+            ("chunk index", self.body_end() - self.file_table_end),
+            ("signature", self.archive_size - self.body_end()),
+        ];
+
+        let mut offset = 0;
+        sections
+            .into_iter()
+            .filter(|&(name, length)| {
+                !matches!(
+                    name,
+                    "provenance"
+                        | "archive size"
+                        | "top-level entry count"
+                        | "chunk index"
+                        | "signature"
+                ) || length > 0
+            })
+            .map(|(name, length)| {
+                let section = LayoutSection {
+                    name,
+                    offset,
+                    length,
+                };
+                offset += length;
+                section
+            })
+            .collect()
+    }
+
+    /// Inspects the chunk at position `index` in the chunk table.
+    ///
+    /// Chunk headers before `index` are read and their data skipped, then the chunk itself is
+    /// decompressed and re-hashed. A chunk that fails to decompress is reported rather than
+    /// returned as an error, since this is meant for diagnosing damaged archives.
+    ///
+    /// # Errors
+    /// Returns `AppError::Archive` if `index` is past the end of the chunk table, or an error if
+    /// the chunk table cannot be read.
+    pub fn chunk_info(&mut self, index: u64) -> Result<ChunkInfo, AppError> {
+        if index >= self.number_of_chunks {
+            return Err(AppError::Archive(format!(
+                "chunk index {index} out of range, squish has {} chunks",
+                self.number_of_chunks
+            )));
+        }
+
+        self.reader
+            .seek(SeekFrom::Start(self.chunk_table_offset))
+            .map_err(AppError::ReaderError)?;
+
+        let mut buf8 = [0u8; 8];
+        let mut position = 0;
+
+        loop {
+            let mut hash = [0u8; 16];
+            self.reader
+                .read_exact(&mut hash)
+                .map_err(AppError::ReaderError)?;
+
+            self.reader
+                .read_exact(&mut buf8)
+                .map_err(AppError::ReaderError)?;
+            let original_size = u64::from_le_bytes(buf8);
+
+            self.reader
+                .read_exact(&mut buf8)
+                .map_err(AppError::ReaderError)?;
+            let (compressed_size, stored_raw) = decode_stored_size(u64::from_le_bytes(buf8));
+
+            let offset = self
+                .reader
+                .stream_position()
+                .map_err(AppError::ReaderError)?;
+
+            if position < index {
+                self.reader
+                    .seek(SeekFrom::Current(compressed_size as i64))
+                    .map_err(AppError::ReaderError)?;
+                position += 1;
+                continue;
+            }
+
+            let location = ChunkLocation {
+                hash,
+                offset,
+                original_size,
+                compressed_size,
+                stored_raw,
+            };
+            let decompressed = self.read_chunk_at(&location).ok();
+            let hash_matches = match &decompressed {
+                Some(data) => self.chunk_hash_matches(data, &hash)?,
+                None => false,
+            };
+
+            return Ok(ChunkInfo {
+                index,
+                hash,
+                original_size,
+                compressed_size,
+                stored_raw,
+                offset,
+                decompresses: decompressed.is_some(),
+                hash_matches,
+            });
+        }
+    }
+
+    /// Whether `data` hashes back to `hash`, either archive-wide or, for chunks hashed within a
+    /// per-file scope, in the scope of any file that references it.
+    fn chunk_hash_matches(&mut self, data: &[u8], hash: &ChunkHash) -> Result<bool, AppError> {
+        if hash_chunk_scoped(data, chunk_seed(self.hash_seed, None)) == *hash {
+            return Ok(true);
+        }
+
+        self.seek_file_table()?;
+        for _ in 0..self.file_count {
+            let record = self.read_file_record(true)?;
+            if record.chunk_hashes.contains(hash)
+                && hash_chunk_scoped(data, chunk_seed(self.hash_seed, Some(&record.path))) == *hash
+            {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Reads and decompresses all chunks from the archive's chunk table into memory.
+    ///
+    /// Seeks to the chunk table offset stored in the archive, then reads and decompresses
+    /// each chunk. Decompressed chunks are stored in a HashMap keyed by their 16-byte hash.
+    ///
+    /// # Arguments
+    /// * `pb` - Optional progress bar for tracking chunk reading progress.
+    ///
+    /// # Returns
+    /// A `HashMap` where keys are chunk hashes (`[u8; 16]`) and values are decompressed chunk data (`Vec<u8>`).
+    ///
+    /// # Errors
+    /// Returns an error if any IO operation or decompression fails.
+    fn read_chunks(
+        &mut self,
+        progress_bar: Option<&ProgressBar>,
+        decompress_threads: Option<usize>,
+    ) -> Result<HashMap<ChunkHash, Vec<u8>>, AppError> {
+        let key = self.chunk_key()?;
+        // Seek to chunk table offset
+        self.reader
+            .seek(std::io::SeekFrom::Start(self.chunk_table_offset))?;
+
+        read_chunk_table(
+            &mut self.reader,
+            self.number_of_chunks,
+            progress_bar,
+            decompress_threads,
+            key.as_ref(),
+        )
+    }
+
+    /// Reads the whole file table in the form the writer produces it.
+    pub(crate) fn file_table(&mut self) -> Result<Vec<FileMetadata>, AppError> {
+        self.seek_file_table()?;
+
+        (0..self.file_count)
+            .map(|_| {
+                let record = self.read_file_record(true)?;
+                Ok(FileMetadata {
+                    path: record.path,
+                    original_size: record.original_size,
+                    chunk_hashes: record.chunk_hashes,
+                    mode: record.mode,
+                    kind: record.kind,
+                    file_hash: record.file_hash,
+                })
+            })
+            .collect()
+    }
+
+    /// The key of an unlocked encrypted archive.
+    pub(crate) fn key(&self) -> Option<&ArchiveKey> {
+        self.key.as_ref()
+    }
+
+    /// Offset of the file table, where its `u32` file count begins.
+    pub(crate) fn file_table_position(&self) -> u64 {
+        self.file_table_offset - 4
+    }
+
+    /// The archive's chunk index in chunk table order, or `None` if it was written without one.
+    pub(crate) fn chunk_index_entries(&self) -> Option<Vec<(ChunkHash, IndexEntry)>> {
+        let mut entries: Vec<_> = self.chunk_index.as_ref()?.clone().into_iter().collect();
+        entries.sort_by_key(|(_, entry)| entry.offset);
+        Some(entries)
+    }
+
+    /// Offset of the top-level entry count in the header, just before the chunk table, or `None`
+    /// if the archive does not record one.
+    pub(crate) fn top_level_count_position(&self) -> Option<u64> {
+        self.records_top_level.then(|| self.chunk_table_offset - 8)
+    }
+
+    /// Length of the counts between the timestamp fields and the chunk table: the chunk count,
+    /// then the top-level entry count when the archive records one.
+    fn counts_len(&self) -> u64 {
+        if self.records_top_level {
+            16
+        } else {
+            8
+        }
+    }
+
+    /// Offset just past the chunk index, where the signature footer of a signed archive starts.
+    fn body_end(&self) -> u64 {
+        self.signature
+            .as_ref()
+            .map_or(self.archive_size, |footer| footer.signed_length)
+    }
+
+    /// Offset of the archive size at the end of the timestamp fields, or `None` if the header
+    /// does not record one.
+    pub(crate) fn archive_size_position(&self) -> Option<u64> {
+        self.records_size
+            .then(|| self.chunk_table_offset - self.counts_len() - 8)
+    }
+
+    /// Refuses to edit a signed archive, since only the private key could sign the result.
+    pub(crate) fn check_unsigned(&self) -> Result<(), AppError> {
+        if self.is_signed() {
+            return Err(AppError::Unsupported(
+                "editing a signed squish would break its signature; pack it again with --sign"
+                    .into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Collects the hash of every chunk referenced by the file table.
+    fn referenced_hashes(&mut self) -> Result<HashSet<ChunkHash>, AppError> {
+        self.seek_file_table()?;
+
+        let mut referenced = HashSet::new();
+        for _ in 0..self.file_count {
+            referenced.extend(self.read_file_record(true)?.chunk_hashes);
+        }
+        Ok(referenced)
+    }
+
+    /// Reads the chunk entry at the current position without its data, leaving the reader at the
+    /// next entry.
+    ///
+    /// # Returns
+    /// The chunk hash and the size of the whole entry, header included.
+    fn skip_chunk_entry(&mut self) -> Result<(ChunkHash, u64), AppError> {
+        let mut hash = [0u8; 16];
+        self.reader
+            .read_exact(&mut hash)
+            .map_err(AppError::ReaderError)?;
+
+        // Original size, then stored size
+        let mut buf8 = [0u8; 8];
+        self.reader
+            .read_exact(&mut buf8)
+            .map_err(AppError::ReaderError)?;
+        self.reader
+            .read_exact(&mut buf8)
+            .map_err(AppError::ReaderError)?;
+        let (stored_size, _) = decode_stored_size(u64::from_le_bytes(buf8));
+
+        self.reader
+            .seek(SeekFrom::Current(stored_size as i64))
+            .map_err(AppError::ReaderError)?;
+
+        Ok((hash, 16 + 8 + 8 + stored_size))
+    }
+
+    /// Moves to the first entry of the file table, which is the decrypted copy of an unlocked
+    /// archive.
+    ///
+    /// # Errors
+    /// Returns `AppError::PasswordRequired` if the archive is encrypted and still locked.
+    fn seek_file_table(&mut self) -> Result<(), AppError> {
+        match &mut self.file_table {
+            Some(file_table) => file_table.set_position(0),
+            None if self.encryption.is_some() => return Err(AppError::PasswordRequired),
+            None => {
+                self.reader
+                    .seek(SeekFrom::Start(self.file_table_offset))
+                    .map_err(AppError::ReaderError)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the next entry of the file table at the current position, see
+    /// [`ArchiveReader::seek_file_table`].
+    ///
+    /// When `with_hashes` is false the chunk hashes are skipped with a seek and
+    /// `chunk_hashes` is left empty.
+    fn read_file_record(&mut self, with_hashes: bool) -> Result<FileRecord, AppError> {
+        match &mut self.file_table {
+            Some(file_table) => read_file_record_from(file_table, self.layout, with_hashes),
+            None => read_file_record_from(&mut self.reader, self.layout, with_hashes),
+        }
+    }
+
+    /// Returns the key chunks are decrypted with, `None` on an unencrypted archive.
+    ///
+    /// # Errors
+    /// Returns `AppError::PasswordRequired` if the archive is encrypted and still locked.
+    fn chunk_key(&self) -> Result<Option<ArchiveKey>, AppError> {
+        match (&self.encryption, &self.key) {
+            (Some(_), None) => Err(AppError::PasswordRequired),
+            (_, key) => Ok(key.clone()),
+        }
+    }
+
+    /// Scans the file table for `relative_path`, returning its record if present.
+    fn find_file_record(&mut self, relative_path: &str) -> Result<Option<FileRecord>, AppError> {
+        self.seek_file_table()?;
+
+        for _ in 0..self.file_count {
+            let record = self.read_file_record(true)?;
+            if record.path == relative_path {
+                return Ok(Some(record));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Records the location of every chunk in `wanted`, from the chunk index when the archive
+    /// has one and otherwise by walking the chunk table.
+    ///
+    /// Only chunk headers are read; compressed data is skipped over with a seek.
+    fn locate_chunks(
+        &mut self,
+        wanted: &HashSet<ChunkHash>,
+    ) -> Result<HashMap<ChunkHash, ChunkLocation>, AppError> {
+        if let Some(index) = &self.chunk_index {
+            // Visit the chunks in table order so the reads move forward through the archive
+            let mut indexed: Vec<_> = wanted
+                .iter()
+                .filter_map(|hash| Some((*hash, index.get(hash)?.offset)))
+                .collect();
+            indexed.sort_by_key(|&(_, offset)| offset);
+
+            let mut locations = HashMap::with_capacity(indexed.len());
+            for (hash, offset) in indexed {
+                self.reader
+                    .seek(SeekFrom::Start(offset - (16 + 8 + 8)))
+                    .map_err(AppError::ReaderError)?;
+                let found = locate_chunk_entries(&mut self.reader, 1, &HashSet::from([hash]))?;
+                if found.is_empty() {
+                    return Err(AppError::Archive(format!(
+                        "chunk index points chunk {} at the wrong entry",
+                        hash_to_hex(&hash)
+                    )));
+                }
+                locations.extend(found);
+            }
+            return Ok(locations);
+        }
+
+        self.reader
+            .seek(SeekFrom::Start(self.chunk_table_offset))
+            .map_err(AppError::ReaderError)?;
+
+        locate_chunk_entries(&mut self.reader, self.number_of_chunks, wanted)
+    }
+
+    /// Reads, decrypts and decompresses the chunk stored at `location`.
+    fn read_chunk_at(&mut self, location: &ChunkLocation) -> Result<Vec<u8>, AppError> {
+        let key = self.chunk_key()?;
+        read_chunk_at(&mut self.reader, location, key.as_ref())
+    }
+
+    /// Builds the on-demand chunk index for unpacking `entries`: the location of every chunk
+    /// they reference, in the archive or else in the chunk pool.
+    fn index_chunks(
+        &mut self,
+        entries: &[FileRecord],
+        options: &UnpackOptions,
+    ) -> Result<LazyChunks, AppError> {
+        let mut references: HashMap<ChunkHash, usize> = HashMap::new();
+        for hash in entries.iter().flat_map(|entry| &entry.chunk_hashes) {
+            *references.entry(*hash).or_default() += 1;
+        }
+        let wanted: HashSet<ChunkHash> = references.keys().copied().collect();
+        let shared = references
+            .into_iter()
+            .filter(|(_, count)| *count > 1)
+            .map(|(hash, _)| hash)
+            .collect();
+
+        let mut sources = vec![self.archive_path.clone()];
+        sources.extend(options.chunk_pool.clone());
+        let mut lazy_chunks = LazyChunks::new(sources, shared, self.chunk_key()?);
+
+        let archive_locations = self.locate_chunks(&wanted)?;
+        let in_archive = archive_locations.len();
+        lazy_chunks.add_locations(0, archive_locations);
+
+        if let Some(pool_path) = &options.chunk_pool {
+            if in_archive < wanted.len() {
+                lazy_chunks.add_locations(1, locate_pool_chunks(pool_path, &wanted)?);
+            }
+        }
+
+        Ok(lazy_chunks)
+    }
+}
+
+/// Checks that this build can extract the archive at `archive_path`, without reading any chunks.
+///
+/// Runs the header checks [`ArchiveReader::new`] performs, the magic prefix, format version and
+/// creation timestamp, so deployment scripts can screen archives before extracting them.
+///
+/// # Returns
+/// The archive's format version.
+///
+/// # Errors
+/// Returns `AppError::FileNotExist` if the archive cannot be opened, or `AppError::Unsupported`
+/// with the reason when its header cannot be handled by this build.
+///
+/// # Example
+///
+/// ```no_run
+/// use squishrs::archive::check_compatibility;
+/// use std::path::Path;
+///
+/// match check_compatibility(Path::new("backup.squish")) {
+///     Ok(version) => println!("extractable (format {version})"),
+///     Err(e) => println!("{e}"),
+/// }
+/// ```
+pub fn check_compatibility(archive_path: &Path) -> Result<String, AppError> {
+    let file =
+        File::open(archive_path).map_err(|_| AppError::FileNotExist(archive_path.to_path_buf()))?;
+    let mut reader = BufReader::new(file);
+
+    let check = |reader: &mut BufReader<File>| -> Result<String, AppError> {
+        let version = verify_header(reader)?;
+
+        let (timestamp, _) = read_timestamp(reader)?;
+        convert_timestamp_to_date(timestamp)?;
+
+        Ok(version)
+    };
+
+    check(&mut reader).map_err(|e| AppError::Unsupported(e.to_string()))
+}
+
+/// Hashes the raw bytes of the archive at `archive_path`.
+///
+/// Byte-identical archives, such as those produced by `pack --reproducible`, share a raw
+/// fingerprint. The file is streamed, so archives of any size can be fingerprinted.
+///
+/// # Errors
+/// Returns `AppError::FileNotExist` if the archive cannot be opened, or an error if reading fails.
+pub fn raw_fingerprint(archive_path: &Path) -> Result<ChunkHash, AppError> {
+    let mut reader = BufReader::new(
+        File::open(archive_path).map_err(|_| AppError::FileNotExist(archive_path.to_path_buf()))?,
+    );
+
+    let mut hasher = Xxh3::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let bytes_read = reader.read(&mut buf).map_err(AppError::ReaderError)?;
+        if bytes_read == 0 {
+            break;
+        }
+        hasher.update(&buf[..bytes_read]);
+    }
+
+    Ok(hasher.digest128().to_le_bytes())
+}
+
+/// Turns a path as recorded in the file table, with `/` between components, into one using
+/// the platform's separator.
+pub(crate) fn platform_path(path: &str) -> PathBuf {
+    path.split('/').collect()
+}
+
+/// Ensures `path` is relative and cannot climb out of the directory it is joined onto.
+///
+/// # Errors
+/// Returns `AppError::UnsafePath` if `path` is absolute or contains a `..` component.
+pub(crate) fn validate_relative_path(path: &Path) -> Result<&Path, AppError> {
+    let is_safe = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+
+    if is_safe {
+        Ok(path)
+    } else {
+        Err(AppError::UnsafePath(path.to_path_buf()))
+    }
+}
+
+/// Unpacks an archive by reading `reader` strictly front to back.
+///
+/// Unlike [`ArchiveReader::unpack_with_options`] this never seeks, so it works on non-seekable
+/// sources such as stdin or a pipe. The price is memory: every chunk is decompressed and held
+/// until the file table, which follows the chunks, has been read.
+///
+/// # Arguments
+/// * `reader` - Source positioned at the start of the archive.
+/// * `output_dir` - Directory path where files should be restored.
+/// * `progress_bar` - Optional progress bar for progress reporting.
+/// * `options` - Settings such as a path prefix, as for a seekable unpack.
+///
+/// # Returns
+/// An `UnpackReport` listing any files restored with zero-filled gaps.
+///
+/// # Errors
+/// Returns an error if the header is invalid, the stream ends early, or decompression or
+/// writing fails.
+///
+/// # Example
+///
+/// ```no_run
+/// use squishrs::archive::reader::{unpack_sequential, UnpackOptions};
+/// use std::path::Path;
+///
+/// let stdin = std::io::stdin().lock();
+/// unpack_sequential(stdin, Path::new("out"), None, &UnpackOptions::default())
+///     .expect("Failed to unpack");
+/// ```
+pub fn unpack_sequential<R: Read>(
+    mut reader: R,
+    output_dir: &Path,
+    progress_bar: Option<&mut ProgressBar>,
+    options: &UnpackOptions,
+) -> Result<UnpackReport, AppError> {
+    // Every chunk is decompressed as it streams past, so one damaged chunk stops the unpack
+    if options.repair {
+        return Err(AppError::Unsupported(
+            "repairing a squish read sequentially, it must be seekable".to_string(),
+        ));
+    }
 
-        let mut buf4 = [0u8; 4];
-        let mut buf8 = [0u8; 8];
-        let mut entries = Vec::with_capacity(self.file_count as usize);
+    let target_dir = match &options.prefix {
+        Some(prefix) => output_dir.join(validate_relative_path(prefix)?),
+        None => output_dir.to_path_buf(),
+    };
+
+    if options.strict_version {
+        verify_header_strict(&mut reader)?;
+    } else {
+        verify_header(&mut reader)?;
+    }
+
+    // Timestamp, provenance and top-level entry count are not needed to restore files
+    let fields = read_timestamp_fields(&mut reader)?;
+    let layout = FileTableLayout::of(&fields);
+    check_zero_fill(options, fields.chunking)?;
+
+    let mut buf8 = [0u8; 8];
+
+    reader
+        .read_exact(&mut buf8)
+        .map_err(AppError::ReaderError)?;
+    let number_of_chunks = u64::from_le_bytes(buf8);
+
+    if fields.top_level_count {
+        reader
+            .read_exact(&mut buf8)
+            .map_err(AppError::ReaderError)?;
+    }
+
+    let key = match (&fields.encryption, &options.password) {
+        (Some(encryption), Some(password)) => Some(encryption.derive_key(password)?),
+        (Some(_), None) => return Err(AppError::PasswordRequired),
+        (None, _) => None,
+    };
+
+    let mut chunk_map = read_chunk_table(
+        &mut reader,
+        number_of_chunks,
+        progress_bar.as_deref(),
+        options.decompress_threads,
+        key.as_ref(),
+    )?;
+    if let Some(pool_path) = &options.chunk_pool {
+        chunk_map.extend(read_pool_chunks(pool_path, options.decompress_threads)?);
+    }
+
+    let mut buf4 = [0u8; 4];
+    reader
+        .read_exact(&mut buf4)
+        .map_err(AppError::ReaderError)?;
+    let file_count = u32::from_le_bytes(buf4);
+
+    let mut entries = Vec::with_capacity(file_count as usize);
+    match &key {
+        Some(key) => {
+            let records = read_sealed_file_table(&mut reader, file_count, key)?;
+            let mut records = records.as_slice();
+            for _ in 0..file_count {
+                entries.push(read_file_record(&mut records, layout)?);
+            }
+        }
+        None => {
+            for _ in 0..file_count {
+                entries.push(read_file_record(&mut reader, layout)?);
+            }
+        }
+    }
+    let entries = select_file(entries, options)?;
+    let (entries, excluded_by_size) = filter_by_size(entries, options);
+
+    let mut report = write_files(
+        &entries,
+        ChunkSource::Memory(&chunk_map),
+        &target_dir,
+        progress_bar.as_deref(),
+        options,
+        fields.hash_seed,
+        fields.chunk_size.map_or(CHUNK_SIZE, |size| size as usize),
+    )?;
+    report.excluded_by_size = excluded_by_size;
+    Ok(report)
+}
+
+/// Keeps only the entry the options' `file` names, or every entry when it is not set.
+///
+/// # Errors
+/// Returns `AppError::FileNotFoundInArchive` if no entry has that path.
+fn select_file(
+    entries: Vec<FileRecord>,
+    options: &UnpackOptions,
+) -> Result<Vec<FileRecord>, AppError> {
+    let Some(path) = &options.file else {
+        return Ok(entries);
+    };
+    let selected: Vec<FileRecord> = entries
+        .into_iter()
+        .filter(|entry| &entry.path == path)
+        .collect();
+    if selected.is_empty() {
+        return Err(AppError::FileNotFoundInArchive(path.clone()));
+    }
+    Ok(selected)
+}
+
+/// Keeps the entries whose original size passes the options' size filter.
+///
+/// # Returns
+/// The kept entries and how many were dropped.
+fn filter_by_size(entries: Vec<FileRecord>, options: &UnpackOptions) -> (Vec<FileRecord>, usize) {
+    let total = entries.len();
+    let kept: Vec<FileRecord> = entries
+        .into_iter()
+        .filter(|entry| options.size_matches(entry.original_size))
+        .collect();
+    let excluded = total - kept.len();
+    (kept, excluded)
+}
+
+/// Walks `number_of_chunks` chunk table entries from the current position of `reader` and
+/// records the location of every chunk in `wanted`, stopping early once all are found.
+///
+/// Only chunk headers are read; compressed data is skipped over with a seek.
+pub(crate) fn locate_chunk_entries<R: Read + Seek>(
+    reader: &mut R,
+    number_of_chunks: u64,
+    wanted: &HashSet<ChunkHash>,
+) -> Result<HashMap<ChunkHash, ChunkLocation>, AppError> {
+    let mut buf8 = [0u8; 8];
+    let mut locations = HashMap::with_capacity(wanted.len());
+
+    for _ in 0..number_of_chunks {
+        if locations.len() == wanted.len() {
+            break;
+        }
+
+        let mut hash = [0u8; 16];
+        reader
+            .read_exact(&mut hash)
+            .map_err(AppError::ReaderError)?;
+
+        reader
+            .read_exact(&mut buf8)
+            .map_err(AppError::ReaderError)?;
+        let original_size = u64::from_le_bytes(buf8);
+        checked_chunk_size(original_size)?;
+
+        reader
+            .read_exact(&mut buf8)
+            .map_err(AppError::ReaderError)?;
+        let (compressed_size, stored_raw) = decode_stored_size(u64::from_le_bytes(buf8));
+
+        let offset = reader.stream_position().map_err(AppError::ReaderError)?;
+        if wanted.contains(&hash) {
+            locations.insert(
+                hash,
+                ChunkLocation {
+                    hash,
+                    offset,
+                    original_size,
+                    compressed_size,
+                    stored_raw,
+                },
+            );
+        }
+
+        reader
+            .seek(SeekFrom::Current(compressed_size as i64))
+            .map_err(AppError::ReaderError)?;
+    }
+
+    Ok(locations)
+}
+
+/// Reads and decompresses the chunk stored at `location` in `reader`, decrypting it first with
+/// `key` when the archive is encrypted.
+pub(crate) fn read_chunk_at<R: Read + Seek>(
+    reader: &mut R,
+    location: &ChunkLocation,
+    key: Option<&ArchiveKey>,
+) -> Result<Vec<u8>, AppError> {
+    reader
+        .seek(SeekFrom::Start(location.offset))
+        .map_err(AppError::ReaderError)?;
+
+    let mut compressed_data = vec![0u8; location.compressed_size as usize];
+    reader
+        .read_exact(&mut compressed_data)
+        .map_err(AppError::ReaderError)?;
+    if let Some(key) = key {
+        compressed_data = key.open(&compressed_data, &location.hash)?;
+    }
+    if location.stored_raw {
+        return Ok(compressed_data);
+    }
+
+    let original_size = checked_chunk_size(location.original_size)?;
+    decompress(&compressed_data, original_size).map_err(AppError::ReaderError)
+}
+
+/// Where [`write_files`] takes decompressed chunks from.
+enum ChunkSource<'a> {
+    /// Every chunk decompressed up front
+    Memory(&'a HashMap<ChunkHash, Vec<u8>>),
+    /// Chunks read from disk as files need them
+    Lazy(&'a LazyChunks),
+}
+
+impl<'a> ChunkSource<'a> {
+    fn get(
+        &self,
+        hash: &ChunkHash,
+        readers: &mut SourceReaders,
+    ) -> Result<Option<Cow<'a, [u8]>>, AppError> {
+        match self {
+            ChunkSource::Memory(chunk_map) => Ok(chunk_map
+                .get(hash)
+                .map(|data| Cow::Borrowed(data.as_slice()))),
+            ChunkSource::Lazy(lazy_chunks) => Ok(lazy_chunks.get(hash, readers)?.map(Cow::Owned)),
+        }
+    }
+}
+
+/// A chunk table entry as stored, before decompression.
+struct StoredChunk {
+    hash: ChunkHash,
+    original_size: usize,
+    stored_raw: bool,
+    data: Vec<u8>,
+}
+
+impl StoredChunk {
+    /// Decrypts the chunk with `key` when the archive is encrypted, then decompresses it.
+    fn decompress(self, key: Option<&ArchiveKey>) -> Result<(ChunkHash, Vec<u8>), AppError> {
+        let data = match key {
+            Some(key) => key.open(&self.data, &self.hash)?,
+            None => self.data,
+        };
+        if self.stored_raw {
+            return Ok((self.hash, data));
+        }
+        let decompressed = decompress(&data, self.original_size).map_err(AppError::ReaderError)?;
+        Ok((self.hash, decompressed))
+    }
+}
+
+/// Converts a chunk's declared original size into the bound to decompress it with.
+///
+/// # Errors
+///
+/// Returns `AppError::ChunkTooLarge` if the size is over [`MAX_CHUNK_SIZE`], so a corrupt chunk
+/// table fails with a clear error instead of an enormous allocation.
+fn checked_chunk_size(original_size: u64) -> Result<usize, AppError> {
+    if original_size > MAX_CHUNK_SIZE {
+        return Err(AppError::ChunkTooLarge(original_size, MAX_CHUNK_SIZE));
+    }
+    original_size
+        .try_into()
+        .map_err(|_| AppError::InvalidChunkSize(original_size))
+}
+
+/// Reads the next chunk table entry from `reader` without decompressing it.
+fn read_stored_chunk<R: Read>(reader: &mut R) -> Result<StoredChunk, AppError> {
+    let (mut chunk, compressed_size) = read_stored_chunk_header(reader)?;
+    chunk.data = vec![0u8; compressed_size as usize];
+    reader
+        .read_exact(&mut chunk.data)
+        .map_err(AppError::ReaderError)?;
+    Ok(chunk)
+}
+
+/// Reads the next chunk table entry if its hash is in `wanted`, seeking past its data otherwise.
+fn read_wanted_stored_chunk<R: Read + Seek>(
+    reader: &mut R,
+    wanted: &HashSet<ChunkHash>,
+) -> Result<Option<StoredChunk>, AppError> {
+    let (mut chunk, compressed_size) = read_stored_chunk_header(reader)?;
+    if !wanted.contains(&chunk.hash) {
+        reader
+            .seek(SeekFrom::Current(compressed_size as i64))
+            .map_err(AppError::ReaderError)?;
+        return Ok(None);
+    }
+
+    chunk.data = vec![0u8; compressed_size as usize];
+    reader
+        .read_exact(&mut chunk.data)
+        .map_err(AppError::ReaderError)?;
+    Ok(Some(chunk))
+}
+
+/// Reads a chunk table entry's hash and sizes, leaving `reader` at the start of its data.
+///
+/// # Returns
+/// The entry with empty `data`, and the number of data bytes that follow.
+fn read_stored_chunk_header<R: Read>(reader: &mut R) -> Result<(StoredChunk, u64), AppError> {
+    let mut buf8 = [0u8; 8];
+    let mut hash = [0u8; 16];
+    reader
+        .read_exact(&mut hash)
+        .map_err(AppError::ReaderError)?;
+
+    // original size
+    reader
+        .read_exact(&mut buf8)
+        .map_err(AppError::ReaderError)?;
+    let original_size = checked_chunk_size(u64::from_le_bytes(buf8))?;
+
+    // compressed size
+    reader
+        .read_exact(&mut buf8)
+        .map_err(AppError::ReaderError)?;
+    let (compressed_size, stored_raw) = decode_stored_size(u64::from_le_bytes(buf8));
+
+    let chunk = StoredChunk {
+        hash,
+        original_size,
+        stored_raw,
+        data: Vec::new(),
+    };
+    Ok((chunk, compressed_size))
+}
+
+/// Reads and decompresses `number_of_chunks` consecutive chunk table entries from `reader`,
+/// decrypting them with `key` when the archive is encrypted.
+///
+/// Chunks are read in batches of one per decompression thread and each batch is decompressed
+/// in parallel, so on top of the finished chunks at most threads × the archive's chunk size bytes
+/// are being decompressed at once. `decompress_threads` bounds this with a dedicated pool; `None` uses
+/// the global pool sized by `--max-threads`.
+///
+/// # Returns
+/// A `HashMap` where keys are chunk hashes (`[u8; 16]`) and values are decompressed chunk data (`Vec<u8>`).
+pub(crate) fn read_chunk_table<R: Read>(
+    reader: &mut R,
+    number_of_chunks: u64,
+    progress_bar: Option<&ProgressBar>,
+    decompress_threads: Option<usize>,
+    key: Option<&ArchiveKey>,
+) -> Result<HashMap<ChunkHash, Vec<u8>>, AppError> {
+    decompress_chunk_entries(
+        number_of_chunks,
+        progress_bar,
+        decompress_threads,
+        key,
+        || read_stored_chunk(reader).map(Some),
+    )
+}
+
+/// Like [`read_chunk_table`], but only reads and decompresses chunks whose hash is in `wanted`;
+/// the data of every other chunk is seeked past untouched.
+fn read_wanted_chunk_table<R: Read + Seek>(
+    reader: &mut R,
+    number_of_chunks: u64,
+    wanted: &HashSet<ChunkHash>,
+    progress_bar: Option<&ProgressBar>,
+    decompress_threads: Option<usize>,
+    key: Option<&ArchiveKey>,
+) -> Result<HashMap<ChunkHash, Vec<u8>>, AppError> {
+    decompress_chunk_entries(
+        number_of_chunks,
+        progress_bar,
+        decompress_threads,
+        key,
+        || read_wanted_stored_chunk(reader, wanted),
+    )
+}
+
+/// Pulls `number_of_chunks` entries from `next_chunk` and decompresses the ones it returns in
+/// parallel batches, see [`read_chunk_table`].
+fn decompress_chunk_entries(
+    number_of_chunks: u64,
+    progress_bar: Option<&ProgressBar>,
+    decompress_threads: Option<usize>,
+    key: Option<&ArchiveKey>,
+    mut next_chunk: impl FnMut() -> Result<Option<StoredChunk>, AppError>,
+) -> Result<HashMap<ChunkHash, Vec<u8>>, AppError> {
+    let pool = decompress_threads
+        .map(|threads| {
+            ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .map_err(AppError::CapThreadsError)
+        })
+        .transpose()?;
+    let batch_size = pool
+        .as_ref()
+        .map_or_else(rayon::current_num_threads, |pool| {
+            pool.current_num_threads()
+        }) as u64;
+
+    let mut chunk_map: HashMap<ChunkHash, Vec<u8>> = HashMap::new();
+
+    // Setup progress bar if one is given
+    if let Some(progress_bar) = progress_bar {
+        progress_bar.set_length(number_of_chunks);
+    }
+
+    let mut remaining = number_of_chunks;
+    while remaining > 0 {
+        let batch_len = remaining.min(batch_size);
+        let mut batch = Vec::with_capacity(batch_len as usize);
+        for _ in 0..batch_len {
+            batch.extend(next_chunk()?);
+        }
+        remaining -= batch_len;
+
+        let decompress_batch = || {
+            batch
+                .into_par_iter()
+                .map(|chunk| chunk.decompress(key))
+                .collect::<Result<Vec<_>, AppError>>()
+        };
+        let decompressed = match &pool {
+            Some(pool) => pool.install(decompress_batch),
+            None => decompress_batch(),
+        }?;
+
+        chunk_map.extend(decompressed);
 
-        // Setup progress bar if one is given
+        // Increment progress bar if it exists
         if let Some(progress_bar) = progress_bar {
-            progress_bar.set_length(self.file_count as u64);
-            progress_bar.set_message("Rebuilding files");
-            progress_bar.set_position(0);
+            progress_bar.inc(batch_len);
         }
+    }
 
-        for _ in 0..self.file_count {
-            // Read Path Length
-            self.reader
-                .read_exact(&mut buf4)
-                .map_err(AppError::ReaderError)?;
-            let path_length = u32::from_le_bytes(buf4) as usize;
+    Ok(chunk_map)
+}
 
-            // Get Full Path of File
-            let mut path_bytes = vec![0u8; path_length];
-            self.reader
-                .read_exact(&mut path_bytes)
-                .map_err(AppError::ReaderError)?;
-            let relative_path = String::from_utf8(path_bytes).map_err(|_| AppError::IllegalUTF8)?;
+/// Reads the next file table entry from `reader`; without `with_hashes` its chunk hashes are
+/// seeked past and `chunk_hashes` is left empty.
+fn read_file_record_from<R: Read + Seek>(
+    reader: &mut R,
+    layout: FileTableLayout,
+    with_hashes: bool,
+) -> Result<FileRecord, AppError> {
+    if with_hashes {
+        return read_file_record(reader, layout);
+    }
 
-            // Read Original Size and Disgard
-            self.reader
-                .read_exact(&mut buf8)
-                .map_err(AppError::ReaderError)?;
+    let record = read_file_record_fields(reader, layout)?;
+    reader
+        .seek(SeekFrom::Current(record.chunk_count as i64 * 16))
+        .map_err(AppError::ReaderError)?;
 
-            // Read Chunk Count
-            self.reader
-                .read_exact(&mut buf4)
-                .map_err(AppError::ReaderError)?;
-            let chunk_count = u32::from_le_bytes(buf4);
+    Ok(record)
+}
 
-            // Read chunk hashes
-            let mut chunks = Vec::with_capacity(chunk_count as usize);
-            for _ in 0..chunk_count {
-                let mut hash = [0u8; 16];
-                self.reader
-                    .read_exact(&mut hash)
+/// Reads the sealed entries of an encrypted file table from `reader`, positioned just after
+/// its file count, and decrypts them with `key`, see [`write_file_table`].
+///
+/// # Errors
+/// Returns `AppError::DecryptionFailed` if `key` is wrong or the table was altered.
+fn read_sealed_file_table<R: Read>(
+    reader: &mut R,
+    file_count: u32,
+    key: &ArchiveKey,
+) -> Result<Vec<u8>, AppError> {
+    let mut buf8 = [0u8; 8];
+    reader
+        .read_exact(&mut buf8)
+        .map_err(AppError::ReaderError)?;
+    let sealed_len = u64::from_le_bytes(buf8);
+
+    // Read rather than allocated up front, so a corrupt length cannot ask for huge buffers
+    let mut sealed = Vec::new();
+    reader
+        .take(sealed_len)
+        .read_to_end(&mut sealed)
+        .map_err(AppError::ReaderError)?;
+    if (sealed.len() as u64) < sealed_len {
+        return Err(AppError::Archive("truncated encrypted file table".into()));
+    }
+    key.open(&sealed, &file_count.to_le_bytes())
+}
+
+/// Reads a complete file table entry, including its chunk hashes, from `reader`.
+///
+/// `layout` gives the optional fields the archive's header announces.
+fn read_file_record<R: Read>(
+    reader: &mut R,
+    layout: FileTableLayout,
+) -> Result<FileRecord, AppError> {
+    let mut record = read_file_record_fields(reader, layout)?;
+
+    record.chunk_hashes.reserve(record.chunk_count as usize);
+    for _ in 0..record.chunk_count {
+        let mut hash = [0u8; 16];
+        reader
+            .read_exact(&mut hash)
+            .map_err(AppError::ReaderError)?;
+        record.chunk_hashes.push(hash);
+    }
+
+    Ok(record)
+}
+
+/// Reads the fixed fields of a file table entry, stopping before its chunk hashes.
+fn read_file_record_fields<R: Read>(
+    reader: &mut R,
+    layout: FileTableLayout,
+) -> Result<FileRecord, AppError> {
+    let mut buf4 = [0u8; 4];
+    let mut buf8 = [0u8; 8];
+
+    // Read Path length
+    reader
+        .read_exact(&mut buf4)
+        .map_err(AppError::ReaderError)?;
+    let path_length = u32::from_le_bytes(buf4) as usize;
+
+    // Read Path
+    let mut path_bytes = vec![0u8; path_length];
+    reader
+        .read_exact(&mut path_bytes)
+        .map_err(AppError::ReaderError)?;
+    let path = String::from_utf8(path_bytes).map_err(|_| AppError::IllegalUTF8)?;
+
+    // Read original size
+    reader
+        .read_exact(&mut buf8)
+        .map_err(AppError::ReaderError)?;
+    let original_size = u64::from_le_bytes(buf8);
+
+    // Read the permission mode, 0 when the packer had none to record
+    let mode = if layout.modes {
+        reader
+            .read_exact(&mut buf4)
+            .map_err(AppError::ReaderError)?;
+        Some(u32::from_le_bytes(buf4)).filter(|&mode| mode != 0)
+    } else {
+        None
+    };
+
+    // Read the entry type, which for a symlink is followed by its target
+    let kind = if layout.entry_types {
+        let mut entry_type = [0u8; 1];
+        reader
+            .read_exact(&mut entry_type)
+            .map_err(AppError::ReaderError)?;
+        match entry_type[0] {
+            ENTRY_REGULAR => EntryKind::Regular,
+            entry_type @ (ENTRY_SYMLINK | ENTRY_HARDLINK) => {
+                reader
+                    .read_exact(&mut buf4)
+                    .map_err(AppError::ReaderError)?;
+                let mut target = vec![0u8; u32::from_le_bytes(buf4) as usize];
+                reader
+                    .read_exact(&mut target)
                     .map_err(AppError::ReaderError)?;
-                chunks.push(hash);
+                let target = String::from_utf8(target).map_err(|_| AppError::IllegalUTF8)?;
+                if entry_type == ENTRY_SYMLINK {
+                    EntryKind::Symlink(target)
+                } else {
+                    EntryKind::HardLink(target)
+                }
+            }
+            ENTRY_DIRECTORY => EntryKind::Directory,
+            other => {
+                return Err(AppError::Archive(format!(
+                    "unknown entry type {other} for `{path}`"
+                )))
             }
+        }
+    } else {
+        EntryKind::Regular
+    };
 
-            entries.push(FileRebuildEntry {
-                relative_path,
-                chunk_hashes: chunks,
-            });
+    // Read the content hash, all zeros when the packer had none to record
+    let file_hash = if layout.file_hashes {
+        let mut hash = [0u8; 16];
+        reader
+            .read_exact(&mut hash)
+            .map_err(AppError::ReaderError)?;
+        Some(hash).filter(|hash| *hash != [0u8; 16])
+    } else {
+        None
+    };
+
+    // Read number of chunks belonging to file
+    reader
+        .read_exact(&mut buf4)
+        .map_err(AppError::ReaderError)?;
+    let chunk_count = u32::from_le_bytes(buf4);
+
+    Ok(FileRecord {
+        path,
+        original_size,
+        chunk_count,
+        chunk_hashes: Vec::new(),
+        mode,
+        kind,
+        file_hash,
+    })
+}
+
+/// Rebuilds every file in `entries` under `output_dir` from the chunks in `chunks`.
+///
+/// A missing chunk fails its file unless `zero_fill_missing` is set, in which case it is
+/// replaced by as many zeros as it would have held and the file is reported as partially
+/// recovered. With `check` set nothing is written, see [`check_files`].
+fn write_files(
+    entries: &[FileRecord],
+    chunks: ChunkSource,
+    output_dir: &Path,
+    progress_bar: Option<&ProgressBar>,
+    options: &UnpackOptions,
+    hash_seed: u64,
+    chunk_size: usize,
+) -> Result<UnpackReport, AppError> {
+    // Setup progress bar if one is given
+    if let Some(progress_bar) = progress_bar {
+        progress_bar.set_length(entries.len() as u64);
+        progress_bar.set_message("Rebuilding files");
+        progress_bar.set_position(0);
+    }
+
+    // Refuse the archive before writing anything if any path could climb out of `output_dir`
+    for entry in entries {
+        if entry.path.is_empty() {
+            return Err(AppError::UnsafePath(PathBuf::new()));
+        }
+        validate_relative_path(Path::new(&entry.path))?;
+    }
+    if options.check {
+        return check_files(entries, chunks, progress_bar, hash_seed);
+    }
+    fs::create_dir_all(output_dir)
+        .map_err(|e| AppError::CreateDirError(output_dir.to_path_buf(), e))?;
+    let root = fs::canonicalize(output_dir)
+        .map_err(|e| AppError::CreateDirError(output_dir.to_path_buf(), e))?;
+
+    // Symlinks are created only once every regular file is written, so no file can be written
+    // through a link into somewhere outside `output_dir`
+    let (links, files): (Vec<&FileRecord>, Vec<&FileRecord>) = entries
+        .iter()
+        .partition(|entry| matches!(entry.kind, EntryKind::Symlink(_)));
+    let (dirs, files): (Vec<&FileRecord>, Vec<&FileRecord>) = files
+        .into_iter()
+        .partition(|entry| entry.kind == EntryKind::Directory);
+    // Hard links are made once the files they link to are written
+    let (hard_links, files): (Vec<&FileRecord>, Vec<&FileRecord>) = files
+        .into_iter()
+        .partition(|entry| matches!(entry.kind, EntryKind::HardLink(_)));
+    let written: HashSet<&str> = files.iter().map(|entry| entry.path.as_str()).collect();
+    for entry in &hard_links {
+        if let EntryKind::HardLink(primary) = &entry.kind {
+            if !written.contains(primary.as_str()) {
+                return Err(AppError::Archive(format!(
+                    "`{}` is a hard link to `{primary}`, which is not being extracted",
+                    entry.path
+                )));
+            }
+        }
+    }
+    if !links.is_empty() {
+        let link_paths: HashSet<&Path> = links.iter().map(|link| Path::new(&link.path)).collect();
+        if let Some(entry) = entries.iter().find(|entry| {
+            Path::new(&entry.path)
+                .ancestors()
+                .skip(1)
+                .any(|ancestor| link_paths.contains(ancestor))
+        }) {
+            return Err(AppError::UnsafePath(PathBuf::from(&entry.path)));
         }
+    }
 
-        // Rebuild files in parallel
-        entries.par_iter().try_for_each(
-            |entry| -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-                let full_path = output_dir.join(PathBuf::from(&entry.relative_path));
-                if let Some(parent) = full_path.parent() {
-                    fs::create_dir_all(parent)
-                        .map_err(|e| AppError::CreateDirError(parent.to_path_buf(), e))?;
+    // Rebuild files in parallel. With `repair`, a file whose chunks are damaged is lost on its
+    // own and the outcome is `Err` rather than failing the unpack
+    let placements = Placements::default();
+    let outcomes = files
+        .par_iter()
+        .map_init(
+            SourceReaders::default,
+            |readers, entry| -> Result<Result<Option<PartialRecovery>, CheckFailure>, AppError> {
+                // Files already written are kept, but no new ones are started
+                check_interrupted()?;
+                let Some(full_path) =
+                    claim_target(&root, output_dir, entry, options.on_conflict, &placements)?
+                else {
+                    if let Some(pb) = progress_bar {
+                        pb.inc(1);
+                    }
+                    return Ok(Ok(None));
+                };
+                let file = File::create(&full_path)
+                    .map_err(|e| AppError::CreateFileError(full_path.to_path_buf(), e))?;
+                if options.preallocate {
+                    preallocate(&file, entry.original_size)
+                        .map_err(|e| AppError::CreateFileError(full_path.to_path_buf(), e))?;
                 }
 
-                let mut writer = BufWriter::new(
-                    File::create(&full_path)
-                        .map_err(|e| AppError::CreateFileError(full_path.to_path_buf(), e))?,
-                );
-                for hash in &entry.chunk_hashes {
-                    if let Some(data) = chunk_map.get(hash) {
-                        writer.write_all(data).map_err(|e| {
-                            AppError::CreateDirError(entry.relative_path.clone().into(), e)
-                        })?;
-                    } else {
-                        return Err(Box::new(AppError::MissingChunk(
-                            entry.relative_path.clone().into(),
-                        )));
+                let recovery = match write_file_content(
+                    entry, &chunks, readers, file, options, hash_seed, chunk_size,
+                ) {
+                    Ok(recovery) => recovery,
+                    Err(e) if options.repair && is_archive_damage(&e) => {
+                        fs::remove_file(&full_path)
+                            .map_err(|e| AppError::CreateFileError(full_path.to_path_buf(), e))?;
+                        if let Some(pb) = progress_bar {
+                            pb.inc(1);
+                        }
+                        return Ok(Err(CheckFailure {
+                            path: entry.path.clone(),
+                            reason: e.to_string(),
+                        }));
                     }
+                    Err(e) => return Err(e),
+                };
+
+                if let Some(mode) = entry.mode {
+                    restore_mode(&full_path, mode, options.special_mode_bits)?;
                 }
 
                 if let Some(pb) = progress_bar {
                     pb.inc(1);
                 }
 
-                Ok::<_, Box<dyn std::error::Error + Send + Sync>>(())
+                Ok(Ok((recovery.missing_chunks > 0).then_some(recovery)))
             },
-        )?;
+        )
+        .collect::<Result<Vec<_>, AppError>>()?;
+    let mut recoveries = Vec::new();
+    let mut lost_files = Vec::new();
+    for outcome in outcomes {
+        match outcome {
+            Ok(recovery) => recoveries.extend(recovery),
+            Err(lost) => lost_files.push(lost),
+        }
+    }
+    let lost: HashSet<String> = lost_files.iter().map(|lost| lost.path.clone()).collect();
 
-        Ok(())
+    for entry in hard_links {
+        if let EntryKind::HardLink(primary) = &entry.kind {
+            // A link to a lost file has nothing to link to, so it is lost with it
+            if lost.contains(primary) {
+                lost_files.push(CheckFailure {
+                    path: entry.path.clone(),
+                    reason: format!("hard link to `{primary}`, which was lost"),
+                });
+                if let Some(pb) = progress_bar {
+                    pb.inc(1);
+                }
+                continue;
+            }
+            let placed_primary = placements
+                .lock()
+                .map_err(|_| AppError::LockPoisoned)?
+                .get(primary.as_str())
+                .cloned();
+            let primary_path = match placed_primary {
+                // Linking to the file that was left in place would join unrelated content
+                Some(None) => {
+                    placements
+                        .lock()
+                        .map_err(|_| AppError::LockPoisoned)?
+                        .insert(&entry.path, None);
+                    None
+                }
+                Some(Some(renamed)) => Some(renamed),
+                None => Some(output_dir.join(platform_path(primary))),
+            };
+            if let Some(primary_path) = primary_path {
+                if let Some(full_path) =
+                    claim_target(&root, output_dir, entry, options.on_conflict, &placements)?
+                {
+                    if fs::symlink_metadata(&full_path).is_ok() {
+                        fs::remove_file(&full_path)
+                            .map_err(|e| AppError::CreateFileError(full_path.clone(), e))?;
+                    }
+                    fs::hard_link(primary_path, &full_path)
+                        .map_err(|e| AppError::CreateFileError(full_path.clone(), e))?;
+                }
+            }
+        }
+        if let Some(pb) = progress_bar {
+            pb.inc(1);
+        }
+    }
+
+    for entry in dirs {
+        let full_path = prepare_target(&root, output_dir, entry)?;
+        fs::create_dir_all(&full_path)
+            .map_err(|e| AppError::CreateDirError(full_path.clone(), e))?;
+        if let Some(mode) = entry.mode {
+            restore_mode(&full_path, mode, options.special_mode_bits)?;
+        }
+        if let Some(pb) = progress_bar {
+            pb.inc(1);
+        }
+    }
+
+    for entry in links {
+        if let EntryKind::Symlink(target) = &entry.kind {
+            if let Some(full_path) =
+                claim_target(&root, output_dir, entry, options.on_conflict, &placements)?
+            {
+                restore_symlink(&full_path, target)?;
+            }
+        }
+        if let Some(pb) = progress_bar {
+            pb.inc(1);
+        }
+    }
+
+    let placements = placements
+        .into_inner()
+        .map_err(|_| AppError::LockPoisoned)?;
+    let skipped_existing = placements.values().filter(|path| path.is_none()).count();
+    Ok(UnpackReport {
+        partially_recovered: recoveries,
+        files_restored: entries.len() - skipped_existing - lost_files.len(),
+        lost_files,
+        skipped_existing,
+        renamed_existing: placements.len() - skipped_existing,
+        ..Default::default()
+    })
+}
+
+/// Writes the content of the regular file `entry` to `file` from `chunks`, then checks it
+/// against the file table as `options` asks.
+///
+/// With `repair` set every chunk is re-hashed and the content hash compared, as with `verify`,
+/// so damage that still decompresses is caught too.
+///
+/// # Returns
+/// The chunks that were missing and zero-filled, only ever any with `zero_fill_missing` set.
+///
+/// # Errors
+/// Returns an error if a chunk is missing or unreadable, or the content does not match the file
+/// table; see [`is_archive_damage`]. Errors writing `file` are `AppError::CreateDirError`.
+fn write_file_content(
+    entry: &FileRecord,
+    chunks: &ChunkSource,
+    readers: &mut SourceReaders,
+    file: File,
+    options: &UnpackOptions,
+    hash_seed: u64,
+    chunk_size: usize,
+) -> Result<PartialRecovery, AppError> {
+    let write_error = |e| AppError::CreateDirError(entry.path.clone().into(), e);
+    let mut writer = BufWriter::new(file);
+    let mut recovery = PartialRecovery {
+        path: entry.path.clone(),
+        missing_chunks: 0,
+        zero_filled_bytes: 0,
+    };
+    let mut bytes_written = 0u64;
+    let mut content_hasher =
+        ((options.verify || options.repair) && entry.file_hash.is_some()).then(ContentHasher::new);
+    for (index, hash) in entry.chunk_hashes.iter().enumerate() {
+        if let Some(data) = chunks.get(hash, readers)? {
+            if options.repair && !chunk_matches(&data, hash, hash_seed, &entry.path) {
+                return Err(AppError::Archive(format!(
+                    "chunk {index} of `{}` does not match its hash",
+                    entry.path
+                )));
+            }
+            writer.write_all(&data).map_err(write_error)?;
+            bytes_written += data.len() as u64;
+            if let Some(hasher) = &mut content_hasher {
+                hasher.update(&data);
+            }
+        } else if options.zero_fill_missing {
+            let len = missing_chunk_len(entry, index, chunk_size);
+            std::io::copy(&mut std::io::repeat(0).take(len), &mut writer).map_err(write_error)?;
+            recovery.missing_chunks += 1;
+            recovery.zero_filled_bytes += len;
+            bytes_written += len;
+        } else {
+            return Err(AppError::MissingChunk(entry.path.clone().into()));
+        }
+    }
+    writer.flush().map_err(write_error)?;
+    drop(writer);
+
+    // A cheap guard against a damaged chunk table, short of re-hashing every chunk
+    if !options.skip_size_check && bytes_written != entry.original_size {
+        return Err(AppError::SizeMismatch(
+            entry.path.clone(),
+            entry.original_size,
+            bytes_written,
+        ));
+    }
+
+    // Zero-filled files cannot match and are already reported as partly recovered
+    if let (Some(hasher), Some(expected)) = (content_hasher, entry.file_hash) {
+        if recovery.missing_chunks == 0 && hasher.finish() != expected {
+            return Err(AppError::FileHashMismatch(entry.path.clone()));
+        }
+    }
+
+    Ok(recovery)
+}
+
+/// Whether `data` hashes to `hash`, under either the archive-wide seed or the per-file seed of
+/// `path`, whichever dedup scope the archive was packed with.
+fn chunk_matches(data: &[u8], hash: &ChunkHash, hash_seed: u64, path: &str) -> bool {
+    hash_chunk_scoped(data, chunk_seed(hash_seed, None)) == *hash
+        || hash_chunk_scoped(data, chunk_seed(hash_seed, Some(path))) == *hash
+}
+
+/// Whether `e` comes from a damaged archive rather than from writing the output, so that
+/// `UnpackOptions::repair` can give up on the one file instead of the whole unpack.
+fn is_archive_damage(e: &AppError) -> bool {
+    matches!(
+        e,
+        AppError::ReaderError(_)
+            | AppError::MissingChunk(_)
+            | AppError::ChunkTooLarge(..)
+            | AppError::InvalidChunkSize(_)
+            | AppError::SizeMismatch(..)
+            | AppError::FileHashMismatch(_)
+            | AppError::Archive(_)
+    )
+}
+
+/// Rebuilds every file in `entries` from `chunks` the way [`write_files`] does, but discards
+/// the bytes instead of writing them.
+///
+/// Each chunk is re-hashed, with the archive's `hash_seed` and, for per-file dedup scopes, the
+/// file's own seed, and each file's rebuilt length and content hash, when one is recorded, are
+/// compared with the file table.
+///
+/// # Errors
+/// Returns an error only if the archive cannot be read; files that fail are reported in
+/// `UnpackReport::check_failures`.
+fn check_files(
+    entries: &[FileRecord],
+    chunks: ChunkSource,
+    progress_bar: Option<&ProgressBar>,
+    hash_seed: u64,
+) -> Result<UnpackReport, AppError> {
+    let failures = entries
+        .par_iter()
+        .map_init(
+            SourceReaders::default,
+            |readers, entry| -> Result<Option<CheckFailure>, AppError> {
+                let failure = |reason: String| {
+                    Ok(Some(CheckFailure {
+                        path: entry.path.clone(),
+                        reason,
+                    }))
+                };
+                if let Some(pb) = progress_bar {
+                    pb.inc(1);
+                }
+
+                let mut rebuilt_size = 0;
+                let mut content_hasher = ContentHasher::new();
+                for (index, hash) in entry.chunk_hashes.iter().enumerate() {
+                    let data = match chunks.get(hash, readers) {
+                        Ok(Some(data)) => data,
+                        Ok(None) => return failure(format!("chunk {index} is missing")),
+                        Err(e) => return failure(format!("chunk {index} is unreadable: {e}")),
+                    };
+                    if !chunk_matches(&data, hash, hash_seed, &entry.path) {
+                        return failure(format!("chunk {index} does not match its hash"));
+                    }
+                    rebuilt_size += data.len() as u64;
+                    content_hasher.update(&data);
+                }
+
+                if rebuilt_size != entry.original_size {
+                    return failure(format!(
+                        "rebuilt to {rebuilt_size} bytes, expected {}",
+                        entry.original_size
+                    ));
+                }
+                if entry
+                    .file_hash
+                    .is_some_and(|expected| content_hasher.finish() != expected)
+                {
+                    return failure("does not match its recorded content hash".to_string());
+                }
+                Ok(None)
+            },
+        )
+        .collect::<Result<Vec<_>, AppError>>()?;
+
+    Ok(UnpackReport {
+        files_restored: entries.len(),
+        check_failures: failures.into_iter().flatten().collect(),
+        ..Default::default()
+    })
+}
+
+/// Creates the parent directories of `entry` under `output_dir` and returns the path to write
+/// it to.
+///
+/// `root` is `output_dir` canonicalized. Symlinks already on disk, e.g. from an earlier unpack,
+/// are resolved so that the parent directory is guaranteed to lie inside it. A symlink left at
+/// the entry's own path is removed, so writing the entry cannot follow it.
+///
+/// # Errors
+/// Returns `AppError::UnsafePath` if the entry would land outside `output_dir`, or an error if a
+/// directory cannot be created.
+fn prepare_target(root: &Path, output_dir: &Path, entry: &FileRecord) -> Result<PathBuf, AppError> {
+    let full_path = output_dir.join(platform_path(&entry.path));
+    prepare_parent(root, &full_path, entry)?;
+
+    if fs::symlink_metadata(&full_path).is_ok_and(|metadata| metadata.file_type().is_symlink()) {
+        fs::remove_file(&full_path)
+            .map_err(|e| AppError::CreateFileError(full_path.to_path_buf(), e))?;
+    }
+
+    Ok(full_path)
+}
+
+/// Checks that the parent directory of `full_path`, where `entry` is extracted, lies inside
+/// `root` and creates it if needed.
+fn prepare_parent(root: &Path, full_path: &Path, entry: &FileRecord) -> Result<(), AppError> {
+    let unsafe_path = || AppError::UnsafePath(PathBuf::from(&entry.path));
+
+    // The nearest ancestor already on disk decides where the missing directories will go
+    let existing = full_path
+        .ancestors()
+        .skip(1)
+        .find(|ancestor| fs::symlink_metadata(ancestor).is_ok())
+        .ok_or_else(unsafe_path)?;
+    let resolved = fs::canonicalize(existing).map_err(|_| unsafe_path())?;
+    if !resolved.starts_with(root) {
+        return Err(unsafe_path());
+    }
+
+    if let Some(parent) = full_path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| AppError::CreateDirError(parent.to_path_buf(), e))?;
+    }
+
+    Ok(())
+}
+
+/// Entries a `ConflictPolicy` kept out of their own path, keyed by archive path: `None` when the
+/// entry was skipped, or the path it was extracted to instead.
+type Placements<'a> = Mutex<HashMap<&'a str, Option<PathBuf>>>;
+
+/// Chooses where `entry` is extracted, applying `policy` if something is already at its path.
+///
+/// The target is made ready as by [`prepare_target`]. Entries that are skipped or renamed are
+/// recorded in `placements`.
+///
+/// # Returns
+/// The path to write, or `None` if the entry should be skipped.
+///
+/// # Errors
+/// Returns `AppError::UnsafePath` if the entry would land outside `output_dir`, or an error if a
+/// directory cannot be created.
+fn claim_target<'a>(
+    root: &Path,
+    output_dir: &Path,
+    entry: &'a FileRecord,
+    policy: ConflictPolicy,
+    placements: &Placements<'a>,
+) -> Result<Option<PathBuf>, AppError> {
+    let full_path = output_dir.join(platform_path(&entry.path));
+    if policy == ConflictPolicy::Overwrite || fs::symlink_metadata(&full_path).is_err() {
+        return prepare_target(root, output_dir, entry).map(Some);
+    }
+
+    let placed = match policy {
+        ConflictPolicy::Skip => None,
+        _ => {
+            prepare_parent(root, &full_path, entry)?;
+            Some(free_path(&full_path))
+        }
+    };
+    placements
+        .lock()
+        .map_err(|_| AppError::LockPoisoned)?
+        .insert(&entry.path, placed.clone());
+    Ok(placed)
+}
+
+/// Returns the first of `name (1).ext`, `name (2).ext`, ... beside `path` that does not exist.
+fn free_path(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+
+    (1u64..)
+        .map(|n| path.with_file_name(format!("{stem} ({n}){extension}")))
+        .find(|candidate| fs::symlink_metadata(candidate).is_err())
+        .expect("some numbered name is free")
+}
+
+/// Applies a recorded permission mode to a rebuilt file. The setuid, setgid and sticky bits are
+/// dropped unless `special_bits` is set.
+#[cfg(unix)]
+fn restore_mode(path: &Path, mode: u32, special_bits: bool) -> Result<(), AppError> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = if special_bits {
+        mode & 0o7777
+    } else {
+        mode & 0o777
+    };
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+        .map_err(|e| AppError::CreateFileError(path.to_path_buf(), e))
+}
+
+/// Permission modes only exist on Unix, so other platforms keep their defaults.
+#[cfg(not(unix))]
+fn restore_mode(_path: &Path, _mode: u32, _special_bits: bool) -> Result<(), AppError> {
+    Ok(())
+}
+
+/// Creates a symlink at `path` pointing at `target`, replacing any file already there.
+#[cfg(unix)]
+fn restore_symlink(path: &Path, target: &str) -> Result<(), AppError> {
+    if fs::symlink_metadata(path).is_ok() {
+        fs::remove_file(path).map_err(|e| AppError::CreateFileError(path.to_path_buf(), e))?;
+    }
+    std::os::unix::fs::symlink(target, path)
+        .map_err(|e| AppError::CreateFileError(path.to_path_buf(), e))
+}
+
+#[cfg(not(unix))]
+fn restore_symlink(path: &Path, _target: &str) -> Result<(), AppError> {
+    Err(AppError::Unsupported(format!(
+        "`{}` is a symlink, which can only be restored on Unix",
+        path.display()
+    )))
+}
+
+/// Rejects zero-filling missing chunks of an archive whose chunk lengths depend on their content,
+/// since the length of a missing chunk cannot be worked out.
+fn check_zero_fill(options: &UnpackOptions, chunking: Chunking) -> Result<(), AppError> {
+    if options.zero_fill_missing && chunking == Chunking::ContentDefined {
+        return Err(AppError::Unsupported(
+            "zero-filling missing chunks of an archive with content-defined chunks".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Number of bytes the chunk at `index` of `entry` held.
+///
+/// Every chunk but a file's last is exactly `chunk_size`, so a missing chunk's length follows
+/// from the file size without needing its chunk table entry.
+fn missing_chunk_len(entry: &FileRecord, index: usize, chunk_size: usize) -> u64 {
+    let chunk_size = chunk_size as u64;
+    if index + 1 < entry.chunk_hashes.len() {
+        chunk_size
+    } else {
+        entry
+            .original_size
+            .saturating_sub(index as u64 * chunk_size)
     }
 }
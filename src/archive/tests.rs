@@ -1,14 +1,25 @@
+use std::collections::{HashMap, HashSet};
 use std::fs::{self, File};
-use std::io::{Cursor, Read, Seek, Write};
-use std::path::Path;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 
-use crate::archive::{ArchiveReader, ArchiveWriter};
+use crate::archive::reader::{platform_path, read_chunk_table};
+use crate::archive::writer::{archive_path, pack_artifacts};
+use crate::archive::{
+    check_compatibility, estimate_pack, parse_rename_map, raw_fingerprint, remove_entries,
+    rename_entries, ArchiveReader, ArchiveWriter, ConflictPolicy, DedupScope, FileEntry,
+    PackOptions, PartialRecovery, UnpackOptions,
+};
+use crate::fsutil::writer::SyncInterval;
+use crate::util::chunk::{ChunkHash, Chunking, CHUNK_SIZE, MAX_CHUNK_SIZE};
+use crate::util::crypto::KdfParams;
 use crate::util::errors::AppError;
 use crate::util::header::{
-    patch_u64, verify_header, write_header, write_placeholder_u64, write_timestamp,
+    patch_u64, verify_header, write_header, write_placeholder_u64, write_timestamp, PREFIX,
 };
 use crate::VERSION;
 
+use ed25519_dalek::SigningKey;
 use tempfile::{tempdir, NamedTempFile};
 
 pub fn create_dummy_archive<W: Write + Seek>(
@@ -57,6 +68,37 @@ pub fn create_dummy_archive<W: Write + Seek>(
     Ok(vec![("file1.txt".to_string(), chunk_data.to_vec())])
 }
 
+/// Writes an archive holding `file1.txt` plus one chunk that no file references.
+///
+/// Returns the size in bytes of the orphaned chunk's table entry.
+fn create_archive_with_orphan<W: Write + Seek>(writer: &mut W) -> Result<u64, AppError> {
+    write_header(writer)?;
+    write_timestamp(writer)?;
+    let chunk_count_pos = write_placeholder_u64(writer)?;
+
+    let mut orphan_entry_size = 0;
+    for (hash, data) in [([1u8; 16], &b"live"[..]), ([2u8; 16], &b"orphaned"[..])] {
+        let compressed = zstd::encode_all(Cursor::new(data), 0)?;
+        writer.write_all(&hash)?;
+        writer.write_all(&(data.len() as u64).to_le_bytes())?;
+        writer.write_all(&(compressed.len() as u64).to_le_bytes())?;
+        writer.write_all(&compressed)?;
+        orphan_entry_size = 16 + 8 + 8 + compressed.len() as u64;
+    }
+
+    patch_u64(writer, chunk_count_pos, 2)?;
+
+    writer.write_all(&1u32.to_le_bytes())?;
+    let path_bytes = b"file1.txt";
+    writer.write_all(&(path_bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(path_bytes)?;
+    writer.write_all(&4u64.to_le_bytes())?;
+    writer.write_all(&1u32.to_le_bytes())?;
+    writer.write_all(&[1u8; 16])?;
+
+    Ok(orphan_entry_size)
+}
+
 #[test]
 fn test_archive_writer_basic() -> Result<(), AppError> {
     // Create temp input directory
@@ -98,7 +140,8 @@ fn test_archive_writer_new() -> Result<(), AppError> {
     let temp_dir = tempdir()?;
     let temp_file = NamedTempFile::new()?;
 
-    let _archive_writer = ArchiveWriter::new(temp_dir.path(), temp_file.path(), None)?;
+    // The archive only appears at the output path once packing finishes
+    ArchiveWriter::new(temp_dir.path(), temp_file.path(), None)?.pack(&[])?;
 
     // Open the file and verify headers are written as expected
     let mut file = File::open(temp_file.path())?;
@@ -129,11 +172,91 @@ fn test_archive_reader_get_summary() -> Result<(), AppError> {
     let summary = reader.get_summary()?;
 
     assert_eq!(summary.unique_chunks, 1);
+    assert_eq!(summary.top_level_entries, 1);
     assert_eq!(summary.total_original_size, 4);
+    assert_eq!(summary.duplicate_chunk_bytes, 0);
     assert!(summary.archive_size > 0);
     assert!(summary.compression_ratio > 0.0);
     assert_eq!(summary.files.len(), 1);
     assert_eq!(summary.files[0].path, "file1.txt");
+    assert_eq!(summary.files[0].chunk_count, 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_summary_counts_duplicate_chunk_bytes() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    let block: Vec<u8> = (0..1024u32).map(|i| (i % 251) as u8).collect();
+    let tail: Vec<u8> = (0..300u32).map(|i| (i % 7) as u8).collect();
+    // Three references to `block`, two of them repeats, plus a unique tail chunk
+    fs::write(
+        input_path.join("twice.bin"),
+        [block.as_slice(), &block].concat(),
+    )?;
+    fs::write(
+        input_path.join("once.bin"),
+        [block.as_slice(), &tail].concat(),
+    )?;
+
+    let archive_path = dir.path().join("archive.squish");
+    let options = PackOptions {
+        chunk_size: 1024,
+        ..Default::default()
+    };
+    ArchiveWriter::with_options(&input_path, &archive_path, None, options)?
+        .pack(&[input_path.join("twice.bin"), input_path.join("once.bin")])?;
+
+    let summary = ArchiveReader::new(&archive_path)?.get_summary()?;
+    assert_eq!(summary.unique_chunks, 2);
+    assert_eq!(summary.total_original_size, 2 * 1024 + 1024 + 300);
+    assert_eq!(summary.duplicate_chunk_bytes, 2 * 1024);
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_reader_entries() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    fs::write(input_path.join("big.bin"), vec![7u8; 2500])?;
+    fs::write(input_path.join("small.txt"), b"small")?;
+
+    let archive_path = dir.path().join("archive.squish");
+    let options = PackOptions {
+        chunk_size: 1024,
+        ..Default::default()
+    };
+    ArchiveWriter::with_options(&input_path, &archive_path, None, options)?
+        .pack(&[input_path.join("big.bin"), input_path.join("small.txt")])?;
+
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    let mut entries = reader.entries()?;
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    assert_eq!(
+        entries,
+        [
+            FileEntry {
+                path: "big.bin".to_string(),
+                original_size: 2500,
+                chunk_count: 3,
+            },
+            FileEntry {
+                path: "small.txt".to_string(),
+                original_size: 5,
+                chunk_count: 1,
+            },
+        ]
+    );
+
+    // The same entries the summary lists, and the reader is still usable afterwards
+    let summary = reader.get_summary()?;
+    let mut summary_files = summary.files;
+    summary_files.sort_by(|a, b| a.path.cmp(&b.path));
+    assert_eq!(summary_files, entries);
 
     Ok(())
 }
@@ -170,3 +293,3132 @@ fn test_invalid_file_path_reader() {
     let res = ArchiveReader::new(Path::new("nonexistent.squish"));
     assert!(matches!(res, Err(AppError::FileNotExist(_))));
 }
+
+#[test]
+fn test_archive_writer_max_archive_size_exceeded() -> Result<(), AppError> {
+    let input_dir = tempdir()?;
+    let input_path = input_dir.path().join("input");
+    fs::create_dir(&input_path)?;
+
+    // Random-ish data so it doesn't compress below the limit
+    let data: Vec<u8> = (0..64 * 1024u32)
+        .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+        .collect();
+    let file_path = input_path.join("big.bin");
+    fs::write(&file_path, &data)?;
+
+    let output_path = input_dir.path().join("archive.squish");
+    let options = PackOptions {
+        max_archive_size: Some(1024),
+        ..Default::default()
+    };
+    let mut writer = ArchiveWriter::with_options(&input_path, &output_path, None, options)?;
+
+    let result = writer.pack(&[file_path]);
+    assert!(
+        matches!(&result, Err(AppError::Archive(msg)) if msg.contains("archive size limit exceeded"))
+    );
+    assert!(!output_path.exists(), "Partial archive should be removed");
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_reader_extract_file() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let archive_path = dir.path().join("dummy.squish");
+
+    let mut file = File::create(&archive_path)?;
+    let files = create_dummy_archive(&mut file)?;
+    file.flush()?;
+
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    for (filename, contents) in files {
+        let mut out = Vec::new();
+        reader.extract_file(&filename, &mut out)?;
+        assert_eq!(out, contents);
+    }
+
+    let mut out = Vec::new();
+    let missing = reader.extract_file("missing.txt", &mut out);
+    assert!(matches!(missing, Err(AppError::FileNotFoundInArchive(_))));
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_archive_reader_extract_file_to_child_process() -> Result<(), AppError> {
+    use std::process::{Command, Stdio};
+
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+
+    // Spans several chunks so the file is streamed rather than written in one go
+    let data: Vec<u8> = (0..5 * 1024 * 1024u32).map(|i| (i % 251) as u8).collect();
+    let file_path = input_path.join("large.bin");
+    fs::write(&file_path, &data)?;
+
+    let archive_path = dir.path().join("archive.squish");
+    let mut writer = ArchiveWriter::new(&input_path, &archive_path, None)?;
+    writer.pack(&[file_path])?;
+
+    let mut child = Command::new("wc")
+        .arg("-c")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    {
+        let mut stdin = child.stdin.take().expect("Child stdin should be piped");
+        reader.extract_file("large.bin", &mut stdin)?;
+    }
+
+    let output = child.wait_with_output()?;
+    let byte_count: usize = String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .expect("wc should print a byte count");
+    assert_eq!(byte_count, data.len());
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_writer_records_top_level_entries() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir_all(input_path.join("docs"))?;
+    fs::create_dir_all(input_path.join("src").join("nested"))?;
+
+    fs::write(input_path.join("README.md"), b"readme")?;
+    fs::write(input_path.join("docs").join("guide.md"), b"guide")?;
+    fs::write(input_path.join("src").join("main.rs"), b"main")?;
+    fs::write(input_path.join("src").join("nested").join("lib.rs"), b"lib")?;
+
+    let files = crate::fsutil::directory::walk_dir(&input_path)?;
+    let archive_path = dir.path().join("archive.squish");
+    let mut writer = ArchiveWriter::new(&input_path, &archive_path, None)?;
+    writer.pack(&files)?;
+
+    let reader = ArchiveReader::new(&archive_path)?;
+    assert_eq!(reader.top_level_entries(), 3);
+
+    Ok(())
+}
+
+#[test]
+fn test_legacy_archive_counts_top_level_entries() -> Result<(), AppError> {
+    // Laid out like an archive from before the count was recorded: no flag, no count
+    let dir = tempdir()?;
+    let archive_path = dir.path().join("legacy.squish");
+    let mut writer = File::create(&archive_path)?;
+    write_header(&mut writer)?;
+    write_timestamp(&mut writer)?;
+    writer.write_all(&1u64.to_le_bytes())?;
+
+    let data = b"shared";
+    let hash = crate::util::chunk::hash_chunk(data);
+    let compressed = zstd::encode_all(Cursor::new(data), 0)?;
+    writer.write_all(&hash)?;
+    writer.write_all(&(data.len() as u64).to_le_bytes())?;
+    writer.write_all(&(compressed.len() as u64).to_le_bytes())?;
+    writer.write_all(&compressed)?;
+
+    let paths = ["docs/a.txt", "docs/b.txt", "c.txt"];
+    writer.write_all(&(paths.len() as u32).to_le_bytes())?;
+    for path in paths {
+        writer.write_all(&(path.len() as u32).to_le_bytes())?;
+        writer.write_all(path.as_bytes())?;
+        writer.write_all(&(data.len() as u64).to_le_bytes())?;
+        writer.write_all(&1u32.to_le_bytes())?;
+        writer.write_all(&hash)?;
+    }
+    drop(writer);
+
+    let reader = ArchiveReader::new(&archive_path)?;
+    assert_eq!(reader.top_level_entries(), 2);
+    assert!(reader
+        .layout()
+        .iter()
+        .all(|section| section.name != "top-level entry count"));
+
+    // Edits keep the legacy layout readable
+    rename_entries(&archive_path, &[("c.txt".into(), "d.txt".into())], None)?;
+    let compact_path = dir.path().join("compact.squish");
+    ArchiveReader::new(&archive_path)?.compact_into(&mut File::create(&compact_path)?)?;
+    let mut reader = ArchiveReader::new(&compact_path)?;
+    assert_eq!(reader.top_level_entries(), 2);
+    let output_dir = dir.path().join("output");
+    reader.unpack(&output_dir, None)?;
+    assert_eq!(fs::read(output_dir.join("d.txt"))?, data);
+    assert_eq!(fs::read(output_dir.join("docs").join("b.txt"))?, data);
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_writer_reproducible_output() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir_all(input_path.join("sub"))?;
+
+    // Several multi-chunk files sharing content so chunks are deduplicated across files
+    let shared: Vec<u8> = (0..3 * 1024 * 1024u32).map(|i| (i % 241) as u8).collect();
+    for name in ["a.bin", "b.bin", "sub/c.bin"] {
+        let mut data = name.as_bytes().to_vec();
+        data.extend_from_slice(&shared);
+        fs::write(input_path.join(name), data)?;
+    }
+    fs::write(input_path.join("sub").join("d.bin"), &shared)?;
+
+    let mut archives = Vec::new();
+    for run in 0..2 {
+        let mut files = crate::fsutil::directory::walk_dir(&input_path)?;
+        if run == 1 {
+            files.reverse();
+        }
+
+        let archive_path = dir.path().join(format!("archive{run}.squish"));
+        let options = PackOptions {
+            reproducible: true,
+            temp_dir: Some(dir.path().to_path_buf()),
+            ..Default::default()
+        };
+        let mut writer = ArchiveWriter::with_options(&input_path, &archive_path, None, options)?;
+        writer.pack(&files)?;
+        archives.push(fs::read(&archive_path)?);
+    }
+
+    assert_eq!(
+        archives[0], archives[1],
+        "Archives should be byte-identical"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_staging_and_spool_follow_temp_dir() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let scratch = dir.path().join("scratch");
+    fs::create_dir(&scratch)?;
+    let output_path = dir.path().join("archive.squish");
+
+    let options = PackOptions {
+        temp_dir: Some(scratch.clone()),
+        ..Default::default()
+    };
+    let artifacts = pack_artifacts(&output_path, &options);
+    assert!(artifacts.contains(&scratch.join("archive.squish.chunks.tmp")));
+    assert!(artifacts.contains(&scratch.join(format!("archive.squish.tmp-{}", std::process::id()))));
+
+    // A temporary directory that cannot be checked falls back to staging next to the output
+    let options = PackOptions {
+        temp_dir: Some(dir.path().join("missing")),
+        ..Default::default()
+    };
+    let artifacts = pack_artifacts(&output_path, &options);
+    assert!(artifacts.contains(&dir.path().join("archive.squish.chunks.tmp")));
+    assert!(artifacts.contains(
+        &dir.path()
+            .join(format!("archive.squish.tmp-{}", std::process::id()))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_archive_reader_unpack_with_prefix() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let archive_path = dir.path().join("dummy.squish");
+
+    let mut file = File::create(&archive_path)?;
+    let files = create_dummy_archive(&mut file)?;
+    file.flush()?;
+
+    let output_dir = dir.path().join("output");
+    let options = UnpackOptions {
+        prefix: Some(PathBuf::from("vendor/lib")),
+        ..Default::default()
+    };
+
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    reader.unpack_with_options(&output_dir, None, &options)?;
+
+    assert!(output_dir.join("vendor").join("lib").is_dir());
+    for (filename, contents) in files {
+        let restored_path = output_dir.join("vendor").join("lib").join(filename);
+        assert_eq!(fs::read(restored_path)?, contents);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_reader_unpack_rejects_unsafe_prefix() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let archive_path = dir.path().join("dummy.squish");
+
+    let mut file = File::create(&archive_path)?;
+    create_dummy_archive(&mut file)?;
+    file.flush()?;
+
+    let output_dir = dir.path().join("output");
+    let options = UnpackOptions {
+        prefix: Some(PathBuf::from("../escape")),
+        ..Default::default()
+    };
+
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    let result = reader.unpack_with_options(&output_dir, None, &options);
+    assert!(matches!(result, Err(AppError::UnsafePath(_))));
+    assert!(!dir.path().join("escape").exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_reader_verify_against() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir_all(input_path.join("sub"))?;
+
+    fs::write(input_path.join("same.txt"), b"unchanged")?;
+    fs::write(input_path.join("edited.txt"), b"before")?;
+    fs::write(input_path.join("sub").join("deleted.txt"), b"gone soon")?;
+
+    let files = crate::fsutil::directory::walk_dir(&input_path)?;
+    let archive_path = dir.path().join("archive.squish");
+    let mut writer = ArchiveWriter::new(&input_path, &archive_path, None)?;
+    writer.pack(&files)?;
+
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    assert!(reader.verify_against(&input_path)?.is_clean());
+
+    // Drift the source after packing
+    fs::write(input_path.join("edited.txt"), b"after")?;
+    fs::remove_file(input_path.join("sub").join("deleted.txt"))?;
+    fs::write(input_path.join("added.txt"), b"new")?;
+
+    let report = reader.verify_against(&input_path)?;
+    assert!(!report.is_clean());
+    assert_eq!(report.matched, vec!["same.txt".to_string()]);
+    assert_eq!(report.mismatched, vec!["edited.txt".to_string()]);
+    assert_eq!(
+        report.missing_from_source,
+        vec!["sub/deleted.txt".to_string()]
+    );
+    assert_eq!(report.missing_from_archive, vec!["added.txt".to_string()]);
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_reader_unpack_with_preallocate() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+
+    let data: Vec<u8> = (0..3 * 1024 * 1024u32).map(|i| (i % 251) as u8).collect();
+    fs::write(input_path.join("large.bin"), &data)?;
+    fs::write(input_path.join("empty.bin"), b"")?;
+
+    let files = crate::fsutil::directory::walk_dir(&input_path)?;
+    let archive_path = dir.path().join("archive.squish");
+    let mut writer = ArchiveWriter::new(&input_path, &archive_path, None)?;
+    writer.pack(&files)?;
+
+    let output_dir = dir.path().join("output");
+    let options = UnpackOptions {
+        preallocate: true,
+        ..Default::default()
+    };
+
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    reader.unpack_with_options(&output_dir, None, &options)?;
+
+    assert_eq!(fs::read(output_dir.join("large.bin"))?, data);
+    assert_eq!(fs::read(output_dir.join("empty.bin"))?, b"");
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_reader_unpack_size_filter() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    fs::write(input_path.join("small.txt"), "s".repeat(10))?;
+    fs::write(input_path.join("medium.txt"), "m".repeat(1000))?;
+    fs::write(input_path.join("big.txt"), "b".repeat(5000))?;
+    let files = ["small.txt", "medium.txt", "big.txt"].map(|name| input_path.join(name));
+
+    let archive_path = dir.path().join("archive.squish");
+    ArchiveWriter::new(&input_path, &archive_path, None)?.pack(&files)?;
+
+    // Corrupt the big file's chunk, which a filter excluding it must never decompress
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    let big_hash = crate::util::chunk::hash_chunk("b".repeat(5000).as_bytes());
+    let big_chunk = (0..3)
+        .map(|index| reader.chunk_info(index))
+        .find(|info| info.as_ref().is_ok_and(|info| info.hash == big_hash))
+        .expect("big file chunk")?;
+    let mut bytes = fs::read(&archive_path)?;
+    let data_start = big_chunk.offset as usize;
+    bytes[data_start..data_start + big_chunk.compressed_size as usize].fill(0xff);
+    fs::write(&archive_path, bytes)?;
+
+    let options = UnpackOptions {
+        min_size: Some(100),
+        max_size: Some(2000),
+        ..Default::default()
+    };
+    let output_dir = dir.path().join("output");
+    let report =
+        ArchiveReader::new(&archive_path)?.unpack_with_options(&output_dir, None, &options)?;
+    assert_eq!(report.files_restored, 1);
+    assert_eq!(report.excluded_by_size, 2);
+    assert_eq!(
+        fs::read(output_dir.join("medium.txt"))?,
+        "m".repeat(1000).as_bytes()
+    );
+    assert!(!output_dir.join("small.txt").exists());
+    assert!(!output_dir.join("big.txt").exists());
+
+    // Streaming unpacks have to read every chunk, so only the bounds are checked there
+    let options = UnpackOptions {
+        max_size: Some(10),
+        ..Default::default()
+    };
+    let stream_dir = dir.path().join("stream");
+    let plain_path = dir.path().join("plain.squish");
+    ArchiveWriter::new(&input_path, &plain_path, None)?.pack(&files)?;
+    let report =
+        crate::archive::unpack_sequential(File::open(&plain_path)?, &stream_dir, None, &options)?;
+    assert_eq!(report.files_restored, 1);
+    assert!(stream_dir.join("small.txt").exists());
+
+    // Without a filter the damaged chunk is hit
+    let full_dir = dir.path().join("full");
+    assert!(ArchiveReader::new(&archive_path)?
+        .unpack(&full_dir, None)
+        .is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_reader_chunk_info() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    fs::write(input_path.join("a.txt"), b"first chunk")?;
+
+    let archive_path = dir.path().join("archive.squish");
+    let mut writer = ArchiveWriter::new(&input_path, &archive_path, None)?;
+    writer.pack(&[input_path.join("a.txt")])?;
+
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    let info = reader.chunk_info(0)?;
+    assert_eq!(info.index, 0);
+    assert_eq!(info.hash, crate::util::chunk::hash_chunk(b"first chunk"));
+    assert!(info.decompresses);
+    assert!(info.hash_matches);
+
+    assert!(matches!(reader.chunk_info(1), Err(AppError::Archive(_))));
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_reader_layout() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    fs::write(input_path.join("a.txt"), b"first chunk")?;
+
+    let archive_path = dir.path().join("archive.squish");
+    let mut writer = ArchiveWriter::new(&input_path, &archive_path, None)?;
+    writer.pack(&[input_path.join("a.txt")])?;
+
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    let layout = reader.layout();
+    let names: Vec<&str> = layout.iter().map(|section| section.name).collect();
+    assert_eq!(
+        names,
+        [
+            "header",
+            "timestamp",
+            "archive size",
+            "chunk count",
+            "top-level entry count",
+            "chunk table",
+            "file count",
+            "file table",
+            "chunk index"
+        ]
+    );
+
+    // Sections are contiguous and cover the whole file
+    assert_eq!(layout[0].offset, 0);
+    for pair in layout.windows(2) {
+        assert_eq!(pair[0].end(), pair[1].offset);
+    }
+    let bytes = fs::read(&archive_path)?;
+    assert_eq!(layout[8].end(), bytes.len() as u64);
+
+    // The recorded size is the length of the whole file
+    let size = &bytes[layout[2].offset as usize..layout[2].end() as usize];
+    assert_eq!(
+        u64::from_le_bytes(size.try_into().unwrap()),
+        bytes.len() as u64
+    );
+
+    // One index entry of a hash, an offset and a size, then the trailer
+    assert_eq!(layout[8].length, 16 + 8 + 8 + 24);
+
+    // The one chunk entry is a hash, two sizes and its data
+    let info = reader.chunk_info(0)?;
+    assert_eq!(layout[5].length, 16 + 8 + 8 + info.compressed_size);
+
+    let file_count = &bytes[layout[6].offset as usize..layout[6].end() as usize];
+    assert_eq!(u32::from_le_bytes(file_count.try_into().unwrap()), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_fingerprints() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir_all(input_path.join("sub"))?;
+    fs::write(input_path.join("a.txt"), b"alpha")?;
+    fs::write(input_path.join("sub").join("b.txt"), b"beta")?;
+
+    // Pack the same content in different orders, so chunk tables differ
+    let mut archive_paths = Vec::new();
+    for run in 0..2 {
+        let mut files = crate::fsutil::directory::walk_dir(&input_path)?;
+        files.sort();
+        if run == 1 {
+            files.reverse();
+        }
+
+        let archive_path = dir.path().join(format!("archive{run}.squish"));
+        let mut writer = ArchiveWriter::new(&input_path, &archive_path, None)?;
+        writer.pack(&files)?;
+        archive_paths.push(archive_path);
+    }
+
+    let first = ArchiveReader::new(&archive_paths[0])?.content_fingerprint()?;
+    let second = ArchiveReader::new(&archive_paths[1])?.content_fingerprint()?;
+    assert_eq!(first, second);
+    assert_ne!(
+        raw_fingerprint(&archive_paths[0])?,
+        raw_fingerprint(&archive_paths[1])?
+    );
+
+    fs::copy(&archive_paths[0], dir.path().join("copy.squish"))?;
+    assert_eq!(
+        raw_fingerprint(&archive_paths[0])?,
+        raw_fingerprint(&dir.path().join("copy.squish"))?
+    );
+
+    // Changing any file changes the content fingerprint
+    fs::write(input_path.join("a.txt"), b"alpha!")?;
+    let files = crate::fsutil::directory::walk_dir(&input_path)?;
+    let changed_path = dir.path().join("changed.squish");
+    let mut writer = ArchiveWriter::new(&input_path, &changed_path, None)?;
+    writer.pack(&files)?;
+    assert_ne!(
+        ArchiveReader::new(&changed_path)?.content_fingerprint()?,
+        first
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_unpack_sequential_from_non_seekable_reader() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let archive_path = dir.path().join("dummy.squish");
+
+    let mut file = File::create(&archive_path)?;
+    let files = create_dummy_archive(&mut file)?;
+    file.flush()?;
+
+    // `Chain` implements Read but not Seek, like a pipe
+    let bytes = fs::read(&archive_path)?;
+    let reader = bytes.as_slice().chain(&[][..]);
+
+    let output_dir = dir.path().join("output");
+    crate::archive::unpack_sequential(reader, &output_dir, None, &UnpackOptions::default())?;
+
+    for (filename, contents) in files {
+        assert_eq!(fs::read(output_dir.join(filename))?, contents);
+    }
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_archive_writer_records_size_mismatch() -> Result<(), AppError> {
+    // procfs reports a size of zero but yields content, like a file growing after it was opened
+    let input_path = Path::new("/proc/self");
+    let file_path = input_path.join("status");
+
+    let dir = tempdir()?;
+    let archive_path = dir.path().join("archive.squish");
+    let mut writer = ArchiveWriter::new(input_path, &archive_path, None)?;
+    writer.pack(std::slice::from_ref(&file_path))?;
+
+    let mismatches = writer.size_mismatches();
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0].path, "status");
+    assert_eq!(mismatches[0].expected, 0);
+    assert!(mismatches[0].actual > 0);
+
+    // The stored size must match the bytes that were actually archived
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    let summary = reader.get_summary()?;
+    assert_eq!(summary.files[0].original_size, mismatches[0].actual);
+
+    // The content hash covers the same bytes as the stored chunks
+    let options = UnpackOptions {
+        verify: true,
+        ..Default::default()
+    };
+    reader.unpack_with_options(&dir.path().join("output"), None, &options)?;
+
+    let strict_path = dir.path().join("strict.squish");
+    let options = PackOptions {
+        strict: true,
+        ..Default::default()
+    };
+    let mut writer = ArchiveWriter::with_options(input_path, &strict_path, None, options)?;
+    let result = writer.pack(&[file_path]);
+    assert!(matches!(result, Err(AppError::FileSizeChanged(..))));
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_writer_balance_splits_large_files() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+
+    // Larger than one balance segment and not chunk-aligned, plus a few small files
+    let data: Vec<u8> = (0..(8 * 2048 * 1024 + 3 * 1024 * 1024 + 17) as u32)
+        .map(|i| (i % 239) as u8)
+        .collect();
+    fs::write(input_path.join("huge.bin"), &data)?;
+    for i in 0..5 {
+        fs::write(
+            input_path.join(format!("small{i}.txt")),
+            format!("small {i}"),
+        )?;
+    }
+
+    let files = crate::fsutil::directory::walk_dir(&input_path)?;
+    let mut archives = Vec::new();
+    for balance in [false, true] {
+        let archive_path = dir.path().join(format!("archive_{balance}.squish"));
+        let options = PackOptions {
+            balance,
+            ..Default::default()
+        };
+        let mut writer = ArchiveWriter::with_options(&input_path, &archive_path, None, options)?;
+        writer.pack(&files)?;
+        archives.push(archive_path);
+    }
+
+    // Same chunk sequences either way, and the balanced archive restores correctly
+    let plain = ArchiveReader::new(&archives[0])?.content_fingerprint()?;
+    let balanced = ArchiveReader::new(&archives[1])?.content_fingerprint()?;
+    assert_eq!(plain, balanced);
+
+    let mut reader = ArchiveReader::new(&archives[1])?;
+    let mut out = Vec::new();
+    reader.extract_file("huge.bin", &mut out)?;
+    assert_eq!(out, data);
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_writer_reuses_exported_chunk_pool() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let base_path = dir.path().join("base");
+    let input_path = dir.path().join("input");
+    fs::create_dir(&base_path)?;
+    fs::create_dir(&input_path)?;
+
+    // A multi-chunk file shared by both trees, plus one novel file
+    let shared: Vec<u8> = (0..5 * 1024 * 1024u32).map(|i| (i % 233) as u8).collect();
+    fs::write(base_path.join("shared.bin"), &shared)?;
+    fs::write(input_path.join("shared.bin"), &shared)?;
+    fs::write(input_path.join("novel.txt"), b"only in the new tree")?;
+
+    let base_archive = dir.path().join("base.squish");
+    let files = crate::fsutil::directory::walk_dir(&base_path)?;
+    ArchiveWriter::new(&base_path, &base_archive, None)?.pack(&files)?;
+
+    let pool_path = dir.path().join("chunks.pack");
+    let exported =
+        ArchiveReader::new(&base_archive)?.export_chunks(&mut File::create(&pool_path)?)?;
+    assert_eq!(
+        crate::archive::pool::read_pool_hashes(&pool_path)?.len() as u64,
+        exported
+    );
+
+    let files = crate::fsutil::directory::walk_dir(&input_path)?;
+    let full_archive = dir.path().join("full.squish");
+    let full_size = ArchiveWriter::new(&input_path, &full_archive, None)?.pack(&files)?;
+
+    let thin_archive = dir.path().join("thin.squish");
+    let options = PackOptions {
+        chunk_pool: Some(pool_path.clone()),
+        ..Default::default()
+    };
+    let thin_size =
+        ArchiveWriter::with_options(&input_path, &thin_archive, None, options)?.pack(&files)?;
+    assert!(thin_size < full_size);
+
+    // Only the novel chunk is stored; the rest comes from the pool at unpack time
+    let mut reader = ArchiveReader::new(&thin_archive)?;
+    assert_eq!(reader.get_summary()?.unique_chunks, 1);
+
+    let output_dir = dir.path().join("output");
+    let options = UnpackOptions {
+        chunk_pool: Some(pool_path),
+        ..Default::default()
+    };
+    reader.unpack_with_options(&output_dir, None, &options)?;
+    assert_eq!(fs::read(output_dir.join("shared.bin"))?, shared);
+    assert_eq!(
+        fs::read(output_dir.join("novel.txt"))?,
+        b"only in the new tree"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_roundtrip_with_raw_chunks() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+
+    // xorshift output does not compress, so every chunk is stored raw
+    let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+    let noise: Vec<u8> = (0..3 * 1024 * 1024)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect();
+    fs::write(input_path.join("noise.bin"), &noise)?;
+    fs::write(input_path.join("text.txt"), "compressible ".repeat(1000))?;
+
+    let files = crate::fsutil::directory::walk_dir(&input_path)?;
+    let archive_path = dir.path().join("archive.squish");
+    ArchiveWriter::new(&input_path, &archive_path, None)?.pack(&files)?;
+
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    let raw_chunks = (0..reader.get_summary()?.unique_chunks)
+        .filter(|index| reader.chunk_info(*index).is_ok_and(|info| info.stored_raw))
+        .count();
+    assert_eq!(raw_chunks, 2);
+
+    let output_dir = dir.path().join("output");
+    reader.unpack(&output_dir, None)?;
+    assert_eq!(fs::read(output_dir.join("noise.bin"))?, noise);
+    assert_eq!(
+        fs::read_to_string(output_dir.join("text.txt"))?,
+        "compressible ".repeat(1000)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_writer_keep_partial_on_failure() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    fs::write(input_path.join("present.txt"), b"here")?;
+
+    let output_path = dir.path().join("archive.squish");
+    let options = PackOptions {
+        keep_partial: true,
+        ..Default::default()
+    };
+    let mut writer = ArchiveWriter::with_options(&input_path, &output_path, None, options)?;
+
+    // The second file does not exist, so packing fails part way
+    let result = writer.pack(&[
+        input_path.join("present.txt"),
+        input_path.join("missing.txt"),
+    ]);
+    assert!(result.is_err());
+
+    assert!(!output_path.exists());
+    assert!(dir.path().join("archive.squish.partial").exists());
+
+    let log = fs::read_to_string(dir.path().join("archive.squish.partial.log"))?;
+    assert!(log.contains("files processed:"));
+    assert!(log.contains("chunks stored:"));
+    assert!(log.contains("error:"));
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_reader_shared_chunks() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    let contents: [(&str, &[u8]); 6] = [
+        ("a.txt", b"common"),
+        ("b.txt", b"common"),
+        ("c.txt", b"common"),
+        ("d.txt", b"alone"),
+        ("e.txt", b"pair"),
+        ("f.txt", b"pair"),
+    ];
+    let mut files = Vec::new();
+    for (name, data) in contents {
+        fs::write(input_path.join(name), data)?;
+        files.push(input_path.join(name));
+    }
+
+    let archive_path = dir.path().join("archive.squish");
+    ArchiveWriter::new(&input_path, &archive_path, None)?.pack(&files)?;
+
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    let shared = reader.shared_chunks(10)?;
+    assert_eq!(shared.len(), 2);
+    assert_eq!(shared[0].hash, crate::util::chunk::hash_chunk(b"common"));
+    assert_eq!(shared[0].size, 6);
+    assert_eq!(shared[0].files, ["a.txt", "b.txt", "c.txt"]);
+    assert_eq!(shared[1].files, ["e.txt", "f.txt"]);
+
+    // The cap keeps only the most shared
+    assert_eq!(reader.shared_chunks(1)?.len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_reader_space_report() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    fs::write(input_path.join("a.txt"), b"packed normally")?;
+
+    let archive_path = dir.path().join("archive.squish");
+    let mut writer = ArchiveWriter::new(&input_path, &archive_path, None)?;
+    writer.pack(&[input_path.join("a.txt")])?;
+
+    let report = ArchiveReader::new(&archive_path)?.space_report()?;
+    assert_eq!(report.on_disk_bytes, fs::metadata(&archive_path)?.len());
+    assert!(report.live_chunk_bytes > 0);
+    assert_eq!(report.orphan_count, 0);
+    assert_eq!(report.orphan_chunk_bytes, 0);
+
+    let mut orphaned = NamedTempFile::new()?;
+    let orphan_entry_size = create_archive_with_orphan(&mut orphaned)?;
+    orphaned.flush()?;
+
+    let report = ArchiveReader::new(orphaned.path())?.space_report()?;
+    assert_eq!(report.orphan_count, 1);
+    assert_eq!(report.orphan_chunk_bytes, orphan_entry_size);
+    assert!(report.live_chunk_bytes > 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_reader_compact_into() -> Result<(), AppError> {
+    let dir = tempdir()?;
+
+    let mut orphaned = NamedTempFile::new()?;
+    create_archive_with_orphan(&mut orphaned)?;
+    orphaned.flush()?;
+
+    let compact_path = dir.path().join("compact.squish");
+    let dropped =
+        ArchiveReader::new(orphaned.path())?.compact_into(&mut File::create(&compact_path)?)?;
+    assert_eq!(dropped, 1);
+
+    let mut compacted = ArchiveReader::new(&compact_path)?;
+    let report = compacted.space_report()?;
+    assert_eq!(report.orphan_count, 0);
+    assert_eq!(compacted.get_summary()?.unique_chunks, 1);
+
+    let original_out = dir.path().join("original");
+    let compact_out = dir.path().join("compacted");
+    ArchiveReader::new(orphaned.path())?.unpack(&original_out, None)?;
+    compacted.unpack(&compact_out, None)?;
+    assert_eq!(
+        fs::read(original_out.join("file1.txt"))?,
+        fs::read(compact_out.join("file1.txt"))?
+    );
+
+    // Compacting an archive without orphans reproduces it exactly
+    let recompact_path = dir.path().join("recompact.squish");
+    let dropped =
+        ArchiveReader::new(&compact_path)?.compact_into(&mut File::create(&recompact_path)?)?;
+    assert_eq!(dropped, 0);
+    assert_eq!(fs::read(&compact_path)?, fs::read(&recompact_path)?);
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_chunk_index() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    fs::write(input_path.join("a.txt"), "indexed ".repeat(4096))?;
+    fs::write(input_path.join("b.txt"), b"second")?;
+
+    let archive_path = dir.path().join("archive.squish");
+    let files = crate::fsutil::directory::walk_dir(&input_path)?;
+    let options = PackOptions {
+        chunk_size: 1024,
+        ..Default::default()
+    };
+    ArchiveWriter::with_options(&input_path, &archive_path, None, options)?.pack(&files)?;
+
+    let index_length = |path: &Path| -> Result<u64, AppError> {
+        Ok(ArchiveReader::new(path)?
+            .layout()
+            .iter()
+            .find(|section| section.name == "chunk index")
+            .map_or(0, |section| section.length))
+    };
+    let unpacked = |path: &Path, name: &str| -> Result<Vec<u8>, AppError> {
+        let output_dir = dir.path().join(name);
+        ArchiveReader::new(path)?.unpack(&output_dir, None)?;
+        Ok(fs::read(output_dir.join("a.txt"))?)
+    };
+    assert!(index_length(&archive_path)? > 0);
+    assert_eq!(
+        unpacked(&archive_path, "indexed")?,
+        fs::read(input_path.join("a.txt"))?
+    );
+
+    // Without the footer or a recorded size the archive reads like one written before the
+    // index existed
+    let bytes = fs::read(&archive_path)?;
+    let size_position = ArchiveReader::new(&archive_path)?
+        .archive_size_position()
+        .unwrap() as usize;
+    let mut stripped = bytes[..bytes.len() - index_length(&archive_path)? as usize].to_vec();
+    stripped[size_position..size_position + 8].fill(0);
+    let stripped_path = dir.path().join("stripped.squish");
+    fs::write(&stripped_path, &stripped)?;
+    assert_eq!(index_length(&stripped_path)?, 0);
+    assert_eq!(
+        unpacked(&stripped_path, "stripped")?,
+        unpacked(&archive_path, "again")?
+    );
+
+    // A damaged marker is ignored rather than trusted
+    let mut damaged = bytes.clone();
+    *damaged.last_mut().unwrap() ^= 0xff;
+    let damaged_path = dir.path().join("damaged.squish");
+    fs::write(&damaged_path, &damaged)?;
+    assert_eq!(index_length(&damaged_path)?, 0);
+    ArchiveReader::new(&damaged_path)?.verify_integrity(None, None)?;
+
+    // Compacting rebuilds the index and renaming carries it past the new file table
+    let compact_path = dir.path().join("compact.squish");
+    ArchiveReader::new(&archive_path)?.compact_into(&mut File::create(&compact_path)?)?;
+    assert_eq!(fs::read(&compact_path)?, bytes);
+
+    rename_entries(
+        &archive_path,
+        &[("a.txt".into(), "a much longer name.txt".into())],
+        None,
+    )?;
+    assert_eq!(index_length(&archive_path)?, index_length(&compact_path)?);
+    let renamed_dir = dir.path().join("renamed");
+    ArchiveReader::new(&archive_path)?.unpack(&renamed_dir, None)?;
+    assert_eq!(
+        fs::read(renamed_dir.join("a much longer name.txt"))?,
+        fs::read(input_path.join("a.txt"))?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_writer_sync_interval_with_fsync() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    fs::write(input_path.join("a.txt"), b"first")?;
+    fs::write(input_path.join("b.txt"), b"second")?;
+
+    let archive_path = dir.path().join("archive.squish");
+    let options = PackOptions {
+        sync_interval: Some(SyncInterval::Chunks(1)),
+        fsync: true,
+        ..Default::default()
+    };
+    let mut writer = ArchiveWriter::with_options(&input_path, &archive_path, None, options)?;
+    writer.pack(&[input_path.join("a.txt"), input_path.join("b.txt")])?;
+
+    let output_dir = dir.path().join("output");
+    ArchiveReader::new(&archive_path)?.unpack(&output_dir, None)?;
+    assert_eq!(fs::read(output_dir.join("a.txt"))?, b"first");
+    assert_eq!(fs::read(output_dir.join("b.txt"))?, b"second");
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_writer_preallocate_trims_to_written_size() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    fs::write(input_path.join("a.txt"), "compressible ".repeat(4096))?;
+    fs::write(input_path.join("b.txt"), b"second")?;
+    let files = [input_path.join("a.txt"), input_path.join("b.txt")];
+
+    let plain_path = dir.path().join("plain.squish");
+    let plain_size = ArchiveWriter::new(&input_path, &plain_path, None)?.pack(&files)?;
+
+    let archive_path = dir.path().join("preallocated.squish");
+    let options = PackOptions {
+        preallocate_archive: true,
+        ..Default::default()
+    };
+    let mut writer = ArchiveWriter::with_options(&input_path, &archive_path, None, options)?;
+    let size = writer.pack(&files)?;
+
+    // The reserved space beyond the file table is given back
+    assert_eq!(size, plain_size);
+    assert_eq!(fs::metadata(&archive_path)?.len(), plain_size);
+
+    let output_dir = dir.path().join("output");
+    ArchiveReader::new(&archive_path)?.unpack(&output_dir, None)?;
+    assert_eq!(fs::read(output_dir.join("a.txt"))?, fs::read(&files[0])?);
+    assert_eq!(fs::read(output_dir.join("b.txt"))?, b"second");
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_writer_rate_limit() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    fs::write(input_path.join("a.txt"), b"first")?;
+    fs::write(input_path.join("b.txt"), b"second")?;
+    let files = [input_path.join("a.txt"), input_path.join("b.txt")];
+
+    // Both the writer thread and the reproducible spool copy are throttled
+    for reproducible in [false, true] {
+        let archive_path = dir.path().join(format!("archive-{reproducible}.squish"));
+        let options = PackOptions {
+            rate_limit: Some(1024 * 1024),
+            reproducible,
+            ..Default::default()
+        };
+        ArchiveWriter::with_options(&input_path, &archive_path, None, options)?.pack(&files)?;
+
+        let output_dir = dir.path().join(format!("output-{reproducible}"));
+        ArchiveReader::new(&archive_path)?.unpack(&output_dir, None)?;
+        assert_eq!(fs::read(output_dir.join("a.txt"))?, b"first");
+        assert_eq!(fs::read(output_dir.join("b.txt"))?, b"second");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_writer_provenance() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    fs::write(input_path.join("a.txt"), b"first")?;
+
+    let archive_path = dir.path().join("archive.squish");
+    let options = PackOptions {
+        provenance: true,
+        ..Default::default()
+    };
+    ArchiveWriter::with_options(&input_path, &archive_path, None, options)?
+        .pack(&[input_path.join("a.txt")])?;
+
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    let provenance = reader.provenance().cloned().expect("provenance recorded");
+    assert_eq!(provenance.tool_version.as_deref(), Some(VERSION));
+    assert_eq!(reader.get_summary()?.provenance, Some(provenance));
+    assert!(reader
+        .layout()
+        .iter()
+        .any(|section| section.name == "provenance" && section.length > 0));
+
+    let output_dir = dir.path().join("output");
+    reader.unpack(&output_dir, None)?;
+    assert_eq!(fs::read(output_dir.join("a.txt"))?, b"first");
+
+    // Streaming readers skip the block too
+    let stream_dir = dir.path().join("stream");
+    crate::archive::unpack_sequential(
+        File::open(&archive_path)?,
+        &stream_dir,
+        None,
+        &UnpackOptions::default(),
+    )?;
+    assert_eq!(fs::read(stream_dir.join("a.txt"))?, b"first");
+    assert!(check_compatibility(&archive_path).is_ok());
+
+    // Without the flag nothing is recorded
+    let plain_path = dir.path().join("plain.squish");
+    ArchiveWriter::new(&input_path, &plain_path, None)?.pack(&[input_path.join("a.txt")])?;
+    assert!(ArchiveReader::new(&plain_path)?.provenance().is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_writer_file_dedup_scope() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    fs::write(input_path.join("a.txt"), b"same secret")?;
+    fs::write(input_path.join("b.txt"), b"same secret")?;
+    let files = [input_path.join("a.txt"), input_path.join("b.txt")];
+
+    // Globally the two files share their only chunk
+    let global_path = dir.path().join("global.squish");
+    ArchiveWriter::new(&input_path, &global_path, None)?.pack(&files)?;
+    assert_eq!(
+        ArchiveReader::new(&global_path)?
+            .get_summary()?
+            .unique_chunks,
+        1
+    );
+
+    let archive_path = dir.path().join("scoped.squish");
+    let options = PackOptions {
+        dedup_scope: DedupScope::File,
+        ..Default::default()
+    };
+    ArchiveWriter::with_options(&input_path, &archive_path, None, options)?.pack(&files)?;
+
+    // Scoped to files, each file stores its own copy under an unrelated hash
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    assert_eq!(reader.get_summary()?.unique_chunks, 2);
+    let hashes: HashSet<ChunkHash> = reader
+        .file_table()?
+        .into_iter()
+        .flat_map(|entry| entry.chunk_hashes)
+        .collect();
+    assert_eq!(hashes.len(), 2);
+
+    // Each chunk hashes back within the scope of the file holding it
+    assert!(reader.chunk_info(0)?.hash_matches);
+    assert!(reader.chunk_info(1)?.hash_matches);
+
+    let output_dir = dir.path().join("output");
+    reader.unpack(&output_dir, None)?;
+    assert_eq!(fs::read(output_dir.join("a.txt"))?, b"same secret");
+    assert_eq!(fs::read(output_dir.join("b.txt"))?, b"same secret");
+
+    let report = reader.verify_against(&input_path)?;
+    assert_eq!(report.matched.len(), 2);
+    assert!(report.mismatched.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_reader_zero_fill_missing_chunks() -> Result<(), AppError> {
+    let dir = tempdir()?;
+
+    // One file whose first chunk is absent from the chunk table
+    let mut archive = NamedTempFile::new()?;
+    write_header(&mut archive)?;
+    write_timestamp(&mut archive)?;
+    let chunk_count_pos = write_placeholder_u64(&mut archive)?;
+
+    let compressed = zstd::encode_all(Cursor::new(b"tail"), 0)?;
+    archive.write_all(&[1u8; 16])?;
+    archive.write_all(&4u64.to_le_bytes())?;
+    archive.write_all(&(compressed.len() as u64).to_le_bytes())?;
+    archive.write_all(&compressed)?;
+    patch_u64(&mut archive, chunk_count_pos, 1)?;
+
+    archive.write_all(&1u32.to_le_bytes())?;
+    archive.write_all(&9u32.to_le_bytes())?;
+    archive.write_all(b"video.mp4")?;
+    archive.write_all(&(CHUNK_SIZE as u64 + 4).to_le_bytes())?;
+    archive.write_all(&2u32.to_le_bytes())?;
+    archive.write_all(&[9u8; 16])?;
+    archive.write_all(&[1u8; 16])?;
+    archive.flush()?;
+
+    let result = ArchiveReader::new(archive.path())?.unpack(&dir.path().join("strict"), None);
+    assert!(matches!(result, Err(AppError::MissingChunk(_))));
+
+    let output_dir = dir.path().join("salvaged");
+    let options = UnpackOptions {
+        zero_fill_missing: true,
+        ..Default::default()
+    };
+    let report =
+        ArchiveReader::new(archive.path())?.unpack_with_options(&output_dir, None, &options)?;
+
+    assert_eq!(
+        report.partially_recovered,
+        vec![PartialRecovery {
+            path: "video.mp4".to_string(),
+            missing_chunks: 1,
+            zero_filled_bytes: CHUNK_SIZE as u64,
+        }]
+    );
+
+    let restored = fs::read(output_dir.join("video.mp4"))?;
+    assert_eq!(restored.len(), CHUNK_SIZE + 4);
+    assert!(restored[..CHUNK_SIZE].iter().all(|&byte| byte == 0));
+    assert_eq!(&restored[CHUNK_SIZE..], b"tail");
+
+    Ok(())
+}
+
+#[test]
+fn test_check_compatibility() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    fs::write(input_path.join("a.txt"), b"compatible")?;
+
+    let archive_path = dir.path().join("archive.squish");
+    let mut writer = ArchiveWriter::new(&input_path, &archive_path, None)?;
+    writer.pack(&[input_path.join("a.txt")])?;
+    assert_eq!(check_compatibility(&archive_path)?, VERSION);
+
+    // Same layout, but from a future major version
+    let mut future = fs::read(&archive_path)?;
+    future[b"squish".len()] = b'9';
+    let future_path = dir.path().join("future.squish");
+    fs::write(&future_path, future)?;
+    assert!(matches!(
+        check_compatibility(&future_path),
+        Err(AppError::Unsupported(reason)) if reason.contains("Incompatible version")
+    ));
+
+    let truncated_path = dir.path().join("truncated.squish");
+    fs::write(&truncated_path, b"squ")?;
+    assert!(matches!(
+        check_compatibility(&truncated_path),
+        Err(AppError::Unsupported(_))
+    ));
+
+    assert!(matches!(
+        check_compatibility(&dir.path().join("missing.squish")),
+        Err(AppError::FileNotExist(_))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_reader_unpack_with_decompress_threads() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+
+    let files: Vec<PathBuf> = (0..5u8)
+        .map(|i| {
+            let path = input_path.join(format!("file{i}.txt"));
+            fs::write(&path, vec![i; 1000 + i as usize]).unwrap();
+            path
+        })
+        .collect();
+
+    let archive_path = dir.path().join("archive.squish");
+    let mut writer = ArchiveWriter::new(&input_path, &archive_path, None)?;
+    writer.pack(&files)?;
+
+    for threads in [1, 3] {
+        let output_dir = dir.path().join(format!("output{threads}"));
+        let options = UnpackOptions {
+            decompress_threads: Some(threads),
+            ..Default::default()
+        };
+        ArchiveReader::new(&archive_path)?.unpack_with_options(&output_dir, None, &options)?;
+
+        for i in 0..5u8 {
+            let restored = fs::read(output_dir.join(format!("file{i}.txt")))?;
+            assert_eq!(restored, vec![i; 1000 + i as usize]);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_read_chunk_table_parallel_matches_sequential() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    let data: Vec<u8> = (0..40 * 1024u32).map(|i| (i * 7 % 253) as u8).collect();
+    fs::write(input_path.join("data.bin"), &data)?;
+
+    let archive_path = dir.path().join("archive.squish");
+    let options = PackOptions {
+        chunk_size: 1024,
+        ..Default::default()
+    };
+    ArchiveWriter::with_options(&input_path, &archive_path, None, options)?
+        .pack(&[input_path.join("data.bin")])?;
+
+    let reader = ArchiveReader::new(&archive_path)?;
+    let chunk_table = reader
+        .layout()
+        .into_iter()
+        .find(|section| section.name == "chunk table")
+        .unwrap();
+    let number_of_chunks = 40;
+
+    let read_with = |threads: usize| -> Result<HashMap<ChunkHash, Vec<u8>>, AppError> {
+        let mut file = File::open(&archive_path)?;
+        file.seek(SeekFrom::Start(chunk_table.offset))?;
+        read_chunk_table(&mut file, number_of_chunks, None, Some(threads), None)
+    };
+
+    // One thread decompresses every chunk in turn; more split each batch across the pool
+    let sequential = read_with(1)?;
+    assert_eq!(sequential.len(), number_of_chunks as usize);
+    assert_eq!(sequential.values().map(Vec::len).sum::<usize>(), data.len());
+    for threads in [2, 4, 7] {
+        assert_eq!(read_with(threads)?, sequential, "{threads} threads");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_rename_entries() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir_all(input_path.join("docs"))?;
+    fs::write(input_path.join("docs/report.txt"), b"report")?;
+    fs::write(input_path.join("notes.txt"), b"notes")?;
+
+    let archive_path = dir.path().join("archive.squish");
+    let mut writer = ArchiveWriter::new(&input_path, &archive_path, None)?;
+    writer.pack(&[
+        input_path.join("docs/report.txt"),
+        input_path.join("notes.txt"),
+    ])?;
+
+    assert!(matches!(
+        rename_entries(
+            &archive_path,
+            &[("notes.txt".into(), "docs/report.txt".into())],
+            None
+        ),
+        Err(AppError::FileExistsInArchive(_))
+    ));
+    assert!(matches!(
+        rename_entries(
+            &archive_path,
+            &[("notes.txt".into(), "../escape.txt".into())],
+            None
+        ),
+        Err(AppError::UnsafePath(_))
+    ));
+    assert!(matches!(
+        rename_entries(
+            &archive_path,
+            &[("missing.txt".into(), "new.txt".into())],
+            None
+        ),
+        Err(AppError::FileNotFoundInArchive(_))
+    ));
+
+    // The second rename reuses the path freed by the first
+    let renames = parse_rename_map(
+        "# reorganise\ndocs/report.txt\tarchive/2025/report.txt\n\nnotes.txt\tdocs/report.txt\n",
+    )?;
+    assert_eq!(renames.len(), 2);
+    rename_entries(&archive_path, &renames, None)?;
+
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    assert_eq!(reader.top_level_entries(), 2);
+
+    let output_dir = dir.path().join("output");
+    reader.unpack(&output_dir, None)?;
+    assert_eq!(
+        fs::read(output_dir.join("archive/2025/report.txt"))?,
+        b"report"
+    );
+    assert_eq!(fs::read(output_dir.join("docs/report.txt"))?, b"notes");
+    assert!(!output_dir.join("notes.txt").exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_reader_extract_range() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+
+    // Three chunks, the last one short
+    let contents: Vec<u8> = (0..2 * CHUNK_SIZE + 100).map(|i| (i % 251) as u8).collect();
+    fs::write(input_path.join("big.log"), &contents)?;
+
+    let archive_path = dir.path().join("archive.squish");
+    let mut writer = ArchiveWriter::new(&input_path, &archive_path, None)?;
+    writer.pack(&[input_path.join("big.log")])?;
+
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    let size = contents.len() as u64;
+    let boundary = CHUNK_SIZE as u64;
+    for (start, end) in [
+        (0, Some(64)),
+        (boundary - 10, Some(boundary + 10)),
+        (10, Some(size)),
+        (2 * boundary + 50, None),
+        (5, Some(5)),
+    ] {
+        let mut out = Vec::new();
+        let written = reader.extract_range("big.log", start, end, &mut out)?;
+        let end = end.unwrap_or(size);
+        assert_eq!(written, end - start);
+        assert_eq!(out, contents[start as usize..end as usize]);
+    }
+
+    assert!(matches!(
+        reader.extract_range("big.log", 0, Some(size + 1), &mut Vec::new()),
+        Err(AppError::InvalidRange(..))
+    ));
+    assert!(matches!(
+        reader.extract_range("big.log", 20, Some(10), &mut Vec::new()),
+        Err(AppError::InvalidRange(..))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_unpack_streaming_matches_in_memory() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir_all(input_path.join("copies"))?;
+
+    // Several files share chunks, so the streaming path goes through its cache
+    let data: Vec<u8> = (0..5 * 1024 * 1024u32).map(|i| (i % 241) as u8).collect();
+    fs::write(input_path.join("original.bin"), &data)?;
+    for i in 0..3 {
+        fs::write(input_path.join(format!("copies/{i}.bin")), &data)?;
+    }
+    fs::write(input_path.join("small.txt"), b"not shared")?;
+
+    let archive_path = dir.path().join("archive.squish");
+    let files = crate::fsutil::directory::walk_dir(&input_path)?;
+    ArchiveWriter::new(&input_path, &archive_path, None)?.pack(&files)?;
+
+    for in_memory in [false, true] {
+        let output_dir = dir.path().join(format!("out-{in_memory}"));
+        let options = UnpackOptions {
+            in_memory,
+            ..Default::default()
+        };
+        let report =
+            ArchiveReader::new(&archive_path)?.unpack_with_options(&output_dir, None, &options)?;
+        assert_eq!(report.files_restored, 5);
+
+        assert_eq!(fs::read(output_dir.join("original.bin"))?, data);
+        for i in 0..3 {
+            assert_eq!(fs::read(output_dir.join(format!("copies/{i}.bin")))?, data);
+        }
+        assert_eq!(fs::read(output_dir.join("small.txt"))?, b"not shared");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_chunk_cache_evicts_least_recently_used() {
+    let mut cache = super::lazy::ChunkCache::new(2);
+    cache.insert([1u8; 16], b"one".to_vec());
+    cache.insert([2u8; 16], b"two".to_vec());
+
+    // Touching the first chunk makes the second the eviction candidate
+    assert_eq!(cache.get(&[1u8; 16]), Some(b"one".to_vec()));
+    cache.insert([3u8; 16], b"three".to_vec());
+
+    assert_eq!(cache.get(&[2u8; 16]), None);
+    assert_eq!(cache.get(&[1u8; 16]), Some(b"one".to_vec()));
+    assert_eq!(cache.get(&[3u8; 16]), Some(b"three".to_vec()));
+}
+
+#[test]
+fn test_verify_integrity_detects_damage() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    fs::write(
+        input_path.join("notes.txt"),
+        b"integrity check ".repeat(512),
+    )?;
+
+    let archive_path = dir.path().join("archive.squish");
+    let files = crate::fsutil::directory::walk_dir(&input_path)?;
+    ArchiveWriter::new(&input_path, &archive_path, None)?.pack(&files)?;
+
+    let report = ArchiveReader::new(&archive_path)?.verify_integrity(None, None)?;
+    assert_eq!(report.chunks_verified, 1);
+    assert_eq!(report.files_checked, 1);
+
+    // Flip the last byte of the only chunk's compressed data
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    let info = reader.chunk_info(0)?;
+    let mut bytes = fs::read(&archive_path)?;
+    bytes[(info.offset + info.compressed_size - 1) as usize] ^= 0xff;
+    fs::write(&archive_path, &bytes)?;
+
+    let result = ArchiveReader::new(&archive_path)?.verify_integrity(None, None);
+    assert!(matches!(result, Err(AppError::CorruptChunk(_, 0, _))));
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_paths_use_forward_slashes() -> Result<(), AppError> {
+    let nested = Path::new("sub").join("file.txt");
+    assert_eq!(archive_path(&nested), "sub/file.txt");
+    assert_eq!(platform_path("sub/file.txt"), nested);
+    #[cfg(windows)]
+    assert_eq!(archive_path(Path::new(r"sub\file.txt")), "sub/file.txt");
+
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir_all(input_path.join("sub"))?;
+    fs::write(input_path.join(&nested), b"nested")?;
+
+    let archive_path = dir.path().join("archive.squish");
+    ArchiveWriter::new(&input_path, &archive_path, None)?.pack(&[input_path.join(&nested)])?;
+
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    assert_eq!(reader.get_summary()?.files[0].path, "sub/file.txt");
+    let output_dir = dir.path().join("output");
+    reader.unpack(&output_dir, None)?;
+    assert_eq!(fs::read(output_dir.join(&nested))?, b"nested");
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_records_partial_last_chunk_size() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    let data: Vec<u8> = (0..2 * 1024 + 100u32).map(|i| (i % 251) as u8).collect();
+    fs::write(input_path.join("odd.bin"), &data)?;
+
+    let archive_path = dir.path().join("archive.squish");
+    let options = PackOptions {
+        chunk_size: 1024,
+        ..Default::default()
+    };
+    ArchiveWriter::with_options(&input_path, &archive_path, None, options)?
+        .pack(&[input_path.join("odd.bin")])?;
+
+    // The trailing chunk records the bytes it holds, not the full chunk size
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    let mut sizes = (0..3)
+        .map(|index| Ok(reader.chunk_info(index)?.original_size))
+        .collect::<Result<Vec<_>, AppError>>()?;
+    sizes.sort();
+    assert_eq!(sizes, [100, 1024, 1024]);
+
+    Ok(())
+}
+
+#[test]
+fn test_remove_entries_drops_orphaned_chunks() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    let shared: Vec<u8> = (0..1024u32).map(|i| (i % 251) as u8).collect();
+    let own: Vec<u8> = (0..1024u32).map(|i| (i % 13) as u8).collect();
+    fs::write(
+        input_path.join("both.bin"),
+        [shared.as_slice(), &own].concat(),
+    )?;
+    fs::write(input_path.join("shared.bin"), &shared)?;
+
+    let archive_path = dir.path().join("archive.squish");
+    let options = PackOptions {
+        chunk_size: 1024,
+        ..Default::default()
+    };
+    ArchiveWriter::with_options(&input_path, &archive_path, None, options)?
+        .pack(&[input_path.join("both.bin"), input_path.join("shared.bin")])?;
+
+    let shared_hash = ArchiveReader::new(&archive_path)?
+        .file_table()?
+        .into_iter()
+        .find(|entry| entry.path == "shared.bin")
+        .unwrap()
+        .chunk_hashes[0];
+
+    assert!(matches!(
+        remove_entries(&archive_path, &["missing.bin".to_string()], None),
+        Err(AppError::FileNotFoundInArchive(_))
+    ));
+    assert_eq!(
+        remove_entries(&archive_path, &["both.bin".to_string()], None)?,
+        1
+    );
+
+    // The shared chunk survives for the remaining file, the one only both.bin used is gone
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    assert_eq!(reader.get_summary()?.unique_chunks, 1);
+    assert_eq!(reader.chunk_info(0)?.hash, shared_hash);
+    let files = reader.file_table()?;
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].path, "shared.bin");
+
+    let output_path = dir.path().join("output");
+    reader.unpack(&output_path, None)?;
+    assert_eq!(fs::read(output_path.join("shared.bin"))?, shared);
+    assert!(!output_path.join("both.bin").exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_unpack_rejects_oversized_chunk() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    fs::write(input_path.join("notes.txt"), b"oversized ".repeat(512))?;
+
+    let archive_path = dir.path().join("archive.squish");
+    let files = crate::fsutil::directory::walk_dir(&input_path)?;
+    ArchiveWriter::new(&input_path, &archive_path, None)?.pack(&files)?;
+
+    // Claim the only chunk decompresses to more than any real chunk could
+    let info = ArchiveReader::new(&archive_path)?.chunk_info(0)?;
+    let size_at = (info.offset - 16) as usize;
+    let mut bytes = fs::read(&archive_path)?;
+    bytes[size_at..size_at + 8].copy_from_slice(&(MAX_CHUNK_SIZE + 1).to_le_bytes());
+    fs::write(&archive_path, &bytes)?;
+
+    let output_dir = dir.path().join("output");
+    for in_memory in [false, true] {
+        let options = UnpackOptions {
+            in_memory,
+            ..Default::default()
+        };
+        let result =
+            ArchiveReader::new(&archive_path)?.unpack_with_options(&output_dir, None, &options);
+        assert!(
+            matches!(result, Err(AppError::ChunkTooLarge(size, MAX_CHUNK_SIZE)) if size == MAX_CHUNK_SIZE + 1)
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_verify_integrity_detects_unknown_chunk() -> Result<(), AppError> {
+    // One file referencing a chunk that is not in the chunk table
+    let mut archive = NamedTempFile::new()?;
+    write_header(&mut archive)?;
+    write_timestamp(&mut archive)?;
+    let chunk_count_pos = write_placeholder_u64(&mut archive)?;
+    patch_u64(&mut archive, chunk_count_pos, 0)?;
+
+    archive.write_all(&1u32.to_le_bytes())?;
+    archive.write_all(&8u32.to_le_bytes())?;
+    archive.write_all(b"lost.bin")?;
+    archive.write_all(&4u64.to_le_bytes())?;
+    archive.write_all(&1u32.to_le_bytes())?;
+    archive.write_all(&[9u8; 16])?;
+    archive.flush()?;
+
+    let result = ArchiveReader::new(archive.path())?.verify_integrity(None, None);
+    match result {
+        Err(AppError::UnknownChunk(path, hash)) => {
+            assert_eq!(path, "lost.bin");
+            assert_eq!(hash, "09".repeat(16));
+        }
+        other => panic!("expected UnknownChunk, got {other:?}"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_hash_seed_round_trips_and_verifies() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    fs::write(
+        input_path.join("report.csv"),
+        b"tenant,bytes\na,1\n".repeat(64),
+    )?;
+    let files = crate::fsutil::directory::walk_dir(&input_path)?;
+
+    let mut first_hashes = Vec::new();
+    for hash_seed in [0, 41, 42] {
+        let archive_path = dir.path().join(format!("seed-{hash_seed}.squish"));
+        let options = PackOptions {
+            hash_seed,
+            ..Default::default()
+        };
+        ArchiveWriter::with_options(&input_path, &archive_path, None, options)?.pack(&files)?;
+
+        let mut reader = ArchiveReader::new(&archive_path)?;
+        assert_eq!(reader.hash_seed(), hash_seed);
+        // debug-chunk re-hashes with the archive's seed, like verify
+        let info = reader.chunk_info(0)?;
+        assert!(info.hash_matches);
+        first_hashes.push(info.hash);
+
+        reader.verify_integrity(None, None)?;
+        assert!(reader.verify_against(&input_path)?.is_clean());
+
+        let output_dir = dir.path().join(format!("out-{hash_seed}"));
+        reader.unpack(&output_dir, None)?;
+        assert_eq!(
+            fs::read(output_dir.join("report.csv"))?,
+            fs::read(input_path.join("report.csv"))?
+        );
+    }
+
+    // Each seed puts the same content in its own hash space
+    assert_ne!(first_hashes[0], first_hashes[1]);
+    assert_ne!(first_hashes[1], first_hashes[2]);
+    assert_ne!(first_hashes[0], first_hashes[2]);
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_preserve_hardlinks_round_trip() -> Result<(), AppError> {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir_all(input_path.join("b"))?;
+    fs::write(input_path.join("a.txt"), "linked ".repeat(512))?;
+    fs::hard_link(input_path.join("a.txt"), input_path.join("b/copy.txt"))?;
+    let files = crate::fsutil::directory::walk_dir(&input_path)?;
+
+    let archive_path = dir.path().join("archive.squish");
+    let options = PackOptions {
+        preserve_hardlinks: true,
+        ..Default::default()
+    };
+    ArchiveWriter::with_options(&input_path, &archive_path, None, options)?.pack(&files)?;
+
+    // Renaming the file that holds the content keeps the link pointing at it
+    rename_entries(&archive_path, &[("a.txt".into(), "main.txt".into())], None)?;
+
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    let sizes: Vec<(String, u64)> = reader
+        .get_summary()?
+        .files
+        .into_iter()
+        .map(|file| (file.path, file.original_size))
+        .collect();
+    assert!(sizes.contains(&("b/copy.txt".to_string(), 0)));
+
+    for in_memory in [false, true] {
+        let output_dir = dir.path().join(format!("out-{in_memory}"));
+        let options = UnpackOptions {
+            in_memory,
+            ..Default::default()
+        };
+        ArchiveReader::new(&archive_path)?.unpack_with_options(&output_dir, None, &options)?;
+
+        let main = fs::metadata(output_dir.join("main.txt"))?;
+        let copy = fs::metadata(output_dir.join("b/copy.txt"))?;
+        assert_eq!(main.ino(), copy.ino());
+        assert_eq!(
+            fs::read(output_dir.join("b/copy.txt"))?,
+            "linked ".repeat(512).as_bytes()
+        );
+    }
+
+    // Extracting only the link has nothing to link to
+    let options = UnpackOptions {
+        file: Some("b/copy.txt".to_string()),
+        ..Default::default()
+    };
+    let result = ArchiveReader::new(&archive_path)?.unpack_with_options(
+        &dir.path().join("single"),
+        None,
+        &options,
+    );
+    assert!(matches!(result, Err(AppError::Archive(_))));
+
+    // Without the option both paths are stored as regular files
+    let plain_path = dir.path().join("plain.squish");
+    ArchiveWriter::new(&input_path, &plain_path, None)?.pack(&files)?;
+    let plain_out = dir.path().join("plain");
+    ArchiveReader::new(&plain_path)?.unpack(&plain_out, None)?;
+    assert_ne!(
+        fs::metadata(plain_out.join("a.txt"))?.ino(),
+        fs::metadata(plain_out.join("b/copy.txt"))?.ino()
+    );
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_file_modes_round_trip() -> Result<(), AppError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    let modes = [
+        ("run.sh", 0o755),
+        ("notes.txt", 0o644),
+        ("locked.txt", 0o444),
+    ];
+    for (name, mode) in modes {
+        let path = input_path.join(name);
+        fs::write(&path, name.repeat(32))?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(mode))?;
+    }
+    let files = crate::fsutil::directory::walk_dir(&input_path)?;
+
+    let archive_path = dir.path().join("archive.squish");
+    ArchiveWriter::new(&input_path, &archive_path, None)?.pack(&files)?;
+
+    // Renaming rewrites the file table, which must keep the modes
+    rename_entries(
+        &archive_path,
+        &[("notes.txt".to_string(), "docs.txt".to_string())],
+        None,
+    )?;
+
+    for in_memory in [false, true] {
+        let output_dir = dir.path().join(format!("out-{in_memory}"));
+        let options = UnpackOptions {
+            in_memory,
+            ..Default::default()
+        };
+        ArchiveReader::new(&archive_path)?.unpack_with_options(&output_dir, None, &options)?;
+
+        for (name, mode) in [
+            ("run.sh", 0o755),
+            ("docs.txt", 0o644),
+            ("locked.txt", 0o444),
+        ] {
+            let permissions = fs::metadata(output_dir.join(name))?.permissions();
+            assert_eq!(permissions.mode() & 0o7777, mode, "{name}");
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_special_mode_bits_need_opting_in() -> Result<(), AppError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    let path = input_path.join("tool");
+    fs::write(&path, b"#!/bin/sh\n")?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o4755))?;
+
+    let archive_path = dir.path().join("archive.squish");
+    ArchiveWriter::new(&input_path, &archive_path, None)?.pack(&[path])?;
+
+    for (special_mode_bits, mode) in [(false, 0o755), (true, 0o4755)] {
+        let output_dir = dir.path().join(format!("out-{special_mode_bits}"));
+        let options = UnpackOptions {
+            special_mode_bits,
+            ..Default::default()
+        };
+        ArchiveReader::new(&archive_path)?.unpack_with_options(&output_dir, None, &options)?;
+
+        let permissions = fs::metadata(output_dir.join("tool"))?.permissions();
+        assert_eq!(permissions.mode() & 0o7777, mode);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_pack_entries_from_memory() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let large: Vec<u8> = (0..CHUNK_SIZE as u32 + 1000)
+        .map(|i| (i % 251) as u8)
+        .collect();
+    let entries = vec![
+        ("config/app.toml".to_string(), b"debug = false\n".to_vec()),
+        ("data/large.bin".to_string(), large.clone()),
+        ("data/copy.bin".to_string(), large.clone()),
+        ("empty".to_string(), Vec::new()),
+    ];
+
+    // The input directory is never read
+    let archive_path = dir.path().join("memory.squish");
+    let missing_input = dir.path().join("does-not-exist");
+    ArchiveWriter::new(&missing_input, &archive_path, None)?.pack_entries(entries.clone())?;
+
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    let summary = reader.get_summary()?;
+    assert_eq!(summary.files.len(), 4);
+    assert_eq!(summary.top_level_entries, 3);
+    // Both copies of the large entry share its two chunks, alongside the toml's one
+    assert_eq!(summary.unique_chunks, 3);
+    for (path, data) in &entries {
+        let mut extracted = Vec::new();
+        reader.extract_file(path, &mut extracted)?;
+        assert_eq!(&extracted, data, "{path}");
+    }
+
+    // The archive also unpacks straight from its bytes
+    let output_dir = dir.path().join("out");
+    let bytes = fs::read(&archive_path)?;
+    crate::archive::unpack_sequential(
+        Cursor::new(bytes),
+        &output_dir,
+        None,
+        &UnpackOptions::default(),
+    )?;
+    for (path, data) in &entries {
+        assert_eq!(&fs::read(output_dir.join(path))?, data, "{path}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_pack_entries_matches_file_pack() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir_all(input_path.join("data"))?;
+    let large: Vec<u8> = (0..CHUNK_SIZE as u32 + 1000)
+        .map(|i| (i % 241) as u8)
+        .collect();
+    let entries = vec![
+        ("data/large.bin".to_string(), large),
+        ("notes.txt".to_string(), b"notes".to_vec()),
+    ];
+    for (path, data) in &entries {
+        fs::write(input_path.join(path), data)?;
+    }
+
+    let file_archive = dir.path().join("files.squish");
+    ArchiveWriter::new(&input_path, &file_archive, None)?.pack(&[
+        input_path.join("data/large.bin"),
+        input_path.join("notes.txt"),
+    ])?;
+    let memory_archive = dir.path().join("memory.squish");
+    ArchiveWriter::new(&input_path, &memory_archive, None)?.pack_entries(entries)?;
+
+    // Both paths chunk and hash the same bytes the same way
+    assert_eq!(
+        ArchiveReader::new(&file_archive)?.content_fingerprint()?,
+        ArchiveReader::new(&memory_archive)?.content_fingerprint()?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_pack_with_single_chunk_writer_queue() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    let files: Vec<PathBuf> = (0..8u32)
+        .map(|i| {
+            let path = input_path.join(format!("file{i}.bin"));
+            let data: Vec<u8> = (0..16 * 1024u32)
+                .map(|j| ((i * 31 + j) % 241) as u8)
+                .collect();
+            fs::write(&path, data).unwrap();
+            path
+        })
+        .collect();
+
+    // Every producer waits on the writer after each chunk, which must neither deadlock nor
+    // lose chunks
+    let archive_path = dir.path().join("archive.squish");
+    let options = PackOptions {
+        chunk_size: 1024,
+        writer_queue: 1,
+        ..Default::default()
+    };
+    ArchiveWriter::with_options(&input_path, &archive_path, None, options)?.pack(&files)?;
+
+    let output_dir = dir.path().join("output");
+    ArchiveReader::new(&archive_path)?.unpack(&output_dir, None)?;
+    for file in &files {
+        assert_eq!(
+            fs::read(output_dir.join(file.file_name().unwrap()))?,
+            fs::read(file)?
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_estimate_pack_matches_real_pack() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir_all(input_path.join("docs"))?;
+    fs::create_dir_all(input_path.join("empty"))?;
+    let data: Vec<u8> = (0..3 * 1024 + 17u32).map(|i| (i % 199) as u8).collect();
+    fs::write(input_path.join("data.bin"), &data)?;
+    fs::write(input_path.join("docs/copy.bin"), &data)?;
+    fs::write(input_path.join("docs/notes.txt"), b"notes ".repeat(100))?;
+    #[cfg(unix)]
+    std::os::unix::fs::symlink("notes.txt", input_path.join("docs/link"))?;
+
+    let tree = crate::fsutil::directory::walk_tree(
+        &input_path,
+        &[],
+        &Default::default(),
+        usize::MAX,
+        |_| true,
+    )?;
+    let files: Vec<PathBuf> = tree.files.into_iter().chain(tree.empty_dirs).collect();
+    let options = PackOptions {
+        chunk_size: 1024,
+        ..Default::default()
+    };
+
+    let estimate = estimate_pack(&input_path, &files, None, &options)?;
+    let archive_path = dir.path().join("archive.squish");
+    let archive_size =
+        ArchiveWriter::with_options(&input_path, &archive_path, None, options)?.pack(&files)?;
+
+    // Nothing is written, yet the estimate matches the real archive byte for byte
+    assert_eq!(estimate.archive_size, archive_size);
+    assert_eq!(estimate.files, files.len() as u64);
+    assert_eq!(estimate.original_size, 2 * data.len() as u64 + 600);
+    let summary = ArchiveReader::new(&archive_path)?.get_summary()?;
+    assert_eq!(estimate.unique_chunks, summary.unique_chunks);
+    assert!(estimate.reduction_percentage() > 0.0);
+
+    Ok(())
+}
+
+#[test]
+fn test_pack_entries_rejects_bad_paths() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let archive_path = dir.path().join("memory.squish");
+
+    let escaping = vec![("../escape".to_string(), b"x".to_vec())];
+    let result = ArchiveWriter::new(dir.path(), &archive_path, None)?.pack_entries(escaping);
+    assert!(matches!(result, Err(AppError::UnsafePath(_))));
+
+    let duplicated = vec![
+        ("same".to_string(), b"a".to_vec()),
+        ("same".to_string(), b"b".to_vec()),
+    ];
+    let result = ArchiveWriter::new(dir.path(), &archive_path, None)?.pack_entries(duplicated);
+    assert!(matches!(result, Err(AppError::FileExistsInArchive(path)) if path == "same"));
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_symlinks_round_trip_as_links() -> Result<(), AppError> {
+    use std::os::unix::fs::symlink;
+
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir_all(input_path.join("docs"))?;
+    fs::write(input_path.join("docs/readme.txt"), b"read me")?;
+    symlink("docs/readme.txt", input_path.join("latest"))?;
+    symlink("docs", input_path.join("docs-link"))?;
+    symlink("missing.txt", input_path.join("dangling"))?;
+    // Following this would recurse forever
+    symlink(".", input_path.join("docs/loop"))?;
+
+    let files = crate::fsutil::directory::walk_dir(&input_path)?;
+    assert_eq!(files.len(), 5);
+
+    let archive_path = dir.path().join("links.squish");
+    ArchiveWriter::new(&input_path, &archive_path, None)?.pack(&files)?;
+
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    assert_eq!(reader.get_summary()?.unique_chunks, 1);
+    assert!(reader.verify_against(&input_path)?.is_clean());
+
+    let output_dir = dir.path().join("output");
+    reader.unpack(&output_dir, None)?;
+    for (link, target) in [
+        ("latest", "docs/readme.txt"),
+        ("docs-link", "docs"),
+        ("dangling", "missing.txt"),
+        ("docs/loop", "."),
+    ] {
+        assert_eq!(fs::read_link(output_dir.join(link))?, PathBuf::from(target));
+    }
+    assert_eq!(fs::read(output_dir.join("latest"))?, b"read me");
+
+    // Unpacking again replaces the links rather than failing on them
+    reader.unpack(&output_dir, None)?;
+    assert_eq!(
+        fs::read_link(output_dir.join("latest"))?,
+        PathBuf::from("docs/readme.txt")
+    );
+
+    Ok(())
+}
+
+/// Writes an archive whose only file, `path`, holds `b"owned"`, without any path checks.
+fn create_archive_with_path(archive_path: &Path, path: &str) -> Result<(), AppError> {
+    let mut writer = File::create(archive_path)?;
+    write_header(&mut writer)?;
+    write_timestamp(&mut writer)?;
+    writer.write_all(&1u64.to_le_bytes())?;
+
+    let data = b"owned";
+    let hash = crate::util::chunk::hash_chunk(data);
+    let compressed = zstd::encode_all(Cursor::new(data), 0)?;
+    writer.write_all(&hash)?;
+    writer.write_all(&(data.len() as u64).to_le_bytes())?;
+    writer.write_all(&(compressed.len() as u64).to_le_bytes())?;
+    writer.write_all(&compressed)?;
+
+    writer.write_all(&1u32.to_le_bytes())?;
+    writer.write_all(&(path.len() as u32).to_le_bytes())?;
+    writer.write_all(path.as_bytes())?;
+    writer.write_all(&(data.len() as u64).to_le_bytes())?;
+    writer.write_all(&1u32.to_le_bytes())?;
+    writer.write_all(&hash)?;
+    Ok(())
+}
+
+#[test]
+fn test_unpack_refuses_paths_outside_output() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let output_dir = dir.path().join("output");
+    let escaped = dir.path().join("evil.txt");
+    let absolute = dir.path().join("absolute.txt");
+
+    for (name, path) in [
+        ("dotdot", "../evil.txt".to_string()),
+        ("nested", "docs/../../evil.txt".to_string()),
+        ("absolute", absolute.to_string_lossy().to_string()),
+        ("empty", String::new()),
+    ] {
+        let archive_path = dir.path().join(format!("{name}.squish"));
+        create_archive_with_path(&archive_path, &path)?;
+
+        for in_memory in [false, true] {
+            let options = UnpackOptions {
+                in_memory,
+                ..Default::default()
+            };
+            let result =
+                ArchiveReader::new(&archive_path)?.unpack_with_options(&output_dir, None, &options);
+            assert!(matches!(result, Err(AppError::UnsafePath(_))), "{name}");
+        }
+        let result = crate::archive::unpack_sequential(
+            File::open(&archive_path)?,
+            &output_dir,
+            None,
+            &UnpackOptions::default(),
+        );
+        assert!(matches!(result, Err(AppError::UnsafePath(_))), "{name}");
+    }
+
+    assert!(!escaped.exists());
+    assert!(!absolute.exists());
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unpack_refuses_writing_through_existing_symlinks() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let outside = dir.path().join("outside");
+    let output_dir = dir.path().join("output");
+    fs::create_dir_all(&outside)?;
+    fs::create_dir_all(&output_dir)?;
+    std::os::unix::fs::symlink(&outside, output_dir.join("escape"))?;
+    std::os::unix::fs::symlink(outside.join("target.txt"), output_dir.join("file.txt"))?;
+
+    // A directory link already in the output cannot carry files out of it
+    let archive_path = dir.path().join("through-link.squish");
+    create_archive_with_path(&archive_path, "escape/payload.txt")?;
+    let result = ArchiveReader::new(&archive_path)?.unpack(&output_dir, None);
+    assert!(matches!(result, Err(AppError::UnsafePath(_))));
+    assert!(!outside.join("payload.txt").exists());
+
+    // A file link is replaced instead of written through
+    let archive_path = dir.path().join("over-link.squish");
+    create_archive_with_path(&archive_path, "file.txt")?;
+    ArchiveReader::new(&archive_path)?.unpack(&output_dir, None)?;
+    assert!(!outside.join("target.txt").exists());
+    assert!(!fs::symlink_metadata(output_dir.join("file.txt"))?
+        .file_type()
+        .is_symlink());
+    assert_eq!(fs::read(output_dir.join("file.txt"))?, b"owned");
+
+    Ok(())
+}
+
+#[test]
+fn test_pack_with_compression_none() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    let data = b"highly compressible ".repeat(4096);
+    fs::write(input_path.join("text.txt"), &data)?;
+    let files = crate::fsutil::directory::walk_dir(&input_path)?;
+
+    let archive_path = dir.path().join("raw.squish");
+    let options = PackOptions {
+        compression: crate::util::chunk::Compression::None,
+        ..Default::default()
+    };
+    ArchiveWriter::with_options(&input_path, &archive_path, None, options)?.pack(&files)?;
+
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    let chunk = reader.chunk_info(0)?;
+    assert!(chunk.stored_raw);
+    assert_eq!(chunk.compressed_size, data.len() as u64);
+    assert!(chunk.hash_matches);
+
+    let output_dir = dir.path().join("output");
+    reader.unpack(&output_dir, None)?;
+    assert_eq!(fs::read(output_dir.join("text.txt"))?, data);
+
+    Ok(())
+}
+
+#[test]
+fn test_pack_with_custom_chunk_size() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    // Over eight chunks, so `balance` splits it into segments of the custom size
+    let data: Vec<u8> = (0..40_000u32).map(|i| (i % 251) as u8).collect();
+    fs::write(input_path.join("data.bin"), &data)?;
+    let files = crate::fsutil::directory::walk_dir(&input_path)?;
+
+    let archive_path = dir.path().join("small.squish");
+    let options = PackOptions {
+        chunk_size: 4096,
+        balance: true,
+        ..Default::default()
+    };
+    ArchiveWriter::with_options(&input_path, &archive_path, None, options)?.pack(&files)?;
+
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    assert_eq!(reader.chunk_size(), 4096);
+    assert_eq!(reader.get_summary()?.files[0].chunk_count, 10);
+
+    // Ranges are located by the recorded chunk size, not the default one
+    let mut range = Vec::new();
+    reader.extract_range("data.bin", 4000, Some(8200), &mut range)?;
+    assert_eq!(range, &data[4000..8200]);
+    assert!(reader.verify_against(&input_path)?.is_clean());
+
+    let output_dir = dir.path().join("output");
+    reader.unpack(&output_dir, None)?;
+    assert_eq!(fs::read(output_dir.join("data.bin"))?, data);
+
+    // Default archives keep the default chunk size and do not record it
+    let default_path = dir.path().join("default.squish");
+    ArchiveWriter::new(&input_path, &default_path, None)?.pack(&files)?;
+    assert_eq!(ArchiveReader::new(&default_path)?.chunk_size(), CHUNK_SIZE);
+
+    let options = PackOptions {
+        chunk_size: 0,
+        ..Default::default()
+    };
+    let result = ArchiveWriter::with_options(&input_path, &archive_path, None, options);
+    assert!(matches!(result, Err(AppError::InvalidChunkSize(0))));
+
+    Ok(())
+}
+
+#[test]
+fn test_pack_with_content_defined_chunks() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+
+    // Two versions of a file, the second with a few bytes inserted near the start
+    let mut state = 0x9e37_79b9_u64;
+    let v1: Vec<u8> = (0..200_000)
+        .map(|_| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 56) as u8
+        })
+        .collect();
+    let mut v2 = v1.clone();
+    v2.splice(500..500, *b"inserted");
+    fs::write(input_path.join("v1.bin"), &v1)?;
+    fs::write(input_path.join("v2.bin"), &v2)?;
+    let files = crate::fsutil::directory::walk_dir(&input_path)?;
+
+    let archive_path = dir.path().join("cdc.squish");
+    let options = PackOptions {
+        chunk_size: 4096,
+        chunking: Chunking::ContentDefined,
+        ..Default::default()
+    };
+    ArchiveWriter::with_options(&input_path, &archive_path, None, options)?.pack(&files)?;
+
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    assert_eq!(reader.chunking(), Chunking::ContentDefined);
+
+    // Nearly every chunk of the second version is shared with the first
+    let summary = reader.get_summary()?;
+    let v1_chunks = summary
+        .files
+        .iter()
+        .find(|f| f.path == "v1.bin")
+        .unwrap()
+        .chunk_count;
+    assert!(summary.unique_chunks <= v1_chunks as u64 + 3);
+
+    // Shared chunk sizes come from the chunk table, so they add up to the shared bytes
+    let shared = reader.shared_chunks(usize::MAX)?;
+    let shared_bytes: u64 = shared.iter().map(|chunk| chunk.size).sum();
+    assert!(shared.iter().all(|chunk| chunk.size <= 4 * 4096));
+    assert!(shared_bytes <= v1.len() as u64);
+    assert!(shared_bytes >= v1.len() as u64 - 3 * 4 * 4096);
+
+    // Ranges and verification follow the recorded chunk lengths
+    let mut range = Vec::new();
+    reader.extract_range("v2.bin", 4000, Some(30_000), &mut range)?;
+    assert_eq!(range, &v2[4000..30_000]);
+    assert!(reader.verify_against(&input_path)?.is_clean());
+
+    let output_dir = dir.path().join("output");
+    reader.unpack(&output_dir, None)?;
+    assert_eq!(fs::read(output_dir.join("v1.bin"))?, v1);
+    assert_eq!(fs::read(output_dir.join("v2.bin"))?, v2);
+
+    // A missing chunk's length cannot be known, so zero-filling is refused
+    let options = UnpackOptions {
+        zero_fill_missing: true,
+        ..Default::default()
+    };
+    let result = reader.unpack_with_options(&dir.path().join("salvage"), None, &options);
+    assert!(matches!(result, Err(AppError::Unsupported(_))));
+
+    Ok(())
+}
+
+#[test]
+fn test_unpack_single_file() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir_all(input_path.join("docs"))?;
+    fs::write(input_path.join("docs/wanted.txt"), b"just this one")?;
+    fs::write(input_path.join("other.txt"), b"not this one")?;
+    let files = crate::fsutil::directory::walk_dir(&input_path)?;
+
+    let archive_path = dir.path().join("archive.squish");
+    ArchiveWriter::new(&input_path, &archive_path, None)?.pack(&files)?;
+
+    for (name, in_memory, sequential) in [
+        ("lazy", false, false),
+        ("memory", true, false),
+        ("sequential", false, true),
+    ] {
+        let output_dir = dir.path().join(name);
+        let options = UnpackOptions {
+            in_memory,
+            file: Some("docs/wanted.txt".to_string()),
+            ..Default::default()
+        };
+        let report = if sequential {
+            crate::archive::unpack_sequential(
+                File::open(&archive_path)?,
+                &output_dir,
+                None,
+                &options,
+            )?
+        } else {
+            ArchiveReader::new(&archive_path)?.unpack_with_options(&output_dir, None, &options)?
+        };
+
+        assert_eq!(report.files_restored, 1, "{name}");
+        assert_eq!(
+            fs::read(output_dir.join("docs/wanted.txt"))?,
+            b"just this one"
+        );
+        assert!(!output_dir.join("other.txt").exists(), "{name}");
+    }
+
+    let options = UnpackOptions {
+        file: Some("missing.txt".to_string()),
+        ..Default::default()
+    };
+    let result = ArchiveReader::new(&archive_path)?.unpack_with_options(
+        &dir.path().join("none"),
+        None,
+        &options,
+    );
+    assert!(matches!(result, Err(AppError::FileNotFoundInArchive(path)) if path == "missing.txt"));
+
+    Ok(())
+}
+
+#[test]
+fn test_unpack_check_writes_nothing_and_reports_damage() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    fs::write(input_path.join("first.txt"), b"first file ".repeat(256))?;
+    fs::write(input_path.join("second.txt"), b"second file ".repeat(256))?;
+    let files = crate::fsutil::directory::walk_dir(&input_path)?;
+
+    // Raw chunks, so damage shows up as a hash mismatch rather than a zstd error
+    let archive_path = dir.path().join("archive.squish");
+    let options = PackOptions {
+        compression: crate::util::chunk::Compression::None,
+        ..Default::default()
+    };
+    ArchiveWriter::with_options(&input_path, &archive_path, None, options)?.pack(&files)?;
+
+    let output_dir = dir.path().join("output");
+    let check = UnpackOptions {
+        check: true,
+        ..Default::default()
+    };
+    let report =
+        ArchiveReader::new(&archive_path)?.unpack_with_options(&output_dir, None, &check)?;
+    assert_eq!(report.files_restored, 2);
+    assert!(report.check_failures.is_empty());
+    assert!(!output_dir.exists());
+
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    let info = reader.chunk_info(0)?;
+    let mut bytes = fs::read(&archive_path)?;
+    bytes[info.offset as usize] ^= 0xff;
+    fs::write(&archive_path, &bytes)?;
+
+    for in_memory in [false, true] {
+        let options = UnpackOptions {
+            in_memory,
+            ..check.clone()
+        };
+        let report =
+            ArchiveReader::new(&archive_path)?.unpack_with_options(&output_dir, None, &options)?;
+        assert_eq!(report.check_failures.len(), 1);
+        assert_eq!(
+            report.check_failures[0].reason,
+            "chunk 0 does not match its hash"
+        );
+    }
+    assert!(!output_dir.exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_pack_skip_errors_packs_remaining_files() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    let kept = input_path.join("kept.txt");
+    fs::write(&kept, b"still packed")?;
+    // Listed by the walk but gone by the time it is read, so opening it fails
+    let unreadable = input_path.join("gone.txt");
+    let files = vec![kept.clone(), unreadable.clone()];
+
+    let archive_path = dir.path().join("archive.squish");
+    let failed = ArchiveWriter::new(&input_path, &archive_path, None)?.pack(&files);
+    assert!(failed.is_err());
+
+    let options = PackOptions {
+        skip_errors: true,
+        ..Default::default()
+    };
+    let mut writer = ArchiveWriter::with_options(&input_path, &archive_path, None, options)?;
+    writer.pack(&files)?;
+    let skipped = writer.skipped_files();
+    assert_eq!(skipped.len(), 1);
+    assert_eq!(skipped[0].path, unreadable);
+
+    let output_dir = dir.path().join("output");
+    ArchiveReader::new(&archive_path)?.unpack(&output_dir, None)?;
+    assert_eq!(fs::read(output_dir.join("kept.txt"))?, b"still packed");
+    assert_eq!(fs::read_dir(&output_dir)?.count(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_header_getters_match_summary() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    let files: Vec<PathBuf> = ["a.txt", "b.txt", "c.txt"]
+        .iter()
+        .map(|name| {
+            let path = input_path.join(name);
+            fs::write(&path, format!("contents of {name}")).unwrap();
+            path
+        })
+        .collect();
+
+    let archive_path = dir.path().join("archive.squish");
+    ArchiveWriter::new(&input_path, &archive_path, None)?.pack(&files)?;
+
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    assert_eq!(reader.file_count(), 3);
+    assert_eq!(reader.archive_size(), fs::metadata(&archive_path)?.len());
+
+    let summary = reader.get_summary()?;
+    assert_eq!(reader.chunk_count(), summary.unique_chunks);
+    assert_eq!(reader.creation_time(), summary.squish_creation_date);
+
+    Ok(())
+}
+
+#[test]
+fn test_failed_pack_leaves_no_output() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    let file = input_path.join("data.bin");
+    let data: Vec<u8> = (0..64 * 1024u32)
+        .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+        .collect();
+    fs::write(&file, &data)?;
+    let files = vec![file];
+
+    // The size limit is hit after chunks have already been written
+    let too_small = || PackOptions {
+        max_archive_size: Some(1024),
+        ..Default::default()
+    };
+    let archive_path = dir.path().join("archive.squish");
+    let result =
+        ArchiveWriter::with_options(&input_path, &archive_path, None, too_small())?.pack(&files);
+    assert!(result.is_err());
+    assert!(!archive_path.exists());
+    assert_eq!(
+        fs::read_dir(dir.path())?.count(),
+        1,
+        "no temporary file is left"
+    );
+
+    // A failed pack must not replace an archive already at the output path
+    ArchiveWriter::new(&input_path, &archive_path, None)?.pack(&files)?;
+    let good_archive = fs::read(&archive_path)?;
+    let result =
+        ArchiveWriter::with_options(&input_path, &archive_path, None, too_small())?.pack(&files);
+    assert!(result.is_err());
+    assert_eq!(fs::read(&archive_path)?, good_archive);
+
+    Ok(())
+}
+
+#[test]
+fn test_unpack_conflict_policies() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    let files: Vec<PathBuf> = ["notes.txt", "data.tar.gz"]
+        .iter()
+        .map(|name| {
+            let path = input_path.join(name);
+            fs::write(&path, format!("archived {name}")).unwrap();
+            path
+        })
+        .collect();
+    let archive_path = dir.path().join("archive.squish");
+    ArchiveWriter::new(&input_path, &archive_path, None)?.pack(&files)?;
+
+    let output_dir = dir.path().join("output");
+    let unpack = |on_conflict| -> Result<_, AppError> {
+        fs::create_dir_all(&output_dir)?;
+        fs::write(output_dir.join("notes.txt"), "local edit")?;
+        let options = UnpackOptions {
+            on_conflict,
+            ..Default::default()
+        };
+        ArchiveReader::new(&archive_path)?.unpack_with_options(&output_dir, None, &options)
+    };
+
+    let report = unpack(ConflictPolicy::Skip)?;
+    assert_eq!((report.skipped_existing, report.files_restored), (1, 1));
+    assert_eq!(
+        fs::read_to_string(output_dir.join("notes.txt"))?,
+        "local edit"
+    );
+    assert_eq!(
+        fs::read_to_string(output_dir.join("data.tar.gz"))?,
+        "archived data.tar.gz"
+    );
+
+    let report = unpack(ConflictPolicy::Rename)?;
+    assert_eq!(report.renamed_existing, 2);
+    assert_eq!(
+        fs::read_to_string(output_dir.join("notes.txt"))?,
+        "local edit"
+    );
+    assert_eq!(
+        fs::read_to_string(output_dir.join("notes (1).txt"))?,
+        "archived notes.txt"
+    );
+    assert!(output_dir.join("data.tar (1).gz").exists());
+
+    let report = unpack(ConflictPolicy::Overwrite)?;
+    assert_eq!((report.skipped_existing, report.renamed_existing), (0, 0));
+    assert_eq!(
+        fs::read_to_string(output_dir.join("notes.txt"))?,
+        "archived notes.txt"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_unpack_rejects_size_mismatch() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    let file = input_path.join("sized.txt");
+    fs::write(&file, b"twelve bytes")?;
+    let archive_path = dir.path().join("archive.squish");
+    ArchiveWriter::new(&input_path, &archive_path, None)?.pack(&[file])?;
+
+    // Record one byte more than the chunks hold
+    let mut bytes = fs::read(&archive_path)?;
+    let path_at = bytes
+        .windows(b"sized.txt".len())
+        .position(|window| window == b"sized.txt")
+        .expect("path is in the file table");
+    let size_at = path_at + b"sized.txt".len();
+    bytes[size_at..size_at + 8].copy_from_slice(&13u64.to_le_bytes());
+    fs::write(&archive_path, bytes)?;
+
+    let result = ArchiveReader::new(&archive_path)?.unpack(&dir.path().join("checked"), None);
+    assert!(matches!(
+        result,
+        Err(AppError::SizeMismatch(path, 13, 12)) if path == "sized.txt"
+    ));
+
+    let options = UnpackOptions {
+        skip_size_check: true,
+        ..Default::default()
+    };
+    let output_dir = dir.path().join("unchecked");
+    ArchiveReader::new(&archive_path)?.unpack_with_options(&output_dir, None, &options)?;
+    assert_eq!(fs::read(output_dir.join("sized.txt"))?, b"twelve bytes");
+
+    Ok(())
+}
+
+#[test]
+fn test_unpack_verify_rejects_reordered_chunks() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    let file = input_path.join("two_chunks.bin");
+    let data = [vec![b'a'; 1024], vec![b'b'; 1024]].concat();
+    fs::write(&file, &data)?;
+    let archive_path = dir.path().join("archive.squish");
+    let options = PackOptions {
+        chunk_size: 1024,
+        ..Default::default()
+    };
+    ArchiveWriter::with_options(&input_path, &archive_path, None, options)?.pack(&[file])?;
+
+    // Swap the file's two chunk hashes, which keeps every chunk present and the size intact
+    let first = crate::util::chunk::hash_chunk(&data[..1024]);
+    let second = crate::util::chunk::hash_chunk(&data[1024..]);
+    let listed = [first, second].concat();
+    let mut bytes = fs::read(&archive_path)?;
+    let at = bytes
+        .windows(listed.len())
+        .position(|window| window == listed)
+        .expect("chunk hashes are in the file table");
+    bytes[at..at + 32].copy_from_slice(&[second, first].concat());
+    fs::write(&archive_path, bytes)?;
+
+    let output_dir = dir.path().join("unverified");
+    ArchiveReader::new(&archive_path)?.unpack(&output_dir, None)?;
+    assert_ne!(fs::read(output_dir.join("two_chunks.bin"))?, data);
+
+    let options = UnpackOptions {
+        verify: true,
+        ..Default::default()
+    };
+    let result = ArchiveReader::new(&archive_path)?.unpack_with_options(
+        &dir.path().join("verified"),
+        None,
+        &options,
+    );
+    assert!(matches!(
+        result,
+        Err(AppError::FileHashMismatch(path)) if path == "two_chunks.bin"
+    ));
+
+    let options = UnpackOptions {
+        check: true,
+        ..Default::default()
+    };
+    let report = ArchiveReader::new(&archive_path)?.unpack_with_options(
+        &dir.path().join("checked"),
+        None,
+        &options,
+    )?;
+    assert_eq!(report.check_failures.len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_newer_major_version_is_rejected() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    let file = input_path.join("a.txt");
+    fs::write(&file, b"contents")?;
+    let archive_path = dir.path().join("archive.squish");
+    ArchiveWriter::new(&input_path, &archive_path, None)?.pack(&[file])?;
+
+    // Same length as this build's version, so only the number itself differs
+    let (major, rest) = VERSION.split_once('.').unwrap();
+    let newer = format!("{}.{rest}", major.parse::<u32>().unwrap() + 1);
+    assert_eq!(newer.len(), VERSION.len());
+    let mut bytes = fs::read(&archive_path)?;
+    bytes[PREFIX.len()..PREFIX.len() + VERSION.len()].copy_from_slice(newer.as_bytes());
+    fs::write(&archive_path, &bytes)?;
+
+    assert!(matches!(
+        ArchiveReader::new(&archive_path),
+        Err(AppError::Archive(message)) if message.starts_with("Incompatible version")
+    ));
+    let output_dir = dir.path().join("output");
+    let sequential = crate::archive::unpack_sequential(
+        bytes.as_slice(),
+        &output_dir,
+        None,
+        &UnpackOptions::default(),
+    );
+    assert!(matches!(sequential, Err(AppError::Archive(_))));
+    assert!(!output_dir.exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_corrupt_timestamp_is_an_error() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    let file = input_path.join("a.txt");
+    fs::write(&file, b"contents")?;
+    let archive_path = dir.path().join("archive.squish");
+    ArchiveWriter::new(&input_path, &archive_path, None)?.pack(&[file])?;
+
+    // Push the timestamp far past the last representable date, leaving the flag bits alone
+    let mut bytes = fs::read(&archive_path)?;
+    let timestamp_at = crate::util::header::magic_version().len();
+    bytes[timestamp_at..timestamp_at + 7].fill(0xff);
+    fs::write(&archive_path, bytes)?;
+
+    assert!(matches!(
+        ArchiveReader::new(&archive_path),
+        Err(AppError::InvalidTimeStamp(_))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_truncated_archive_is_rejected_on_open() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir(&input_path)?;
+    let file = input_path.join("a.txt");
+    fs::write(&file, "contents ".repeat(512))?;
+    let archive_path = dir.path().join("archive.squish");
+    ArchiveWriter::new(&input_path, &archive_path, None)?.pack(&[file])?;
+
+    let bytes = fs::read(&archive_path)?;
+    let truncated_path = dir.path().join("truncated.squish");
+    fs::write(&truncated_path, &bytes[..bytes.len() - 100])?;
+
+    match ArchiveReader::new(&truncated_path) {
+        Err(AppError::Archive(message)) => assert!(message.starts_with("truncated archive")),
+        Err(e) => panic!("unexpected error: {e}"),
+        Ok(_) => panic!("a truncated archive was opened"),
+    }
+
+    // Edits in place keep the recorded size in step with the file
+    rename_entries(&archive_path, &[("a.txt".into(), "a".into())], None)?;
+    let position = ArchiveReader::new(&archive_path)?
+        .archive_size_position()
+        .unwrap() as usize;
+    let bytes = fs::read(&archive_path)?;
+    let recorded = u64::from_le_bytes(bytes[position..position + 8].try_into().unwrap());
+    assert_eq!(recorded, bytes.len() as u64);
+
+    Ok(())
+}
+
+#[test]
+fn test_repair_skips_files_with_damaged_chunks() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir_all(input_path.join("sub"))?;
+    let damaged = b"damaged ".repeat(512);
+    fs::write(input_path.join("damaged.txt"), &damaged)?;
+    fs::write(input_path.join("intact.txt"), b"intact ".repeat(512))?;
+    fs::write(input_path.join("sub/also_intact.txt"), b"also intact")?;
+    let files = vec![
+        input_path.join("damaged.txt"),
+        input_path.join("intact.txt"),
+        input_path.join("sub/also_intact.txt"),
+    ];
+    let archive_path = dir.path().join("archive.squish");
+    ArchiveWriter::new(&input_path, &archive_path, None)?.pack(&files)?;
+
+    // Cut off the back half of the damaged file's chunk data, leaving the table layout intact
+    let hash = crate::util::chunk::hash_chunk(&damaged);
+    let mut bytes = fs::read(&archive_path)?;
+    let entry_at = bytes
+        .windows(hash.len())
+        .position(|window| window == hash)
+        .expect("chunk is in the chunk table");
+    let size_at = entry_at + 16 + 8;
+    let stored_size = u64::from_le_bytes(bytes[size_at..size_at + 8].try_into().unwrap());
+    let data_at = size_at + 8;
+    let (compressed_size, _) = crate::util::chunk::decode_stored_size(stored_size);
+    let half = compressed_size as usize / 2;
+    bytes[data_at + half..data_at + compressed_size as usize].fill(0);
+    fs::write(&archive_path, bytes)?;
+
+    assert!(ArchiveReader::new(&archive_path)?
+        .unpack(&dir.path().join("unpacked"), None)
+        .is_err());
+
+    let output_dir = dir.path().join("repaired");
+    let report = ArchiveReader::new(&archive_path)?.repair(&output_dir, None)?;
+    assert_eq!(report.files_restored, 2);
+    assert_eq!(report.lost_files.len(), 1);
+    assert_eq!(report.lost_files[0].path, "damaged.txt");
+    assert!(!output_dir.join("damaged.txt").exists());
+    assert_eq!(
+        fs::read(output_dir.join("intact.txt"))?,
+        b"intact ".repeat(512)
+    );
+    assert_eq!(
+        fs::read(output_dir.join("sub/also_intact.txt"))?,
+        b"also intact"
+    );
+
+    Ok(())
+}
+
+/// Argon2id costs low enough to keep tests fast
+const TEST_KDF: KdfParams = KdfParams {
+    memory_kib: 64,
+    iterations: 1,
+    parallelism: 1,
+};
+
+/// Packs `secret-notes.txt` and `docs/plan.txt` into an archive encrypted with `password`.
+fn pack_encrypted(dir: &Path, password: &str) -> Result<PathBuf, AppError> {
+    let input_path = dir.join("input");
+    fs::create_dir_all(input_path.join("docs"))?;
+    fs::write(input_path.join("secret-notes.txt"), b"meet at noon")?;
+    fs::write(input_path.join("docs/plan.txt"), b"the plan")?;
+
+    let archive_path = dir.join("encrypted.squish");
+    let options = PackOptions {
+        password: Some(password.to_string()),
+        kdf: TEST_KDF,
+        ..Default::default()
+    };
+    ArchiveWriter::with_options(&input_path, &archive_path, None, options)?.pack(&[
+        input_path.join("secret-notes.txt"),
+        input_path.join("docs/plan.txt"),
+    ])?;
+    Ok(archive_path)
+}
+
+#[test]
+fn test_encrypted_archive_roundtrip() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let archive_path = pack_encrypted(dir.path(), "hunter2")?;
+
+    // Neither paths nor contents appear in the archive's bytes
+    let bytes = fs::read(&archive_path)?;
+    for needle in [&b"secret-notes"[..], b"docs/plan", b"meet at noon"] {
+        assert!(!bytes.windows(needle.len()).any(|window| window == needle));
+    }
+
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    assert!(reader.is_encrypted());
+    assert_eq!(reader.file_count(), 2);
+    assert!(matches!(reader.entries(), Err(AppError::PasswordRequired)));
+    assert!(matches!(
+        reader.unlock("hunter3"),
+        Err(AppError::DecryptionFailed)
+    ));
+
+    reader.unlock("hunter2")?;
+    let output_path = dir.path().join("output");
+    reader.unpack(&output_path, None)?;
+    assert_eq!(
+        fs::read(output_path.join("secret-notes.txt"))?,
+        b"meet at noon"
+    );
+    assert_eq!(fs::read(output_path.join("docs/plan.txt"))?, b"the plan");
+
+    let mut plan = Vec::new();
+    reader.extract_file("docs/plan.txt", &mut plan)?;
+    assert_eq!(plan, b"the plan");
+    assert_eq!(reader.verify_integrity(None, None)?.chunks_verified, 2);
+
+    // Reading it front to back needs the password too
+    let sequential_output = dir.path().join("sequential");
+    assert!(matches!(
+        crate::archive::unpack_sequential(
+            File::open(&archive_path)?,
+            &sequential_output,
+            None,
+            &UnpackOptions::default()
+        ),
+        Err(AppError::PasswordRequired)
+    ));
+    let options = UnpackOptions {
+        password: Some("hunter2".to_string()),
+        ..Default::default()
+    };
+    crate::archive::unpack_sequential(
+        File::open(&archive_path)?,
+        &sequential_output,
+        None,
+        &options,
+    )?;
+    assert_eq!(
+        fs::read(sequential_output.join("docs/plan.txt"))?,
+        b"the plan"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_edit_encrypted_archive() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let archive_path = pack_encrypted(dir.path(), "hunter2")?;
+
+    assert!(matches!(
+        rename_entries(
+            &archive_path,
+            &[("docs/plan.txt".into(), "plan.txt".into())],
+            None
+        ),
+        Err(AppError::PasswordRequired)
+    ));
+    rename_entries(
+        &archive_path,
+        &[("docs/plan.txt".into(), "plan.txt".into())],
+        Some("hunter2"),
+    )?;
+    remove_entries(
+        &archive_path,
+        &["secret-notes.txt".to_string()],
+        Some("hunter2"),
+    )?;
+
+    // The rewritten file table is sealed again
+    let bytes = fs::read(&archive_path)?;
+    assert!(!bytes.windows(8).any(|window| window == b"plan.txt"));
+
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    reader.unlock("hunter2")?;
+    let paths: Vec<String> = reader
+        .entries()?
+        .into_iter()
+        .map(|file| file.path)
+        .collect();
+    assert_eq!(paths, ["plan.txt"]);
+
+    let mut plan = Vec::new();
+    reader.extract_file("plan.txt", &mut plan)?;
+    assert_eq!(plan, b"the plan");
+
+    let mut pool = Vec::new();
+    assert!(matches!(
+        reader.export_chunks(&mut pool),
+        Err(AppError::Unsupported(_))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_encrypted_archive_rejects_reproducible() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let options = PackOptions {
+        reproducible: true,
+        password: Some("hunter2".to_string()),
+        kdf: TEST_KDF,
+        ..Default::default()
+    };
+    assert!(matches!(
+        ArchiveWriter::with_options(dir.path(), &dir.path().join("out.squish"), None, options),
+        Err(AppError::Unsupported(_))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_signed_archive() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let input_path = dir.path().join("input");
+    fs::create_dir_all(&input_path)?;
+    fs::write(input_path.join("release-notes.txt"), b"version 2")?;
+    let files = [input_path.join("release-notes.txt")];
+
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let archive_path = dir.path().join("signed.squish");
+    let options = PackOptions {
+        signing_key: Some(key.clone()),
+        ..Default::default()
+    };
+    let archive_size =
+        ArchiveWriter::with_options(&input_path, &archive_path, None, options)?.pack(&files)?;
+
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    assert!(reader.is_signed());
+    assert_eq!(reader.archive_size(), archive_size);
+    reader.verify_signature(&key.verifying_key())?;
+    assert!(matches!(
+        reader.verify_signature(&SigningKey::from_bytes(&[8; 32]).verifying_key()),
+        Err(AppError::SignatureMismatch(_))
+    ));
+
+    // The footer does not get in the way of reading the archive
+    let mut notes = Vec::new();
+    reader.extract_file("release-notes.txt", &mut notes)?;
+    assert_eq!(notes, b"version 2");
+    assert_eq!(
+        reader.layout().last().map(|section| section.name),
+        Some("signature")
+    );
+    drop(reader);
+
+    // Editing would break the signature, so it is refused
+    assert!(matches!(
+        rename_entries(
+            &archive_path,
+            &[("release-notes.txt".into(), "notes.txt".into())],
+            None
+        ),
+        Err(AppError::Unsupported(_))
+    ));
+
+    // Any changed byte before the footer is caught
+    let mut bytes = fs::read(&archive_path)?;
+    let file_table = bytes.windows(17).position(|w| w == b"release-notes.txt");
+    bytes[file_table.expect("path in file table")] ^= 0x20;
+    let tampered_path = dir.path().join("tampered.squish");
+    fs::write(&tampered_path, &bytes)?;
+    assert!(matches!(
+        ArchiveReader::new(&tampered_path)?.verify_signature(&key.verifying_key()),
+        Err(AppError::SignatureMismatch(_))
+    ));
+
+    // An unsigned archive says so
+    let unsigned_path = dir.path().join("unsigned.squish");
+    ArchiveWriter::new(&input_path, &unsigned_path, None)?.pack(&files)?;
+    assert!(matches!(
+        ArchiveReader::new(&unsigned_path)?.verify_signature(&key.verifying_key()),
+        Err(AppError::NotSigned)
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_reencrypt_archive() -> Result<(), AppError> {
+    let dir = tempdir()?;
+    let archive_path = pack_encrypted(dir.path(), "hunter2")?;
+    let rekeyed_path = dir.path().join("rekeyed.squish");
+
+    let mut reader = ArchiveReader::new(&archive_path)?;
+    let mut out = Vec::new();
+    assert!(matches!(
+        reader.reencrypt_into(&mut out, "correct horse", TEST_KDF, None),
+        Err(AppError::PasswordRequired)
+    ));
+    reader.unlock("hunter2")?;
+    let mut rekeyed = File::create(&rekeyed_path)?;
+    assert_eq!(
+        reader.reencrypt_into(&mut rekeyed, "correct horse", TEST_KDF, None)?,
+        2
+    );
+    drop(rekeyed);
+
+    // Chunks and the file table stay where they were, but their sealed bytes change
+    let old_bytes = fs::read(&archive_path)?;
+    let new_bytes = fs::read(&rekeyed_path)?;
+    assert_eq!(old_bytes.len(), new_bytes.len());
+    let mut rekeyed_reader = ArchiveReader::new(&rekeyed_path)?;
+    assert_eq!(reader.layout(), rekeyed_reader.layout());
+    assert_ne!(old_bytes, new_bytes);
+
+    assert!(matches!(
+        rekeyed_reader.unlock("hunter2"),
+        Err(AppError::DecryptionFailed)
+    ));
+    rekeyed_reader.unlock("correct horse")?;
+    assert_eq!(rekeyed_reader.entries()?, reader.entries()?);
+    let output_path = dir.path().join("output");
+    rekeyed_reader.unpack(&output_path, None)?;
+    assert_eq!(
+        fs::read(output_path.join("secret-notes.txt"))?,
+        b"meet at noon"
+    );
+
+    // Only encrypted archives have a key to change
+    let plain_input = dir.path().join("plain");
+    fs::create_dir_all(&plain_input)?;
+    fs::write(plain_input.join("notes.txt"), b"hello")?;
+    let plain_path = dir.path().join("plain.squish");
+    ArchiveWriter::new(&plain_input, &plain_path, None)?.pack(&[plain_input.join("notes.txt")])?;
+    assert!(matches!(
+        ArchiveReader::new(&plain_path)?.reencrypt_into(&mut out, "x", TEST_KDF, None),
+        Err(AppError::Unsupported(_))
+    ));
+
+    Ok(())
+}
@@ -1,18 +1,268 @@
-use std::fs::File;
-use std::io::{BufReader, BufWriter, Read, Write};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 
-use crossbeam::channel::{unbounded, Sender};
+use crossbeam::channel::{bounded, Sender};
+use ed25519_dalek::SigningKey;
 use indicatif::ProgressBar;
 use rayon::prelude::*;
 
-use crate::fsutil::writer::{writer_thread, ChunkMessage, ThreadSafeWriter};
-use crate::util::chunk::{ChunkHash, ChunkStore, CHUNK_SIZE};
+use crate::archive::index::{index_chunk_table, write_chunk_index};
+use crate::archive::pool::read_pool_hashes;
+use crate::archive::reader::validate_relative_path;
+use crate::fsutil::alloc::preallocate;
+use crate::fsutil::directory::find_hard_links;
+use crate::fsutil::temp::{scratch_path, staging_path};
+use crate::fsutil::throttle::TokenBucket;
+use crate::fsutil::writer::{
+    is_out_of_space, writer_thread, ChunkMessage, SizeLimit, SyncInterval, SyncPolicy,
+    ThreadSafeWriter, WriterConfig,
+};
+use crate::util::chunk::{
+    check_chunk_size, check_compression_level, chunk_seed, decode_stored_size, hash_content,
+    ChunkHash, ChunkStore, Chunker, Chunking, Compression, ContentHasher, CHUNK_SIZE,
+    COMPRESSION_LEVEL, DEFAULT_MIN_GAIN_PERCENT,
+};
+use crate::util::crypto::{ArchiveKey, Encryption, KdfParams};
 use crate::util::errors::AppError;
-use crate::util::header::{patch_u64, write_header, write_placeholder_u64, write_timestamp};
+use crate::util::header::{
+    patch_u64, source_date_epoch, unix_now, write_header, write_placeholder_u64,
+    write_timestamp_fields, Provenance, TimestampFields,
+};
+use crate::util::interrupt::check_interrupted;
+use crate::util::signing::{sign, write_footer, FOOTER_SIZE};
 
-type PackedResult = Result<(String, u64, Vec<ChunkHash>), Box<dyn std::error::Error + Send + Sync>>;
+/// Suffix of the file chunks are spooled to for reproducible archives
+const SPOOL_SUFFIX: &str = ".chunks.tmp";
+
+/// Suffix a failed archive is renamed to under `keep_partial`
+const PARTIAL_SUFFIX: &str = ".partial";
+
+/// Suffix of the log written beside a kept partial archive
+const PARTIAL_LOG_SUFFIX: &str = ".partial.log";
+
+/// Entry type byte of a regular file
+pub(crate) const ENTRY_REGULAR: u8 = 0;
+
+/// Entry type byte of a symlink, followed by its target
+pub(crate) const ENTRY_SYMLINK: u8 = 1;
+
+/// Entry type byte of an empty directory
+pub(crate) const ENTRY_DIRECTORY: u8 = 2;
+
+/// Entry type byte of a hard link, followed by the path of the file it links to
+pub(crate) const ENTRY_HARDLINK: u8 = 3;
+
+/// Layout of the file tables this build writes. Only Unix has permission modes to record
+pub(crate) const WRITTEN_LAYOUT: FileTableLayout = FileTableLayout {
+    modes: cfg!(unix),
+    entry_types: true,
+    file_hashes: true,
+};
+
+/// Files larger than this many chunks are split into segments of this many chunks under
+/// `balance`, so segment boundaries fall on chunk boundaries.
+const BALANCE_SEGMENT_CHUNKS: u64 = 8;
+
+/// Compressed chunks allowed to queue for a rate-limited writer before producers block
+const RATE_LIMITED_QUEUE_CHUNKS: usize = 16;
+
+/// Default number of compressed chunks allowed to wait for the writer thread
+pub const DEFAULT_WRITER_QUEUE_CHUNKS: usize = 64;
+
+/// A file table entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FileMetadata {
+    /// Path relative to the input directory
+    pub path: String,
+    /// Bytes stored for the file; 0 for a symlink, empty directory or hard link
+    pub original_size: u64,
+    pub chunk_hashes: Vec<ChunkHash>,
+    /// Unix permission mode, if known
+    pub mode: Option<u32>,
+    pub kind: EntryKind,
+    /// xxh3-128 hash of a regular file's content, if recorded
+    pub file_hash: Option<ChunkHash>,
+}
+
+/// What a file table entry restores to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum EntryKind {
+    Regular,
+    /// A symlink pointing at the target, stored as written rather than resolved
+    Symlink(String),
+    /// A directory with nothing in it, which no file entry would otherwise recreate
+    Directory,
+    /// A hard link to the regular file entry at this path, which holds the content
+    HardLink(String),
+}
+
+/// Optional fields in each file table entry, announced by flags in the archive header.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct FileTableLayout {
+    /// Entries record a `u32` permission mode
+    pub modes: bool,
+    /// Entries record an entry type byte, followed by the target for symlinks
+    pub entry_types: bool,
+    /// Entries record an xxh3-128 hash of the file's content, all zeros for other entry types
+    pub file_hashes: bool,
+}
+
+impl FileTableLayout {
+    /// Returns the layout announced by an archive's header fields.
+    pub(crate) fn of(fields: &TimestampFields) -> Self {
+        Self {
+            modes: fields.file_modes,
+            entry_types: fields.entry_types,
+            file_hashes: fields.file_hashes,
+        }
+    }
+}
+
+type PackedResult = Result<FileMetadata, AppError>;
+
+/// Optional settings for packing an archive.
+#[derive(Clone)]
+pub struct PackOptions {
+    /// Abort packing once the archive would grow beyond this many bytes
+    pub max_archive_size: Option<u64>,
+    /// Produce byte-identical output for identical input: fixed timestamp, file table sorted by
+    /// path and chunks ordered by first reference rather than by compression completion
+    pub reproducible: bool,
+    /// Directory for intermediate files; defaults to alongside the output archive
+    pub temp_dir: Option<PathBuf>,
+    /// Fail instead of warning when a file changes size while it is being packed
+    pub strict: bool,
+    /// Leave out files that cannot be read instead of failing the pack; see
+    /// [`ArchiveWriter::skipped_files`]
+    pub skip_errors: bool,
+    /// Split files larger than one segment across workers so one huge file cannot leave the
+    /// rest of the pool idle
+    pub balance: bool,
+    /// Chunk pool from `export-chunks`; chunks found in it are referenced but not stored, so the
+    /// archive needs the same pool to unpack
+    pub chunk_pool: Option<PathBuf>,
+    /// Minimum percentage compression must save for a chunk to be stored compressed
+    pub min_gain_percent: f64,
+    /// zstd level chunks are compressed at; higher is smaller but slower. Decompression does
+    /// not depend on it, so it is not recorded in the archive
+    pub compression_level: i32,
+    /// `Compression::None` stores every chunk raw, skipping zstd entirely
+    pub compression: Compression,
+    /// On failure, keep the incomplete archive as `<output>.partial` with a log of how far
+    /// packing got, instead of cleaning it up
+    pub keep_partial: bool,
+    /// Flush the archive every time this much chunk data has been written, rather than only
+    /// at the end
+    pub sync_interval: Option<SyncInterval>,
+    /// Also fsync on every `sync_interval` flush so written chunks survive a power loss
+    pub fsync: bool,
+    /// Reserve the estimated archive size on disk before packing and trim the excess at the
+    /// end, so the output is laid out contiguously instead of growing write by write
+    pub preallocate_archive: bool,
+    /// Which chunks a chunk may be deduplicated against
+    pub dedup_scope: DedupScope,
+    /// xxh3 seed for chunk hashes, recorded in the header. Archives packed with different
+    /// seeds never share chunk hashes, partitioning dedup namespaces (e.g. per tenant) without
+    /// the space cost of a per-file scope; 0 keeps the unseeded hashes
+    pub hash_seed: u64,
+    /// Write the archive no faster than this many bytes per second
+    pub rate_limit: Option<u64>,
+    /// Record this machine's hostname and the squishrs version in the header. Off by default
+    /// since it identifies the machine and makes archives differ between hosts
+    pub provenance: bool,
+    /// Bytes per chunk. Smaller chunks find more duplicates between similar files, larger ones
+    /// cut per-chunk overhead for big media; a size other than `CHUNK_SIZE` is recorded in the
+    /// header
+    pub chunk_size: usize,
+    /// Cut chunks at fixed offsets, or where the content says so, which keeps an edited
+    /// file deduplicating against its earlier versions. Recorded in the header
+    pub chunking: Chunking,
+    /// Record files sharing an inode as hard links to the first of them, so unpacking links
+    /// them again instead of writing separate copies. Only detected on Unix
+    pub preserve_hardlinks: bool,
+    /// Compressed chunks allowed to wait for the writer thread before compression blocks, so a
+    /// disk slower than compression cannot pile chunks up in memory. At most this many times the
+    /// chunk size is held; a rate-limited pack queues no more than 16
+    pub writer_queue: usize,
+    /// Encrypt every chunk and the file table with a key derived from this password. Chunk
+    /// hashes and sizes stay readable, so the archive's layout can still be inspected
+    pub password: Option<String>,
+    /// Argon2id costs the key is derived from `password` with
+    pub kdf: KdfParams,
+    /// Sign everything up to the end of the chunk index with this ed25519 key and close the
+    /// archive with a footer holding the signature and the public key's fingerprint
+    pub signing_key: Option<SigningKey>,
+}
+
+/// How widely chunks are deduplicated when packing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DedupScope {
+    /// Identical chunks are stored once across the whole archive
+    #[default]
+    Global,
+    /// Chunks are only deduplicated within the file they came from. Each file's chunk hashes
+    /// are seeded from its path, so files sharing content cannot be linked through the archive
+    /// and deleting a file never strands chunks another file needs. Content repeated across
+    /// files is stored once per file, so the archive grows by the size of every duplicate
+    File,
+}
+
+impl Default for PackOptions {
+    fn default() -> Self {
+        Self {
+            max_archive_size: None,
+            reproducible: false,
+            temp_dir: None,
+            strict: false,
+            skip_errors: false,
+            balance: false,
+            chunk_pool: None,
+            min_gain_percent: DEFAULT_MIN_GAIN_PERCENT,
+            compression_level: COMPRESSION_LEVEL,
+            compression: Compression::Zstd,
+            keep_partial: false,
+            sync_interval: None,
+            fsync: false,
+            preallocate_archive: false,
+            dedup_scope: DedupScope::Global,
+            hash_seed: 0,
+            rate_limit: None,
+            provenance: false,
+            chunk_size: CHUNK_SIZE,
+            chunking: Chunking::Fixed,
+            preserve_hardlinks: false,
+            writer_queue: DEFAULT_WRITER_QUEUE_CHUNKS,
+            password: None,
+            kdf: KdfParams::default(),
+            signing_key: None,
+        }
+    }
+}
+
+/// A file whose size on disk differed from the number of bytes actually read while packing,
+/// typically because it was being written to at the time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeMismatch {
+    /// Path relative to the input directory
+    pub path: String,
+    /// Size reported by the file's metadata when it was opened
+    pub expected: u64,
+    /// Bytes actually read, which is the size recorded in the archive
+    pub actual: u64,
+}
+
+/// A file left out of the archive because it could not be read, see `skip_errors`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedFile {
+    /// The file's path on disk
+    pub path: PathBuf,
+    /// Why it could not be packed
+    pub reason: String,
+}
 
 pub struct ArchiveWriter {
     writer: Arc<Mutex<BufWriter<File>>>,
@@ -20,17 +270,36 @@ pub struct ArchiveWriter {
     sender: Option<Sender<ChunkMessage>>,
     progress_bar: Option<ProgressBar>,
     input_path: PathBuf,
+    output_path: PathBuf,
+    /// Where the archive is written until packing succeeds and it is renamed to `output_path`
+    staging_path: PathBuf,
+    options: PackOptions,
+    spool_path: Option<PathBuf>,
+    halt: Arc<AtomicBool>,
+    /// Chunk entries the writer thread has written so far
+    chunks_written: Arc<AtomicU64>,
+    pooled_chunks: HashSet<ChunkHash>,
+    /// Primary path, relative to the input directory, of every file packed as a hard link
+    hard_links: HashMap<PathBuf, String>,
+    files_completed: AtomicU64,
+    size_mismatches: Mutex<Vec<SizeMismatch>>,
+    skipped_files: Mutex<Vec<SkippedFile>>,
     chunks_count_position: u64,
-    writer_handle: Option<std::thread::JoinHandle<std::io::Result<()>>>,
+    top_level_count_position: u64,
+    archive_size_position: u64,
+    /// Key chunks and the file table are sealed with when packing with a password
+    key: Option<ArchiveKey>,
+    writer_handle: Option<std::thread::JoinHandle<Result<(), AppError>>>,
 }
 
 impl ArchiveWriter {
     /// Creates a new `ArchiveWriter` for packing files into an archive.
     ///
     /// This function initializes the archive by:
-    /// - Creating and buffering the output file,
+    /// - Creating and buffering a temporary file next to the output, which is renamed into
+    ///   place once packing succeeds,
     /// - Writing the archive header and a timestamp,
-    /// - Reserving space for the number of chunks (to be patched later),
+    /// - Reserving space for the number of chunks and top-level entries (to be patched later),
     /// - Setting up a `ChunkStore` for deduplication,
     /// - Spawning a background writer thread to handle chunk writing,
     /// - Optionally associating a progress bar for visual feedback.
@@ -68,32 +337,167 @@ impl ArchiveWriter {
         output_path: &Path,
         progress_bar: Option<&mut ProgressBar>,
     ) -> Result<Self, AppError> {
-        // Open output writer
-        let output = File::create(output_path)?;
+        Self::with_options(input_dir, output_path, progress_bar, PackOptions::default())
+    }
+
+    /// Creates a new `ArchiveWriter` configured with the given `PackOptions`.
+    ///
+    /// Behaves like [`ArchiveWriter::new`], but allows optional pack settings such as a maximum
+    /// archive size to be supplied.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use squishrs::archive::writer::{ArchiveWriter, PackOptions};
+    /// use std::path::Path;
+    ///
+    /// let options = PackOptions {
+    ///     max_archive_size: Some(1_000_000),
+    ///     ..Default::default()
+    /// };
+    /// let writer = ArchiveWriter::with_options(Path::new("./files"), Path::new("output.squish"), None, options)
+    ///     .expect("Failed to setup writer");
+    /// ```
+    pub fn with_options(
+        input_dir: &Path,
+        output_path: &Path,
+        progress_bar: Option<&mut ProgressBar>,
+        options: PackOptions,
+    ) -> Result<Self, AppError> {
+        // Reject a bad level or chunk size before creating the output
+        check_compression_level(options.compression_level)?;
+        check_chunk_size(options.chunk_size as u64)?;
+        if options.reproducible && options.password.is_some() {
+            return Err(AppError::Unsupported(
+                "reproducible archives cannot be encrypted, every pack picks a new salt and nonces"
+                    .into(),
+            ));
+        }
+
+        // Write under a temporary name so a failed pack never leaves a truncated archive at the
+        // output path, or replaces an earlier archive there
+        let staging_path = staging_output_path(output_path, options.temp_dir.as_deref());
+        let output = File::create(&staging_path)?;
+        Self::start(
+            input_dir,
+            output_path,
+            &staging_path,
+            output,
+            progress_bar,
+            options,
+        )
+        .inspect_err(|_| {
+            let _ = fs::remove_file(&staging_path);
+        })
+    }
+
+    /// Writes the preamble to `output`, the freshly created staging file, and spawns the
+    /// writer thread.
+    fn start(
+        input_dir: &Path,
+        output_path: &Path,
+        staging_path: &Path,
+        output: File,
+        progress_bar: Option<&mut ProgressBar>,
+        options: PackOptions,
+    ) -> Result<Self, AppError> {
         let writer = Arc::new(Mutex::new(BufWriter::new(output)));
 
+        // Each archive gets its own salt, so the same password gives a different key
+        let encryption = options
+            .password
+            .as_ref()
+            .map(|_| Encryption::generate(options.kdf));
+        let key = match (&encryption, &options.password) {
+            (Some(encryption), Some(password)) => Some(encryption.derive_key(password)?),
+            _ => None,
+        };
+
         // Write header and timestamp
-        let chunks_count_position;
-        {
+        let (chunks_count_position, top_level_count_position, archive_size_position, header_size) = {
             let mut guard = writer.lock().map_err(|_| AppError::LockPoisoned)?;
-            write_header(&mut *guard).map_err(AppError::WriterError)?;
-            write_timestamp(&mut *guard).map_err(AppError::WriterError)?;
-
-            // Write placeholder for chunk count
-            chunks_count_position =
-                write_placeholder_u64(&mut *guard).map_err(AppError::WriterError)?;
+            let (chunks_count_position, top_level_count_position, archive_size_position) =
+                write_preamble(&mut *guard, &options, encryption.as_ref())?;
+            let header_size = guard.stream_position().map_err(AppError::WriterError)?;
             guard.flush()?;
-        }
+            (
+                chunks_count_position,
+                top_level_count_position,
+                archive_size_position,
+                header_size,
+            )
+        };
 
-        let chunk_store = ChunkStore::new();
-        let (sender, receiver) = unbounded::<ChunkMessage>();
+        // Chunks already in the pool count as stored, so only novel chunks are written
+        let chunk_store = ChunkStore::with_min_gain(options.min_gain_percent)
+            .with_level(options.compression_level)?
+            .with_compression(options.compression);
+        let pooled_chunks = match &options.chunk_pool {
+            Some(pool_path) => read_pool_hashes(pool_path)?,
+            None => HashSet::new(),
+        };
+        chunk_store.seed(pooled_chunks.iter().copied());
+        // A writer that falls behind compression makes producers wait once the queue is full,
+        // so memory stays flat. A throttled writer gets a shorter queue, so progress also tracks
+        // the rate actually written
+        let queue_chunks = match options.rate_limit {
+            Some(_) => options.writer_queue.min(RATE_LIMITED_QUEUE_CHUNKS),
+            None => options.writer_queue,
+        };
+        let (sender, receiver) = bounded::<ChunkMessage>(queue_chunks.max(1));
 
-        // Spawn writer thread
-        let thread_safe_writer = ThreadSafeWriter::new(Arc::clone(&writer));
-        let handle = std::thread::spawn(move || -> std::io::Result<()> {
-            writer_thread(thread_safe_writer, receiver)
-                .map_err(|_e| std::io::Error::other("Writer Thread Failed"))
-        });
+        // Shared flag the writer thread raises to stop producers early
+        let halt = Arc::new(AtomicBool::new(false));
+        let chunks_written = Arc::new(AtomicU64::new(0));
+        let mut writer_config = WriterConfig {
+            size_limit: options.max_archive_size.map(|max_bytes| SizeLimit {
+                max_bytes,
+                bytes_written: header_size,
+                halt: Arc::clone(&halt),
+            }),
+            sync: None,
+            rate_limit: None,
+            chunks_written: Some(Arc::clone(&chunks_written)),
+        };
+
+        // Syncing needs its own handle to whichever file the writer thread ends up writing to
+        let sync_policy = |file: &File| -> Result<Option<SyncPolicy>, AppError> {
+            let Some(interval) = options.sync_interval else {
+                return Ok(None);
+            };
+            let fsync = if options.fsync {
+                Some(file.try_clone().map_err(AppError::WriterError)?)
+            } else {
+                None
+            };
+            Ok(Some(SyncPolicy { interval, fsync }))
+        };
+
+        // Spawn writer thread. Reproducible archives spool chunks to a side file first, as
+        // parallel compression finishes chunks in a nondeterministic order
+        let spool_path = options
+            .reproducible
+            .then(|| spool_output_path(output_path, options.temp_dir.as_deref()));
+        let handle = match &spool_path {
+            Some(spool_path) => {
+                let spool_file = File::create(spool_path)
+                    .map_err(|e| AppError::CreateFileError(spool_path.clone(), e))?;
+                writer_config.sync = sync_policy(&spool_file)?;
+                let spool = BufWriter::new(spool_file);
+                std::thread::spawn(move || -> Result<(), AppError> {
+                    writer_thread(spool, receiver, writer_config)
+                })
+            }
+            None => {
+                writer_config.sync =
+                    sync_policy(writer.lock().map_err(|_| AppError::LockPoisoned)?.get_ref())?;
+                writer_config.rate_limit = options.rate_limit.map(TokenBucket::new);
+                let thread_safe_writer = ThreadSafeWriter::new(Arc::clone(&writer));
+                std::thread::spawn(move || -> Result<(), AppError> {
+                    writer_thread(thread_safe_writer, receiver, writer_config)
+                })
+            }
+        };
 
         Ok(Self {
             writer,
@@ -101,7 +505,21 @@ impl ArchiveWriter {
             sender: Some(sender),
             progress_bar: progress_bar.cloned(),
             input_path: input_dir.to_path_buf(),
+            output_path: output_path.to_path_buf(),
+            staging_path: staging_path.to_path_buf(),
+            options,
+            spool_path,
+            halt,
+            chunks_written,
+            pooled_chunks,
+            hard_links: HashMap::new(),
+            files_completed: AtomicU64::new(0),
+            size_mismatches: Mutex::new(Vec::new()),
+            skipped_files: Mutex::new(Vec::new()),
             chunks_count_position,
+            top_level_count_position,
+            archive_size_position,
+            key,
             writer_handle: Some(handle),
         })
     }
@@ -125,14 +543,24 @@ impl ArchiveWriter {
     /// # Returns
     ///
     /// * `Ok(u64)` - The total size of the resulting archive in bytes, if the operation is successful.
-    /// * `Err(Box<dyn std::error::Error>)` - If any I/O, thread join, or metadata-related error occurs.
+    /// * `Err(AppError)` - If any I/O, thread join, or metadata-related error occurs.
     ///
     /// # Errors
     ///
     /// Returns an error if:
     /// - Any file fails to be read or processed,
     /// - The writer thread fails or panics,
-    /// - File metadata cannot be written or retrieved.
+    /// - File metadata cannot be written or retrieved,
+    /// - The archive would exceed the configured `max_archive_size`, in which case the partial
+    ///   output is removed.
+    /// - The disk fills up, reported as `AppError::Archive` naming the archive and how many
+    ///   chunks were written; the partial output is removed.
+    ///
+    /// The archive is written to a temporary file next to the output and renamed into place
+    /// only once packing succeeds, so on any error nothing is left at the output path and an
+    /// archive already there is untouched. With `keep_partial` set, the failed archive is
+    /// renamed to `<output>.partial` and described in `<output>.partial.log` instead of being
+    /// removed.
     ///
     /// # Example
     ///
@@ -149,11 +577,104 @@ impl ArchiveWriter {
     /// println!("Archive written ({} bytes)", archive_size);
     /// ```
     pub fn pack(&mut self, files: &[PathBuf]) -> Result<u64, AppError> {
+        self.run_pack(|writer| writer.pack_files(files))
+    }
+
+    /// Packs in-memory entries into the archive, without reading anything from disk.
+    ///
+    /// Each entry's bytes are chunked and deduplicated exactly like a packed file's, so the
+    /// result unpacks like any other archive. The input directory given to the constructor is
+    /// not used. Entries carry no permission mode, and `preallocate_archive` is ignored.
+    ///
+    /// # Arguments
+    /// * `entries` - `(path, bytes)` pairs, where each path is relative and used as-is in the
+    ///   file table
+    ///
+    /// # Returns
+    /// The size of the finished archive in bytes.
+    ///
+    /// # Errors
+    /// Returns an error if a path is empty, absolute or escapes the archive root, if two entries
+    /// share a path, or for any reason [`ArchiveWriter::pack`] can fail.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use squishrs::archive::ArchiveWriter;
+    /// use std::path::Path;
+    ///
+    /// let entries = vec![
+    ///     ("config/app.toml".to_string(), b"debug = false\n".to_vec()),
+    ///     ("README".to_string(), b"Generated bundle\n".to_vec()),
+    /// ];
+    /// let mut writer = ArchiveWriter::new(Path::new("."), Path::new("bundle.squish"), None)
+    ///     .expect("Failed to setup writer");
+    /// writer.pack_entries(entries).expect("Failed to pack entries");
+    /// ```
+    pub fn pack_entries<I>(&mut self, entries: I) -> Result<u64, AppError>
+    where
+        I: IntoIterator<Item = (String, Vec<u8>)>,
+    {
+        let entries: Vec<(String, Vec<u8>)> = entries.into_iter().collect();
+        self.run_pack(|writer| writer.pack_in_memory(&entries))
+    }
+
+    /// Runs a pack, turning a full disk into a clear error and keeping or removing the partial
+    /// archive on failure.
+    fn run_pack(
+        &mut self,
+        pack: impl FnOnce(&mut Self) -> Result<u64, AppError>,
+    ) -> Result<u64, AppError> {
+        let result = pack(self).map_err(|e| {
+            if is_out_of_space(&e) {
+                self.abort_out_of_space()
+            } else {
+                e
+            }
+        });
+
+        match &result {
+            Ok(_) => self.commit_output()?,
+            Err(e) if self.options.keep_partial => self.preserve_partial(e),
+            Err(_) => self.discard_output(),
+        }
+
+        result
+    }
+
+    /// Renames the finished archive from its staging path to the output path, replacing any
+    /// archive already there.
+    fn commit_output(&self) -> Result<(), AppError> {
+        fs::rename(&self.staging_path, &self.output_path).map_err(|e| {
+            let _ = fs::remove_file(&self.staging_path);
+            AppError::WriterError(e)
+        })
+    }
+
+    fn pack_files(&mut self, files: &[PathBuf]) -> Result<u64, AppError> {
+        if self.options.preallocate_archive {
+            self.preallocate_output(files)?;
+        }
+        if self.options.preserve_hardlinks {
+            for (link, primary) in find_hard_links(files) {
+                let primary = archive_path(self.relative_path(&primary)?);
+                self.hard_links.insert(link, primary);
+            }
+        }
+
         // Run process_file function concurrently
-        let files_metadata: Vec<_> = files
+        let files_result = files
             .par_iter()
-            .map(|file_path| -> PackedResult {
-                let result = self.process_file(file_path)?;
+            .map(|file_path| -> Result<Option<FileMetadata>, AppError> {
+                check_interrupted()?;
+                let result = match self.process_file(file_path) {
+                    Err(e) if self.options.skip_errors && is_read_error(&e) => {
+                        self.skip_file(file_path, e.to_string())?;
+                        None
+                    }
+                    result => Some(result?),
+                };
+                self.files_completed.fetch_add(1, Ordering::SeqCst);
 
                 // Increment progres bar if present
                 if let Some(pb) = self.progress_bar.as_ref() {
@@ -162,38 +683,414 @@ impl ArchiveWriter {
 
                 Ok(result)
             })
-            .collect::<Result<Vec<_>, _>>()?;
+            .collect::<Result<Vec<_>, _>>()
+            .and_then(|packed| self.drop_links_to_skipped(packed.into_iter().flatten().collect()));
+
+        self.finish_pack(files_result)
+    }
+
+    fn pack_in_memory(&mut self, entries: &[(String, Vec<u8>)]) -> Result<u64, AppError> {
+        let mut paths = HashSet::with_capacity(entries.len());
+        for (path, _) in entries {
+            if path.is_empty() {
+                return Err(AppError::UnsafePath(path.into()));
+            }
+            validate_relative_path(Path::new(path))?;
+            if !paths.insert(path.as_str()) {
+                return Err(AppError::FileExistsInArchive(path.clone()));
+            }
+        }
+
+        let files_result = entries
+            .par_iter()
+            .map(|(path, data)| -> PackedResult {
+                let result = self.process_entry(path, data)?;
+                self.files_completed.fetch_add(1, Ordering::SeqCst);
+
+                if let Some(pb) = self.progress_bar.as_ref() {
+                    pb.inc(1);
+                }
+
+                Ok(result)
+            })
+            .collect::<Result<Vec<_>, _>>();
+
+        self.finish_pack(files_result)
+    }
 
+    /// Waits for every chunk to be written, then writes the file table and patches the header.
+    ///
+    /// # Returns
+    /// The size of the finished archive in bytes.
+    fn finish_pack(
+        &mut self,
+        files_result: Result<Vec<FileMetadata>, AppError>,
+    ) -> Result<u64, AppError> {
         // Close sender so writer thread can finish
         if let Some(sender) = self.sender.take() {
             drop(sender);
         }
 
         if let Some(handle) = self.writer_handle.take() {
-            handle.join().expect("Writer thread panicked")?;
+            let writer_result = handle.join().expect("Writer thread panicked");
+            if self.halt.load(Ordering::SeqCst) {
+                return Err(self.abort_size_limit_exceeded());
+            }
+            writer_result?;
+        }
+
+        let mut files_metadata = files_result?;
+
+        if self.options.reproducible {
+            files_metadata.sort_by(|a, b| a.path.cmp(&b.path));
+            self.write_spooled_chunks(&files_metadata)?;
         }
 
         // Write number of chunks in the placeholder
         {
             let mut guard = self.writer.lock().map_err(|_| AppError::LockPoisoned)?;
+            let file_table_position = guard.stream_position().map_err(AppError::WriterError)?;
             patch_u64(
                 &mut *guard,
                 self.chunks_count_position,
-                self.chunk_store.len(),
+                self.chunk_store.len() - self.pooled_chunks.len() as u64,
             )?;
+            patch_u64(
+                &mut *guard,
+                self.top_level_count_position,
+                count_top_level_entries(&files_metadata),
+            )?;
+
+            // Patching leaves the writer at the end of the file, which is past the last chunk
+            // while the file is preallocated
+            guard
+                .seek(SeekFrom::Start(file_table_position))
+                .map_err(AppError::WriterError)?;
         }
 
         // Write metadata at the end
         self.write_files_metadata(&files_metadata)?;
+        self.write_chunk_index()?;
+
+        let body_end = {
+            let mut guard = self.writer.lock().map_err(|_| AppError::LockPoisoned)?;
+            let body_end = guard.stream_position().map_err(AppError::WriterError)?;
+            // The footer's size is fixed, so the archive size is final before anything is signed
+            let end = match self.options.signing_key {
+                Some(_) => body_end + FOOTER_SIZE,
+                None => body_end,
+            };
+            // Give back the preallocated space the archive did not need
+            if self.options.preallocate_archive {
+                guard
+                    .get_ref()
+                    .set_len(end)
+                    .map_err(AppError::WriterError)?;
+            }
+            patch_u64(&mut *guard, self.archive_size_position, end)?;
+            guard.flush().map_err(AppError::FlushError)?;
+            body_end
+        };
+        self.write_signature(body_end)?;
 
         // Return archive size
-        let guard = self.writer.lock().map_err(|_| AppError::LockPoisoned)?;
-        let file = guard.get_ref();
-        let size = file.metadata()?.len();
+        let size = {
+            let guard = self.writer.lock().map_err(|_| AppError::LockPoisoned)?;
+            guard.get_ref().metadata()?.len()
+        };
+
+        // The file table is only known at the end, so check the limit once more
+        if let Some(max_archive_size) = self.options.max_archive_size {
+            if size > max_archive_size {
+                return Err(self.abort_size_limit_exceeded());
+            }
+        }
 
         Ok(size)
     }
 
+    /// Appends the chunk index after the file table, so readers can locate chunks without
+    /// walking the chunk table.
+    ///
+    /// Chunk offsets are read back from the finished chunk table rather than tracked while
+    /// writing, as chunks reach the writer thread in no fixed order.
+    fn write_chunk_index(&mut self) -> Result<(), AppError> {
+        let mut guard = self.writer.lock().map_err(|_| AppError::LockPoisoned)?;
+        guard.flush().map_err(AppError::FlushError)?;
+        let position = guard.stream_position().map_err(AppError::WriterError)?;
+
+        let mut reader = BufReader::new(
+            File::open(&self.staging_path)
+                .map_err(|_| AppError::FileNotExist(self.staging_path.clone()))?,
+        );
+        reader
+            .seek(SeekFrom::Start(self.top_level_count_position + 8))
+            .map_err(AppError::ReaderError)?;
+        let entries = index_chunk_table(
+            &mut reader,
+            self.chunk_store.len() - self.pooled_chunks.len() as u64,
+        )?;
+
+        write_chunk_index(&mut *guard, &entries, position)?;
+        guard.flush().map_err(AppError::FlushError)
+    }
+
+    /// Signs the first `body_end` bytes of the finished archive and appends the signature
+    /// footer, when packing with a signing key.
+    ///
+    /// The signed bytes are read back from the staging file, since the writer thread wrote the
+    /// chunks in no fixed order and the header counts were patched afterwards.
+    fn write_signature(&mut self, body_end: u64) -> Result<(), AppError> {
+        let Some(key) = &self.options.signing_key else {
+            return Ok(());
+        };
+
+        let reader = BufReader::new(
+            File::open(&self.staging_path)
+                .map_err(|_| AppError::FileNotExist(self.staging_path.clone()))?,
+        );
+        let footer = sign(reader, body_end, key)?;
+
+        let mut guard = self.writer.lock().map_err(|_| AppError::LockPoisoned)?;
+        guard
+            .seek(SeekFrom::Start(body_end))
+            .map_err(AppError::WriterError)?;
+        write_footer(&mut *guard, &footer)?;
+        guard.flush().map_err(AppError::FlushError)
+    }
+
+    /// Reserves disk space for the archive before any chunk is written.
+    ///
+    /// The estimate assumes nothing compresses or deduplicates, so it is rarely too small; the
+    /// excess is trimmed once the file table is written. Preallocation is only an optimisation,
+    /// so failing to reserve the space, e.g. on a nearly full disk, is not an error.
+    fn preallocate_output(&self, files: &[PathBuf]) -> Result<(), AppError> {
+        let input_bytes: u64 = files
+            .iter()
+            .filter_map(|file| fs::metadata(file).ok())
+            .map(|metadata| metadata.len())
+            .sum();
+        let path_bytes: u64 = files.iter().map(|file| file.as_os_str().len() as u64).sum();
+
+        // Chunk entries carry a hash and two sizes; file entries a path, a size, a chunk count
+        // and one hash per chunk
+        let chunk_entries = input_bytes / self.options.chunk_size as u64 + files.len() as u64;
+        let estimate = input_bytes
+            + chunk_entries * (16 + 8 + 8 + 16)
+            + path_bytes
+            + files.len() as u64 * (4 + 8 + 4);
+
+        let mut guard = self.writer.lock().map_err(|_| AppError::LockPoisoned)?;
+        let header_size = guard.stream_position().map_err(AppError::WriterError)?;
+        let _ = preallocate(guard.get_ref(), header_size + estimate);
+        Ok(())
+    }
+
+    /// Returns the files left out of the archive because they could not be read.
+    ///
+    /// Only filled in with `skip_errors`; without it the first such file fails the pack. Chunks
+    /// a skipped file had already stored before the failure stay in the archive unreferenced
+    /// until it is compacted.
+    pub fn skipped_files(&self) -> Vec<SkippedFile> {
+        self.skipped_files
+            .lock()
+            .map(|skipped| skipped.clone())
+            .unwrap_or_default()
+    }
+
+    /// Records `file_path` as skipped because of `reason`.
+    fn skip_file(&self, file_path: &Path, reason: String) -> Result<(), AppError> {
+        self.skipped_files
+            .lock()
+            .map_err(|_| AppError::LockPoisoned)?
+            .push(SkippedFile {
+                path: file_path.to_path_buf(),
+                reason,
+            });
+        Ok(())
+    }
+
+    /// Skips the hard links whose primary was skipped, since they would have nothing to point
+    /// at. The links share the primary's inode, so they could not have been read either.
+    fn drop_links_to_skipped(
+        &self,
+        files_metadata: Vec<FileMetadata>,
+    ) -> Result<Vec<FileMetadata>, AppError> {
+        if self.hard_links.is_empty() || self.skipped_files().is_empty() {
+            return Ok(files_metadata);
+        }
+        let packed: HashSet<String> = files_metadata
+            .iter()
+            .map(|entry| entry.path.clone())
+            .collect();
+
+        let mut kept = Vec::with_capacity(files_metadata.len());
+        for entry in files_metadata {
+            match &entry.kind {
+                EntryKind::HardLink(primary) if !packed.contains(primary) => self.skip_file(
+                    &self.input_path.join(&entry.path),
+                    format!("its hard link target `{primary}` was skipped"),
+                )?,
+                _ => kept.push(entry),
+            }
+        }
+        Ok(kept)
+    }
+
+    /// Returns the files whose size changed while they were being packed.
+    ///
+    /// Each one was stored with the number of bytes actually read, so the archive stays
+    /// internally consistent. Always empty in `strict` mode, where a mismatch fails the pack.
+    pub fn size_mismatches(&self) -> Vec<SizeMismatch> {
+        self.size_mismatches
+            .lock()
+            .map(|mismatches| mismatches.clone())
+            .unwrap_or_default()
+    }
+
+    /// Returns the hashes of the chunks stored in this archive, excluding any that were
+    /// deduplicated against a chunk pool.
+    pub fn stored_chunk_hashes(&self) -> Vec<ChunkHash> {
+        self.chunk_store
+            .primary_store
+            .iter()
+            .map(|entry| *entry.key())
+            .filter(|hash| !self.pooled_chunks.contains(hash))
+            .collect()
+    }
+
+    /// Removes the partially written archive and builds the error reported when the
+    /// configured `max_archive_size` is exceeded.
+    fn abort_size_limit_exceeded(&self) -> AppError {
+        self.discard_output();
+        AppError::Archive(format!(
+            "archive size limit exceeded ({} files packed before the limit was hit)",
+            self.files_completed.load(Ordering::SeqCst)
+        ))
+    }
+
+    /// Removes the partially written archive and builds the error reported when the disk
+    /// fills up, naming the archive and how many chunks made it out before it did.
+    fn abort_out_of_space(&self) -> AppError {
+        self.discard_output();
+        AppError::Archive(format!(
+            "ran out of disk space while writing {} after {} chunks",
+            self.output_path.display(),
+            self.chunks_written.load(Ordering::SeqCst)
+        ))
+    }
+
+    /// Removes the incomplete archive and any spool file, unless `keep_partial` asks for them
+    /// to be kept.
+    fn discard_output(&self) {
+        if !self.options.keep_partial {
+            let _ = fs::remove_file(&self.staging_path);
+            if let Some(spool_path) = &self.spool_path {
+                let _ = fs::remove_file(spool_path);
+            }
+        }
+    }
+
+    /// Keeps a failed archive for inspection instead of leaving it at the output path.
+    ///
+    /// The incomplete output is renamed to `<output>.partial` and a `<output>.partial.log` is
+    /// written beside it recording how far packing got and the error that stopped it. Failures
+    /// here are ignored, since the original error is the one worth reporting.
+    fn preserve_partial(&self, error: &AppError) {
+        if let Ok(mut guard) = self.writer.lock() {
+            let _ = guard.flush();
+        }
+
+        let partial_path = scratch_path(&self.output_path, None, PARTIAL_SUFFIX);
+        let log_path = scratch_path(&self.output_path, None, PARTIAL_LOG_SUFFIX);
+        let _ = fs::rename(&self.staging_path, &partial_path);
+
+        let log = format!(
+            "output: {}\nfiles processed: {}\nchunks stored: {}\nbytes written: {}\nspool: {}\nerror: {}\n",
+            self.output_path.display(),
+            self.files_completed.load(Ordering::SeqCst),
+            self.chunk_store.len() - self.pooled_chunks.len() as u64,
+            fs::metadata(&partial_path).map_or(0, |metadata| metadata.len()),
+            self.spool_path
+                .as_ref()
+                .map_or_else(|| "none".to_string(), |path| path.display().to_string()),
+            error
+        );
+        let _ = fs::write(log_path, log);
+    }
+
+    /// Copies chunks from the spool file into the archive in a deterministic order.
+    ///
+    /// Chunks are emitted in the order they are first referenced when walking `files_metadata`
+    /// front to back, so the chunk table no longer depends on which worker finished compressing
+    /// first. The spool file is removed afterwards.
+    ///
+    /// # Errors
+    /// Returns an error if the spool cannot be read or the archive cannot be written.
+    fn write_spooled_chunks(&self, files_metadata: &[FileMetadata]) -> Result<(), AppError> {
+        let Some(spool_path) = &self.spool_path else {
+            return Ok(());
+        };
+
+        let mut spool = BufReader::new(File::open(spool_path).map_err(AppError::ReaderError)?);
+        let spool_len = spool.get_ref().metadata()?.len();
+
+        // Index every spooled chunk entry: hash -> (offset, entry length)
+        let mut entries: HashMap<ChunkHash, (u64, u64)> = HashMap::new();
+        let mut offset = 0;
+        let mut hash = [0u8; 16];
+        let mut buf8 = [0u8; 8];
+        while offset < spool_len {
+            spool.read_exact(&mut hash).map_err(AppError::ReaderError)?;
+            spool.read_exact(&mut buf8).map_err(AppError::ReaderError)?;
+            spool.read_exact(&mut buf8).map_err(AppError::ReaderError)?;
+            let (compressed_size, _) = decode_stored_size(u64::from_le_bytes(buf8));
+            spool
+                .seek(SeekFrom::Current(compressed_size as i64))
+                .map_err(AppError::ReaderError)?;
+
+            let entry_len = 16 + 8 + 8 + compressed_size;
+            entries.insert(hash, (offset, entry_len));
+            offset += entry_len;
+        }
+
+        {
+            let mut guard = self.writer.lock().map_err(|_| AppError::LockPoisoned)?;
+            let mut written = HashSet::with_capacity(entries.len());
+            // Chunks reach the archive here rather than in the writer thread, so throttle here
+            let mut rate_limit = self.options.rate_limit.map(TokenBucket::new);
+
+            for FileMetadata {
+                path, chunk_hashes, ..
+            } in files_metadata
+            {
+                for hash in chunk_hashes {
+                    if self.pooled_chunks.contains(hash) || !written.insert(*hash) {
+                        continue;
+                    }
+                    let (offset, entry_len) = entries
+                        .get(hash)
+                        .copied()
+                        .ok_or_else(|| AppError::MissingChunk(path.into()))?;
+
+                    if let Some(bucket) = rate_limit.as_mut() {
+                        bucket.take(entry_len);
+                    }
+                    spool
+                        .seek(SeekFrom::Start(offset))
+                        .map_err(AppError::ReaderError)?;
+                    std::io::copy(&mut (&mut spool).take(entry_len), &mut *guard)
+                        .map_err(AppError::WriterError)?;
+                }
+            }
+            guard.flush().map_err(AppError::WriterError)?;
+        }
+
+        drop(spool);
+        fs::remove_file(spool_path)?;
+        Ok(())
+    }
+
     /// Processes a single file by reading it in fixed-size chunks, inserting those chunks into
     /// a chunk store, and optionally sending compressed chunk data through a channel.
     ///
@@ -207,6 +1104,7 @@ impl ArchiveWriter {
     /// - The file path relative to the configured input directory as a `String`.
     /// - The original uncompressed size of the file as a `u64`.
     /// - A `Vec` of 16-byte chunk hashes (`[u8; 16]`) representing the chunks of the file.
+    /// - The file's Unix permission mode, on platforms that have one.
     ///
     /// # Errors
     ///
@@ -221,110 +1119,563 @@ impl ArchiveWriter {
     ///
     /// The method:
     /// - Opens the file and obtains its size.
-    /// - Reads the file in chunks of size `CHUNK_SIZE`.
+    /// - Reads the file in chunks of `PackOptions::chunk_size` bytes.
     /// - Inserts each chunk into the chunk store, which may return compressed data.
     /// - If compressed data is returned, it sends a `ChunkMessage` containing the chunk hash,
     ///   compressed data, and original chunk size through a channel.
     /// - Collects all chunk hashes to associate with the processed file.
     fn process_file(&self, file_path: &Path) -> PackedResult {
-        let rel_path = file_path.strip_prefix(&self.input_path)?;
-        let rel_path_str = rel_path.to_string_lossy();
+        let rel_path_str = archive_path(self.relative_path(file_path)?);
+
+        // Symlinks are recorded rather than followed, so they store no chunks
+        let link_metadata = fs::symlink_metadata(file_path)?;
+        if link_metadata.file_type().is_symlink() {
+            let target = fs::read_link(file_path)?;
+            return Ok(FileMetadata {
+                path: rel_path_str.to_string(),
+                original_size: 0,
+                chunk_hashes: Vec::new(),
+                mode: None,
+                kind: EntryKind::Symlink(target.to_string_lossy().to_string()),
+                file_hash: None,
+            });
+        }
+        // Later links to an inode store no chunks and point at the first path packed for it
+        if let Some(primary) = self.hard_links.get(file_path) {
+            return Ok(FileMetadata {
+                path: rel_path_str.to_string(),
+                original_size: 0,
+                chunk_hashes: Vec::new(),
+                mode: None,
+                kind: EntryKind::HardLink(primary.clone()),
+                file_hash: None,
+            });
+        }
+        if link_metadata.is_dir() {
+            return Ok(FileMetadata {
+                path: rel_path_str.to_string(),
+                original_size: 0,
+                chunk_hashes: Vec::new(),
+                mode: file_mode(&link_metadata),
+                kind: EntryKind::Directory,
+                file_hash: None,
+            });
+        }
 
         let file = File::open(file_path)?;
         let metadata = file.metadata()?;
         let orig_file_size = metadata.len();
 
-        let mut reader = BufReader::new(file);
+        let scope = match self.options.dedup_scope {
+            DedupScope::Global => chunk_seed(self.options.hash_seed, None),
+            DedupScope::File => chunk_seed(self.options.hash_seed, Some(&rel_path_str)),
+        };
+
+        let (file_chunk_hashes, total_bytes_read, file_hash) =
+            // Content-defined boundaries depend on everything before them, so a file cannot be
+            // split into independently chunked segments
+            if self.options.balance
+                && self.options.chunking == Chunking::Fixed
+                && orig_file_size > self.balance_segment_size()
+            {
+                let (hashes, bytes_read) =
+                    self.process_segments(file_path, orig_file_size, scope)?;
+                // Segments are read in parallel, so the whole-file hash takes a pass of its own,
+                // over no more than was stored in case the file grew in the meantime
+                let file_hash = hash_content(&mut BufReader::new(file).take(bytes_read))
+                    .map_err(AppError::ReaderError)?;
+                (hashes, bytes_read, file_hash)
+            } else {
+                self.process_chunks(&mut BufReader::new(file), scope)?
+            };
+
+        // The file grew or shrank after it was opened; record what was actually stored
+        if total_bytes_read != orig_file_size {
+            if self.options.strict {
+                return Err(AppError::FileSizeChanged(
+                    file_path.to_path_buf(),
+                    orig_file_size,
+                    total_bytes_read,
+                ));
+            }
+            self.size_mismatches
+                .lock()
+                .map_err(|_| AppError::LockPoisoned)?
+                .push(SizeMismatch {
+                    path: rel_path_str.to_string(),
+                    expected: orig_file_size,
+                    actual: total_bytes_read,
+                });
+        }
+
+        Ok(FileMetadata {
+            path: rel_path_str.to_string(),
+            original_size: total_bytes_read,
+            chunk_hashes: file_chunk_hashes,
+            mode: file_mode(&metadata),
+            kind: EntryKind::Regular,
+            file_hash: Some(file_hash),
+        })
+    }
+
+    /// Path of `file_path` relative to the input directory, as recorded in the file table.
+    fn relative_path<'a>(&self, file_path: &'a Path) -> Result<&'a Path, AppError> {
+        file_path.strip_prefix(&self.input_path).map_err(|_| {
+            AppError::Other(format!(
+                "`{}` is not inside `{}`",
+                file_path.display(),
+                self.input_path.display()
+            ))
+        })
+    }
+
+    /// Size in bytes of the segments `balance` splits large files into.
+    fn balance_segment_size(&self) -> u64 {
+        BALANCE_SEGMENT_CHUNKS * self.options.chunk_size as u64
+    }
+
+    /// Chunks an in-memory entry the way [`ArchiveWriter::process_file`] chunks a file.
+    fn process_entry(&self, path: &str, data: &[u8]) -> PackedResult {
+        let scope = match self.options.dedup_scope {
+            DedupScope::Global => chunk_seed(self.options.hash_seed, None),
+            DedupScope::File => chunk_seed(self.options.hash_seed, Some(path)),
+        };
+        let (file_chunk_hashes, total_bytes_read, file_hash) =
+            self.process_chunks(&mut &data[..], scope)?;
+
+        Ok(FileMetadata {
+            path: path.to_string(),
+            original_size: total_bytes_read,
+            chunk_hashes: file_chunk_hashes,
+            mode: None,
+            kind: EntryKind::Regular,
+            file_hash: Some(file_hash),
+        })
+    }
+
+    /// Splits a large file into chunk-aligned segments of `BALANCE_SEGMENT_CHUNKS` chunks and
+    /// processes them in parallel.
+    ///
+    /// Each segment opens its own handle to the file, so rayon's work stealing can spread a
+    /// single huge file across idle workers. Hashes are returned in file order. The final
+    /// segment reads through to the end of the file, so data appended after `expected_size` was
+    /// taken is still stored.
+    fn process_segments(
+        &self,
+        file_path: &Path,
+        expected_size: u64,
+        scope: Option<u64>,
+    ) -> Result<(Vec<ChunkHash>, u64), AppError> {
+        let segment_size = self.balance_segment_size();
+        let segment_count = expected_size.div_ceil(segment_size);
+
+        let segments = (0..segment_count)
+            .into_par_iter()
+            .map(|segment| -> Result<(Vec<ChunkHash>, u64), AppError> {
+                let mut file = File::open(file_path)?;
+                file.seek(SeekFrom::Start(segment * segment_size))
+                    .map_err(AppError::ReaderError)?;
+                let mut reader = BufReader::new(file);
+
+                // A segment's own content hash is of no use, the whole file is hashed separately
+                let (hashes, bytes_read, _) = if segment + 1 == segment_count {
+                    self.process_chunks(&mut reader, scope)?
+                } else {
+                    self.process_chunks(&mut (&mut reader).take(segment_size), scope)?
+                };
+                Ok((hashes, bytes_read))
+            })
+            .collect::<Result<Vec<_>, AppError>>()?;
+
         let mut file_chunk_hashes = Vec::new();
+        let mut total_bytes_read = 0;
+        for (hashes, bytes_read) in segments {
+            file_chunk_hashes.extend(hashes);
+            total_bytes_read += bytes_read;
+        }
+
+        Ok((file_chunk_hashes, total_bytes_read))
+    }
 
-        let mut chunk_buf = vec![0u8; CHUNK_SIZE];
+    /// Splits `reader` into chunks as `PackOptions::chunking` says, inserting each into the chunk
+    /// store and sending newly compressed chunks to the writer thread. Chunks are deduplicated
+    /// within `scope`, or across the whole archive when it is `None`.
+    ///
+    /// # Returns
+    ///
+    /// The chunk hashes in the order they were read, the total number of bytes read, and the
+    /// xxh3-128 hash of everything read.
+    fn process_chunks<R: Read>(
+        &self,
+        reader: &mut R,
+        scope: Option<u64>,
+    ) -> Result<(Vec<ChunkHash>, u64, ChunkHash), AppError> {
+        let mut file_chunk_hashes = Vec::new();
+        let mut total_bytes_read = 0u64;
+        let mut content_hasher = ContentHasher::new();
+
+        let mut chunker = Chunker::new(reader, self.options.chunk_size, self.options.chunking);
         loop {
-            let bytes_read = reader.read(&mut chunk_buf).map_err(AppError::ReaderError)?;
-            if bytes_read == 0 {
-                break;
+            // Stop early once the writer thread has hit the size limit
+            if self.halt.load(Ordering::SeqCst) {
+                return Err(AppError::Other("Packing halted by writer thread".into()));
             }
-            let slice = &chunk_buf[..bytes_read];
+            // A large file would otherwise finish before Ctrl-C is noticed
+            check_interrupted()?;
+
+            let Some(slice) = chunker.next_chunk().map_err(AppError::ReaderError)? else {
+                break;
+            };
+            total_bytes_read += slice.len() as u64;
+            content_hasher.update(slice);
 
             // Insert chunk via ChunkStore
-            let result = self.chunk_store.insert(slice)?;
+            let result = self.chunk_store.insert_scoped(slice, scope)?;
 
             if let Some(compressed) = result.compressed_data {
+                // Sealing here keeps encryption on the worker threads, off the writer thread
+                let compressed = match &self.key {
+                    Some(key) => Arc::new(key.seal(&compressed, &result.hash)?),
+                    None => compressed,
+                };
                 let msg = ChunkMessage {
                     hash: result.hash,
                     compressed_data: compressed,
-                    original_size: chunk_buf.len() as u64,
+                    original_size: slice.len() as u64,
+                    stored_raw: result.stored_raw,
                 };
                 if let Some(sender) = &self.sender {
                     sender
                         .send(msg)
                         .map_err(|e| AppError::SenderError(Box::new(e)))?;
                 } else {
-                    // sender is None, maybe return an error or handle accordingly
-                    return Err("Sender channel is closed".into());
+                    return Err(AppError::Other("Sender channel is closed".into()));
                 }
             }
             // Calculate chunk hash and store it for the file metadata
             file_chunk_hashes.push(result.hash);
         }
 
-        Ok((rel_path_str.to_string(), orig_file_size, file_chunk_hashes))
+        Ok((file_chunk_hashes, total_bytes_read, content_hasher.finish()))
     }
 
     /// Writes file metadata at the end of the archive using the shared writer.
     ///
-    /// This method locks the internal writer once and then writes:
-    /// 1. Number of files in the archive (`u32`, little-endian)
-    /// 2. For each file:
-    ///    - Path length (`u32`, little-endian)
-    ///    - Path bytes (UTF-8)
-    ///    - Original file size (`u64`, little-endian)
-    ///    - Number of chunks for this file (`u32`, little-endian)
-    ///    - Each 16-byte chunk hash
-    ///
-    /// # Arguments
-    /// * `files_metadata` – Slice of `(String, u64, Vec<[u8; 16]>)` tuples containing:
-    ///     1. File’s relative path
-    ///     2. Original file size
-    ///     3. Vector of chunk hashes
+    /// The shared writer is locked once for the whole table, see [`write_file_table`] for the
+    /// layout. The table is sealed when packing with a password.
     ///
     /// # Errors
-    /// Returns an error if any I/O write operation fails.
-    fn write_files_metadata(
-        &self,
-        files_metadata: &[(String, u64, Vec<ChunkHash>)],
-    ) -> Result<(), AppError> {
-        // Lock the shared writer once
-        let mut guard = self.writer.lock().unwrap();
+    /// Returns an error if any I/O write operation fails or the writer lock is poisoned.
+    fn write_files_metadata(&self, files_metadata: &[FileMetadata]) -> Result<(), AppError> {
+        let mut guard = self.writer.lock().map_err(|_| AppError::LockPoisoned)?;
+        write_file_table(
+            &mut *guard,
+            files_metadata,
+            WRITTEN_LAYOUT,
+            self.key.as_ref(),
+        )?;
+        guard.flush().map_err(AppError::WriterError)?;
+        Ok(())
+    }
+}
 
-        // Number of files
-        let file_count = files_metadata.len() as u32;
-        guard
-            .write_all(&file_count.to_le_bytes())
-            .map_err(AppError::WriterError)?;
+impl Drop for ArchiveWriter {
+    /// Removes the staging file of a writer that was never packed. After a pack it has already
+    /// been renamed to the output, or to `<output>.partial`, or removed.
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.staging_path);
+    }
+}
 
-        // For each file: path length, path, original size, chunk count, chunk hashes
-        for (path, orig_size, chunk_hashes) in files_metadata {
-            let path_bytes = path.as_bytes();
-            let path_len = path_bytes.len() as u32;
+/// Writes everything that precedes the chunk table: the header, the timestamp fields and
+/// placeholders for the chunk count and the number of top-level entries.
+///
+/// `encryption` is recorded in the timestamp fields when the archive is encrypted.
+///
+/// # Returns
+/// The positions of the chunk count and top-level entry count placeholders and of the archive
+/// size placeholder at the end of the timestamp fields, to patch once they are known.
+///
+/// # Errors
+/// Returns an error if any I/O write operation fails.
+pub(crate) fn write_preamble<W: Write + Seek>(
+    writer: &mut W,
+    options: &PackOptions,
+    encryption: Option<&Encryption>,
+) -> Result<(u64, u64, u64), AppError> {
+    write_header(writer).map_err(AppError::WriterError)?;
+    let timestamp = if options.reproducible {
+        source_date_epoch()
+    } else {
+        unix_now()?
+    };
+    let fields = TimestampFields {
+        timestamp,
+        provenance: options.provenance.then(Provenance::current),
+        hash_seed: options.hash_seed,
+        file_modes: WRITTEN_LAYOUT.modes,
+        entry_types: WRITTEN_LAYOUT.entry_types,
+        file_hashes: WRITTEN_LAYOUT.file_hashes,
+        chunk_size: (options.chunk_size != CHUNK_SIZE).then_some(options.chunk_size as u32),
+        chunking: options.chunking,
+        archive_size: Some(0),
+        top_level_count: true,
+        encryption: encryption.copied(),
+        signed: options.signing_key.is_some(),
+    };
+    write_timestamp_fields(writer, &fields).map_err(AppError::WriterError)?;
+    // The archive size is the last of the timestamp fields
+    let archive_size_position = writer.stream_position().map_err(AppError::WriterError)? - 8;
 
-            guard
-                .write_all(&path_len.to_le_bytes())
-                .map_err(AppError::WriterError)?;
-            guard.write_all(path_bytes).map_err(AppError::WriterError)?;
-            guard
-                .write_all(&orig_size.to_le_bytes())
+    // Write placeholder for chunk count
+    let chunks_count_position = write_placeholder_u64(writer).map_err(AppError::WriterError)?;
+
+    // Write placeholder for number of distinct top-level entries
+    let top_level_count_position = write_placeholder_u64(writer).map_err(AppError::WriterError)?;
+
+    Ok((
+        chunks_count_position,
+        top_level_count_position,
+        archive_size_position,
+    ))
+}
+
+/// Writes the file table to `writer`:
+/// 1. Number of files in the archive (`u32`, little-endian)
+/// 2. For each file:
+///    - Path length (`u32`, little-endian)
+///    - Path bytes (UTF-8)
+///    - Original file size (`u64`, little-endian)
+///    - Permission mode (`u32`, little-endian, 0 when unknown), only when `layout.modes` is set
+///    - Entry type (`u8`, 0 regular, 1 symlink, 2 empty directory, 3 hard link), only when
+///      `layout.entry_types` is set
+///    - For symlinks and hard links, target length (`u32`, little-endian) and target bytes
+///      (UTF-8)
+///    - xxh3-128 hash of the file's content (16 bytes, all zeros when unknown or not a regular
+///      file), only when `layout.file_hashes` is set
+///    - Number of chunks for this file (`u32`, little-endian)
+///    - Each 16-byte chunk hash
+///
+/// With a `key` the entries are sealed as one block instead, so paths are not readable: its
+/// length (`u64`, little-endian), then the nonce, the encrypted entries and the tag. The file
+/// count stays in the clear and is authenticated with them.
+///
+/// # Arguments
+/// * `files_metadata` – The entries to write
+/// * `layout` – Optional fields the archive header announces
+/// * `key` – Key of an encrypted archive
+///
+/// # Errors
+/// Returns an error if any I/O write operation fails.
+pub(crate) fn write_file_table<W: Write>(
+    writer: &mut W,
+    files_metadata: &[FileMetadata],
+    layout: FileTableLayout,
+    key: Option<&ArchiveKey>,
+) -> Result<(), AppError> {
+    // Number of files
+    let file_count = files_metadata.len() as u32;
+    writer
+        .write_all(&file_count.to_le_bytes())
+        .map_err(AppError::WriterError)?;
+
+    let Some(key) = key else {
+        return write_file_records(writer, files_metadata, layout);
+    };
+    let mut records = Vec::new();
+    write_file_records(&mut records, files_metadata, layout)?;
+    write_sealed_file_table(writer, file_count, &records, key)
+}
+
+/// Seals the serialized entries of a file table of `file_count` entries with `key` and writes
+/// them with their sealed length (`u64`, little-endian), see [`write_file_table`].
+pub(crate) fn write_sealed_file_table<W: Write>(
+    writer: &mut W,
+    file_count: u32,
+    records: &[u8],
+    key: &ArchiveKey,
+) -> Result<(), AppError> {
+    let sealed = key.seal(records, &file_count.to_le_bytes())?;
+    writer
+        .write_all(&(sealed.len() as u64).to_le_bytes())
+        .map_err(AppError::WriterError)?;
+    writer.write_all(&sealed).map_err(AppError::WriterError)
+}
+
+/// Writes the entries of the file table, everything after its file count, see
+/// [`write_file_table`].
+fn write_file_records<W: Write>(
+    writer: &mut W,
+    files_metadata: &[FileMetadata],
+    layout: FileTableLayout,
+) -> Result<(), AppError> {
+    // For each file: path length, path, original size, mode, entry type, chunk count, hashes
+    for entry in files_metadata {
+        let path_bytes = entry.path.as_bytes();
+        let path_len = path_bytes.len() as u32;
+
+        writer
+            .write_all(&path_len.to_le_bytes())
+            .map_err(AppError::WriterError)?;
+        writer
+            .write_all(path_bytes)
+            .map_err(AppError::WriterError)?;
+        writer
+            .write_all(&entry.original_size.to_le_bytes())
+            .map_err(AppError::WriterError)?;
+        if layout.modes {
+            writer
+                .write_all(&entry.mode.unwrap_or(0).to_le_bytes())
                 .map_err(AppError::WriterError)?;
+        }
+        match (&entry.kind, layout.entry_types) {
+            (EntryKind::Regular, true) => writer
+                .write_all(&[ENTRY_REGULAR])
+                .map_err(AppError::WriterError)?,
+            (EntryKind::Symlink(target), true) => {
+                writer
+                    .write_all(&[ENTRY_SYMLINK])
+                    .map_err(AppError::WriterError)?;
+                writer
+                    .write_all(&(target.len() as u32).to_le_bytes())
+                    .map_err(AppError::WriterError)?;
+                writer
+                    .write_all(target.as_bytes())
+                    .map_err(AppError::WriterError)?;
+            }
+            (EntryKind::Directory, true) => writer
+                .write_all(&[ENTRY_DIRECTORY])
+                .map_err(AppError::WriterError)?,
+            (EntryKind::HardLink(primary), true) => {
+                writer
+                    .write_all(&[ENTRY_HARDLINK])
+                    .map_err(AppError::WriterError)?;
+                writer
+                    .write_all(&(primary.len() as u32).to_le_bytes())
+                    .map_err(AppError::WriterError)?;
+                writer
+                    .write_all(primary.as_bytes())
+                    .map_err(AppError::WriterError)?;
+            }
+            (EntryKind::Regular, false) => {}
+            (EntryKind::Symlink(_), false) => {
+                return Err(AppError::Archive(format!(
+                    "`{}` is a symlink, which this squish cannot record",
+                    entry.path
+                )));
+            }
+            (EntryKind::Directory, false) => {
+                return Err(AppError::Archive(format!(
+                    "`{}` is an empty directory, which this squish cannot record",
+                    entry.path
+                )));
+            }
+            (EntryKind::HardLink(_), false) => {
+                return Err(AppError::Archive(format!(
+                    "`{}` is a hard link, which this squish cannot record",
+                    entry.path
+                )));
+            }
+        }
 
-            let chunk_count = chunk_hashes.len() as u32;
-            guard
-                .write_all(&chunk_count.to_le_bytes())
+        if layout.file_hashes {
+            writer
+                .write_all(&entry.file_hash.unwrap_or_default())
                 .map_err(AppError::WriterError)?;
+        }
 
-            for hash in chunk_hashes {
-                guard.write_all(hash).map_err(AppError::WriterError)?;
-            }
+        let chunk_count = entry.chunk_hashes.len() as u32;
+        writer
+            .write_all(&chunk_count.to_le_bytes())
+            .map_err(AppError::WriterError)?;
+
+        for hash in &entry.chunk_hashes {
+            writer.write_all(hash).map_err(AppError::WriterError)?;
         }
-        guard.flush().map_err(AppError::WriterError)?;
-        Ok(())
     }
+    Ok(())
+}
+
+/// Returns every path packing to `output_path` may write: the archive itself, the chunk spool
+/// and the files kept on failure.
+///
+/// Leave these out of the files to pack, or an archive written inside its own input could end
+/// up packing itself.
+///
+/// # Example
+///
+/// ```
+/// use squishrs::archive::writer::{pack_artifacts, PackOptions};
+/// use std::path::{Path, PathBuf};
+///
+/// let artifacts = pack_artifacts(Path::new("data/out.squish"), &PackOptions::default());
+/// assert!(artifacts.contains(&PathBuf::from("data/out.squish.partial")));
+/// ```
+pub fn pack_artifacts(output_path: &Path, options: &PackOptions) -> Vec<PathBuf> {
+    vec![
+        output_path.to_path_buf(),
+        spool_output_path(output_path, options.temp_dir.as_deref()),
+        scratch_path(output_path, None, PARTIAL_SUFFIX),
+        scratch_path(output_path, None, PARTIAL_LOG_SUFFIX),
+        staging_output_path(output_path, options.temp_dir.as_deref()),
+    ]
+}
+
+/// Path the archive for `output_path` is written to before being renamed into place.
+///
+/// It goes in `temp_dir` when that shares a filesystem with the output, and next to the output
+/// otherwise, so the rename stays atomic. It carries the process id so concurrent packs to the
+/// same output do not share it.
+fn staging_output_path(output_path: &Path, temp_dir: Option<&Path>) -> PathBuf {
+    staging_path(
+        output_path,
+        temp_dir,
+        &format!(".tmp-{}", std::process::id()),
+    )
+}
+
+/// Path reproducible archives spool their chunks to, with the same placement rules as the
+/// staging file.
+fn spool_output_path(output_path: &Path, temp_dir: Option<&Path>) -> PathBuf {
+    staging_path(output_path, temp_dir, SPOOL_SUFFIX)
+}
+
+/// Whether `error` came from reading one input file, which `skip_errors` tolerates, rather than
+/// from writing the archive, which always fails the pack.
+fn is_read_error(error: &AppError) -> bool {
+    matches!(error, AppError::Io(_) | AppError::ReaderError(_))
+}
+
+/// Returns the permission bits of a file, including setuid, setgid and sticky.
+#[cfg(unix)]
+fn file_mode(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn file_mode(_metadata: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+/// Spells a path relative to the input directory the way the file table records it, with `/`
+/// between components whatever the platform's separator, so archives unpack anywhere.
+pub(crate) fn archive_path(relative: &Path) -> String {
+    relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Counts the distinct first path components across all packed files.
+///
+/// Loose files at the root of the input count as their own top-level entry.
+pub(crate) fn count_top_level_entries(files_metadata: &[FileMetadata]) -> u64 {
+    files_metadata
+        .iter()
+        .filter_map(|entry| Path::new(&entry.path).components().next())
+        .map(|component| component.as_os_str().to_os_string())
+        .collect::<HashSet<_>>()
+        .len() as u64
 }
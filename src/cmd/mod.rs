@@ -1,21 +1,79 @@
 pub mod progress_bar;
 
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
-use crate::archive::reader::ArchiveSummary;
+use crate::archive::reader::{
+    ArchiveReader, ArchiveSummary, CheckFailure, ChunkInfo, ConflictPolicy, FileEntry,
+    LayoutSection, SharedChunk, SpaceReport, VerifyReport,
+};
+use crate::archive::writer::{DedupScope, DEFAULT_WRITER_QUEUE_CHUNKS};
+use crate::cmd::progress_bar::{parse_progress_template, ProgressMode};
+use crate::fsutil::writer::SyncInterval;
+use crate::util::analyze::DedupReport;
+use crate::util::chunk::{
+    check_chunk_size, check_compression_level, hash_to_hex, Chunking, Compression, CHUNK_SIZE,
+    COMPRESSION_LEVEL, DEFAULT_MIN_GAIN_PERCENT,
+};
+use crate::util::errors::AppError;
+use crate::util::header::Provenance;
+use crate::util::signing::fingerprint_to_hex;
 use byte_unit::{Byte, UnitType};
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use num_format::{Locale, ToFormattedString};
 use prettytable::{format::consts::FORMAT_NO_LINESEP_WITH_TITLE, row, Cell, Row, Table};
+use serde_json::{json, Value};
 
 #[derive(Parser)]
 #[clap(name = "squishrs")]
 #[clap(about = "Compact, compress, and deduplicate files into a single archive")]
 pub struct Cli {
-    /// Maximum number of threads to use
-    #[arg(long = "max-threads", short = 'j', default_value_t = 25, global = true)]
+    /// Maximum number of threads to use, or `auto` to match the available cores
+    #[arg(
+        long = "max-threads",
+        short = 'j',
+        default_value = "auto",
+        value_parser = parse_thread_count,
+        global = true
+    )]
     pub max_threads: usize,
 
+    /// How to report progress: animated bar, plain-text lines, JSON lines, none, or auto-detect
+    #[arg(long, value_enum, default_value_t = ProgressMode::Auto, global = true)]
+    pub progress: ProgressMode,
+
+    /// Show no progress bars or spinners, the same as `--progress none`. Results are still
+    /// printed unless `--no-summary` is also given
+    #[arg(long, short = 'q', default_value_t = false, global = true)]
+    pub quiet: bool,
+
+    /// With `--quiet`, also leave out the completion summary printed after pack and unpack
+    #[arg(
+        long = "no-summary",
+        default_value_t = false,
+        requires = "quiet",
+        global = true
+    )]
+    pub no_summary: bool,
+
+    /// Progress bar layout: `minimal`, `detailed`, or an indicatif template string
+    #[arg(long = "progress-template", value_parser = parse_progress_template, global = true)]
+    pub progress_template: Option<String>,
+
+    /// Refuse archives written by a different minor version instead of warning about them
+    #[arg(long = "strict-version", default_value_t = false, global = true)]
+    pub strict_version: bool,
+
+    /// Password of an encrypted archive, asked for on the terminal when it is not given; `pack`
+    /// encrypts the archive with it. Other users can see it in the process list, so prefer
+    /// `--password-file` or the prompt on shared machines
+    #[arg(long, conflicts_with = "password_file", global = true)]
+    pub password: Option<String>,
+
+    /// Read the password from the first line of this file
+    #[arg(long = "password-file", global = true)]
+    pub password_file: Option<String>,
+
     #[clap(subcommand)]
     pub command: Commands,
 }
@@ -30,8 +88,126 @@ pub enum Commands {
     )]
     Pack {
         input: String,
+        /// Archive to write, or `-` to stream it to stdout
         #[clap(short, long)]
         output: Option<String>,
+        /// Abort if the archive would grow beyond this size (e.g. 500MB, 2GiB)
+        #[arg(long = "max-archive-size", value_parser = parse_size)]
+        max_archive_size: Option<u64>,
+        /// Produce byte-identical archives for identical input (honours SOURCE_DATE_EPOCH)
+        #[arg(long, default_value_t = false)]
+        reproducible: bool,
+        /// Directory for intermediate files (defaults to $TMPDIR, then the output's directory)
+        #[arg(long = "temp-dir")]
+        temp_dir: Option<String>,
+        /// Leave out files that are already .squish archives instead of only warning about them
+        #[arg(long = "skip-squished", default_value_t = false)]
+        skip_squished: bool,
+        /// Abort before packing anything if the input holds more than this many files
+        #[arg(long = "max-files")]
+        max_files: Option<usize>,
+        /// Ask whether to carry on when --max-files is exceeded instead of failing
+        #[arg(
+            long = "confirm-large",
+            default_value_t = false,
+            requires = "max_files"
+        )]
+        confirm_large: bool,
+        /// Fail if a file changes size while being packed, instead of warning
+        #[arg(long, default_value_t = false)]
+        strict: bool,
+        /// Leave out files that cannot be read, with a warning for each, instead of failing
+        #[arg(long = "skip-errors", default_value_t = false)]
+        skip_errors: bool,
+        /// Split large files across threads so one huge file doesn't leave the others idle
+        #[arg(long, default_value_t = false)]
+        balance: bool,
+        /// Reuse chunks from a pool written by export-chunks instead of storing them again
+        #[arg(long = "chunk-pool")]
+        chunk_pool: Option<String>,
+        /// Store chunks uncompressed unless compression saves at least this percentage
+        #[arg(long = "min-gain", default_value_t = DEFAULT_MIN_GAIN_PERCENT, value_parser = parse_percent)]
+        min_gain: f64,
+        /// zstd compression level: 1-3 for quick scratch backups, 19-22 for long-term storage
+        #[arg(long, default_value_t = COMPRESSION_LEVEL, value_parser = parse_compression_level, allow_negative_numbers = true)]
+        level: i32,
+        /// `zstd`, or `none` to store every chunk uncompressed, which is much faster for data
+        /// that is already compressed (video, images, zip files)
+        #[arg(long, default_value = "zstd", value_parser = parse_compression)]
+        compression: Compression,
+        /// On failure keep the incomplete archive as <output>.partial with a .partial.log
+        #[arg(long = "keep-partial", default_value_t = false)]
+        keep_partial: bool,
+        /// Flush the archive every N chunks (e.g. 64) or bytes (e.g. 256MB) instead of only at the end
+        #[arg(long = "sync-interval", value_parser = parse_sync_interval)]
+        sync_interval: Option<SyncInterval>,
+        /// Also fsync on every --sync-interval flush so written data survives a power loss
+        #[arg(long, default_value_t = false, requires = "sync_interval")]
+        fsync: bool,
+        /// Pack each subdirectory of the input into its own archive inside the -o directory,
+        /// with files directly in the input going to _root.squish
+        #[arg(long = "split-by-dir", default_value_t = false, requires = "output")]
+        split_by_dir: bool,
+        /// Reserve the archive's estimated size up front to limit fragmentation (Linux only)
+        #[arg(long = "preallocate-archive", default_value_t = false)]
+        preallocate_archive: bool,
+        /// Deduplicate chunks across the whole archive (`global`) or only within each file
+        /// (`file`), which keeps files unlinkable at the cost of storing shared content per file
+        #[arg(long = "dedup-scope", default_value = "global", value_parser = parse_dedup_scope)]
+        dedup_scope: DedupScope,
+        /// Seed chunk hashes so this archive's chunks never match another seed's, e.g. one seed
+        /// per tenant. Recorded in the archive; 0 keeps the default hashes
+        #[arg(long = "hash-seed", default_value_t = 0)]
+        hash_seed: u64,
+        /// Write the archive no faster than this many bytes per second (e.g. 10MB, 512KiB)
+        #[arg(long = "rate-limit", value_parser = parse_rate_limit)]
+        rate_limit: Option<u64>,
+        /// Record this machine's hostname and the squishrs version in the archive
+        #[arg(long, default_value_t = false)]
+        provenance: bool,
+        /// Bytes per chunk (e.g. 512KiB, 8MB): smaller chunks dedup similar files better,
+        /// larger ones cut overhead on big media. Recorded in the archive
+        #[arg(long = "chunk-size", default_value_t = CHUNK_SIZE, value_parser = parse_chunk_size)]
+        chunk_size: usize,
+        /// `fixed`, or `cdc` to cut chunks where the content says so, which keeps versioned
+        /// backups deduplicating when bytes are inserted or removed
+        #[arg(long, default_value = "fixed", value_parser = parse_chunking)]
+        chunking: Chunking,
+        /// Record hard links as links to one stored copy, so unpacking links them again
+        /// (Unix only, no-op elsewhere)
+        #[arg(long = "preserve-hardlinks", default_value_t = false)]
+        preserve_hardlinks: bool,
+        /// Only pack files matching this glob, relative to the input (repeatable)
+        #[arg(long, value_name = "GLOB")]
+        include: Vec<String>,
+        /// Leave out files and directories matching this glob, e.g. `target` or `*.log`
+        /// (repeatable). Wins over --include
+        #[arg(long, value_name = "GLOB")]
+        exclude: Vec<String>,
+        /// Leave out `.git` and files ignored by the `.gitignore` files in the input tree
+        #[arg(long, default_value_t = false)]
+        gitignore: bool,
+        /// Chunk and compress everything but write nothing, then print the projected archive
+        /// size. Takes about as long as a real pack
+        #[arg(
+            long = "dry-run",
+            default_value_t = false,
+            conflicts_with = "split_by_dir"
+        )]
+        dry_run: bool,
+        /// Compressed chunks that may wait for a slow disk before compression pauses; memory
+        /// for them peaks around this many × the chunk size
+        #[arg(
+            long = "writer-queue",
+            value_name = "N",
+            default_value_t = DEFAULT_WRITER_QUEUE_CHUNKS,
+            value_parser = parse_queue_length
+        )]
+        writer_queue: usize,
+        /// Sign the archive with this ed25519 private key (PKCS#8 PEM, e.g. from
+        /// `openssl genpkey -algorithm ed25519`) so recipients can check it with verify-signature
+        #[arg(long, value_name = "KEYFILE")]
+        sign: Option<String>,
     },
 
     /// List contents of a .squish archive
@@ -43,6 +219,45 @@ pub enum Commands {
         squish: String,
         #[arg(long, default_value_t = false)]
         simple: bool,
+        /// Show how many chunks each file is split into
+        #[arg(long, default_value_t = false)]
+        chunks: bool,
+        /// List the files with their size and chunk count, largest first
+        #[arg(long, default_value_t = false)]
+        files: bool,
+        /// List at most N files with --files
+        #[arg(long, value_name = "N", requires = "files")]
+        limit: Option<usize>,
+        /// Print the summary and every file as JSON
+        #[arg(long, default_value_t = false, conflicts_with = "simple")]
+        json: bool,
+    },
+
+    /// Report how well a directory would deduplicate
+    #[command(
+        about = "Analyze deduplication potential",
+        long_about = "Chunk and hash a directory without compressing or writing anything, and report how much of it deduplicates"
+    )]
+    Analyze {
+        input: String,
+        /// Chunk size to analyze with (e.g. 512KiB, 4MB)
+        #[arg(long = "chunk-size", value_parser = parse_size)]
+        chunk_size: Option<u64>,
+        /// Print the report as JSON
+        #[arg(long, default_value_t = false)]
+        json: bool,
+        /// Compress a sample of each file type to estimate how well it compresses
+        #[arg(long = "sample-compression", default_value_t = false)]
+        sample_compression: bool,
+        /// List the N files (default 20) whose unique chunks add the most to the compressed
+        /// archive, i.e. what excluding each would save. Compresses every chunk, so it is slow
+        #[arg(
+            long = "per-file",
+            value_name = "N",
+            num_args = 0..=1,
+            default_missing_value = "20"
+        )]
+        per_file: Option<usize>,
     },
 
     /// Unpack files from a .squish archive
@@ -51,10 +266,265 @@ pub enum Commands {
         long_about = "Unpacks all files from a .squish archive into a target directory"
     )]
     Unpack {
+        /// Archive to unpack, or `-` to read it from stdin
+        squish: String,
+        #[clap(short, long)]
+        output: Option<String>,
+        /// Prepend this relative path to every extracted file (e.g. vendor/lib)
+        #[arg(long = "prefix-out")]
+        prefix_out: Option<String>,
+        /// Reserve each file's full size on disk before writing it (Linux only, no-op elsewhere)
+        #[arg(long, default_value_t = false)]
+        preallocate: bool,
+        /// Read the archive front to back without seeking, so it can come from a pipe
+        #[arg(long, default_value_t = false)]
+        sequential: bool,
+        /// Chunk pool the archive was packed against with pack --chunk-pool
+        #[arg(long = "chunk-pool")]
+        chunk_pool: Option<String>,
+        /// Salvage files with missing chunks by writing zeros in their place
+        #[arg(
+            long = "zero-fill-missing",
+            alias = "continue-on-missing-chunk",
+            default_value_t = false
+        )]
+        zero_fill_missing: bool,
+        /// Chunks to decompress at once, separate from --max-threads; memory for chunks in
+        /// flight peaks around this many × the archive's chunk size
+        #[arg(long = "decompress-threads", value_parser = parse_thread_count)]
+        decompress_threads: Option<usize>,
+        /// Only extract files of at least this size (e.g. 10MiB); other files' chunks are skipped
+        #[arg(long = "min-size", value_parser = parse_size)]
+        min_size: Option<u64>,
+        /// Only extract files of at most this size (e.g. 1GB)
+        #[arg(long = "max-size", value_parser = parse_size)]
+        max_size: Option<u64>,
+        /// Decompress all chunks into memory before writing files; faster, but needs about as
+        /// much RAM as the unpacked chunks. By default chunks are read from disk as needed
+        #[arg(long = "in-memory", default_value_t = false)]
+        in_memory: bool,
+        /// Rebuild every file and check its chunk hashes and size without writing anything
+        #[arg(long, default_value_t = false, conflicts_with_all = ["output", "prefix_out", "preallocate", "zero_fill_missing"])]
+        check: bool,
+        /// Only extract the file at this path inside the archive, as shown by list; only its
+        /// chunks are decompressed
+        #[arg(long)]
+        file: Option<String>,
+        /// When a file already exists: `overwrite` it, `skip` extracting it, or `rename` the
+        /// extracted copy to `name (1).ext`
+        #[arg(long = "on-conflict", default_value = "overwrite", value_parser = parse_conflict_policy)]
+        on_conflict: ConflictPolicy,
+        /// Don't check that each extracted file comes out at its recorded size
+        #[arg(long = "no-verify-size", default_value_t = false)]
+        no_verify_size: bool,
+        /// Hash each extracted file and compare it with the hash recorded when it was packed
+        #[arg(long = "verify", default_value_t = false)]
+        verify: bool,
+        /// Also restore setuid, setgid and sticky bits; only use with archives you trust
+        #[arg(long = "preserve-special-bits", default_value_t = false)]
+        preserve_special_bits: bool,
+    },
+
+    /// Extract every intact file from a damaged .squish archive
+    #[command(
+        about = "Recover the readable files from a damaged archive",
+        long_about = "Extract every file whose chunks are all present, decompress and hash correctly, leaving out files that reference a damaged or missing chunk instead of stopping at the first one, then list which files were lost"
+    )]
+    Repair {
         squish: String,
+        /// Directory to extract into, defaults to the archive name without `.squish`
         #[clap(short, long)]
         output: Option<String>,
+        /// Also write the recovered and lost files to this path as JSON
+        #[arg(long)]
+        report: Option<String>,
+        /// Chunk pool the archive was packed against with pack --chunk-pool
+        #[arg(long = "chunk-pool")]
+        chunk_pool: Option<String>,
+    },
+
+    /// Print a single file from a .squish archive
+    #[command(
+        about = "Print a file from an archive",
+        long_about = "Write one file from a .squish archive to stdout, optionally only a byte range of it. Only the chunks covering the requested bytes are decompressed"
+    )]
+    Cat {
+        squish: String,
+        /// Path of the file inside the archive, as shown by list
+        path: String,
+        /// Only print bytes START:END, where END is exclusive and may be left out (e.g. 0:64KiB)
+        #[arg(long, value_parser = parse_byte_range)]
+        range: Option<(u64, Option<u64>)>,
+    },
+
+    /// Verify a .squish archive is intact, or matches its source directory
+    #[command(
+        about = "Verify an archive's integrity or against a directory",
+        long_about = "Decompress and re-hash every chunk in a .squish archive and confirm every file's chunks are present, without extracting. With --against, instead re-chunk and hash the files in a directory and confirm they match what the archive recorded"
+    )]
+    Verify {
+        squish: String,
+        /// Directory the archive was packed from
+        #[arg(long)]
+        against: Option<String>,
+        /// Only check that this build can extract the archive, without reading any chunks
+        #[arg(long, default_value_t = false, conflicts_with = "against")]
+        compat: bool,
+        /// Chunk pool the archive was packed against, supplying chunks it does not store
+        #[arg(long = "chunk-pool", conflicts_with_all = ["against", "compat"])]
+        chunk_pool: Option<String>,
+    },
+
+    /// Check the signature of a .squish archive
+    #[command(
+        name = "verify-signature",
+        about = "Check an archive's signature",
+        long_about = "Check that a .squish archive packed with --sign was signed by the private half of a public key, and has not been altered since. The archive is read once, and encrypted archives need no password"
+    )]
+    VerifySignature {
+        squish: String,
+        /// The signer's ed25519 public key as PEM, e.g. from `openssl pkey -pubout`
+        #[arg(long, value_name = "PUBKEY")]
+        key: String,
     },
+
+    /// Audit a .squish archive
+    #[command(
+        about = "Audit an archive",
+        long_about = "Inspect a .squish archive for problems such as dead space left by orphaned chunks"
+    )]
+    Audit {
+        squish: String,
+        /// Report how much of the archive is taken by chunks no file references
+        #[arg(long, default_value_t = false)]
+        space: bool,
+        /// List chunks shared by more than one file, and the files sharing them
+        #[arg(long, default_value_t = false)]
+        shared: bool,
+        /// Number of most-shared chunks to list with --shared
+        #[arg(long, default_value_t = 20, requires = "shared")]
+        top: usize,
+    },
+
+    /// Rename files inside a .squish archive
+    #[command(
+        about = "Rename files in an archive",
+        long_about = "Rewrite the paths of files inside a .squish archive without repacking. Only the file table is rewritten; chunk data is left untouched"
+    )]
+    Mv {
+        squish: String,
+        /// Current path of the file, as shown by list
+        #[arg(required_unless_present = "from_file", requires = "new_path")]
+        old_path: Option<String>,
+        /// Path to move the file to
+        new_path: Option<String>,
+        /// Apply every rename in a file of `<old path><TAB><new path>` lines
+        #[arg(long = "from-file", conflicts_with = "old_path")]
+        from_file: Option<String>,
+    },
+
+    /// Remove files from a .squish archive
+    #[command(
+        about = "Remove files from an archive",
+        long_about = "Drop files from a .squish archive along with any chunks no remaining file references. Surviving chunks are copied as-is, so nothing is recompressed"
+    )]
+    Remove {
+        squish: String,
+        /// Paths of the files to remove, as shown by list
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+
+    /// Rewrite a .squish archive without its orphaned chunks
+    #[command(
+        about = "Rewrite an archive without orphaned chunks",
+        long_about = "Copy a .squish archive to a new file, keeping only chunks still referenced by its files. Chunk data is copied as-is, so nothing is recompressed"
+    )]
+    Compact { squish: String, output: String },
+
+    /// Copy an encrypted .squish archive under a new password
+    #[command(
+        about = "Change an encrypted archive's password",
+        long_about = "Copy an encrypted .squish archive to a new file, decrypting every chunk and the file table with the old password and encrypting them again with a key derived from the new one under a fresh salt. Nothing is recompressed and the source files are not needed"
+    )]
+    Reencrypt {
+        squish: String,
+        output: String,
+        /// Current password of the archive, defaulting to --password or --password-file; asked
+        /// for on the terminal when none is given
+        #[arg(long = "old-password")]
+        old_password: Option<String>,
+        /// Password to encrypt the copy with; asked for on the terminal, twice, when not given
+        #[arg(long = "new-password")]
+        new_password: Option<String>,
+    },
+
+    /// Export the chunks of a .squish archive as a shareable chunk pool
+    #[command(
+        name = "export-chunks",
+        about = "Export an archive's chunks to a chunk pool",
+        long_about = "Write the compressed chunks of a .squish archive to a standalone pool that later packs can reuse with --chunk-pool"
+    )]
+    ExportChunks {
+        squish: String,
+        #[clap(short, long)]
+        output: String,
+    },
+
+    /// Print a fingerprint identifying an archive
+    #[command(
+        about = "Fingerprint an archive",
+        long_about = "Print a short identifier for a .squish archive so identical archives can be recognised without comparing them byte by byte"
+    )]
+    Fingerprint {
+        squish: String,
+        /// Hash the raw archive bytes, or the packed files regardless of timestamp and chunk order
+        #[arg(long, value_enum, default_value_t = FingerprintMode::Content)]
+        mode: FingerprintMode,
+    },
+
+    /// Print an archive's header details
+    #[command(
+        about = "Show archive header details",
+        long_about = "Print the format version, creation date, chunk count, file count and size of a .squish archive without reading its file table, so it is near-instant however large the archive is"
+    )]
+    Info { squish: String },
+
+    /// Print diagnostics for a single chunk
+    #[command(
+        name = "debug-chunk",
+        hide = true,
+        about = "Inspect one chunk of an archive",
+        long_about = "Print the hash, sizes and offset of the chunk at a chunk table position, and check that it decompresses and re-hashes correctly"
+    )]
+    DebugChunk {
+        squish: String,
+        /// Zero-based position in the chunk table
+        index: u64,
+    },
+
+    /// Print the byte offsets of each section of an archive
+    #[command(
+        name = "debug-layout",
+        hide = true,
+        about = "Show an archive's internal layout",
+        long_about = "Print the offset and length of the header, counts, chunk table and file table of a .squish archive, for external parsers and diagnosing corruption"
+    )]
+    DebugLayout {
+        squish: String,
+        /// Print the layout as JSON
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+}
+
+/// What the `fingerprint` command hashes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum FingerprintMode {
+    /// Every byte of the archive file
+    Raw,
+    /// The file table: paths, sizes and chunk hashes
+    Content,
 }
 
 /// Prints a summary table of the archive contents including overall statistics
@@ -66,15 +536,20 @@ pub enum Commands {
 /// - Compression reduction percentage
 /// - Number of files
 /// - Number of unique chunks
+/// - Number of distinct top-level entries
+/// - Who created the archive, when it was packed with provenance
 ///
 /// After the summary, the function prints a "Top-level directory breakdown"
 /// table that shows the count of files grouped by the first path component,
-/// providing insight into the archive's directory structure.
+/// providing insight into the archive's directory structure. With `files` set, a
+/// "Largest files" table follows, listing each file's size and chunk count.
 ///
 /// # Arguments
 ///
 /// * `summary` - A reference to a `ArchiveSummary` struct containing the archive metadata,
 ///   including file paths, sizes, chunk counts, and compression stats.
+/// * `files` - Whether to add the per-file table.
+/// * `limit` - List at most this many files in that table; `None` lists them all.
 ///
 /// # Example
 ///
@@ -84,17 +559,24 @@ pub enum Commands {
 ///
 /// let summary = ArchiveSummary {
 ///     unique_chunks: 10,
+///     top_level_entries: 2,
 ///     total_original_size: 5000,
+///     duplicate_chunk_bytes: 0,
 ///     archive_size: 3500,
 ///     compression_ratio: 30.0,
 ///     squish_creation_date: "2025-07-19".to_string(),
 ///     squish_version: "1.0".to_string(),
+///     provenance: None,
 ///     files: vec![], // empty for example
 /// };
 ///
-/// build_list_summary_table(&summary);
+/// build_list_summary_table(&summary, true, Some(20));
 /// ```
-pub fn build_list_summary_table(summary: &ArchiveSummary) -> String {
+pub fn build_list_summary_table(
+    summary: &ArchiveSummary,
+    files: bool,
+    limit: Option<usize>,
+) -> String {
     let mut output = Vec::new();
 
     // -- Summary Table --
@@ -107,6 +589,9 @@ pub fn build_list_summary_table(summary: &ArchiveSummary) -> String {
 
     summary_table.add_row(row!["Creation Date (UTC)", summary.squish_creation_date]);
     summary_table.add_row(row!["Squish Version", summary.squish_version]);
+    if let Some(provenance) = &summary.provenance {
+        summary_table.add_row(row!["Created by", describe_provenance(provenance)]);
+    }
     summary_table.add_row(row!["Compressed size", format_bytes(summary.archive_size)]);
     summary_table.add_row(row![
         "Original size",
@@ -116,6 +601,10 @@ pub fn build_list_summary_table(summary: &ArchiveSummary) -> String {
         "Compression Ratio",
         format!("{:.1}%", summary.compression_ratio)
     ]);
+    summary_table.add_row(row![
+        "Saved by deduplication",
+        describe_dedup_savings(summary.duplicate_chunk_bytes, summary.total_original_size)
+    ]);
     summary_table.add_row(row![
         "Number of files",
         summary.files.len().to_formatted_string(&Locale::en)
@@ -124,6 +613,10 @@ pub fn build_list_summary_table(summary: &ArchiveSummary) -> String {
         "Number of chunks",
         summary.unique_chunks.to_formatted_string(&Locale::en)
     ]);
+    summary_table.add_row(row![
+        "Top-level entries",
+        summary.top_level_entries.to_formatted_string(&Locale::en)
+    ]);
 
     output.push(summary_table.to_string());
 
@@ -147,6 +640,7 @@ pub fn build_list_summary_table(summary: &ArchiveSummary) -> String {
 
     // Sort directories by file count descending
     let mut dir_counts_vec: Vec<_> = dir_counts.into_iter().collect();
+    #[allow(clippy::unnecessary_sort_by)]
     dir_counts_vec.sort_by(|a, b| b.1.cmp(&a.1));
 
     for (dir, count) in dir_counts_vec {
@@ -154,9 +648,706 @@ pub fn build_list_summary_table(summary: &ArchiveSummary) -> String {
     }
     output.push(breakdown_table.to_string());
 
+    if files {
+        output.push("\nLargest files:".to_string());
+
+        let mut files_table = Table::new();
+        files_table.set_format(*FORMAT_NO_LINESEP_WITH_TITLE);
+        files_table.set_titles(Row::new(vec![
+            Cell::new("File Path").style_spec("bFc"),
+            Cell::new("Size").style_spec("bFc"),
+            Cell::new("Chunks").style_spec("bFc"),
+        ]));
+
+        let largest = largest_files(&summary.files, limit);
+        for file in &largest {
+            files_table.add_row(row![
+                file.path,
+                format_bytes(file.original_size),
+                file.chunk_count.to_formatted_string(&Locale::en)
+            ]);
+        }
+        output.push(files_table.to_string());
+
+        if largest.len() < summary.files.len() {
+            output.push(format!(
+                "... and {} more files",
+                (summary.files.len() - largest.len()).to_formatted_string(&Locale::en)
+            ));
+        }
+    }
+
     output.join("\n")
 }
 
+/// Builds the JSON form of the `list` summary, with all sizes as raw byte counts.
+///
+/// Holds the same fields as [`build_list_summary_table`] plus every file's path, size and chunk
+/// count, so scripts never have to parse the human-formatted sizes.
+pub fn build_list_json(summary: &ArchiveSummary) -> Value {
+    let files: Vec<Value> = summary
+        .files
+        .iter()
+        .map(|file| {
+            json!({
+                "path": file.path,
+                "original_size": file.original_size,
+                "chunk_count": file.chunk_count,
+            })
+        })
+        .collect();
+
+    let provenance = summary.provenance.as_ref().map(|provenance| {
+        json!({
+            "hostname": provenance.hostname,
+            "tool_version": provenance.tool_version,
+        })
+    });
+
+    json!({
+        "creation_date": summary.squish_creation_date,
+        "version": summary.squish_version,
+        "provenance": provenance,
+        "archive_size": summary.archive_size,
+        "total_original_size": summary.total_original_size,
+        "duplicate_chunk_bytes": summary.duplicate_chunk_bytes,
+        "compression_ratio": summary.compression_ratio,
+        "unique_chunks": summary.unique_chunks,
+        "top_level_entries": summary.top_level_entries,
+        "files": files,
+    })
+}
+
+/// Builds the JSON report of a `repair`: the paths of the files it `recovered`, and each lost
+/// file with the reason it could not be restored.
+pub fn build_repair_json(recovered: &[String], lost: &[CheckFailure]) -> Value {
+    let lost: Vec<Value> = lost
+        .iter()
+        .map(|lost| json!({ "path": lost.path, "reason": lost.reason }))
+        .collect();
+
+    json!({
+        "recovered": recovered,
+        "lost": lost,
+    })
+}
+
+/// Describes the bytes deduplication saved, with their share of the original size.
+fn describe_dedup_savings(duplicate_bytes: u64, total_original_size: u64) -> String {
+    if total_original_size == 0 {
+        return format_bytes(duplicate_bytes);
+    }
+    format!(
+        "{} ({:.1}%)",
+        format_bytes(duplicate_bytes),
+        duplicate_bytes as f64 / total_original_size as f64 * 100.0
+    )
+}
+
+/// Returns `files` largest first, capped at `limit`. Files of equal size keep their archive
+/// order.
+///
+/// # Arguments
+///
+/// * `files` - The files to rank, in archive order.
+/// * `limit` - Keep at most this many files; `None` keeps them all.
+pub fn largest_files(files: &[FileEntry], limit: Option<usize>) -> Vec<&FileEntry> {
+    let mut files: Vec<_> = files.iter().collect();
+    files.sort_by_key(|file| std::cmp::Reverse(file.original_size));
+    files.truncate(limit.unwrap_or(usize::MAX));
+    files
+}
+
+/// Builds a table listing every file in the archive with its size and chunk count.
+///
+/// Files split into many small chunks versus few large ones show how chunking behaved.
+///
+/// # Arguments
+///
+/// * `summary` - A reference to a `ArchiveSummary` whose `files` are listed.
+pub fn build_file_chunks_table(summary: &ArchiveSummary) -> String {
+    let mut table = Table::new();
+    table.set_format(*FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(Row::new(vec![
+        Cell::new("File Path").style_spec("bFc"),
+        Cell::new("Size").style_spec("bFc"),
+        Cell::new("Chunks").style_spec("bFc"),
+    ]));
+
+    for file in &summary.files {
+        table.add_row(row![
+            file.path,
+            format_bytes(file.original_size),
+            file.chunk_count.to_formatted_string(&Locale::en)
+        ]);
+    }
+
+    format!("\nFile chunk counts:\n{table}")
+}
+
+/// Builds the human readable report for the `analyze` command.
+///
+/// Contains an overview table (files, chunks, bytes and dedup ratio) followed by a histogram of
+/// how many times each distinct chunk repeats.
+///
+/// # Arguments
+///
+/// * `report` - The deduplication statistics produced by `analyze_files`.
+pub fn build_analyze_table(report: &DedupReport) -> String {
+    let mut output = Vec::new();
+
+    output.push("\nDeduplication analysis:".to_string());
+    let mut summary_table = Table::new();
+    summary_table.set_format(*FORMAT_NO_LINESEP_WITH_TITLE);
+    summary_table.set_titles(Row::new(vec![Cell::new("Dedup Summary").with_hspan(2)]));
+
+    summary_table.add_row(row!["Chunk size", format_bytes(report.chunk_size as u64)]);
+    summary_table.add_row(row![
+        "Number of files",
+        report.files.to_formatted_string(&Locale::en)
+    ]);
+    summary_table.add_row(row![
+        "Total chunks",
+        report.total_chunks.to_formatted_string(&Locale::en)
+    ]);
+    summary_table.add_row(row![
+        "Unique chunks",
+        report.unique_chunks.to_formatted_string(&Locale::en)
+    ]);
+    summary_table.add_row(row!["Total size", format_bytes(report.total_bytes)]);
+    summary_table.add_row(row!["Unique size", format_bytes(report.unique_bytes)]);
+    summary_table.add_row(row![
+        "Saved by deduplication",
+        format_bytes(report.duplicate_bytes())
+    ]);
+    summary_table.add_row(row!["Dedup ratio", format!("{:.2}x", report.dedup_ratio())]);
+    output.push(summary_table.to_string());
+
+    output.push("\nChunk repeat histogram:".to_string());
+    let mut histogram_table = Table::new();
+    histogram_table.set_format(*FORMAT_NO_LINESEP_WITH_TITLE);
+    histogram_table.set_titles(Row::new(vec![
+        Cell::new("Times Seen").style_spec("bFc"),
+        Cell::new("Chunks").style_spec("bFc"),
+    ]));
+    for (occurrences, chunks) in &report.repeat_histogram {
+        histogram_table.add_row(row![
+            occurrences.to_formatted_string(&Locale::en),
+            chunks.to_formatted_string(&Locale::en)
+        ]);
+    }
+    output.push(histogram_table.to_string());
+
+    output.push("\nBy file type:".to_string());
+    let mut type_table = Table::new();
+    type_table.set_format(*FORMAT_NO_LINESEP_WITH_TITLE);
+    type_table.set_titles(Row::new(vec![
+        Cell::new("Type").style_spec("bFc"),
+        Cell::new("Files").style_spec("bFc"),
+        Cell::new("Total Size").style_spec("bFc"),
+        Cell::new("Unique Size").style_spec("bFc"),
+        Cell::new("Dedup Ratio").style_spec("bFc"),
+        Cell::new("Compression (sample)").style_spec("bFc"),
+    ]));
+    for stats in &report.by_type {
+        let compression = stats
+            .compression_ratio()
+            .map_or_else(|| "-".to_string(), |ratio| format!("{ratio:.2}x"));
+        type_table.add_row(row![
+            stats.extension,
+            stats.files.to_formatted_string(&Locale::en),
+            format_bytes(stats.total_bytes),
+            format_bytes(stats.unique_bytes),
+            format!("{:.2}x", stats.dedup_ratio()),
+            compression
+        ]);
+    }
+    output.push(type_table.to_string());
+
+    if !report.by_file.is_empty() {
+        output.push("\nLargest unique contributions:".to_string());
+        let mut file_table = Table::new();
+        file_table.set_format(*FORMAT_NO_LINESEP_WITH_TITLE);
+        file_table.set_titles(Row::new(vec![
+            Cell::new("File").style_spec("bFc"),
+            Cell::new("Size").style_spec("bFc"),
+            Cell::new("Unique (compressed)").style_spec("bFc"),
+            Cell::new("Shared (compressed)").style_spec("bFc"),
+        ]));
+        for file in &report.by_file {
+            file_table.add_row(row![
+                file.path.display(),
+                format_bytes(file.total_bytes),
+                format_bytes(file.unique_compressed_bytes),
+                format_bytes(file.shared_compressed_bytes)
+            ]);
+        }
+        output.push(file_table.to_string());
+    }
+
+    output.join("\n")
+}
+
+/// Builds the JSON form of the `analyze` report, with all sizes as raw byte counts.
+pub fn build_analyze_json(report: &DedupReport) -> Value {
+    let histogram: Vec<Value> = report
+        .repeat_histogram
+        .iter()
+        .map(|(occurrences, chunks)| json!({ "times_seen": occurrences, "chunks": chunks }))
+        .collect();
+
+    let by_type: Vec<Value> = report
+        .by_type
+        .iter()
+        .map(|stats| {
+            json!({
+                "extension": stats.extension,
+                "files": stats.files,
+                "total_bytes": stats.total_bytes,
+                "unique_bytes": stats.unique_bytes,
+                "dedup_ratio": stats.dedup_ratio(),
+                "sampled_bytes": stats.sampled_bytes,
+                "sampled_compressed_bytes": stats.sampled_compressed_bytes,
+                "compression_ratio": stats.compression_ratio(),
+            })
+        })
+        .collect();
+
+    let by_file: Vec<Value> = report
+        .by_file
+        .iter()
+        .map(|file| {
+            json!({
+                "path": file.path.display().to_string(),
+                "total_bytes": file.total_bytes,
+                "unique_compressed_bytes": file.unique_compressed_bytes,
+                "shared_compressed_bytes": file.shared_compressed_bytes,
+            })
+        })
+        .collect();
+
+    json!({
+        "chunk_size": report.chunk_size,
+        "files": report.files,
+        "total_chunks": report.total_chunks,
+        "unique_chunks": report.unique_chunks,
+        "total_bytes": report.total_bytes,
+        "unique_bytes": report.unique_bytes,
+        "duplicate_bytes": report.duplicate_bytes(),
+        "dedup_ratio": report.dedup_ratio(),
+        "repeat_histogram": histogram,
+        "by_type": by_type,
+        "by_file": by_file,
+    })
+}
+
+/// Builds the report printed by the `verify` command.
+///
+/// Lists every mismatched or missing file grouped by problem, followed by a one-line tally.
+///
+/// # Arguments
+///
+/// * `report` - The comparison produced by `ArchiveReader::verify_against`.
+pub fn build_verify_report(report: &VerifyReport) -> String {
+    let mut output = Vec::new();
+
+    let sections = [
+        ("Changed since packing", &report.mismatched),
+        ("Missing from source directory", &report.missing_from_source),
+        ("Not in archive", &report.missing_from_archive),
+    ];
+    for (title, paths) in sections {
+        if paths.is_empty() {
+            continue;
+        }
+        output.push(format!("{title}:"));
+        for path in paths {
+            output.push(format!("  {path}"));
+        }
+    }
+
+    output.push(format!(
+        "{} matched, {} changed, {} missing from source, {} not in archive",
+        report.matched.len().to_formatted_string(&Locale::en),
+        report.mismatched.len().to_formatted_string(&Locale::en),
+        report
+            .missing_from_source
+            .len()
+            .to_formatted_string(&Locale::en),
+        report
+            .missing_from_archive
+            .len()
+            .to_formatted_string(&Locale::en)
+    ));
+
+    output.join("\n")
+}
+
+/// Describes an archive's provenance as "squishrs X.Y.Z on host `foo`", leaving out whichever
+/// part was not recorded.
+pub fn describe_provenance(provenance: &Provenance) -> String {
+    let tool = match &provenance.tool_version {
+        Some(version) => format!("squishrs {version}"),
+        None => "squishrs".to_string(),
+    };
+    match &provenance.hostname {
+        Some(hostname) => format!("{tool} on host `{hostname}`"),
+        None => tool,
+    }
+}
+
+/// Builds the table printed by `audit --shared`.
+///
+/// # Arguments
+///
+/// * `shared` - The chunks produced by `ArchiveReader::shared_chunks`, most shared first.
+pub fn build_shared_chunks_table(shared: &[SharedChunk]) -> String {
+    if shared.is_empty() {
+        return "No chunks are shared between files".to_string();
+    }
+
+    let mut table = Table::new();
+    table.set_format(*FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(row!["Chunk", "Size", "Files", "Shared by"]);
+
+    for chunk in shared {
+        table.add_row(row![
+            hash_to_hex(&chunk.hash),
+            format_bytes(chunk.size),
+            chunk.files.len(),
+            chunk.files.join("\n")
+        ]);
+    }
+
+    table.to_string()
+}
+
+/// Builds the table printed by `audit --space`.
+///
+/// # Arguments
+///
+/// * `report` - The space breakdown produced by `ArchiveReader::space_report`.
+pub fn build_space_report_table(report: &SpaceReport) -> String {
+    let mut table = Table::new();
+    table.set_format(*FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(Row::new(vec![Cell::new("Space Audit").with_hspan(2)]));
+
+    table.add_row(row!["On-disk size", format_bytes(report.on_disk_bytes)]);
+    table.add_row(row!["Live chunks", format_bytes(report.live_chunk_bytes)]);
+    table.add_row(row![
+        "Orphaned chunks",
+        report.orphan_count.to_formatted_string(&Locale::en)
+    ]);
+    table.add_row(row![
+        "Orphaned size",
+        format_bytes(report.orphan_chunk_bytes)
+    ]);
+
+    let verdict = if report.orphan_count == 0 {
+        "Archive is compact".to_string()
+    } else {
+        format!(
+            "{} can be reclaimed by compacting",
+            format_bytes(report.orphan_chunk_bytes)
+        )
+    };
+
+    format!("{table}\n{verdict}")
+}
+
+/// Builds the table printed by the `info` command.
+///
+/// Only details `ArchiveReader::new` already read are shown, so the file table is never walked.
+///
+/// # Arguments
+///
+/// * `reader` - The opened archive.
+pub fn build_info_table(reader: &ArchiveReader) -> String {
+    let mut table = Table::new();
+    table.set_format(*FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(Row::new(vec![Cell::new("Squash Info").with_hspan(2)]));
+
+    table.add_row(row!["Squish Version", reader.version()]);
+    table.add_row(row!["Creation Date (UTC)", reader.creation_time()]);
+    table.add_row(row![
+        "Number of chunks",
+        reader.chunk_count().to_formatted_string(&Locale::en)
+    ]);
+    table.add_row(row![
+        "Number of files",
+        reader.file_count().to_formatted_string(&Locale::en)
+    ]);
+    table.add_row(row!["Archive size", format_bytes(reader.archive_size())]);
+    table.add_row(row![
+        "Encrypted",
+        if reader.is_encrypted() { "yes" } else { "no" }
+    ]);
+    // Unchecked; verify-signature confirms the key really signed the archive
+    table.add_row(row![
+        "Signed by",
+        reader
+            .signer_fingerprint()
+            .map_or("not signed".to_string(), fingerprint_to_hex)
+    ]);
+
+    table.to_string()
+}
+
+/// Builds the table printed by the hidden `debug-chunk` command.
+///
+/// Sizes and offsets are shown as raw byte counts since this is for format debugging.
+///
+/// # Arguments
+///
+/// * `info` - The chunk details produced by `ArchiveReader::chunk_info`.
+pub fn build_chunk_info_table(info: &ChunkInfo) -> String {
+    let yes_no = |flag: bool| if flag { "yes" } else { "no" };
+
+    let mut table = Table::new();
+    table.set_format(*FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(Row::new(vec![
+        Cell::new(&format!("Chunk {}", info.index)).with_hspan(2)
+    ]));
+
+    table.add_row(row!["Hash", hash_to_hex(&info.hash)]);
+    table.add_row(row!["Original size", info.original_size]);
+    table.add_row(row!["Compressed size", info.compressed_size]);
+    table.add_row(row!["Stored raw", yes_no(info.stored_raw)]);
+    table.add_row(row!["Offset", info.offset]);
+    table.add_row(row!["Decompresses", yes_no(info.decompresses)]);
+    table.add_row(row!["Hash matches", yes_no(info.hash_matches)]);
+
+    table.to_string()
+}
+
+/// Builds the table printed by the hidden `debug-layout` command.
+///
+/// Offsets are raw byte positions, with `end` exclusive.
+///
+/// # Arguments
+///
+/// * `layout` - The sections produced by `ArchiveReader::layout`.
+pub fn build_layout_table(layout: &[LayoutSection]) -> String {
+    let mut table = Table::new();
+    table.set_format(*FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(row!["Section", "Start", "End", "Length"]);
+
+    for section in layout {
+        table.add_row(row![
+            section.name,
+            section.offset,
+            section.end(),
+            section.length
+        ]);
+    }
+
+    table.to_string()
+}
+
+/// Builds the JSON form of the `debug-layout` output.
+pub fn build_layout_json(layout: &[LayoutSection]) -> Value {
+    let sections: Vec<Value> = layout
+        .iter()
+        .map(|section| {
+            json!({
+                "name": section.name,
+                "start": section.offset,
+                "end": section.end(),
+                "length": section.length,
+            })
+        })
+        .collect();
+
+    json!({ "sections": sections })
+}
+
+/// Parse a thread count, where `auto` resolves to the detected available parallelism
+pub fn parse_thread_count(threads: &str) -> Result<usize, String> {
+    if threads.eq_ignore_ascii_case("auto") {
+        return Ok(std::thread::available_parallelism().map_or(1, |n| n.get()));
+    }
+
+    match threads.parse::<usize>() {
+        Ok(0) | Err(_) => Err(format!(
+            "invalid thread count `{threads}`: expected a positive number or `auto`"
+        )),
+        Ok(n) => Ok(n),
+    }
+}
+
+/// Parse a queue length, which must hold at least one item
+pub fn parse_queue_length(length: &str) -> Result<usize, String> {
+    match length.parse::<usize>() {
+        Ok(0) | Err(_) => Err(format!(
+            "invalid queue length `{length}`: expected a positive number"
+        )),
+        Ok(n) => Ok(n),
+    }
+}
+
+/// Parse a zstd compression level, rejecting levels zstd does not accept
+pub fn parse_compression_level(level: &str) -> Result<i32, String> {
+    let level: i32 = level
+        .parse()
+        .map_err(|_| format!("invalid compression level `{level}`"))?;
+    check_compression_level(level).map_err(|e| e.to_string())
+}
+
+/// Parse a percentage between 0 and 100, with or without a trailing `%`
+pub fn parse_percent(percent: &str) -> Result<f64, String> {
+    let value: f64 = percent
+        .trim_end_matches('%')
+        .parse()
+        .map_err(|_| format!("invalid percentage `{percent}`"))?;
+
+    if (0.0..=100.0).contains(&value) {
+        Ok(value)
+    } else {
+        Err(format!("percentage `{percent}` must be between 0 and 100"))
+    }
+}
+
+/// Works out where `pack` should write its archive, turning paths that would fail with a
+/// cryptic OS error into clear ones.
+///
+/// An `output` naming an existing directory gets `<input name>.squish` inside it; without an
+/// `output` the archive goes next to the input.
+///
+/// # Errors
+///
+/// Returns `AppError::InvalidOutput` if the output's parent exists but is not a directory.
+pub fn resolve_pack_output(input: &str, output: Option<&str>) -> Result<PathBuf, AppError> {
+    let input = input.trim_end_matches(&['/', '\\'][..]);
+    let Some(output) = output else {
+        return Ok(PathBuf::from(format!("{input}.squish")));
+    };
+
+    let output = PathBuf::from(output);
+    if output.is_dir() {
+        // `.` and `..` have no file name of their own
+        let input_name = Path::new(input)
+            .canonicalize()
+            .ok()
+            .and_then(|path| {
+                path.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            })
+            .unwrap_or_else(|| "archive".to_string());
+        return Ok(output.join(format!("{input_name}.squish")));
+    }
+
+    if let Some(parent) = output.parent() {
+        if parent.exists() && !parent.is_dir() {
+            return Err(AppError::InvalidOutput(
+                output.clone(),
+                format!("`{}` is a file, not a directory", parent.display()),
+            ));
+        }
+    }
+
+    Ok(output)
+}
+
+/// Parse a `--sync-interval`: a bare number counts chunks, a size with a unit counts bytes
+pub fn parse_sync_interval(interval: &str) -> Result<SyncInterval, String> {
+    let parsed = match interval.parse::<u64>() {
+        Ok(chunks) => SyncInterval::Chunks(chunks),
+        Err(_) => SyncInterval::Bytes(parse_size(interval)?),
+    };
+
+    match parsed {
+        SyncInterval::Chunks(0) | SyncInterval::Bytes(0) => Err(format!(
+            "invalid sync interval `{interval}`: must be greater than zero"
+        )),
+        parsed => Ok(parsed),
+    }
+}
+
+/// Parse a `--rate-limit` in bytes per second, which must be greater than zero
+pub fn parse_rate_limit(rate: &str) -> Result<u64, String> {
+    match parse_size(rate)? {
+        0 => Err(format!(
+            "invalid rate limit `{rate}`: must be greater than zero"
+        )),
+        bytes_per_second => Ok(bytes_per_second),
+    }
+}
+
+/// Parse a `--compression`: `zstd` or `none`
+pub fn parse_compression(compression: &str) -> Result<Compression, String> {
+    match compression.to_ascii_lowercase().as_str() {
+        "zstd" => Ok(Compression::Zstd),
+        "none" => Ok(Compression::None),
+        _ => Err(format!(
+            "invalid compression `{compression}`: expected `zstd` or `none`"
+        )),
+    }
+}
+
+/// Parse a `--chunking`: `fixed` or `cdc`
+pub fn parse_chunking(chunking: &str) -> Result<Chunking, String> {
+    match chunking.to_ascii_lowercase().as_str() {
+        "fixed" => Ok(Chunking::Fixed),
+        "cdc" => Ok(Chunking::ContentDefined),
+        _ => Err(format!(
+            "invalid chunking `{chunking}`: expected `fixed` or `cdc`"
+        )),
+    }
+}
+
+/// Parse a `--dedup-scope`: `global` or `file`
+pub fn parse_dedup_scope(scope: &str) -> Result<DedupScope, String> {
+    match scope.to_ascii_lowercase().as_str() {
+        "global" => Ok(DedupScope::Global),
+        "file" => Ok(DedupScope::File),
+        _ => Err(format!(
+            "invalid dedup scope `{scope}`: expected `global` or `file`"
+        )),
+    }
+}
+
+/// Parse an `--on-conflict`: `overwrite`, `skip` or `rename`
+pub fn parse_conflict_policy(policy: &str) -> Result<ConflictPolicy, String> {
+    match policy.to_ascii_lowercase().as_str() {
+        "overwrite" => Ok(ConflictPolicy::Overwrite),
+        "skip" => Ok(ConflictPolicy::Skip),
+        "rename" => Ok(ConflictPolicy::Rename),
+        _ => Err(format!(
+            "invalid conflict policy `{policy}`: expected `overwrite`, `skip` or `rename`"
+        )),
+    }
+}
+
+/// Parse a `START:END` byte range, where either bound may use a size unit and END may be empty
+pub fn parse_byte_range(range: &str) -> Result<(u64, Option<u64>), String> {
+    let (start, end) = range
+        .split_once(':')
+        .ok_or_else(|| format!("invalid range `{range}`: expected START:END"))?;
+
+    let start = parse_size(start)?;
+    let end = match end {
+        "" => None,
+        end => Some(parse_size(end)?),
+    };
+
+    match end {
+        Some(end) if end < start => Err(format!("invalid range `{range}`: end is before start")),
+        _ => Ok((start, end)),
+    }
+}
+
+/// Parse a human readable size such as `500MB` or `2GiB` into a number of bytes
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    Byte::parse_str(size, true)
+        .map(|byte| byte.as_u64())
+        .map_err(|e| format!("invalid size `{size}`: {e}"))
+}
+
+/// Parse a `--chunk-size` (e.g. 512KiB, 8MB), rejecting sizes a reader would not accept
+pub fn parse_chunk_size(size: &str) -> Result<usize, String> {
+    check_chunk_size(parse_size(size)?).map_err(|e| e.to_string())
+}
+
 /// Convert bytes into a more human readable form
 pub fn format_bytes(bytes: u64) -> String {
     let byte = Byte::from_u128(bytes as u128);
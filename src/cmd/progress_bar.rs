@@ -1,6 +1,88 @@
-use indicatif::{ProgressBar, ProgressStyle};
+use clap::ValueEnum;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use serde_json::json;
+use std::io::{IsTerminal, Write};
 use std::time::Duration;
 
+/// How often plain-text and JSON progress lines are emitted
+const PLAIN_PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Layout of the progress bar when no `--progress-template` is given
+pub const DEFAULT_PROGRESS_TEMPLATE: &str = "{msg} [{bar:40.cyan/blue}] {pos}/{len} ({eta})";
+
+/// Named `--progress-template` presets and the indicatif templates they stand for
+const PROGRESS_TEMPLATE_PRESETS: &[(&str, &str)] = &[
+    ("minimal", "{msg} {pos}/{len}"),
+    (
+        "detailed",
+        "{msg} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} {percent}% ({per_sec}, {eta} left)",
+    ),
+];
+
+/// How progress is reported while packing and unpacking.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ProgressMode {
+    /// Animated bar on a terminal, plain lines otherwise
+    #[default]
+    Auto,
+    /// Always draw the animated bar
+    Bar,
+    /// Print a plain-text progress line to stderr every second
+    Plain,
+    /// Print a `{"stage":…,"processed":N,"total":M}` line to stderr every second
+    Json,
+    /// Report no progress at all
+    None,
+}
+
+impl ProgressMode {
+    /// Resolves `Auto` based on whether stderr is attached to a terminal.
+    pub fn use_plain(self) -> bool {
+        match self {
+            ProgressMode::Auto => !std::io::stderr().is_terminal(),
+            ProgressMode::Bar | ProgressMode::Json | ProgressMode::None => false,
+            ProgressMode::Plain => true,
+        }
+    }
+
+    /// Whether spinners should be drawn. They have no count to report, so JSON progress and
+    /// no progress hide them.
+    pub fn shows_spinners(self) -> bool {
+        !matches!(self, ProgressMode::Json | ProgressMode::None)
+    }
+}
+
+/// How progress bars are drawn: where they go and, optionally, a custom layout.
+#[derive(Clone, Debug, Default)]
+pub struct ProgressConfig {
+    pub mode: ProgressMode,
+    /// indicatif template replacing `DEFAULT_PROGRESS_TEMPLATE`, already validated by
+    /// [`parse_progress_template`]
+    pub template: Option<String>,
+}
+
+/// Parse a `--progress-template`: a preset name (`minimal`, `detailed`) or an indicatif
+/// template string such as `{msg} {bytes_per_sec} {wide_bar}`.
+///
+/// Templates are checked here so a typo fails before any work starts rather than when the
+/// first progress bar is drawn.
+///
+/// # Returns
+///
+/// The indicatif template to use.
+pub fn parse_progress_template(template: &str) -> Result<String, String> {
+    if let Some((_, preset)) = PROGRESS_TEMPLATE_PRESETS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(template))
+    {
+        return Ok(preset.to_string());
+    }
+
+    ProgressStyle::with_template(template)
+        .map(|_| template.to_string())
+        .map_err(|e| format!("invalid progress template `{template}`: {e}"))
+}
+
 /// Creates and returns a configured progress bar with a custom message.
 ///
 /// # Arguments
@@ -25,16 +107,166 @@ use std::time::Duration;
 /// ```
 pub fn create_progress_bar(length: u64, message: &'static str) -> ProgressBar {
     let pb = ProgressBar::new(length);
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template("{msg} [{bar:40.cyan/blue}] {pos}/{len} ({eta})")
-            .unwrap()
-            .progress_chars("=> "),
-    );
+    pb.set_style(bar_style(DEFAULT_PROGRESS_TEMPLATE).expect("default template is valid"));
     pb.set_message(message);
     pb
 }
 
+/// Builds the bar style for `template`, keeping the default bar characters.
+fn bar_style(template: &str) -> Result<ProgressStyle, indicatif::style::TemplateError> {
+    Ok(ProgressStyle::default_bar()
+        .template(template)?
+        .progress_chars("=> "))
+}
+
+/// Creates a progress bar that honours the requested `ProgressMode`.
+///
+/// In plain mode the animated bar is hidden and a background thread prints a line such as
+/// `Packing: 1234/5678 (21%)` to stderr every second, flushing each line so progress is usable
+/// in logs, pipes and over SSH. The thread stops once the bar is finished.
+///
+/// # Arguments
+///
+/// * `length` - The total length (count) of the progress bar.
+/// * `message` - The message prefix, also used as the stage name in plain-text lines.
+/// * `mode` - How progress should be displayed.
+///
+/// # Example
+///
+/// ```
+/// use squishrs::cmd::progress_bar::{create_progress_bar_with_mode, ProgressMode};
+///
+/// let pb = create_progress_bar_with_mode(10, "Packing", ProgressMode::Plain);
+/// pb.inc(10);
+/// pb.finish_and_clear();
+/// ```
+pub fn create_progress_bar_with_mode(
+    length: u64,
+    message: &'static str,
+    mode: ProgressMode,
+) -> ProgressBar {
+    let config = ProgressConfig {
+        mode,
+        template: None,
+    };
+    create_progress_bar_with_config(length, message, &config)
+}
+
+/// Creates a progress bar that honours both the `ProgressMode` and any custom template in
+/// `config`, see [`create_progress_bar_with_mode`].
+///
+/// A template that fails to parse falls back to the default layout; templates from the
+/// command line have already been validated by [`parse_progress_template`].
+///
+/// # Example
+///
+/// ```
+/// use squishrs::cmd::progress_bar::{create_progress_bar_with_config, ProgressConfig};
+///
+/// let config = ProgressConfig {
+///     template: Some("{msg} {pos}/{len}".to_string()),
+///     ..Default::default()
+/// };
+/// let pb = create_progress_bar_with_config(10, "Packing", &config);
+/// pb.finish_and_clear();
+/// ```
+pub fn create_progress_bar_with_config(
+    length: u64,
+    message: &'static str,
+    config: &ProgressConfig,
+) -> ProgressBar {
+    let pb = create_progress_bar(length, message);
+    if let Some(style) = config
+        .template
+        .as_deref()
+        .and_then(|template| bar_style(template).ok())
+    {
+        pb.set_style(style);
+    }
+
+    if config.mode.use_plain() {
+        pb.set_draw_target(ProgressDrawTarget::hidden());
+        spawn_line_reporter(pb.clone(), PLAIN_PROGRESS_INTERVAL, |pb| {
+            format_plain_progress(&pb.message(), pb.position(), pb.length())
+        });
+    } else if config.mode == ProgressMode::Json {
+        pb.set_draw_target(ProgressDrawTarget::hidden());
+        spawn_line_reporter(pb.clone(), PLAIN_PROGRESS_INTERVAL, |pb| {
+            format_json_progress(&pb.message(), pb.position(), pb.length())
+        });
+    } else if config.mode == ProgressMode::None {
+        pb.set_draw_target(ProgressDrawTarget::hidden());
+    }
+    pb
+}
+
+/// Creates a progress bar as [`create_progress_bar_with_config`] does, or `None` when
+/// `config` asks for no progress, ready to pass on as an optional progress bar.
+///
+/// # Example
+///
+/// ```
+/// use squishrs::cmd::progress_bar::{optional_progress_bar, ProgressConfig, ProgressMode};
+///
+/// let config = ProgressConfig {
+///     mode: ProgressMode::None,
+///     ..Default::default()
+/// };
+/// assert!(optional_progress_bar(10, "Packing", &config).is_none());
+/// ```
+pub fn optional_progress_bar(
+    length: u64,
+    message: &'static str,
+    config: &ProgressConfig,
+) -> Option<ProgressBar> {
+    (config.mode != ProgressMode::None)
+        .then(|| create_progress_bar_with_config(length, message, config))
+}
+
+/// Formats a single plain-text progress line, e.g. `Packing: 12/40 (30%)`.
+pub fn format_plain_progress(message: &str, position: u64, length: Option<u64>) -> String {
+    match length {
+        Some(length) if length > 0 => format!(
+            "{message}: {position}/{length} ({}%)",
+            position.saturating_mul(100) / length
+        ),
+        Some(length) => format!("{message}: {position}/{length}"),
+        None => format!("{message}: {position}"),
+    }
+}
+
+/// Formats a single JSON progress line, e.g.
+/// `{"stage":"Packing","processed":12,"total":40}`. `total` is `null` when the length is unknown.
+pub fn format_json_progress(message: &str, position: u64, length: Option<u64>) -> String {
+    json!({
+        "stage": message,
+        "processed": position,
+        "total": length,
+    })
+    .to_string()
+}
+
+/// Prints the state of `pb`, as `format` renders it, to stderr every `interval` until it is
+/// finished.
+fn spawn_line_reporter(
+    pb: ProgressBar,
+    interval: Duration,
+    format: impl Fn(&ProgressBar) -> String + Send + 'static,
+) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(interval);
+
+        let line = format(&pb);
+        let mut stderr = std::io::stderr().lock();
+        let _ = writeln!(stderr, "{line}");
+        let _ = stderr.flush();
+
+        if pb.is_finished() {
+            break;
+        }
+    });
+}
+
 /// Creates and configures a spinner-style progress bar for displaying file listing progress.
 ///
 /// The spinner updates every 500 milliseconds and cycles through a sequence of dots to indicate activity.
@@ -55,6 +287,27 @@ pub fn create_progress_bar(length: u64, message: &'static str) -> ProgressBar {
 /// pb.finish_with_message("Done scanning files");
 /// ```
 pub fn create_spinner(message: &'static str) -> ProgressBar {
+    create_spinner_with_mode(message, ProgressMode::Bar)
+}
+
+/// Creates a spinner as [`create_spinner`] does, but hidden when `mode` does not show spinners
+/// (see [`ProgressMode::shows_spinners`]).
+///
+/// # Example
+///
+/// ```
+/// use squishrs::cmd::progress_bar::{create_spinner_with_mode, ProgressMode};
+/// let pb = create_spinner_with_mode("Scanning files", ProgressMode::None);
+/// assert!(pb.is_hidden());
+/// pb.finish_and_clear();
+/// ```
+pub fn create_spinner_with_mode(message: &'static str, mode: ProgressMode) -> ProgressBar {
+    if !mode.shows_spinners() {
+        let pb = ProgressBar::hidden();
+        pb.set_message(message);
+        return pb;
+    }
+
     let pb = ProgressBar::new_spinner();
     pb.set_message(message);
     pb.enable_steady_tick(Duration::from_millis(500)); // update spinner every 500ms
@@ -1,6 +1,31 @@
-use super::format_bytes;
-use crate::archive::reader::ArchiveSummary;
-use crate::{build_list_summary_table, create_progress_bar, create_spinner};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use super::{
+    build_analyze_json, build_analyze_table, build_chunk_info_table, build_file_chunks_table,
+    build_layout_json, build_layout_table, build_list_json, build_shared_chunks_table,
+    build_space_report_table, describe_provenance, format_bytes, largest_files, parse_byte_range,
+    parse_chunk_size, parse_chunking, parse_compression, parse_compression_level,
+    parse_conflict_policy, parse_dedup_scope, parse_percent, parse_queue_length, parse_rate_limit,
+    parse_size, parse_sync_interval, parse_thread_count, resolve_pack_output,
+};
+use crate::archive::reader::{
+    ArchiveSummary, ChunkInfo, ConflictPolicy, FileEntry, LayoutSection, SharedChunk, SpaceReport,
+};
+use crate::archive::writer::DedupScope;
+use crate::build_list_summary_table;
+use crate::cmd::progress_bar::{
+    create_progress_bar, create_progress_bar_with_config, create_progress_bar_with_mode,
+    create_spinner, create_spinner_with_mode, format_json_progress, format_plain_progress,
+    optional_progress_bar, parse_progress_template, ProgressConfig, ProgressMode,
+    DEFAULT_PROGRESS_TEMPLATE,
+};
+use crate::fsutil::writer::SyncInterval;
+use crate::util::analyze::{DedupReport, FileContribution, TypeStats};
+use crate::util::chunk::{Chunking, Compression};
+use crate::util::errors::AppError;
+use crate::util::header::Provenance;
 
 #[test]
 fn test_create_progress_bar_basic() {
@@ -38,19 +63,536 @@ fn test_format_bytes() {
 fn test_build_list_summary_table() {
     let summary = ArchiveSummary {
         unique_chunks: 32,
+        top_level_entries: 3,
         total_original_size: 100,
+        duplicate_chunk_bytes: 25,
         archive_size: 20,
         compression_ratio: 80.0,
         squish_creation_date: "DATE".to_string(),
         squish_version: "1.0.1".to_string(),
+        provenance: Some(Provenance {
+            hostname: Some("backup01".to_string()),
+            tool_version: Some("1.0.3".to_string()),
+        }),
         files: Vec::new(),
     };
-    let output = build_list_summary_table(&summary);
+    let output = build_list_summary_table(&summary, false, None);
 
     assert!(output.contains("Squash Summary"));
     assert!(output.contains("Compressed size"));
     assert!(output.contains("Original size"));
     assert!(output.contains("Number of files"));
     assert!(output.contains("Number of chunks"));
+    assert!(output.contains("Saved by deduplication"));
+    assert!(output.contains("25.00 B (25.0%)"));
+    assert!(output.contains("Top-level entries"));
     assert!(output.contains("Top-level directory breakdown"));
+    assert!(output.contains("squishrs 1.0.3 on host `backup01`"));
+}
+
+#[test]
+fn test_build_list_json() {
+    let summary = ArchiveSummary {
+        unique_chunks: 2,
+        top_level_entries: 1,
+        total_original_size: 3_000_000,
+        duplicate_chunk_bytes: 0,
+        archive_size: 1_500_000,
+        compression_ratio: 50.0,
+        squish_creation_date: "DATE".to_string(),
+        squish_version: "1.0.1".to_string(),
+        provenance: None,
+        files: vec![FileEntry {
+            path: "docs/report.pdf".to_string(),
+            original_size: 3_000_000,
+            chunk_count: 2,
+        }],
+    };
+    let json = build_list_json(&summary);
+
+    // Sizes stay raw byte counts rather than `format_bytes` strings
+    assert_eq!(json["archive_size"], 1_500_000);
+    assert_eq!(json["total_original_size"], 3_000_000);
+    assert_eq!(json["duplicate_chunk_bytes"], 0);
+    assert_eq!(json["compression_ratio"], 50.0);
+    assert_eq!(json["unique_chunks"], 2);
+    assert_eq!(json["creation_date"], "DATE");
+    assert_eq!(json["version"], "1.0.1");
+    assert!(json["provenance"].is_null());
+    assert_eq!(json["files"][0]["path"], "docs/report.pdf");
+    assert_eq!(json["files"][0]["original_size"], 3_000_000);
+    assert_eq!(json["files"][0]["chunk_count"], 2);
+}
+
+#[test]
+fn test_describe_provenance() {
+    let provenance = Provenance {
+        hostname: None,
+        tool_version: Some("1.2.0".to_string()),
+    };
+    assert_eq!(describe_provenance(&provenance), "squishrs 1.2.0");
+}
+
+#[test]
+fn test_parse_size() {
+    assert_eq!(parse_size("1024"), Ok(1024));
+    assert_eq!(parse_size("2KB"), Ok(2000));
+    assert_eq!(parse_size("1MiB"), Ok(1024 * 1024));
+    assert!(parse_size("lots").is_err());
+}
+
+#[test]
+fn test_parse_compression_level() {
+    assert_eq!(parse_compression_level("19"), Ok(19));
+    assert_eq!(parse_compression_level("-5"), Ok(-5));
+    assert!(parse_compression_level("23")
+        .unwrap_err()
+        .contains("out of range"));
+    assert!(parse_compression_level("max").is_err());
+}
+
+#[test]
+fn test_parse_percent() {
+    assert_eq!(parse_percent("5"), Ok(5.0));
+    assert_eq!(parse_percent("2.5%"), Ok(2.5));
+    assert!(parse_percent("150").is_err());
+    assert!(parse_percent("lots").is_err());
+}
+
+#[test]
+fn test_parse_sync_interval() {
+    assert_eq!(parse_sync_interval("64"), Ok(SyncInterval::Chunks(64)));
+    assert_eq!(
+        parse_sync_interval("256MB"),
+        Ok(SyncInterval::Bytes(256_000_000))
+    );
+    assert!(parse_sync_interval("0").is_err());
+    assert!(parse_sync_interval("often").is_err());
+}
+
+#[test]
+fn test_resolve_pack_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("photos");
+    fs::create_dir(&input).unwrap();
+    let input = format!("{}/", input.display());
+
+    assert_eq!(
+        resolve_pack_output("data/", None).unwrap(),
+        PathBuf::from("data.squish")
+    );
+    assert_eq!(
+        resolve_pack_output(&input, Some("out.squish")).unwrap(),
+        PathBuf::from("out.squish")
+    );
+
+    // An existing directory gets the archive named after the input inside it
+    let out_dir = dir.path().to_str().unwrap();
+    assert_eq!(
+        resolve_pack_output(&input, Some(out_dir)).unwrap(),
+        dir.path().join("photos.squish")
+    );
+
+    let file = dir.path().join("notes.txt");
+    fs::write(&file, b"not a directory").unwrap();
+    let beneath_file = file.join("out.squish");
+    assert!(matches!(
+        resolve_pack_output(&input, beneath_file.to_str()),
+        Err(AppError::InvalidOutput(..))
+    ));
+}
+
+#[test]
+fn test_parse_rate_limit() {
+    assert_eq!(parse_rate_limit("10MB"), Ok(10_000_000));
+    assert!(parse_rate_limit("0").is_err());
+    assert!(parse_rate_limit("fast").is_err());
+}
+
+#[test]
+fn test_parse_chunk_size() {
+    assert_eq!(parse_chunk_size("4096"), Ok(4096));
+    assert_eq!(parse_chunk_size("512KiB"), Ok(512 * 1024));
+    assert!(parse_chunk_size("0").is_err());
+    assert!(parse_chunk_size("2GiB").is_err());
+}
+
+#[test]
+fn test_parse_chunking() {
+    assert_eq!(parse_chunking("fixed"), Ok(Chunking::Fixed));
+    assert_eq!(parse_chunking("CDC"), Ok(Chunking::ContentDefined));
+    assert!(parse_chunking("rolling").is_err());
+}
+
+#[test]
+fn test_parse_compression() {
+    assert_eq!(parse_compression("zstd"), Ok(Compression::Zstd));
+    assert_eq!(parse_compression("None"), Ok(Compression::None));
+    assert!(parse_compression("gzip").is_err());
+}
+
+#[test]
+fn test_parse_dedup_scope() {
+    assert_eq!(parse_dedup_scope("global"), Ok(DedupScope::Global));
+    assert_eq!(parse_dedup_scope("File"), Ok(DedupScope::File));
+    assert!(parse_dedup_scope("tenant").is_err());
+}
+
+#[test]
+fn test_parse_conflict_policy() {
+    assert_eq!(
+        parse_conflict_policy("overwrite"),
+        Ok(ConflictPolicy::Overwrite)
+    );
+    assert_eq!(parse_conflict_policy("Skip"), Ok(ConflictPolicy::Skip));
+    assert_eq!(parse_conflict_policy("rename"), Ok(ConflictPolicy::Rename));
+    assert!(parse_conflict_policy("merge").is_err());
+}
+
+#[test]
+fn test_parse_byte_range() {
+    assert_eq!(parse_byte_range("0:65536"), Ok((0, Some(65536))));
+    assert_eq!(parse_byte_range("1KiB:"), Ok((1024, None)));
+    assert!(parse_byte_range("100:10").is_err());
+    assert!(parse_byte_range("100").is_err());
+}
+
+#[test]
+fn test_parse_queue_length() {
+    assert_eq!(parse_queue_length("64"), Ok(64));
+    assert_eq!(parse_queue_length("1"), Ok(1));
+    assert!(parse_queue_length("0").is_err());
+    assert!(parse_queue_length("-3").is_err());
+    assert!(parse_queue_length("many").is_err());
+}
+
+#[test]
+fn test_parse_thread_count() {
+    assert_eq!(parse_thread_count("8"), Ok(8));
+    assert!(parse_thread_count("auto").unwrap() >= 1);
+    assert!(parse_thread_count("0").is_err());
+    assert!(parse_thread_count("many").is_err());
+}
+
+fn sample_dedup_report() -> DedupReport {
+    DedupReport {
+        chunk_size: 1024,
+        files: 2,
+        total_chunks: 4,
+        unique_chunks: 3,
+        total_bytes: 4096,
+        unique_bytes: 3072,
+        repeat_histogram: BTreeMap::from([(1, 2), (2, 1)]),
+        by_type: vec![TypeStats {
+            extension: "log".to_string(),
+            files: 2,
+            total_bytes: 4096,
+            unique_bytes: 3072,
+            sampled_bytes: 2048,
+            sampled_compressed_bytes: 512,
+        }],
+        by_file: vec![FileContribution {
+            path: PathBuf::from("logs/app.log"),
+            total_bytes: 3072,
+            unique_compressed_bytes: 700,
+            shared_compressed_bytes: 150,
+        }],
+    }
+}
+
+#[test]
+fn test_build_analyze_table() {
+    let output = build_analyze_table(&sample_dedup_report());
+
+    assert!(output.contains("Dedup Summary"));
+    assert!(output.contains("Unique chunks"));
+    assert!(output.contains("1.33x"));
+    assert!(output.contains("Chunk repeat histogram"));
+    assert!(output.contains("By file type"));
+    assert!(output.contains("4.00x"));
+    assert!(output.contains("Largest unique contributions"));
+    assert!(output.contains("logs/app.log"));
+}
+
+#[test]
+fn test_build_analyze_json() {
+    let json = build_analyze_json(&sample_dedup_report());
+
+    assert_eq!(json["total_chunks"], 4);
+    assert_eq!(json["unique_chunks"], 3);
+    assert_eq!(json["duplicate_bytes"], 1024);
+    assert_eq!(json["repeat_histogram"][1]["times_seen"], 2);
+    assert_eq!(json["by_type"][0]["extension"], "log");
+    assert_eq!(json["by_file"][0]["unique_compressed_bytes"], 700);
+    assert_eq!(json["by_type"][0]["compression_ratio"], 4.0);
+}
+
+#[test]
+fn test_build_file_chunks_table() {
+    let summary = ArchiveSummary {
+        unique_chunks: 3,
+        top_level_entries: 1,
+        total_original_size: 5_000_000,
+        duplicate_chunk_bytes: 0,
+        archive_size: 1_000,
+        compression_ratio: 0.02,
+        squish_creation_date: "DATE".to_string(),
+        squish_version: "1.0.1".to_string(),
+        provenance: None,
+        files: vec![
+            FileEntry {
+                path: "notes/small.txt".to_string(),
+                original_size: 1_500,
+                chunk_count: 1,
+            },
+            FileEntry {
+                path: "videos/big.mp4".to_string(),
+                original_size: 5_000_000,
+                chunk_count: 3,
+            },
+        ],
+    };
+    let output = build_file_chunks_table(&summary);
+
+    assert!(output.contains("File chunk counts"));
+    assert!(output.contains("Chunks"));
+    assert!(output.contains("5.00 MB"));
+    // Archive order
+    assert!(output.find("notes/small.txt").unwrap() < output.find("videos/big.mp4").unwrap());
+}
+
+#[test]
+fn test_build_list_summary_table_largest_files() {
+    let summary = ArchiveSummary {
+        unique_chunks: 4,
+        top_level_entries: 2,
+        total_original_size: 5_001_500,
+        duplicate_chunk_bytes: 0,
+        archive_size: 4_000_000,
+        compression_ratio: 20.0,
+        squish_creation_date: "DATE".to_string(),
+        squish_version: "1.0.1".to_string(),
+        provenance: None,
+        files: vec![
+            FileEntry {
+                path: "notes/small.txt".to_string(),
+                original_size: 1_500,
+                chunk_count: 1,
+            },
+            FileEntry {
+                path: "videos/big.mp4".to_string(),
+                original_size: 5_000_000,
+                chunk_count: 3,
+            },
+        ],
+    };
+
+    let output = build_list_summary_table(&summary, false, None);
+    assert!(!output.contains("Largest files"));
+
+    let output = build_list_summary_table(&summary, true, None);
+    assert!(output.contains("Largest files"));
+    assert!(output.contains("5.00 MB"));
+    assert!(output.find("videos/big.mp4").unwrap() < output.find("notes/small.txt").unwrap());
+
+    let limited = build_list_summary_table(&summary, true, Some(1));
+    assert!(limited.contains("videos/big.mp4"));
+    assert!(!limited.contains("notes/small.txt"));
+    assert!(limited.contains("and 1 more files"));
+}
+
+#[test]
+fn test_largest_files() {
+    let entry = |path: &str, original_size| FileEntry {
+        path: path.to_string(),
+        original_size,
+        chunk_count: 1,
+    };
+    let files = vec![
+        entry("a", 10),
+        entry("b", 30),
+        entry("c", 10),
+        entry("d", 20),
+    ];
+
+    let paths = |files: Vec<&FileEntry>| -> Vec<String> {
+        files.into_iter().map(|file| file.path.clone()).collect()
+    };
+    // Ties keep archive order
+    assert_eq!(paths(largest_files(&files, None)), ["b", "d", "a", "c"]);
+    assert_eq!(paths(largest_files(&files, Some(2))), ["b", "d"]);
+    assert!(largest_files(&files, Some(0)).is_empty());
+}
+
+#[test]
+fn test_format_plain_progress() {
+    assert_eq!(
+        format_plain_progress("Packing", 12, Some(40)),
+        "Packing: 12/40 (30%)"
+    );
+    assert_eq!(format_plain_progress("Packing", 0, Some(0)), "Packing: 0/0");
+    assert_eq!(format_plain_progress("Scanning", 7, None), "Scanning: 7");
+}
+
+#[test]
+fn test_create_progress_bar_plain_mode_is_hidden() {
+    let pb = create_progress_bar_with_mode(5, "Packing", ProgressMode::Plain);
+    assert!(pb.is_hidden());
+    assert_eq!(pb.length(), Some(5));
+
+    pb.inc(5);
+    pb.finish_and_clear();
+    assert!(ProgressMode::Plain.use_plain());
+    assert!(!ProgressMode::Bar.use_plain());
+}
+
+#[test]
+fn test_format_json_progress() {
+    assert_eq!(
+        format_json_progress("Packing", 12, Some(40)),
+        r#"{"processed":12,"stage":"Packing","total":40}"#
+    );
+    assert_eq!(
+        format_json_progress("Reading Chunks", 3, None),
+        r#"{"processed":3,"stage":"Reading Chunks","total":null}"#
+    );
+}
+
+#[test]
+fn test_progress_mode_none_and_json() {
+    let none = ProgressConfig {
+        mode: ProgressMode::None,
+        ..Default::default()
+    };
+    assert!(optional_progress_bar(5, "Packing", &none).is_none());
+    assert!(create_spinner_with_mode("Scanning", ProgressMode::None).is_hidden());
+
+    let json = ProgressConfig {
+        mode: ProgressMode::Json,
+        ..Default::default()
+    };
+    let pb = optional_progress_bar(5, "Packing", &json).expect("JSON mode reports progress");
+    assert!(pb.is_hidden());
+    pb.finish_and_clear();
+    assert!(create_spinner_with_mode("Scanning", ProgressMode::Json).is_hidden());
+    assert!(!ProgressMode::Json.use_plain());
+}
+
+#[test]
+fn test_parse_progress_template() {
+    assert_eq!(
+        parse_progress_template("minimal").unwrap(),
+        "{msg} {pos}/{len}"
+    );
+    assert!(parse_progress_template("DETAILED")
+        .unwrap()
+        .contains("{elapsed_precise}"));
+    assert_eq!(
+        parse_progress_template(DEFAULT_PROGRESS_TEMPLATE).unwrap(),
+        DEFAULT_PROGRESS_TEMPLATE
+    );
+    assert_eq!(
+        parse_progress_template("{msg} {wide_bar} {bytes}").unwrap(),
+        "{msg} {wide_bar} {bytes}"
+    );
+
+    let err = parse_progress_template("{msg} {bar:wide}").unwrap_err();
+    assert!(err.contains("invalid progress template"), "{err}");
+}
+
+#[test]
+fn test_create_progress_bar_with_template() {
+    let config = ProgressConfig {
+        mode: ProgressMode::Plain,
+        template: Some(parse_progress_template("minimal").unwrap()),
+    };
+    let pb = create_progress_bar_with_config(3, "Packing", &config);
+    assert!(pb.is_hidden());
+    assert_eq!(pb.length(), Some(3));
+    pb.finish_and_clear();
+}
+
+#[test]
+fn test_build_chunk_info_table() {
+    let info = ChunkInfo {
+        index: 7,
+        hash: [0xab; 16],
+        original_size: 2048,
+        compressed_size: 512,
+        stored_raw: false,
+        offset: 1234,
+        decompresses: true,
+        hash_matches: false,
+    };
+    let output = build_chunk_info_table(&info);
+
+    assert!(output.contains("Chunk 7"));
+    assert!(output.contains(&"ab".repeat(16)));
+    assert!(output.contains("1234"));
+    assert!(output.contains("Hash matches"));
+}
+
+#[test]
+fn test_build_space_report_table() {
+    let compact = SpaceReport {
+        on_disk_bytes: 4096,
+        live_chunk_bytes: 4000,
+        orphan_chunk_bytes: 0,
+        orphan_count: 0,
+    };
+    let output = build_space_report_table(&compact);
+    assert!(output.contains("Space Audit"));
+    assert!(output.contains("Archive is compact"));
+
+    let wasteful = SpaceReport {
+        on_disk_bytes: 4096,
+        live_chunk_bytes: 2000,
+        orphan_chunk_bytes: 2000,
+        orphan_count: 3,
+    };
+    let output = build_space_report_table(&wasteful);
+    assert!(output.contains("Orphaned chunks"));
+    assert!(output.contains("can be reclaimed by compacting"));
+}
+
+#[test]
+fn test_build_layout_output() {
+    let layout = vec![
+        LayoutSection {
+            name: "header",
+            offset: 0,
+            length: 11,
+        },
+        LayoutSection {
+            name: "timestamp",
+            offset: 11,
+            length: 8,
+        },
+    ];
+
+    let output = build_layout_table(&layout);
+    assert!(output.contains("Section"));
+    assert!(output.contains("timestamp"));
+    assert!(output.contains("19"));
+
+    let json = build_layout_json(&layout);
+    assert_eq!(json["sections"][1]["name"], "timestamp");
+    assert_eq!(json["sections"][1]["start"], 11);
+    assert_eq!(json["sections"][1]["end"], 19);
+}
+
+#[test]
+fn test_build_shared_chunks_table() {
+    assert!(build_shared_chunks_table(&[]).contains("No chunks are shared"));
+
+    let shared = [SharedChunk {
+        hash: [0xcd; 16],
+        size: 1500,
+        files: vec!["a/one.bin".to_string(), "b/two.bin".to_string()],
+    }];
+    let output = build_shared_chunks_table(&shared);
+    assert!(output.contains(&"cd".repeat(16)));
+    assert!(output.contains("1.50 KB"));
+    assert!(output.contains("a/one.bin"));
+    assert!(output.contains("b/two.bin"));
 }
@@ -0,0 +1,37 @@
+use std::fs::File;
+use std::io;
+
+/// Reserves `len` bytes of disk space for `file` before it is written.
+///
+/// Allocating the whole extent up front lets the filesystem lay the file out contiguously and
+/// avoids growing it one write at a time, which matters for large restores. On platforms or
+/// filesystems without preallocation support this is a no-op.
+///
+/// # Errors
+///
+/// Returns an I/O error if the filesystem supports preallocation but cannot reserve the space,
+/// e.g. because the device is full.
+#[cfg(target_os = "linux")]
+pub fn preallocate(file: &File, len: u64) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    if len == 0 {
+        return Ok(());
+    }
+
+    let len = libc::off_t::try_from(len)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "file too large"))?;
+
+    // SAFETY: the descriptor is owned by `file` and stays open for the duration of the call
+    match unsafe { libc::posix_fallocate(file.as_raw_fd(), 0, len) } {
+        0 => Ok(()),
+        // Filesystems such as some network mounts cannot preallocate; fall back to plain writes
+        libc::EOPNOTSUPP | libc::EINVAL => Ok(()),
+        errno => Err(io::Error::from_raw_os_error(errno)),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn preallocate(_file: &File, _len: u64) -> io::Result<()> {
+    Ok(())
+}
@@ -1,10 +1,16 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsString;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use rayon::iter::Either;
 use rayon::prelude::*;
 
+use crate::fsutil::filter::PathFilter;
+use crate::fsutil::gitignore::{is_ignored, Gitignore};
 use crate::util::errors::AppError;
+use crate::util::header::has_squish_magic;
 
 /// Recursively walks a directory and returns a vector of all file paths found.
 ///
@@ -12,6 +18,9 @@ use crate::util::errors::AppError;
 /// at the given `path`. It collects directory entries and processes them in parallel using
 /// Rayon to improve performance when traversing large directory hierarchies.
 ///
+/// Symlinks are listed like files and never followed, so a symlink loop cannot make the walk
+/// recurse forever.
+///
 /// # Arguments
 ///
 /// * `path` - A reference to a `Path` representing the root directory to walk.
@@ -37,22 +46,152 @@ use crate::util::errors::AppError;
 /// println!("Found {} files", files.len());
 /// ```
 pub fn walk_dir(path: &Path) -> Result<Vec<PathBuf>, AppError> {
-    let mut stack = vec![path.to_path_buf()];
+    walk_dir_excluding(path, &[])
+}
+
+/// Walks a directory like [`walk_dir`], leaving out the paths in `excluded`.
+///
+/// Paths are compared after canonicalization, so an excluded path matches however it is
+/// spelled, and it may not exist yet. An excluded directory is not descended into. The
+/// exclusions are resolved once before walking, so the result does not depend on what appears
+/// on disk while the walk runs, e.g. an archive being written inside the input directory.
+///
+/// # Errors
+///
+/// Fails like [`walk_dir`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use squishrs::fsutil::directory::walk_dir_excluding;
+/// use std::path::{Path, PathBuf};
+///
+/// let excluded = [PathBuf::from("./data/backup.squish")];
+/// let files = walk_dir_excluding(Path::new("./data"), &excluded).expect("Failed to walk directory");
+/// ```
+pub fn walk_dir_excluding(path: &Path, excluded: &[PathBuf]) -> Result<Vec<PathBuf>, AppError> {
+    walk_dir_limited(path, excluded, usize::MAX, |_| true)
+}
+
+/// Walks a directory like [`walk_dir_excluding`], stopping early once more than `max_files`
+/// files have been found.
+///
+/// The count is checked after each directory is read, so a huge tree fails fast instead of
+/// being listed in full first. When the limit is passed `confirm` is called once with the
+/// number of files found so far; returning `true` lifts the limit and the walk carries on.
+///
+/// # Errors
+///
+/// Returns `AppError::Archive` if the limit is exceeded and `confirm` returns `false`, and
+/// otherwise fails like [`walk_dir`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use squishrs::fsutil::directory::walk_dir_limited;
+/// use std::path::Path;
+///
+/// // Refuse to pack a directory with more than 10,000 files
+/// let files = walk_dir_limited(Path::new("./data"), &[], 10_000, |_| false)?;
+/// # Ok::<(), squishrs::util::errors::AppError>(())
+/// ```
+pub fn walk_dir_limited(
+    path: &Path,
+    excluded: &[PathBuf],
+    max_files: usize,
+    confirm: impl FnMut(usize) -> bool,
+) -> Result<Vec<PathBuf>, AppError> {
+    walk_tree(path, excluded, &PathFilter::default(), max_files, confirm).map(|tree| tree.files)
+}
+
+/// Files found under a directory together with the directories that hold nothing at all.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct WalkedTree {
+    pub files: Vec<PathBuf>,
+    /// Directories with no entries on disk. The walked directory itself is never listed
+    pub empty_dirs: Vec<PathBuf>,
+}
+
+/// Walks a directory like [`walk_dir_limited`], also collecting its empty directories and
+/// applying `filter`.
+///
+/// A directory's parents are recreated along with it, so only the innermost empty
+/// directories are listed; a directory holding nothing but empty directories is not.
+/// Directories the filter excludes are not descended into, and files and empty directories
+/// are only listed if the filter keeps them. The limit counts files the filter keeps. With
+/// [`PathFilter::with_gitignore`], `.git` and paths ignored by the `.gitignore` files met
+/// along the way are left out the same way.
+///
+/// # Errors
+///
+/// Fails like [`walk_dir_limited`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use squishrs::fsutil::directory::walk_tree;
+/// use squishrs::fsutil::filter::PathFilter;
+/// use std::path::Path;
+///
+/// let filter = PathFilter::new(Vec::new(), vec!["target".into(), "*.log".into()]);
+/// let tree = walk_tree(Path::new("./data"), &[], &filter, usize::MAX, |_| true)?;
+/// println!("{} files, {} empty directories", tree.files.len(), tree.empty_dirs.len());
+/// # Ok::<(), squishrs::util::errors::AppError>(())
+/// ```
+pub fn walk_tree(
+    path: &Path,
+    excluded: &[PathBuf],
+    filter: &PathFilter,
+    max_files: usize,
+    mut confirm: impl FnMut(usize) -> bool,
+) -> Result<WalkedTree, AppError> {
+    let mut limit = Some(max_files);
+    let excluded = ExcludedPaths::new(excluded);
+    // Each directory still to walk, with the `.gitignore` files of the directories above it
+    let mut stack = vec![(path.to_path_buf(), Vec::new())];
     let mut files = Vec::new();
+    let mut empty_dirs = Vec::new();
+    // Canonical paths of directories already walked, so a directory reachable twice, e.g.
+    // through a bind mount, is listed once and a loop cannot recurse forever
+    let mut visited = HashSet::new();
+
+    while let Some((dir, mut gitignores)) = stack.pop() {
+        if !visited.insert(fs::canonicalize(&dir).unwrap_or_else(|_| dir.clone())) {
+            continue;
+        }
 
-    while let Some(dir) = stack.pop() {
         // Collect all Dir entries into a vector
         let entries = fs::read_dir(&dir)
             .map_err(|e| AppError::ReadDirError(dir.display().to_string(), e))?
             .collect::<Result<Vec<_>, _>>()
             .map_err(|e| AppError::ReadEntryError(dir.clone(), e))?;
 
+        if entries.is_empty() && dir != path && filter.keeps(relative_to(&dir, path)) {
+            empty_dirs.push(dir);
+            continue;
+        }
+
+        if filter.uses_gitignore() {
+            gitignores.extend(Gitignore::load(&dir, relative_to(&dir, path))?.map(Arc::new));
+        }
+
         // Process each entry concurrently
         let (dirs, regular_files): (Vec<_>, Vec<_>) = entries
             .into_par_iter()
             .map(|entry| {
-                let path = entry.path();
-                if path.is_dir() {
+                // Symlinks are not followed; they are listed like files and packed as links
+                let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+                (entry.path(), is_dir)
+            })
+            .filter(|(path, _)| !excluded.matches(path))
+            .filter(|(entry_path, _)| !filter.excludes(relative_to(entry_path, path)))
+            .filter(|(entry_path, is_dir)| {
+                !filter.uses_gitignore()
+                    || (entry_path.file_name() != Some(".git".as_ref())
+                        && !is_ignored(&gitignores, relative_to(entry_path, path), *is_dir))
+            })
+            .map(|(path, is_dir)| {
+                if is_dir {
                     (Some(path), None)
                 } else {
                     (None, Some(path))
@@ -65,9 +204,189 @@ pub fn walk_dir(path: &Path) -> Result<Vec<PathBuf>, AppError> {
             });
 
         // Update for next iteration
-        stack.extend(dirs);
-        files.extend(regular_files);
+        stack.extend(dirs.into_iter().map(|dir| (dir, gitignores.clone())));
+        files.extend(
+            regular_files
+                .into_iter()
+                .filter(|file| filter.keeps(relative_to(file, path))),
+        );
+
+        if limit.is_some_and(|max_files| files.len() > max_files) {
+            if !confirm(files.len()) {
+                return Err(AppError::Archive(format!(
+                    "file count limit exceeded: more than {} files under `{}`",
+                    max_files,
+                    path.display()
+                )));
+            }
+            limit = None;
+        }
+    }
+
+    Ok(WalkedTree { files, empty_dirs })
+}
+
+/// `path` relative to the walked directory `root`, as filter patterns see it.
+fn relative_to<'a>(path: &'a Path, root: &Path) -> &'a Path {
+    path.strip_prefix(root).unwrap_or(path)
+}
+
+/// Canonical forms of the paths a walk must leave out.
+struct ExcludedPaths {
+    /// File names of the excluded paths, checked first so most entries are never canonicalized
+    names: HashSet<OsString>,
+    canonical: HashSet<PathBuf>,
+}
+
+impl ExcludedPaths {
+    fn new(paths: &[PathBuf]) -> Self {
+        let mut excluded = Self {
+            names: HashSet::new(),
+            canonical: HashSet::new(),
+        };
+        for path in paths {
+            if let (Some(name), Some(canonical)) = (path.file_name(), canonical_target(path)) {
+                excluded.names.insert(name.to_os_string());
+                excluded.canonical.insert(canonical);
+            }
+        }
+        excluded
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        path.file_name()
+            .is_some_and(|name| self.names.contains(name))
+            && canonical_target(path).is_some_and(|path| self.canonical.contains(&path))
+    }
+}
+
+/// Canonicalizes `path`, or only its parent directory when `path` does not exist yet.
+fn canonical_target(path: &Path) -> Option<PathBuf> {
+    if let Ok(canonical) = fs::canonicalize(path) {
+        return Some(canonical);
+    }
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    Some(fs::canonicalize(parent).ok()?.join(path.file_name()?))
+}
+
+/// Returns the files in `files` that are already squish archives.
+///
+/// Archives are compressed already, so packing them again wastes time for little gain and is
+/// usually a mistake. Each file is identified by sniffing its magic bytes, not its extension.
+///
+/// # Examples
+///
+/// ```no_run
+/// use squishrs::fsutil::directory::{find_squished, walk_dir};
+/// use std::path::Path;
+///
+/// let files = walk_dir(Path::new("./data")).expect("Failed to walk directory");
+/// for archive in find_squished(&files) {
+///     println!("{} is already squished", archive.display());
+/// }
+/// ```
+pub fn find_squished(files: &[PathBuf]) -> Vec<PathBuf> {
+    files
+        .par_iter()
+        .filter(|path| has_squish_magic(path))
+        .cloned()
+        .collect()
+}
+
+/// Finds the files in `files` that are hard links to another file in the list.
+///
+/// Files sharing a device and inode are grouped, and every file in a group except the first
+/// by path is mapped to that first one, its primary. Symlinks are not followed. Only Unix
+/// exposes inode numbers, so elsewhere no links are found.
+///
+/// # Examples
+///
+/// ```no_run
+/// use squishrs::fsutil::directory::{find_hard_links, walk_dir};
+/// use std::path::Path;
+///
+/// let files = walk_dir(Path::new("./data")).expect("Failed to walk directory");
+/// for (link, primary) in find_hard_links(&files) {
+///     println!("{} is a hard link to {}", link.display(), primary.display());
+/// }
+/// ```
+pub fn find_hard_links(files: &[PathBuf]) -> HashMap<PathBuf, PathBuf> {
+    let mut groups: HashMap<(u64, u64), Vec<&PathBuf>> = HashMap::new();
+    for file in files {
+        if let Some(id) = fs::symlink_metadata(file)
+            .ok()
+            .and_then(|m| linked_file_id(&m))
+        {
+            groups.entry(id).or_default().push(file);
+        }
+    }
+
+    let mut links = HashMap::new();
+    for mut group in groups.into_values().filter(|group| group.len() > 1) {
+        group.sort();
+        let primary = group[0];
+        for link in &group[1..] {
+            links.insert((*link).clone(), primary.clone());
+        }
+    }
+    links
+}
+
+/// Device and inode of a regular file with more than one link.
+#[cfg(unix)]
+fn linked_file_id(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    (metadata.is_file() && metadata.nlink() > 1).then(|| (metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn linked_file_id(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Files found under a directory, split by the immediate subdirectory they live in.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct TopLevelGroups {
+    /// Files under each immediate subdirectory, keyed by the subdirectory's name
+    pub dirs: BTreeMap<String, Vec<PathBuf>>,
+    /// Files directly inside the root directory
+    pub loose: Vec<PathBuf>,
+}
+
+/// Groups `files`, as returned by [`walk_dir`] for `root`, by their top-level subdirectory.
+///
+/// Subdirectories that contain no files do not get a group.
+///
+/// # Examples
+///
+/// ```rust
+/// use squishrs::fsutil::directory::group_by_top_level_dir;
+/// use std::path::{Path, PathBuf};
+///
+/// let files = vec![PathBuf::from("root/a/x.txt"), PathBuf::from("root/notes.txt")];
+/// let groups = group_by_top_level_dir(Path::new("root"), &files);
+/// assert_eq!(groups.dirs["a"], vec![PathBuf::from("root/a/x.txt")]);
+/// assert_eq!(groups.loose, vec![PathBuf::from("root/notes.txt")]);
+/// ```
+pub fn group_by_top_level_dir(root: &Path, files: &[PathBuf]) -> TopLevelGroups {
+    let mut groups = TopLevelGroups::default();
+
+    for file in files {
+        let relative = file.strip_prefix(root).unwrap_or(file);
+        let mut components = relative.components();
+        match (components.next(), components.next()) {
+            (Some(dir), Some(_)) => groups
+                .dirs
+                .entry(dir.as_os_str().to_string_lossy().into_owned())
+                .or_default()
+                .push(file.clone()),
+            _ => groups.loose.push(file.clone()),
+        }
     }
 
-    Ok(files)
+    groups
 }
@@ -0,0 +1,214 @@
+use std::path::Path;
+
+/// Include and exclude glob patterns deciding which paths under the input directory are packed.
+///
+/// Patterns are matched against paths relative to the input directory, with `/` between
+/// components on every platform:
+/// - `*` matches any run of characters within one component and `?` any single one
+/// - `[abc]`, `[a-z]` and `[!abc]` match one character from (or not from) a set
+/// - `**` matches any number of whole components, so `target/**` is everything under `target`
+/// - A pattern without a `/`, such as `*.log` or `node_modules`, matches a name at any depth
+///
+/// A pattern matching a directory applies to everything inside it. Excludes win over includes,
+/// and with no includes every path not excluded is kept.
+///
+/// # Examples
+///
+/// ```
+/// use squishrs::fsutil::filter::PathFilter;
+/// use std::path::Path;
+///
+/// let filter = PathFilter::new(vec!["src/**".into()], vec!["*.log".into()]);
+/// assert!(filter.keeps(Path::new("src/main.rs")));
+/// assert!(!filter.keeps(Path::new("src/debug.log")));
+/// assert!(!filter.keeps(Path::new("README.md")));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PathFilter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+    gitignore: bool,
+}
+
+/// One glob pattern of a [`PathFilter`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Pattern {
+    glob: String,
+    /// Matched against the whole relative path rather than a single name, because the pattern
+    /// contains a `/`. A leading `/` only anchors the pattern and is dropped
+    anchored: bool,
+}
+
+impl Pattern {
+    fn parse(pattern: &str) -> Option<Self> {
+        let trimmed = pattern.trim_end_matches('/');
+        let glob = trimmed.trim_start_matches('/');
+        (!glob.is_empty()).then(|| Self {
+            glob: glob.to_string(),
+            anchored: trimmed.contains('/'),
+        })
+    }
+
+    /// Matches the directory or file at `path`, whose last component is `name`.
+    fn matches(&self, path: &str, name: &str) -> bool {
+        if !self.anchored {
+            return glob_match(&self.glob, name);
+        }
+        // `dir/**` also covers `dir` itself, so the walk can skip it outright
+        glob_match(&self.glob, path)
+            || self
+                .glob
+                .strip_suffix("/**")
+                .is_some_and(|dir| glob_match(dir, path))
+    }
+}
+
+impl PathFilter {
+    pub fn new(include: Vec<String>, exclude: Vec<String>) -> Self {
+        let parse = |patterns: Vec<String>| {
+            patterns
+                .iter()
+                .filter_map(|pattern| Pattern::parse(pattern))
+                .collect()
+        };
+        Self {
+            include: parse(include),
+            exclude: parse(exclude),
+            gitignore: false,
+        }
+    }
+
+    /// Also leaves out `.git` and whatever the `.gitignore` files in the walked tree ignore.
+    ///
+    /// Each `.gitignore` applies to the directory holding it and everything below, and a
+    /// deeper one overrides a shallower one. Only files inside the walked directory are read.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use squishrs::fsutil::filter::PathFilter;
+    ///
+    /// let filter = PathFilter::default().with_gitignore(true);
+    /// assert!(filter.uses_gitignore());
+    /// ```
+    pub fn with_gitignore(mut self, gitignore: bool) -> Self {
+        self.gitignore = gitignore;
+        self
+    }
+
+    /// Returns true if walks should honor `.gitignore` files.
+    pub fn uses_gitignore(&self) -> bool {
+        self.gitignore
+    }
+
+    /// Returns true when no pattern is set and `.gitignore` files are not honored, so every
+    /// path is kept.
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty() && !self.gitignore
+    }
+
+    /// Returns true if `relative`, or a directory it is in, matches an exclude pattern.
+    ///
+    /// Walks use this to skip excluded directories without descending into them.
+    pub fn excludes(&self, relative: &Path) -> bool {
+        matches_any(&self.exclude, relative)
+    }
+
+    /// Returns true if the file at `relative` should be packed.
+    pub fn keeps(&self, relative: &Path) -> bool {
+        !self.excludes(relative)
+            && (self.include.is_empty() || matches_any(&self.include, relative))
+    }
+}
+
+/// Returns true if any pattern matches `relative` or one of the directories it is in.
+fn matches_any(patterns: &[Pattern], relative: &Path) -> bool {
+    if patterns.is_empty() {
+        return false;
+    }
+
+    let components: Vec<String> = relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    (1..=components.len()).any(|depth| {
+        let path = components[..depth].join("/");
+        let name = &components[depth - 1];
+        patterns.iter().any(|pattern| pattern.matches(&path, name))
+    })
+}
+
+/// Matches `text` against a glob `pattern`, see [`PathFilter`] for the syntax.
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    match_from(&pattern, &text)
+}
+
+fn match_from(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', '*'] => true,
+        ['*', '*', rest @ ..] => {
+            // `**/` spans whole components, so the rest must start at a component boundary
+            let rest = rest.strip_prefix(&['/']).unwrap_or(rest);
+            (0..=text.len())
+                .filter(|&start| start == 0 || text[start - 1] == '/')
+                .any(|start| match_from(rest, &text[start..]))
+        }
+        ['*', rest @ ..] => {
+            let run = text.iter().take_while(|&&c| c != '/').count();
+            (0..=run).any(|skip| match_from(rest, &text[skip..]))
+        }
+        ['?', rest @ ..] => match text {
+            [c, text @ ..] if *c != '/' => match_from(rest, text),
+            _ => false,
+        },
+        ['[', rest @ ..] => match (text, class_end(rest)) {
+            ([c, text @ ..], Some(end)) => {
+                *c != '/' && class_matches(&rest[..end], *c) && match_from(&rest[end + 1..], text)
+            }
+            // An unclosed `[` is an ordinary character
+            ([c, text @ ..], None) => *c == '[' && match_from(rest, text),
+            ([], _) => false,
+        },
+        [p, rest @ ..] => match text {
+            [c, text @ ..] if c == p => match_from(rest, text),
+            _ => false,
+        },
+    }
+}
+
+/// Index of the `]` closing a character class whose contents start at `class[0]`.
+fn class_end(class: &[char]) -> Option<usize> {
+    // A `]` right after the opening bracket (or its `!`) is part of the set
+    let first = match class.first() {
+        Some('!') => 2,
+        _ => 1,
+    };
+    class
+        .iter()
+        .skip(first)
+        .position(|&c| c == ']')
+        .map(|index| index + first)
+}
+
+fn class_matches(class: &[char], c: char) -> bool {
+    let (negated, set) = match class {
+        ['!', set @ ..] => (true, set),
+        set => (false, set),
+    };
+
+    let mut found = false;
+    let mut index = 0;
+    while index < set.len() {
+        if index + 2 < set.len() && set[index + 1] == '-' {
+            found |= (set[index]..=set[index + 2]).contains(&c);
+            index += 3;
+        } else {
+            found |= set[index] == c;
+            index += 1;
+        }
+    }
+    found != negated
+}
@@ -0,0 +1,124 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::fsutil::filter::glob_match;
+use crate::util::errors::AppError;
+
+/// The rules of one `.gitignore` file, found while walking a directory.
+///
+/// Patterns follow git's syntax: `#` starts a comment, `!` re-includes a path an earlier rule
+/// ignored, a trailing `/` only matches directories, and a pattern with a `/` elsewhere is
+/// matched against the path relative to the `.gitignore`'s directory rather than a name at
+/// any depth. Within a file the last matching rule wins.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Gitignore {
+    /// Directory holding the `.gitignore`, relative to the walked directory
+    base: PathBuf,
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rule {
+    glob: String,
+    anchored: bool,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.starts_with('#') {
+            return None;
+        }
+
+        // Trailing spaces are dropped unless escaped with a backslash
+        let trimmed = line.trim_end_matches(' ');
+        let line = match trimmed.strip_suffix('\\') {
+            Some(escaped) if trimmed.len() < line.len() => format!("{escaped} "),
+            _ => trimmed.to_string(),
+        };
+
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(&line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let glob = line.strip_prefix('/').unwrap_or(line);
+
+        (!glob.is_empty()).then(|| Self {
+            glob: glob.to_string(),
+            anchored,
+            negated,
+            dir_only,
+        })
+    }
+}
+
+impl Gitignore {
+    /// Parses the contents of the `.gitignore` in `base`, relative to the walked directory.
+    pub(crate) fn parse(base: &Path, contents: &str) -> Self {
+        Self {
+            base: base.to_path_buf(),
+            rules: contents.lines().filter_map(Rule::parse).collect(),
+        }
+    }
+
+    /// Reads the `.gitignore` in `dir`, if there is one.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Io` if the file exists but cannot be read.
+    pub(crate) fn load(dir: &Path, base: &Path) -> Result<Option<Self>, AppError> {
+        match fs::read_to_string(dir.join(".gitignore")) {
+            Ok(contents) => Ok(Some(Self::parse(base, &contents))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(AppError::Io(e)),
+        }
+    }
+
+    /// Returns `Some(true)` if the last rule matching `relative` ignores it, `Some(false)` if it
+    /// re-includes it, and `None` if no rule applies.
+    fn decide(&self, relative: &Path, is_dir: bool) -> Option<bool> {
+        let inside = relative.strip_prefix(&self.base).ok()?;
+        let components: Vec<String> = inside
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        let name = components.last()?;
+        let path = components.join("/");
+
+        self.rules
+            .iter()
+            .rev()
+            .filter(|rule| is_dir || !rule.dir_only)
+            .find(|rule| {
+                if rule.anchored {
+                    glob_match(&rule.glob, &path)
+                } else {
+                    glob_match(&rule.glob, name)
+                }
+            })
+            .map(|rule| !rule.negated)
+    }
+}
+
+/// Returns true if `relative`, a path inside the walked directory, is ignored by `gitignores`.
+///
+/// `gitignores` holds the files found in the path's parent directories, outermost first. A
+/// deeper `.gitignore` overrides a shallower one, so they are consulted innermost first.
+/// Paths inside an ignored directory are never asked about, because the walk does not descend
+/// into it, which matches git: a file in an ignored directory cannot be re-included.
+pub(crate) fn is_ignored(gitignores: &[Arc<Gitignore>], relative: &Path, is_dir: bool) -> bool {
+    gitignores
+        .iter()
+        .rev()
+        .find_map(|gitignore| gitignore.decide(relative, is_dir))
+        .unwrap_or(false)
+}
@@ -1,4 +1,9 @@
+pub mod alloc;
 pub mod directory;
+pub mod filter;
+mod gitignore;
+pub mod temp;
+pub mod throttle;
 pub mod writer;
 
 #[cfg(test)]
@@ -0,0 +1,79 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Environment variable consulted when no temporary directory is given explicitly.
+pub const TMPDIR_ENV: &str = "TMPDIR";
+
+/// Resolves the directory intermediate files should be written to.
+///
+/// An explicitly configured directory wins, then `TMPDIR` if it is set. `None` means
+/// "alongside the destination", which is the default.
+pub fn resolve_temp_dir(explicit: Option<PathBuf>) -> Option<PathBuf> {
+    explicit.or_else(|| {
+        std::env::var_os(TMPDIR_ENV)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    })
+}
+
+/// Builds a path for a scratch file derived from `target`, e.g. `archive.squish.chunks.tmp`.
+///
+/// The file is placed in `temp_dir` when given, otherwise next to `target`. Use this for
+/// intermediate data that is copied rather than renamed into place.
+///
+/// # Examples
+///
+/// ```
+/// use squishrs::fsutil::temp::scratch_path;
+/// use std::path::{Path, PathBuf};
+///
+/// let path = scratch_path(Path::new("out/archive.squish"), Some(Path::new("/fast")), ".tmp");
+/// assert_eq!(path, PathBuf::from("/fast/archive.squish.tmp"));
+/// ```
+pub fn scratch_path(target: &Path, temp_dir: Option<&Path>, suffix: &str) -> PathBuf {
+    let mut file_name = target
+        .file_name()
+        .map(|name| name.to_os_string())
+        .unwrap_or_else(|| OsString::from("squish"));
+    file_name.push(suffix);
+
+    match temp_dir {
+        Some(dir) => dir.join(file_name),
+        None => target.with_file_name(file_name),
+    }
+}
+
+/// Builds a path for a file that will later be renamed onto `target`.
+///
+/// Renames only work within one filesystem; across devices they would fail or, worse, degrade
+/// into a slow silent copy. If `temp_dir` lives on a different device than `target`'s directory
+/// the staging file is placed next to `target` instead, trading the choice of volume for a
+/// guaranteed atomic rename.
+pub fn staging_path(target: &Path, temp_dir: Option<&Path>, suffix: &str) -> PathBuf {
+    let target_dir = match target.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    let temp_dir = temp_dir.filter(|dir| same_device(dir, target_dir));
+    scratch_path(target, temp_dir, suffix)
+}
+
+#[cfg(unix)]
+fn same_device(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (std::fs::metadata(a), std::fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_device(a: &Path, b: &Path) -> bool {
+    // Without device ids, only trust a rename when both paths share a root
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a.components().next() == b.components().next(),
+        _ => false,
+    }
+}
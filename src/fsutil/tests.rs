@@ -1,11 +1,23 @@
 use std::fs;
 use std::fs::File;
 use std::io::{BufWriter, Cursor, Read, Seek, SeekFrom, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
-
-use crate::fsutil::directory::walk_dir;
-use crate::fsutil::writer::{writer_thread, ChunkMessage, ThreadSafeWriter};
+use std::time::{Duration, Instant};
+
+use crate::fsutil::directory::{
+    find_squished, group_by_top_level_dir, walk_dir, walk_dir_excluding, walk_dir_limited,
+    walk_tree,
+};
+use crate::fsutil::filter::PathFilter;
+use crate::fsutil::gitignore::{is_ignored, Gitignore};
+use crate::fsutil::temp::{scratch_path, staging_path};
+use crate::fsutil::throttle::TokenBucket;
+use crate::fsutil::writer::{
+    is_out_of_space, writer_thread, ChunkMessage, SizeLimit, SyncInterval, SyncPolicy,
+    ThreadSafeWriter, WriterConfig,
+};
 
 use crossbeam::channel::unbounded;
 use tempfile::{tempdir, tempfile};
@@ -35,6 +47,172 @@ fn test_empty_directory() {
     assert!(files.is_empty());
 }
 
+#[test]
+fn test_walk_tree_empty_dirs() {
+    let dir = tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("logs")).unwrap();
+    fs::create_dir_all(dir.path().join("cache/tmp/deep")).unwrap();
+    fs::create_dir_all(dir.path().join("src")).unwrap();
+    File::create(dir.path().join("src/main.rs")).unwrap();
+
+    let mut tree = walk_tree(dir.path(), &[], &PathFilter::default(), usize::MAX, |_| {
+        true
+    })
+    .unwrap();
+    tree.empty_dirs.sort();
+
+    // Only the innermost empty directories, never the root or directories holding files
+    assert_eq!(
+        tree.empty_dirs,
+        vec![dir.path().join("cache/tmp/deep"), dir.path().join("logs")]
+    );
+    assert_eq!(tree.files, vec![dir.path().join("src/main.rs")]);
+    assert!(walk_tree(
+        &dir.path().join("logs"),
+        &[],
+        &PathFilter::default(),
+        usize::MAX,
+        |_| true
+    )
+    .unwrap()
+    .empty_dirs
+    .is_empty());
+}
+
+#[test]
+fn test_path_filter_patterns() {
+    let logs = PathFilter::new(Vec::new(), vec!["*.log".into()]);
+    assert!(!logs.keeps(Path::new("debug.log")));
+    assert!(!logs.keeps(Path::new("app/logs/debug.log")));
+    assert!(logs.keeps(Path::new("debug.log.txt")));
+
+    // Directory patterns cover everything inside, at the root or, without a `/`, at any depth
+    let dirs = PathFilter::new(Vec::new(), vec!["target/**".into(), "node_modules/".into()]);
+    assert!(dirs.excludes(Path::new("target")));
+    assert!(!dirs.keeps(Path::new("target/debug/app")));
+    assert!(dirs.keeps(Path::new("src/target.rs")));
+    assert!(dirs.keeps(Path::new("crates/target/lib.rs")));
+    assert!(!dirs.keeps(Path::new("web/node_modules/react/index.js")));
+
+    // Excludes win over overlapping includes
+    let overlap = PathFilter::new(
+        vec!["src/**".into(), "*.md".into()],
+        vec!["src/**/gen_*.rs".into(), "[A-Z]*.md".into()],
+    );
+    assert!(overlap.keeps(Path::new("src/main.rs")));
+    assert!(overlap.keeps(Path::new("docs/guide.md")));
+    assert!(!overlap.keeps(Path::new("src/proto/gen_types.rs")));
+    assert!(!overlap.keeps(Path::new("src/gen_types.rs")));
+    assert!(!overlap.keeps(Path::new("README.md")));
+    assert!(!overlap.keeps(Path::new("Cargo.toml")));
+
+    let single = PathFilter::new(vec!["data/file?.[!c]sv".into()], Vec::new());
+    assert!(single.keeps(Path::new("data/file1.tsv")));
+    assert!(!single.keeps(Path::new("data/file1.csv")));
+    assert!(!single.keeps(Path::new("data/file10.tsv")));
+}
+
+#[test]
+fn test_walk_tree_filter() {
+    let dir = tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("target/debug")).unwrap();
+    fs::create_dir_all(dir.path().join("src")).unwrap();
+    fs::create_dir_all(dir.path().join("empty")).unwrap();
+    File::create(dir.path().join("target/debug/app")).unwrap();
+    File::create(dir.path().join("src/main.rs")).unwrap();
+    File::create(dir.path().join("src/build.log")).unwrap();
+
+    let filter = PathFilter::new(Vec::new(), vec!["target".into(), "*.log".into()]);
+    let tree = walk_tree(dir.path(), &[], &filter, usize::MAX, |_| true).unwrap();
+    assert_eq!(tree.files, vec![dir.path().join("src/main.rs")]);
+    assert_eq!(tree.empty_dirs, vec![dir.path().join("empty")]);
+
+    // Empty directories must match an include like files do
+    let filter = PathFilter::new(vec!["src/**".into()], Vec::new());
+    let tree = walk_tree(dir.path(), &[], &filter, usize::MAX, |_| true).unwrap();
+    assert!(tree.empty_dirs.is_empty());
+}
+
+#[test]
+fn test_gitignore_rules() {
+    let root = Arc::new(Gitignore::parse(
+        Path::new(""),
+        "build/\n\\#notes\n\\!bang\ntrailing\\ \n/only-root\n**/deep/*.tmp\n",
+    ));
+    let nested = Arc::new(Gitignore::parse(Path::new("sub"), "!build/\n"));
+    let gitignores = [root, nested];
+
+    // A trailing slash only matches directories
+    assert!(is_ignored(&gitignores, Path::new("build"), true));
+    assert!(!is_ignored(&gitignores, Path::new("build"), false));
+    assert!(is_ignored(&gitignores, Path::new("#notes"), false));
+    assert!(is_ignored(&gitignores, Path::new("!bang"), false));
+    assert!(is_ignored(&gitignores, Path::new("trailing "), false));
+    assert!(!is_ignored(&gitignores, Path::new("trailing"), false));
+    assert!(is_ignored(&gitignores, Path::new("only-root"), false));
+    assert!(!is_ignored(&gitignores, Path::new("a/only-root"), false));
+    assert!(is_ignored(&gitignores, Path::new("deep/x.tmp"), false));
+    assert!(is_ignored(&gitignores, Path::new("a/b/deep/x.tmp"), false));
+
+    // A deeper .gitignore overrides the root one, but only below its own directory
+    assert!(!is_ignored(&gitignores, Path::new("sub/build"), true));
+    assert!(is_ignored(&gitignores, Path::new("other/build"), true));
+}
+
+#[test]
+fn test_walk_tree_gitignore() {
+    let dir = tempdir().unwrap();
+    for sub in [".git/objects", "target/debug", "src/generated", "docs"] {
+        fs::create_dir_all(dir.path().join(sub)).unwrap();
+    }
+    for file in [
+        ".git/HEAD",
+        "target/debug/app",
+        "src/main.rs",
+        "src/generated/types.rs",
+        "src/generated/keep.rs",
+        "docs/build.log",
+        "docs/important.log",
+        "notes.txt",
+    ] {
+        File::create(dir.path().join(file)).unwrap();
+    }
+    fs::write(
+        dir.path().join(".gitignore"),
+        "# build output\n/target/\n*.log\n!important.log\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("src/.gitignore"),
+        "generated/*\n!generated/keep.rs\n",
+    )
+    .unwrap();
+
+    let filter = PathFilter::default().with_gitignore(true);
+    let mut tree = walk_tree(dir.path(), &[], &filter, usize::MAX, |_| true).unwrap();
+    tree.files.sort();
+
+    let expected: Vec<PathBuf> = [
+        ".gitignore",
+        "docs/important.log",
+        "notes.txt",
+        "src/.gitignore",
+        "src/generated/keep.rs",
+        "src/main.rs",
+    ]
+    .iter()
+    .map(|file| dir.path().join(file))
+    .collect();
+    assert_eq!(tree.files, expected);
+
+    // Without the flag nothing is left out
+    let tree = walk_tree(dir.path(), &[], &PathFilter::default(), usize::MAX, |_| {
+        true
+    })
+    .unwrap();
+    assert_eq!(tree.files.len(), 10);
+}
+
 #[test]
 fn test_directory_with_files() {
     let dir = tempdir().unwrap();
@@ -51,6 +229,92 @@ fn test_directory_with_files() {
     assert_eq!(files, expected);
 }
 
+#[test]
+fn test_walk_dir_excluding() {
+    let dir = tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("nested/out")).unwrap();
+    fs::write(dir.path().join("keep.txt"), b"1").unwrap();
+    fs::write(dir.path().join("nested/out.squish"), b"2").unwrap();
+    fs::write(dir.path().join("nested/out/stale.squish"), b"3").unwrap();
+
+    // Spelled differently from the walked paths, and one does not exist yet
+    let excluded = vec![
+        dir.path().join("nested/../nested/out.squish"),
+        dir.path().join("nested/out"),
+        dir.path().join("nested/out.squish.partial"),
+    ];
+    let files = walk_dir_excluding(dir.path(), &excluded).unwrap();
+    assert_eq!(files, vec![dir.path().join("keep.txt")]);
+
+    // Once an excluded path exists it is still left out
+    fs::write(dir.path().join("nested/out.squish.partial"), b"5").unwrap();
+    let files = walk_dir_excluding(dir.path(), &excluded).unwrap();
+    assert_eq!(files, vec![dir.path().join("keep.txt")]);
+}
+
+#[test]
+fn test_walk_dir_limited() {
+    let dir = tempdir().unwrap();
+    fs::create_dir(dir.path().join("nested")).unwrap();
+    for name in ["a.txt", "b.txt", "nested/c.txt"] {
+        fs::write(dir.path().join(name), b"x").unwrap();
+    }
+
+    // At the limit is fine
+    let files = walk_dir_limited(dir.path(), &[], 3, |_| false).unwrap();
+    assert_eq!(files.len(), 3);
+
+    // Over it fails unless confirmed, and confirming is asked once
+    let err = walk_dir_limited(dir.path(), &[], 2, |_| false).unwrap_err();
+    assert!(err.to_string().contains("file count limit exceeded"));
+
+    let mut asked = 0;
+    let files = walk_dir_limited(dir.path(), &[], 1, |_| {
+        asked += 1;
+        true
+    })
+    .unwrap();
+    assert_eq!(files.len(), 3);
+    assert_eq!(asked, 1);
+}
+
+#[test]
+fn test_group_by_top_level_dir() {
+    let dir = tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("alpha/nested")).unwrap();
+    fs::create_dir(dir.path().join("beta")).unwrap();
+    fs::create_dir(dir.path().join("empty")).unwrap();
+    fs::write(dir.path().join("alpha/one.txt"), b"1").unwrap();
+    fs::write(dir.path().join("alpha/nested/two.txt"), b"2").unwrap();
+    fs::write(dir.path().join("beta/three.txt"), b"3").unwrap();
+    fs::write(dir.path().join("loose.txt"), b"4").unwrap();
+
+    let files = walk_dir(dir.path()).unwrap();
+    let groups = group_by_top_level_dir(dir.path(), &files);
+
+    assert_eq!(
+        groups.dirs.keys().collect::<Vec<_>>(),
+        vec!["alpha", "beta"]
+    );
+    assert_eq!(groups.dirs["alpha"].len(), 2);
+    assert_eq!(groups.dirs["beta"], vec![dir.path().join("beta/three.txt")]);
+    assert_eq!(groups.loose, vec![dir.path().join("loose.txt")]);
+}
+
+#[test]
+fn test_find_squished_sniffs_magic() {
+    let dir = tempdir().unwrap();
+    let archive = dir.path().join("renamed.bin");
+    let lookalike = dir.path().join("notes.squish");
+    let short = dir.path().join("tiny.txt");
+    fs::write(&archive, b"squish1.2.0 rest of archive").unwrap();
+    fs::write(&lookalike, b"plain text").unwrap();
+    fs::write(&short, b"sq").unwrap();
+
+    let files = vec![archive.clone(), lookalike, short];
+    assert_eq!(find_squished(&files), vec![archive]);
+}
+
 #[test]
 fn test_directory_with_nested_subdirs() {
     let dir = tempdir().unwrap();
@@ -90,13 +354,169 @@ fn test_writer_thread_happy_path() {
         hash,
         compressed_data: data.clone(),
         original_size,
+        stored_raw: false,
     })
     .unwrap();
 
     drop(tx); // Close channel to end the loop
 
     // Run writer_thread
-    writer_thread(writer, rx).unwrap();
+    writer_thread(writer, rx, WriterConfig::default()).unwrap();
+}
+
+#[test]
+fn test_writer_thread_size_limit_exceeded() {
+    let writer = Cursor::new(Vec::new());
+    let (tx, rx) = unbounded();
+
+    tx.send(ChunkMessage {
+        hash: [1u8; 16],
+        compressed_data: Arc::new(vec![2u8; 100]),
+        original_size: 100,
+        stored_raw: false,
+    })
+    .unwrap();
+    drop(tx);
+
+    let halt = Arc::new(AtomicBool::new(false));
+    let config = WriterConfig {
+        size_limit: Some(SizeLimit {
+            max_bytes: 64,
+            bytes_written: 0,
+            halt: Arc::clone(&halt),
+        }),
+        ..Default::default()
+    };
+
+    let result = writer_thread(writer, rx, config);
+    assert!(result.is_err());
+    assert!(
+        halt.load(Ordering::SeqCst),
+        "Producers should be told to halt"
+    );
+}
+
+#[test]
+fn test_writer_thread_rate_limit() {
+    let flushes = Arc::new(AtomicUsize::new(0));
+    let (tx, rx) = unbounded();
+
+    // Three 50 KB entries against a 100 KB/s bucket: the first two fit the initial burst and
+    // the third has to wait for half a second of refill
+    for i in 0..3u8 {
+        tx.send(ChunkMessage {
+            hash: [i; 16],
+            compressed_data: Arc::new(vec![i; 50_000 - 32]),
+            original_size: 50_000 - 32,
+            stored_raw: false,
+        })
+        .unwrap();
+    }
+    drop(tx);
+
+    let config = WriterConfig {
+        rate_limit: Some(TokenBucket::new(100_000)),
+        ..Default::default()
+    };
+
+    let started = Instant::now();
+    writer_thread(FlushCounter(flushes), rx, config).unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(400));
+}
+
+/// Accepts `capacity` bytes, then fails every write as a full disk would.
+struct FullDisk {
+    capacity: usize,
+}
+
+impl Write for FullDisk {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.capacity == 0 {
+            return Err(std::io::Error::from(std::io::ErrorKind::StorageFull));
+        }
+        let written = buf.len().min(self.capacity);
+        self.capacity -= written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_writer_thread_reports_full_disk() {
+    let (tx, rx) = unbounded();
+
+    // Each entry is 100 bytes and the disk holds 250, so the third chunk fails
+    for i in 0..4u8 {
+        tx.send(ChunkMessage {
+            hash: [i; 16],
+            compressed_data: Arc::new(vec![i; 68]),
+            original_size: 68,
+            stored_raw: false,
+        })
+        .unwrap();
+    }
+    drop(tx);
+
+    let chunks_written = Arc::new(AtomicU64::new(0));
+    let config = WriterConfig {
+        chunks_written: Some(Arc::clone(&chunks_written)),
+        ..Default::default()
+    };
+
+    let error = writer_thread(FullDisk { capacity: 250 }, rx, config).unwrap_err();
+    assert!(is_out_of_space(&error));
+    assert_eq!(chunks_written.load(Ordering::SeqCst), 2);
+}
+
+/// Discards writes and counts how often it is flushed.
+struct FlushCounter(Arc<AtomicUsize>);
+
+impl Write for FlushCounter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+fn flushes_with_sync_interval(interval: SyncInterval) -> usize {
+    let flushes = Arc::new(AtomicUsize::new(0));
+    let (tx, rx) = unbounded();
+
+    for i in 0..5u8 {
+        tx.send(ChunkMessage {
+            hash: [i; 16],
+            compressed_data: Arc::new(vec![i; 68]),
+            original_size: 68,
+            stored_raw: false,
+        })
+        .unwrap();
+    }
+    drop(tx);
+
+    let config = WriterConfig {
+        sync: Some(SyncPolicy {
+            interval,
+            fsync: None,
+        }),
+        ..Default::default()
+    };
+    writer_thread(FlushCounter(Arc::clone(&flushes)), rx, config).unwrap();
+    flushes.load(Ordering::SeqCst)
+}
+
+#[test]
+fn test_writer_thread_sync_interval() {
+    // Each entry is 100 bytes; the final flush always happens
+    assert_eq!(flushes_with_sync_interval(SyncInterval::Chunks(2)), 2 + 1);
+    assert_eq!(flushes_with_sync_interval(SyncInterval::Bytes(250)), 1 + 1);
+    assert_eq!(flushes_with_sync_interval(SyncInterval::Chunks(1)), 5 + 1);
 }
 
 #[test]
@@ -135,3 +555,29 @@ fn test_thread_safe_writer_write_and_flush() {
 
     assert_eq!(&output[..], data);
 }
+
+#[test]
+fn test_scratch_path_defaults_next_to_target() {
+    let path = scratch_path(Path::new("out/archive.squish"), None, ".tmp");
+    assert_eq!(path, Path::new("out/archive.squish.tmp"));
+}
+
+#[test]
+fn test_staging_path_uses_temp_dir_on_same_device() {
+    let dir = tempdir().unwrap();
+    let temp = dir.path().join("temp");
+    fs::create_dir(&temp).unwrap();
+    let target = dir.path().join("archive.squish");
+
+    let path = staging_path(&target, Some(&temp), ".tmp");
+    assert_eq!(path, temp.join("archive.squish.tmp"));
+}
+
+#[test]
+fn test_staging_path_falls_back_when_temp_dir_missing() {
+    let dir = tempdir().unwrap();
+    let target = dir.path().join("archive.squish");
+
+    let path = staging_path(&target, Some(Path::new("/nonexistent/temp")), ".tmp");
+    assert_eq!(path, dir.path().join("archive.squish.tmp"));
+}
@@ -0,0 +1,54 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Token bucket capping how many bytes per second are written.
+///
+/// The bucket holds at most one second's worth of tokens, so after an idle period up to `rate`
+/// bytes pass without waiting; beyond that writes are paced to the configured rate. A write
+/// larger than the bucket is allowed through and paid back by sleeping, so single chunks never
+/// deadlock against a low rate.
+pub struct TokenBucket {
+    /// Bytes added to the bucket per second
+    rate: f64,
+    /// Tokens currently available; negative while a large write is being paid back
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Creates a full bucket that refills at `bytes_per_second`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use squishrs::fsutil::throttle::TokenBucket;
+    ///
+    /// let mut bucket = TokenBucket::new(1024 * 1024);
+    /// bucket.take(4096); // within the initial burst, returns immediately
+    /// ```
+    pub fn new(bytes_per_second: u64) -> Self {
+        let rate = bytes_per_second.max(1) as f64;
+        Self {
+            rate,
+            tokens: rate,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Spends `bytes` tokens, sleeping until the bucket has refilled enough to cover them.
+    pub fn take(&mut self, bytes: u64) {
+        self.refill();
+        self.tokens -= bytes as f64;
+
+        if self.tokens < 0.0 {
+            thread::sleep(Duration::from_secs_f64(-self.tokens / self.rate));
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.last_refill = now;
+    }
+}
@@ -1,10 +1,13 @@
 use std::fs;
-use std::io::{BufWriter, Write};
+use std::io::{self, BufWriter, ErrorKind, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::sync::Mutex;
 
-use crate::util::chunk::ChunkHash;
+use crate::fsutil::throttle::TokenBucket;
+use crate::util::chunk::{encode_stored_size, ChunkHash};
 use crate::util::errors::AppError;
+use crate::util::interrupt::check_interrupted;
 
 use crossbeam::channel::Receiver;
 
@@ -12,14 +15,115 @@ pub struct ChunkMessage {
     pub hash: ChunkHash,
     pub compressed_data: Arc<Vec<u8>>,
     pub original_size: u64,
+    /// `compressed_data` holds the chunk uncompressed
+    pub stored_raw: bool,
 }
 
+/// Upper bound on the number of bytes the writer thread may emit into the archive.
+pub struct SizeLimit {
+    /// Maximum size of the whole archive in bytes
+    pub max_bytes: u64,
+    /// Bytes already written before the writer thread started (header, timestamp, ...)
+    pub bytes_written: u64,
+    /// Raised by the writer thread once the limit is hit so producers stop early
+    pub halt: Arc<AtomicBool>,
+}
+
+/// How much chunk data the writer thread emits between flushes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncInterval {
+    /// Flush after this many chunks
+    Chunks(u64),
+    /// Flush once at least this many bytes have been written since the last flush
+    Bytes(u64),
+}
+
+/// Periodic flushing, trading throughput for losing less of a long pack on a crash.
+pub struct SyncPolicy {
+    pub interval: SyncInterval,
+    /// Handle to the underlying file to `fsync` after each flush; `None` only flushes
+    pub fsync: Option<fs::File>,
+}
+
+/// Options controlling how `writer_thread` emits chunks.
+#[derive(Default)]
+pub struct WriterConfig {
+    pub size_limit: Option<SizeLimit>,
+    /// Flush (and optionally fsync) periodically instead of only once all chunks are written
+    pub sync: Option<SyncPolicy>,
+    /// Pace chunk writes so output never runs faster than the bucket's rate
+    pub rate_limit: Option<TokenBucket>,
+    /// Counts chunk entries written, so callers can report how far writing got when it fails
+    pub chunks_written: Option<Arc<AtomicU64>>,
+}
+
+/// Whether `error` failed a write because the destination ran out of space (ENOSPC) or quota.
+///
+/// # Example
+///
+/// ```
+/// use squishrs::fsutil::writer::is_out_of_space;
+/// use squishrs::util::errors::AppError;
+/// use std::io::{Error, ErrorKind};
+///
+/// assert!(is_out_of_space(&AppError::WriterError(Error::from(ErrorKind::StorageFull))));
+/// assert!(!is_out_of_space(&AppError::WriterError(Error::from(ErrorKind::BrokenPipe))));
+/// ```
+pub fn is_out_of_space(error: &AppError) -> bool {
+    let io_error: &io::Error = match error {
+        AppError::Io(e) | AppError::WriterError(e) | AppError::FlushError(e) => e,
+        _ => return false,
+    };
+    matches!(
+        io_error.kind(),
+        ErrorKind::StorageFull | ErrorKind::QuotaExceeded
+    )
+}
+
+/// Drains compressed chunks from `rx` and writes them to the chunk table.
+///
+/// Each chunk is written as its hash, original size, compressed size and compressed bytes.
+/// When a `SizeLimit` is configured the thread tracks the bytes written and, if the next chunk
+/// would exceed the limit, raises the halt flag and stops accepting chunks.
+///
+/// Output is only flushed once the channel closes unless a `SyncPolicy` is configured, in
+/// which case it is also flushed, and optionally fsynced, every time the interval elapses.
+/// With a `rate_limit` each chunk entry waits for enough tokens before it is written.
+///
+/// # Errors
+///
+/// Returns `AppError::WriterError` if writing fails, `AppError::FlushError` if a flush or fsync
+/// fails, or `AppError::Archive` when the size limit would be exceeded.
 pub fn writer_thread<W: Write + Send + 'static>(
     mut writer: W,
     rx: Receiver<ChunkMessage>,
+    config: WriterConfig,
 ) -> Result<(), AppError> {
+    let mut size_limit = config.size_limit;
+    let sync = config.sync;
+    let mut rate_limit = config.rate_limit;
+    let mut chunks_since_sync = 0u64;
+    let mut bytes_since_sync = 0u64;
+
     for chunk_msg in rx.iter() {
+        // Stop rather than drain the queue, which can take a while under a rate limit
+        check_interrupted()?;
+
         let compressed_size = chunk_msg.compressed_data.len() as u64;
+        // hash + original size + compressed size + data
+        let entry_size = 16 + 8 + 8 + compressed_size;
+
+        if let Some(limit) = size_limit.as_mut() {
+            if limit.bytes_written + entry_size > limit.max_bytes {
+                limit.halt.store(true, Ordering::SeqCst);
+                return Err(AppError::Archive("archive size limit exceeded".into()));
+            }
+            limit.bytes_written += entry_size;
+        }
+
+        if let Some(bucket) = rate_limit.as_mut() {
+            bucket.take(entry_size);
+        }
 
         writer
             .write_all(&chunk_msg.hash)
@@ -28,11 +132,32 @@ pub fn writer_thread<W: Write + Send + 'static>(
             .write_all(&chunk_msg.original_size.to_le_bytes())
             .map_err(AppError::WriterError)?;
         writer
-            .write_all(&compressed_size.to_le_bytes())
+            .write_all(&encode_stored_size(compressed_size, chunk_msg.stored_raw).to_le_bytes())
             .map_err(AppError::WriterError)?;
         writer
             .write_all(&chunk_msg.compressed_data)
             .map_err(AppError::WriterError)?;
+        if let Some(chunks_written) = &config.chunks_written {
+            chunks_written.fetch_add(1, Ordering::SeqCst);
+        }
+
+        if let Some(policy) = &sync {
+            chunks_since_sync += 1;
+            bytes_since_sync += entry_size;
+
+            let due = match policy.interval {
+                SyncInterval::Chunks(n) => chunks_since_sync >= n,
+                SyncInterval::Bytes(n) => bytes_since_sync >= n,
+            };
+            if due {
+                writer.flush().map_err(AppError::FlushError)?;
+                if let Some(file) = &policy.fsync {
+                    file.sync_data().map_err(AppError::FlushError)?;
+                }
+                chunks_since_sync = 0;
+                bytes_since_sync = 0;
+            }
+        }
     }
     writer.flush().map_err(AppError::FlushError)?;
     Ok(())
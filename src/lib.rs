@@ -3,66 +3,306 @@ pub mod cmd;
 pub mod fsutil;
 pub mod util;
 
-use crate::archive::{ArchiveReader, ArchiveWriter};
-use crate::cmd::progress_bar::{create_progress_bar, create_spinner};
-use crate::cmd::{build_list_summary_table, format_bytes, Cli, Commands};
-use crate::fsutil::directory::walk_dir;
+use crate::archive::writer::pack_artifacts;
+use crate::archive::{
+    check_compatibility, estimate_pack, parse_rename_map, raw_fingerprint, remove_entries,
+    rename_entries, unpack_sequential, ArchiveReader, ArchiveWriter, PackOptions, UnpackOptions,
+};
+use crate::cmd::progress_bar::{
+    create_spinner_with_mode, optional_progress_bar, ProgressConfig, ProgressMode,
+};
+use crate::cmd::{
+    build_analyze_json, build_analyze_table, build_chunk_info_table, build_file_chunks_table,
+    build_info_table, build_layout_json, build_layout_table, build_list_json,
+    build_list_summary_table, build_repair_json, build_shared_chunks_table,
+    build_space_report_table, build_verify_report, format_bytes, largest_files,
+    resolve_pack_output, Cli, Commands, FingerprintMode,
+};
+use crate::fsutil::directory::{
+    find_squished, group_by_top_level_dir, walk_dir, walk_tree, WalkedTree,
+};
+use crate::fsutil::filter::PathFilter;
+use crate::fsutil::temp::resolve_temp_dir;
+use crate::util::analyze::{analyze_files_with_options, AnalyzeOptions};
+use crate::util::chunk::{hash_to_hex, ChunkHash, CHUNK_SIZE};
+use crate::util::crypto::KdfParams;
 use crate::util::errors::AppError;
+use crate::util::header::{check_version, VersionMatch};
+use crate::util::interrupt::install_interrupt_handler;
+use crate::util::signing::{fingerprint, fingerprint_to_hex, load_signing_key, load_verifying_key};
 
 use clap::Parser;
 use colored::*;
-use rayon::{ThreadPoolBuildError, ThreadPoolBuilder};
-use std::path::Path;
+use num_format::{Locale, ToFormattedString};
+use rayon::ThreadPoolBuilder;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// Archive path that means "read from stdin"
+const STDIN_PATH: &str = "-";
+
+/// Archive path that means "write to stdout"
+const STDOUT_PATH: &str = "-";
+
+/// Name of the archive `pack --split-by-dir` creates for files directly inside the input
+const SPLIT_ROOT_NAME: &str = "_root";
+
 pub fn run() -> Result<(), AppError> {
     let cli = Cli::parse();
+    let progress = ProgressConfig {
+        mode: if cli.quiet {
+            ProgressMode::None
+        } else {
+            cli.progress
+        },
+        template: cli.progress_template.clone(),
+    };
+    // Only the closing stats of pack and unpack; output that is the point of a command stays
+    let print_summary = !cli.no_summary;
 
     // Cap the number of threads globally that can spawn
-    cap_max_threads(cli.max_threads).map_err(AppError::CapThreadsError)?;
+    cap_max_threads(cli.max_threads)?;
+
+    let password = given_password(cli.password.clone(), cli.password_file.as_deref())?;
 
     match cli.command {
-        Commands::Pack { input, output } => {
+        Commands::Pack {
+            input,
+            output,
+            max_archive_size,
+            reproducible,
+            temp_dir,
+            skip_squished,
+            max_files,
+            confirm_large,
+            strict,
+            skip_errors,
+            balance,
+            chunk_pool,
+            min_gain,
+            level,
+            compression,
+            keep_partial,
+            sync_interval,
+            fsync,
+            split_by_dir,
+            preallocate_archive,
+            dedup_scope,
+            hash_seed,
+            rate_limit,
+            provenance,
+            chunk_size,
+            chunking,
+            preserve_hardlinks,
+            include,
+            exclude,
+            gitignore,
+            dry_run,
+            writer_queue,
+            sign,
+        } => {
+            // Let Ctrl-C stop packing cleanly rather than leave a temporary archive behind
+            install_interrupt_handler();
+
             //Remove ending front and back slashes from input
             let trimmed_input = input.trim_end_matches(&['/', '\\'][..]).to_string();
 
-            // Default filename.out if output is not given
-            let output = output.unwrap_or_else(|| format!("{input}.squish"));
+            // The archive is patched in place once packed, so it goes to stdout only when done
+            let to_stdout = output.as_deref() == Some(STDOUT_PATH) && !dry_run;
+            if to_stdout {
+                if split_by_dir {
+                    return Err(AppError::Other(
+                        "--split-by-dir writes several archives and cannot stream to stdout".into(),
+                    ));
+                }
+                if std::io::stdout().is_terminal() {
+                    return Err(AppError::Other(
+                        "refusing to write an archive to a terminal, redirect stdout".into(),
+                    ));
+                }
+            }
+
+            // Validate the output before spending time walking the input
+            let output = if to_stdout {
+                resolve_temp_dir(temp_dir.clone().map(PathBuf::from))
+                    .unwrap_or_else(std::env::temp_dir)
+                    .join(format!("squishrs-stdout-{}.squish", std::process::id()))
+            } else if split_by_dir {
+                let output_dir = PathBuf::from(output.ok_or_else(|| {
+                    AppError::Other("--split-by-dir needs an output directory given with -o".into())
+                })?);
+                if output_dir.exists() && !output_dir.is_dir() {
+                    return Err(AppError::InvalidOutput(
+                        output_dir,
+                        "--split-by-dir needs a directory, not a file".into(),
+                    ));
+                }
+                output_dir
+            } else {
+                resolve_pack_output(&input, output.as_deref())?
+            };
+
+            let options = PackOptions {
+                max_archive_size,
+                reproducible,
+                temp_dir: resolve_temp_dir(temp_dir.map(PathBuf::from)),
+                strict,
+                skip_errors,
+                balance,
+                chunk_pool: chunk_pool.map(PathBuf::from),
+                min_gain_percent: min_gain,
+                compression_level: level,
+                compression,
+                keep_partial,
+                sync_interval,
+                fsync,
+                preallocate_archive,
+                dedup_scope,
+                hash_seed,
+                rate_limit,
+                provenance,
+                chunk_size,
+                chunking,
+                preserve_hardlinks,
+                writer_queue,
+                password: password.clone(),
+                kdf: KdfParams::default(),
+                signing_key: sign
+                    .map(|key_path| load_signing_key(Path::new(&key_path)))
+                    .transpose()?,
+            };
+
+            // Leave out whatever this pack writes, in case it lands inside the input
+            let excluded = if split_by_dir {
+                vec![output.clone()]
+            } else {
+                pack_artifacts(&output, &options)
+            };
 
-            let files_spinner = create_spinner("Finding Files");
+            let files_spinner = create_spinner_with_mode("Finding Files", progress.mode);
 
             // Count total files for progress bar
-            let files = walk_dir(Path::new(&trimmed_input))?;
+            let WalkedTree {
+                mut files,
+                empty_dirs,
+            } = walk_tree(
+                Path::new(&trimmed_input),
+                &excluded,
+                &PathFilter::new(include, exclude).with_gitignore(gitignore),
+                max_files.unwrap_or(usize::MAX),
+                |found| files_spinner.suspend(|| confirm_large && confirm_file_count(found)),
+            )?;
             files_spinner.finish_and_clear();
 
-            // Setup progress bar
-            let mut pb = create_progress_bar(files.len() as u64, "Packing");
+            // Packing an archive again gains little and is usually a mistake
+            let squished = find_squished(&files);
+            if !squished.is_empty() {
+                if skip_squished {
+                    let skipped: HashSet<&PathBuf> = squished.iter().collect();
+                    files.retain(|file| !skipped.contains(file));
+                }
+                eprintln!(
+                    "{}: {} file(s) are already squish archives{}",
+                    "Warning".yellow(),
+                    squished.len(),
+                    if skip_squished {
+                        " and were skipped"
+                    } else {
+                        ", use --skip-squished to leave them out"
+                    }
+                );
+                for file in &squished {
+                    eprintln!("  {}", file.display());
+                }
+            }
+
+            // Empty directories hold no files, so they are packed as entries of their own
+            files.extend(empty_dirs);
+
+            if dry_run {
+                let pb = optional_progress_bar(files.len() as u64, "Estimating", &progress);
+                let estimate = estimate_pack(Path::new(&input), &files, pb.as_ref(), &options)?;
+                if let Some(pb) = pb {
+                    pb.finish_and_clear();
+                }
+
+                println!(
+                    "{}\n{}: {}\n{}: {}\n{}: {}\n{}: {:.1}%",
+                    "Dry run complete, nothing was written".green(),
+                    "Estimated archive size".blue(),
+                    format_bytes(estimate.archive_size),
+                    "Original size".blue(),
+                    format_bytes(estimate.original_size),
+                    "Unique chunks".blue(),
+                    estimate.unique_chunks.to_formatted_string(&Locale::en),
+                    "Reduction".blue(),
+                    estimate.reduction_percentage()
+                );
+                return Ok(());
+            }
+
+            if split_by_dir {
+                return pack_split_by_dir(
+                    Path::new(&trimmed_input),
+                    &output,
+                    &files,
+                    options,
+                    &progress,
+                    print_summary,
+                );
+            }
 
-            // Package file to archive
-            let mut archive_writer =
-                ArchiveWriter::new(Path::new(&input), Path::new(&output), Some(&mut pb))?;
+            let (compressed_size, _) =
+                pack_archive(Path::new(&input), &output, &files, options, &progress)?;
 
-            let compressed_size = archive_writer.pack(&files)?;
-            pb.finish_and_clear();
+            if to_stdout {
+                copy_to_stdout(&output)?;
+                if !print_summary {
+                    return Ok(());
+                }
+                eprintln!(
+                    "{}\n{}: {}",
+                    "Packing complete!".green(),
+                    "Final archive size".blue(),
+                    format_bytes(compressed_size)
+                );
+                return Ok(());
+            }
 
+            if !print_summary {
+                return Ok(());
+            }
             println!(
                 "{}\nCompressed to {}\n{}: {}",
                 "Packing complete!".green(),
-                output.strip_prefix("./").unwrap_or(&output),
+                output.strip_prefix("./").unwrap_or(&output).display(),
                 "Final archive size".blue(),
                 format_bytes(compressed_size)
             );
         }
-        Commands::List { squish, simple } => {
-            let discovery_spinner = create_spinner("Scanning Squish");
-
-            let mut archive_reader = ArchiveReader::new(Path::new(&squish))?;
+        Commands::List {
+            squish,
+            simple,
+            chunks,
+            files,
+            limit,
+            json,
+        } => {
+            // Opened before the spinner starts, which would draw over a password prompt
+            let mut archive_reader =
+                open_unlocked(&squish, cli.strict_version, password.as_deref())?;
+            let discovery_spinner = create_spinner_with_mode("Scanning Squish", progress.mode);
 
             let summary = archive_reader.get_summary()?;
             discovery_spinner.finish_and_clear();
 
-            if simple {
+            if json {
+                println!("{}", build_list_json(&summary));
+            } else if simple {
                 // Make it machine readable, could be piped to fzf
                 println!(
                     "squish_size(bytes): {}, original_size(bytes): {}, compression ratio: {:.2}%, number_of_files: {}, chunks_count: {}",
@@ -73,18 +313,233 @@ pub fn run() -> Result<(), AppError> {
                     summary.unique_chunks
                 );
 
-                println!("{:>10}  File Path", "Size (Bytes)");
-                println!("----------  --------------------");
-                for file in summary.files {
-                    println!("{:>10}  {}", file.original_size, file.path);
+                if files {
+                    println!("{:>10}  {:>8}  File Path", "Size (Bytes)", "Chunks");
+                    println!("----------  --------  --------------------");
+                    for file in largest_files(&summary.files, limit) {
+                        println!(
+                            "{:>10}  {:>8}  {}",
+                            file.original_size, file.chunk_count, file.path
+                        );
+                    }
+                } else if chunks {
+                    println!("{:>10}  {:>8}  File Path", "Size (Bytes)", "Chunks");
+                    println!("----------  --------  --------------------");
+                    for file in &summary.files {
+                        println!(
+                            "{:>10}  {:>8}  {}",
+                            file.original_size, file.chunk_count, file.path
+                        );
+                    }
+                } else {
+                    println!("{:>10}  File Path", "Size (Bytes)");
+                    println!("----------  --------------------");
+                    for file in summary.files {
+                        println!("{:>10}  {}", file.original_size, file.path);
+                    }
                 }
             } else {
-                let output = build_list_summary_table(&summary);
+                let output = build_list_summary_table(&summary, files, limit);
                 println!("{output}");
+
+                if chunks {
+                    println!("{}", build_file_chunks_table(&summary));
+                }
+            }
+        }
+        Commands::Analyze {
+            input,
+            chunk_size,
+            json,
+            sample_compression,
+            per_file,
+        } => {
+            let chunk_size = chunk_size.map_or(CHUNK_SIZE, |size| size as usize);
+
+            let files_spinner = create_spinner_with_mode("Analyzing Files", progress.mode);
+            let mut files = walk_dir(Path::new(&input))?;
+            // Pack stores symlinks as links, so they add no chunks
+            files.retain(|file| !file.is_symlink());
+            let options = AnalyzeOptions {
+                sample_compression,
+                per_file,
+            };
+            let report = analyze_files_with_options(&files, chunk_size, &options)?;
+            files_spinner.finish_and_clear();
+
+            if json {
+                println!("{}", build_analyze_json(&report));
+            } else {
+                println!("{}", build_analyze_table(&report));
             }
         }
-        Commands::Unpack { squish, output } => {
+        Commands::Unpack {
+            squish,
+            output,
+            prefix_out,
+            preallocate,
+            sequential,
+            chunk_pool,
+            zero_fill_missing,
+            decompress_threads,
+            min_size,
+            max_size,
+            in_memory,
+            check,
+            file,
+            on_conflict,
+            no_verify_size,
+            verify,
+            preserve_special_bits,
+        } => {
+            // Let Ctrl-C stop the unpack between files
+            install_interrupt_handler();
+
+            let from_stdin = squish == STDIN_PATH;
+
             // Default filename.squish if output is not given
+            let output = match output {
+                Some(output) => output,
+                // Nothing is written when checking
+                None if check => String::new(),
+                None if from_stdin => {
+                    return Err(AppError::Other(
+                        "--output is required when reading the squish from stdin".into(),
+                    ))
+                }
+                None => squish
+                    .strip_suffix(".squish")
+                    .unwrap_or(&squish)
+                    .to_string(),
+            };
+
+            let mut pb = optional_progress_bar(0, "Reading Chunks", &progress);
+
+            let options = UnpackOptions {
+                prefix: prefix_out.map(PathBuf::from),
+                preallocate,
+                chunk_pool: chunk_pool.map(PathBuf::from),
+                zero_fill_missing,
+                decompress_threads,
+                strict_version: cli.strict_version,
+                min_size,
+                max_size,
+                in_memory,
+                check,
+                file,
+                skip_size_check: no_verify_size,
+                verify,
+                repair: false,
+                on_conflict,
+                special_mode_bits: preserve_special_bits,
+                password: password.clone(),
+            };
+
+            let report = if from_stdin {
+                let stdin = std::io::stdin().lock();
+                unpack_sequential(stdin, Path::new(&output), pb.as_mut(), &options)?
+            } else if sequential {
+                let options = UnpackOptions {
+                    password: checked_password(&squish, cli.strict_version, password.as_deref())?,
+                    ..options.clone()
+                };
+                let file = File::open(&squish)
+                    .map_err(|_| AppError::FileNotExist(PathBuf::from(&squish)))?;
+                unpack_sequential(
+                    BufReader::new(file),
+                    Path::new(&output),
+                    pb.as_mut(),
+                    &options,
+                )?
+            } else {
+                let mut archive_reader =
+                    open_unlocked(&squish, cli.strict_version, password.as_deref())?;
+                archive_reader.unpack_with_options(Path::new(&output), pb.as_mut(), &options)?
+            };
+            if let Some(pb) = pb {
+                pb.finish_and_clear();
+            }
+
+            if check {
+                for failure in &report.check_failures {
+                    eprintln!(
+                        "{}: {}: {}",
+                        "Failed".red().bold(),
+                        failure.path,
+                        failure.reason
+                    );
+                }
+                if !report.check_failures.is_empty() {
+                    return Err(AppError::Archive(format!(
+                        "{} of {} files failed to rebuild",
+                        report.check_failures.len(),
+                        report.files_restored
+                    )));
+                }
+                if !print_summary {
+                    return Ok(());
+                }
+                println!(
+                    "{}\nAll {} files in {} rebuilt correctly",
+                    "Check passed!".green(),
+                    report.files_restored,
+                    squish
+                );
+                return Ok(());
+            }
+
+            if !report.partially_recovered.is_empty() {
+                eprintln!(
+                    "{}: {} file(s) had missing chunks and were only partially recovered, the gaps are filled with zeros:",
+                    "Warning".red().bold(),
+                    report.partially_recovered.len()
+                );
+                for recovery in &report.partially_recovered {
+                    eprintln!(
+                        "  {} ({} missing chunk(s), {} zeroed)",
+                        recovery.path,
+                        recovery.missing_chunks,
+                        format_bytes(recovery.zero_filled_bytes)
+                    );
+                }
+            }
+            if !print_summary {
+                return Ok(());
+            }
+            if report.skipped_existing > 0 {
+                println!(
+                    "Skipped {} file(s) that already existed",
+                    report.skipped_existing
+                );
+            }
+            if report.renamed_existing > 0 {
+                println!(
+                    "Renamed {} file(s) to avoid overwriting existing ones",
+                    report.renamed_existing
+                );
+            }
+            if options.has_size_filter() {
+                println!(
+                    "{} of {} files matched the size filter",
+                    report.files_restored,
+                    report.files_restored + report.excluded_by_size
+                );
+            }
+            println!(
+                "{}\n{} was unsquished into /{}",
+                "Unpacking complete!".green(),
+                squish,
+                output
+            );
+        }
+        Commands::Repair {
+            squish,
+            output,
+            report,
+            chunk_pool,
+        } => {
+            install_interrupt_handler();
+
             let output = output.unwrap_or_else(|| {
                 squish
                     .strip_suffix(".squish")
@@ -92,21 +547,584 @@ pub fn run() -> Result<(), AppError> {
                     .to_string()
             });
 
-            let mut pb = create_progress_bar(0, "Reading Chunks");
+            let mut archive_reader =
+                open_unlocked(&squish, cli.strict_version, password.as_deref())?;
+            let paths: Vec<String> = archive_reader
+                .entries()?
+                .into_iter()
+                .map(|file| file.path)
+                .collect();
+
+            let mut pb = optional_progress_bar(0, "Repairing", &progress);
+            let options = UnpackOptions {
+                chunk_pool: chunk_pool.map(PathBuf::from),
+                repair: true,
+                ..Default::default()
+            };
+            let repair_report =
+                archive_reader.unpack_with_options(Path::new(&output), pb.as_mut(), &options)?;
+            if let Some(pb) = pb {
+                pb.finish_and_clear();
+            }
+
+            let lost: HashSet<&str> = repair_report
+                .lost_files
+                .iter()
+                .map(|lost| lost.path.as_str())
+                .collect();
+            let recovered: Vec<String> = paths
+                .into_iter()
+                .filter(|path| !lost.contains(path.as_str()))
+                .collect();
+            if let Some(report) = report {
+                let json = build_repair_json(&recovered, &repair_report.lost_files);
+                fs::write(&report, format!("{json}\n"))
+                    .map_err(|e| AppError::CreateFileError(PathBuf::from(&report), e))?;
+            }
+
+            for lost in &repair_report.lost_files {
+                eprintln!("{}: {}: {}", "Lost".red().bold(), lost.path, lost.reason);
+            }
+            if print_summary {
+                println!(
+                    "{}\nRecovered {} of {} files from {} into /{}",
+                    "Repair complete!".green(),
+                    recovered.len(),
+                    recovered.len() + lost.len(),
+                    squish,
+                    output
+                );
+            }
+        }
+        Commands::Cat {
+            squish,
+            path,
+            range,
+        } => {
+            let mut archive_reader =
+                open_unlocked(&squish, cli.strict_version, password.as_deref())?;
+            let mut stdout = std::io::stdout().lock();
 
-            let mut archive_reader = ArchiveReader::new(Path::new(&squish))?;
+            match range {
+                Some((start, end)) => {
+                    archive_reader.extract_range(&path, start, end, &mut stdout)?;
+                }
+                None => archive_reader.extract_file(&path, &mut stdout)?,
+            }
+        }
+        Commands::Verify {
+            squish,
+            against,
+            compat,
+            chunk_pool,
+        } => {
+            if compat {
+                let version = check_compatibility(Path::new(&squish))?;
+                check_archive_version(&version, cli.strict_version)?;
+                println!("extractable (format {version})");
+                return Ok(());
+            }
+
+            let Some(against) = against else {
+                let mut archive_reader =
+                    open_unlocked(&squish, cli.strict_version, password.as_deref())?;
+                let pb = optional_progress_bar(0, "Verifying Chunks", &progress);
+                let report = archive_reader
+                    .verify_integrity(chunk_pool.as_deref().map(Path::new), pb.as_ref())?;
+                if let Some(pb) = pb {
+                    pb.finish_and_clear();
+                }
+
+                println!(
+                    "Verified {} chunks referenced by {} files",
+                    report.chunks_verified, report.files_checked
+                );
+                println!("{}", "Verification passed!".green());
+                return Ok(());
+            };
+
+            let mut archive_reader =
+                open_unlocked(&squish, cli.strict_version, password.as_deref())?;
+            let verify_spinner = create_spinner_with_mode("Verifying Squish", progress.mode);
+            let report = archive_reader.verify_against(Path::new(&against))?;
+            verify_spinner.finish_and_clear();
+
+            println!("{}", build_verify_report(&report));
+
+            if !report.is_clean() {
+                return Err(AppError::VerificationFailed(
+                    report.mismatched.len()
+                        + report.missing_from_source.len()
+                        + report.missing_from_archive.len(),
+                ));
+            }
+            println!("{}", "Verification passed!".green());
+        }
+        Commands::VerifySignature { squish, key } => {
+            let key = load_verifying_key(Path::new(&key))?;
+            let mut archive_reader = open_archive(&squish, cli.strict_version)?;
+            archive_reader.verify_signature(&key)?;
 
-            archive_reader.unpack(Path::new(&output), Some(&mut pb))?;
-            pb.finish_and_clear();
             println!(
-                "{}\n{} was unsquished into /{}",
-                "Unpacking complete!".green(),
+                "{}\nSigned by key {}",
+                "Signature verified!".green(),
+                fingerprint_to_hex(&fingerprint(&key))
+            );
+        }
+        Commands::Audit {
+            squish,
+            space,
+            shared,
+            top,
+        } => {
+            let mut archive_reader =
+                open_unlocked(&squish, cli.strict_version, password.as_deref())?;
+
+            // The space audit is the default when no mode is picked
+            if space || !shared {
+                let report = archive_reader.space_report()?;
+                println!("{}", build_space_report_table(&report));
+            }
+            if shared {
+                let shared_chunks = archive_reader.shared_chunks(top)?;
+                println!("{}", build_shared_chunks_table(&shared_chunks));
+            }
+        }
+        Commands::Mv {
+            squish,
+            old_path,
+            new_path,
+            from_file,
+        } => {
+            let renames = match (from_file, old_path, new_path) {
+                (Some(from_file), _, _) => {
+                    let mapping = fs::read_to_string(&from_file)
+                        .map_err(|_| AppError::FileNotExist(PathBuf::from(&from_file)))?;
+                    parse_rename_map(&mapping)?
+                }
+                (None, Some(old_path), Some(new_path)) => vec![(old_path, new_path)],
+                _ => {
+                    return Err(AppError::Other(
+                        "give an old and new path, or --from-file".into(),
+                    ))
+                }
+            };
+
+            let password = checked_password(&squish, cli.strict_version, password.as_deref())?;
+            rename_entries(Path::new(&squish), &renames, password.as_deref())?;
+            println!(
+                "{}\nRenamed {} file(s) in {}",
+                "Rename complete!".green(),
+                renames.len(),
+                squish
+            );
+        }
+        Commands::Remove { squish, paths } => {
+            let password = checked_password(&squish, cli.strict_version, password.as_deref())?;
+            let size_before = fs::metadata(&squish)?.len();
+
+            let dropped = remove_entries(Path::new(&squish), &paths, password.as_deref())?;
+            let reclaimed = size_before.saturating_sub(fs::metadata(&squish)?.len());
+            println!(
+                "{}\nRemoved {} file(s) from {}, dropping {} unreferenced chunks and reclaiming {}",
+                "Removal complete!".green(),
+                paths.len(),
                 squish,
+                dropped,
+                format_bytes(reclaimed)
+            );
+        }
+        Commands::Compact { squish, output } => {
+            // Creating the output would truncate the archive still being read
+            if fs::canonicalize(&output).ok() == Some(fs::canonicalize(&squish)?) {
+                return Err(AppError::Other(
+                    "compact cannot write over its input, choose another output".into(),
+                ));
+            }
+
+            let mut archive_reader =
+                open_unlocked(&squish, cli.strict_version, password.as_deref())?;
+            // Checked before the output is created, so a refusal leaves nothing behind
+            archive_reader.check_unsigned()?;
+
+            let compact_file = File::create(&output)
+                .map_err(|e| AppError::CreateFileError(PathBuf::from(&output), e))?;
+            let dropped = archive_reader.compact_into(&mut BufWriter::new(compact_file))?;
+
+            if dropped == 0 {
+                println!("{} is already compact", squish);
+            } else {
+                let reclaimed = fs::metadata(&squish)?
+                    .len()
+                    .saturating_sub(fs::metadata(&output)?.len());
+                println!(
+                    "{}\nDropped {} orphaned chunks, reclaiming {}",
+                    "Compaction complete!".green(),
+                    dropped,
+                    format_bytes(reclaimed)
+                );
+            }
+        }
+        Commands::Reencrypt {
+            squish,
+            output,
+            old_password,
+            new_password,
+        } => {
+            // Creating the output would truncate the archive still being read
+            if fs::canonicalize(&output).ok() == Some(fs::canonicalize(&squish)?) {
+                return Err(AppError::Other(
+                    "reencrypt cannot write over its input, choose another output".into(),
+                ));
+            }
+
+            let mut archive_reader = open_archive(&squish, cli.strict_version)?;
+            archive_reader.check_reencryptable()?;
+            archive_reader.unlock(&archive_password(
+                &squish,
+                old_password.or(password).as_deref(),
+            )?)?;
+            let new_password = confirmed_password(&output, new_password)?;
+
+            let pb =
+                optional_progress_bar(archive_reader.chunk_count(), "Re-encrypting", &progress);
+            let reencrypted = File::create(&output)
+                .map_err(|e| AppError::CreateFileError(PathBuf::from(&output), e))
+                .and_then(|file| {
+                    archive_reader.reencrypt_into(
+                        &mut BufWriter::new(file),
+                        &new_password,
+                        KdfParams::default(),
+                        pb.as_ref(),
+                    )
+                });
+            if let Some(pb) = pb {
+                pb.finish_and_clear();
+            }
+            // A half-written copy would only fail to open later
+            let chunk_count = reencrypted.inspect_err(|_| {
+                let _ = fs::remove_file(&output);
+            })?;
+
+            println!(
+                "{}\nRe-encrypted {} chunks into {}",
+                "Re-encryption complete!".green(),
+                chunk_count,
+                output
+            );
+        }
+        Commands::ExportChunks { squish, output } => {
+            let mut archive_reader = open_archive(&squish, cli.strict_version)?;
+
+            let pool_file = File::create(&output)
+                .map_err(|e| AppError::CreateFileError(PathBuf::from(&output), e))?;
+            let chunk_count = archive_reader.export_chunks(&mut BufWriter::new(pool_file))?;
+
+            println!(
+                "{}\nExported {} chunks to {}",
+                "Export complete!".green(),
+                chunk_count,
                 output
             );
         }
+        Commands::Fingerprint { squish, mode } => {
+            let fingerprint = match mode {
+                FingerprintMode::Raw => raw_fingerprint(Path::new(&squish))?,
+                FingerprintMode::Content => {
+                    open_unlocked(&squish, cli.strict_version, password.as_deref())?
+                        .content_fingerprint()?
+                }
+            };
+            println!("{}", hash_to_hex(&fingerprint));
+        }
+        Commands::Info { squish } => {
+            let archive_reader = open_archive(&squish, cli.strict_version)?;
+            println!("{}", build_info_table(&archive_reader));
+        }
+        Commands::DebugChunk { squish, index } => {
+            let mut archive_reader =
+                open_unlocked(&squish, cli.strict_version, password.as_deref())?;
+            let info = archive_reader.chunk_info(index)?;
+            println!("{}", build_chunk_info_table(&info));
+        }
+        Commands::DebugLayout { squish, json } => {
+            let archive_reader = open_archive(&squish, cli.strict_version)?;
+            let layout = archive_reader.layout();
+
+            if json {
+                println!("{}", build_layout_json(&layout));
+            } else {
+                println!("{}", build_layout_table(&layout));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Asks on stdin whether to keep packing once `found` files, more than `--max-files`, turned up.
+///
+/// Anything but an explicit yes, including a closed stdin, counts as no.
+fn confirm_file_count(found: usize) -> bool {
+    eprint!(
+        "{}: {} files found so far, more than --max-files allows. Continue anyway? [y/N] ",
+        "Warning".yellow(),
+        found
+    );
+    let _ = std::io::stderr().flush();
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).is_ok()
+        && matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+}
+
+/// Opens the archive at `squish`, warning if it was written by a different minor version.
+///
+/// With `strict_version` such archives are rejected instead.
+fn open_archive(squish: &str, strict_version: bool) -> Result<ArchiveReader, AppError> {
+    let archive_reader = ArchiveReader::new(Path::new(squish))?;
+    check_archive_version(archive_reader.version(), strict_version)?;
+    Ok(archive_reader)
+}
+
+/// Opens the archive at `squish` like [`open_archive`], unlocking it if it is encrypted with
+/// `password`, or else with one asked for on the terminal.
+fn open_unlocked(
+    squish: &str,
+    strict_version: bool,
+    password: Option<&str>,
+) -> Result<ArchiveReader, AppError> {
+    let mut archive_reader = open_archive(squish, strict_version)?;
+    if archive_reader.is_encrypted() {
+        archive_reader.unlock(&archive_password(squish, password)?)?;
+    }
+    Ok(archive_reader)
+}
+
+/// Opens the archive at `squish` like [`open_unlocked`] for commands that reopen it
+/// themselves, returning the password it was unlocked with.
+///
+/// The password is checked here, so a wrong one fails before anything is written.
+fn checked_password(
+    squish: &str,
+    strict_version: bool,
+    password: Option<&str>,
+) -> Result<Option<String>, AppError> {
+    let mut archive_reader = open_archive(squish, strict_version)?;
+    if !archive_reader.is_encrypted() {
+        return Ok(None);
+    }
+    let password = archive_password(squish, password)?;
+    archive_reader.unlock(&password)?;
+    Ok(Some(password))
+}
+
+/// Returns `password`, or asks for the password of `squish` on the terminal when none was given.
+///
+/// # Errors
+/// Returns `AppError::PasswordRequired` when there is no terminal to ask on.
+fn archive_password(squish: &str, password: Option<&str>) -> Result<String, AppError> {
+    match password {
+        Some(password) => Ok(password.to_string()),
+        None => rpassword::prompt_password(format!("Password for {squish}: "))
+            .map_err(|_| AppError::PasswordRequired),
+    }
+}
+
+/// Returns `password`, or asks for a new password for `squish` on the terminal, twice so a typo
+/// cannot lock the archive away.
+///
+/// # Errors
+/// Returns `AppError::Other` if the two entries differ or there is no terminal to ask on.
+fn confirmed_password(squish: &str, password: Option<String>) -> Result<String, AppError> {
+    if let Some(password) = password {
+        return Ok(password);
+    }
+    let no_terminal = |_| AppError::Other("give the new password with --new-password".into());
+    let password =
+        rpassword::prompt_password(format!("New password for {squish}: ")).map_err(no_terminal)?;
+    let repeated = rpassword::prompt_password("Repeat the new password: ").map_err(no_terminal)?;
+    if password != repeated {
+        return Err(AppError::Other("the new passwords do not match".into()));
+    }
+    Ok(password)
+}
+
+/// Returns the password given with `--password`, or the first line of `--password-file`.
+fn given_password(
+    password: Option<String>,
+    password_file: Option<&str>,
+) -> Result<Option<String>, AppError> {
+    let Some(password_file) = password_file else {
+        return Ok(password);
+    };
+    let contents = fs::read_to_string(password_file)
+        .map_err(|_| AppError::FileNotExist(PathBuf::from(password_file)))?;
+    Ok(Some(
+        contents.lines().next().unwrap_or_default().to_string(),
+    ))
+}
+
+/// Warns about, or with `strict_version` rejects, an archive version whose minor part differs
+/// from this build's.
+fn check_archive_version(version: &str, strict_version: bool) -> Result<(), AppError> {
+    if check_version(version, strict_version)? == VersionMatch::MinorMismatch {
+        eprintln!(
+            "{}: archive was written by squishrs {version}, this is {VERSION}; reading it anyway, use --strict-version to refuse",
+            "Warning".yellow()
+        );
+    }
+    Ok(())
+}
+
+/// Packs `files` from `input` into a single archive at `output`, warning about any file that
+/// changed size along the way.
+///
+/// # Returns
+///
+/// The archive's size and the hashes of the chunks stored in it.
+fn pack_archive(
+    input: &Path,
+    output: &Path,
+    files: &[PathBuf],
+    options: PackOptions,
+    progress: &ProgressConfig,
+) -> Result<(u64, Vec<ChunkHash>), AppError> {
+    let mut pb = optional_progress_bar(files.len() as u64, "Packing", progress);
+
+    let mut archive_writer = ArchiveWriter::with_options(input, output, pb.as_mut(), options)?;
+
+    let compressed_size = archive_writer.pack(files)?;
+    if let Some(pb) = &pb {
+        pb.finish_and_clear();
+    }
+
+    let mismatches = archive_writer.size_mismatches();
+    for mismatch in &mismatches {
+        eprintln!(
+            "{}: {} changed size while packing (expected {} bytes, stored {})",
+            "Warning".yellow(),
+            mismatch.path,
+            mismatch.expected,
+            mismatch.actual
+        );
+    }
+    if !mismatches.is_empty() {
+        eprintln!(
+            "{}: {} file(s) changed while being packed and were stored as read; \
+             use --strict to fail instead",
+            "Warning".yellow(),
+            mismatches.len()
+        );
+    }
+
+    let skipped = archive_writer.skipped_files();
+    for file in &skipped {
+        eprintln!(
+            "{}: skipped {}: {}",
+            "Warning".yellow(),
+            file.path.display(),
+            file.reason
+        );
+    }
+    if !skipped.is_empty() {
+        eprintln!(
+            "{}: {} file(s) could not be read and were left out of the archive",
+            "Warning".yellow(),
+            skipped.len()
+        );
+    }
+
+    Ok((compressed_size, archive_writer.stored_chunk_hashes()))
+}
+
+/// Streams the finished archive at `path` to stdout, then deletes it.
+fn copy_to_stdout(path: &Path) -> Result<(), AppError> {
+    let copied = File::open(path).and_then(|mut archive| {
+        let mut stdout = std::io::stdout().lock();
+        std::io::copy(&mut archive, &mut stdout)?;
+        stdout.flush()
+    });
+    let _ = fs::remove_file(path);
+    copied.map_err(AppError::WriterError)
+}
+
+/// Packs every immediate subdirectory of `input` into its own archive in `output_dir`, plus
+/// `_root.squish` for files directly inside `input`.
+///
+/// Each archive is self-contained; chunks shared between them are only reported, not
+/// deduplicated, so any one archive can be unpacked on its own. The report is left out unless
+/// `print_summary` is set.
+fn pack_split_by_dir(
+    input: &Path,
+    output_dir: &Path,
+    files: &[PathBuf],
+    options: PackOptions,
+    progress: &ProgressConfig,
+    print_summary: bool,
+) -> Result<(), AppError> {
+    let groups = group_by_top_level_dir(input, files);
+    if groups.dirs.contains_key(SPLIT_ROOT_NAME) && !groups.loose.is_empty() {
+        return Err(AppError::Other(format!(
+            "cannot split: a subdirectory is named `{SPLIT_ROOT_NAME}`, which is reserved for loose files"
+        )));
+    }
+
+    fs::create_dir_all(output_dir)
+        .map_err(|e| AppError::CreateDirError(output_dir.to_path_buf(), e))?;
+
+    let mut jobs: Vec<(PathBuf, &str, &[PathBuf])> = groups
+        .dirs
+        .iter()
+        .map(|(name, files)| (input.join(name), name.as_str(), files.as_slice()))
+        .collect();
+    if !groups.loose.is_empty() {
+        jobs.push((
+            input.to_path_buf(),
+            SPLIT_ROOT_NAME,
+            groups.loose.as_slice(),
+        ));
+    }
+
+    let mut archives = Vec::with_capacity(jobs.len());
+    let mut chunk_archive_counts: HashMap<ChunkHash, usize> = HashMap::new();
+    for (group_input, name, group_files) in jobs {
+        let output = output_dir.join(format!("{name}.squish"));
+        let (size, hashes) = pack_archive(
+            &group_input,
+            &output,
+            group_files,
+            options.clone(),
+            progress,
+        )?;
+
+        for hash in hashes {
+            *chunk_archive_counts.entry(hash).or_default() += 1;
+        }
+        archives.push((output, size));
+    }
+
+    if !print_summary {
+        return Ok(());
+    }
+    println!(
+        "{}\nCreated {} archives:",
+        "Packing complete!".green(),
+        archives.len()
+    );
+    for (path, size) in &archives {
+        println!("  {}  {}", path.display(), format_bytes(*size));
     }
 
+    let shared = chunk_archive_counts
+        .values()
+        .filter(|&&count| count > 1)
+        .count();
+    println!(
+        "{}: {}",
+        "Chunks stored in more than one archive".blue(),
+        shared
+    );
+
     Ok(())
 }
 
@@ -128,8 +1146,8 @@ pub fn run() -> Result<(), AppError> {
 ///
 /// # Errors
 ///
-/// Returns an `AppError` wrapping a `rayon::ThreadPoolBuildError` if the thread pool has
-/// already been set or the configuration fails.
+/// Returns `AppError::CapThreadsError` wrapping a `rayon::ThreadPoolBuildError` if the thread
+/// pool has already been set or the configuration fails.
 ///
 /// # Examples
 ///
@@ -142,8 +1160,9 @@ pub fn run() -> Result<(), AppError> {
 /// # Note
 ///
 /// This function can only be called once per process. All subsequent attempts will return an error.
-pub fn cap_max_threads(max_number_of_threads: usize) -> Result<(), ThreadPoolBuildError> {
+pub fn cap_max_threads(max_number_of_threads: usize) -> Result<(), AppError> {
     ThreadPoolBuilder::new()
         .num_threads(max_number_of_threads)
         .build_global()
+        .map_err(AppError::CapThreadsError)
 }
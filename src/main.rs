@@ -1,10 +1,17 @@
 use squishrs::run;
+use squishrs::util::errors::AppError;
 
 use colored::*;
 
 fn main() {
     if let Err(e) = run() {
         eprintln!("{}: {e}", "Error".red());
-        std::process::exit(1);
+        // Exit the way a shell expects of a process stopped by SIGINT
+        let code = if matches!(e, AppError::Interrupted) {
+            130
+        } else {
+            1
+        };
+        std::process::exit(code);
     }
 }
@@ -0,0 +1,378 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use dashmap::DashMap;
+use rayon::prelude::*;
+use zstd::bulk::compress;
+
+use crate::util::chunk::{hash_chunk, read_chunk, ChunkHash, COMPRESSION_LEVEL};
+use crate::util::errors::AppError;
+
+/// Unique chunks compressed per file type when sampling compression
+const COMPRESSION_SAMPLE_CHUNKS: u64 = 8;
+
+/// Grouping key for files without an extension
+const NO_EXTENSION: &str = "(none)";
+
+/// Optional settings for [`analyze_files_with_options`].
+#[derive(Default, Clone)]
+pub struct AnalyzeOptions {
+    /// Compress a sample of each file type's unique chunks to estimate its compression ratio
+    pub sample_compression: bool,
+    /// Report this many files contributing the most unique compressed bytes. Every distinct
+    /// chunk is compressed to measure it, so this costs about as much CPU as packing
+    pub per_file: Option<usize>,
+}
+
+/// Deduplication statistics gathered by [`analyze_files`].
+pub struct DedupReport {
+    pub chunk_size: usize,
+    pub files: u64,
+    pub total_chunks: u64,
+    pub unique_chunks: u64,
+    pub total_bytes: u64,
+    pub unique_bytes: u64,
+    /// Maps "times a chunk appears" to "number of distinct chunks appearing that often"
+    pub repeat_histogram: BTreeMap<u64, u64>,
+    /// Per file extension breakdown, largest total size first
+    pub by_type: Vec<TypeStats>,
+    /// Files contributing the most unique compressed bytes, largest first; empty unless
+    /// `per_file` was requested
+    pub by_file: Vec<FileContribution>,
+}
+
+/// One file's estimated share of the compressed archive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileContribution {
+    pub path: PathBuf,
+    pub total_bytes: u64,
+    /// Compressed size of the chunks no other file contains, which is what leaving the file
+    /// out of the archive would save
+    pub unique_compressed_bytes: u64,
+    /// Compressed size of the chunks shared with other files, split evenly between them.
+    /// Leaving the file out saves none of it
+    pub shared_compressed_bytes: u64,
+}
+
+/// Deduplication, and optionally compression, statistics for one file extension.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeStats {
+    /// Lowercase extension without the dot, or `(none)`
+    pub extension: String,
+    pub files: u64,
+    pub total_bytes: u64,
+    /// Bytes left after deduplicating among files of this type only
+    pub unique_bytes: u64,
+    /// Unique bytes compressed for the sample; zero unless compression was sampled
+    pub sampled_bytes: u64,
+    pub sampled_compressed_bytes: u64,
+}
+
+impl TypeStats {
+    /// Ratio of total bytes to unique bytes within this type; `1.0` means nothing deduplicates.
+    pub fn dedup_ratio(&self) -> f64 {
+        if self.unique_bytes > 0 {
+            self.total_bytes as f64 / self.unique_bytes as f64
+        } else {
+            1.0
+        }
+    }
+
+    /// Ratio of sampled bytes to their compressed size, or `None` if nothing was sampled.
+    pub fn compression_ratio(&self) -> Option<f64> {
+        (self.sampled_compressed_bytes > 0)
+            .then(|| self.sampled_bytes as f64 / self.sampled_compressed_bytes as f64)
+    }
+}
+
+/// Running totals for one file type while files are scanned.
+#[derive(Default)]
+struct TypeAccumulator {
+    files: u64,
+    total_bytes: u64,
+    /// Chunk hash -> length, for chunks seen in files of this type
+    chunks: HashMap<ChunkHash, u64>,
+    sampled_chunks: u64,
+    sampled_bytes: u64,
+    sampled_compressed_bytes: u64,
+}
+
+impl DedupReport {
+    /// Ratio of total bytes to unique bytes; `1.0` means nothing deduplicates.
+    pub fn dedup_ratio(&self) -> f64 {
+        if self.unique_bytes > 0 {
+            self.total_bytes as f64 / self.unique_bytes as f64
+        } else {
+            1.0
+        }
+    }
+
+    /// Bytes that deduplication would avoid storing.
+    pub fn duplicate_bytes(&self) -> u64 {
+        self.total_bytes - self.unique_bytes
+    }
+}
+
+/// Chunks and hashes every file to measure how well the data would deduplicate.
+///
+/// Files are read with the same fixed-size chunking as packing, but nothing is compressed or
+/// written, so this is a cheap way to answer "is this dataset worth deduplicating, and at what
+/// chunk size".
+///
+/// # Arguments
+///
+/// * `files` - The files to scan, typically from `walk_dir`.
+/// * `chunk_size` - Size of each chunk in bytes.
+///
+/// # Errors
+///
+/// Returns `AppError::InvalidChunkSize` for a zero chunk size, or an I/O error if any file
+/// cannot be read.
+///
+/// # Examples
+///
+/// ```no_run
+/// use squishrs::fsutil::directory::walk_dir;
+/// use squishrs::util::analyze::analyze_files;
+/// use squishrs::util::chunk::CHUNK_SIZE;
+/// use std::path::Path;
+///
+/// let files = walk_dir(Path::new("./data")).expect("Failed to walk directory");
+/// let report = analyze_files(&files, CHUNK_SIZE).expect("Failed to analyze");
+/// println!("Dedup ratio: {:.2}x", report.dedup_ratio());
+/// ```
+pub fn analyze_files(files: &[PathBuf], chunk_size: usize) -> Result<DedupReport, AppError> {
+    analyze_files_with_options(files, chunk_size, &AnalyzeOptions::default())
+}
+
+/// Like [`analyze_files`], with settings such as sampling compression per file type.
+///
+/// With `sample_compression` the first few unique chunks of every file type are compressed at
+/// the packing level, giving a rough idea of which types are worth compressing at all.
+///
+/// # Errors
+///
+/// Fails like [`analyze_files`], or with `AppError::Compression` if a sample cannot be
+/// compressed.
+pub fn analyze_files_with_options(
+    files: &[PathBuf],
+    chunk_size: usize,
+    options: &AnalyzeOptions,
+) -> Result<DedupReport, AppError> {
+    if chunk_size == 0 {
+        return Err(AppError::InvalidChunkSize(0));
+    }
+
+    // hash -> (occurrences, chunk length)
+    let chunks: DashMap<ChunkHash, (u64, u64)> = DashMap::new();
+    let types: DashMap<String, TypeAccumulator> = DashMap::new();
+    // hash -> size as stored, only filled in for a per-file report
+    let stored_sizes: DashMap<ChunkHash, u64> = DashMap::new();
+
+    let scanned = files
+        .par_iter()
+        .map(|file_path| {
+            let scan = ScanState {
+                chunks: &chunks,
+                types: &types,
+                stored_sizes: options.per_file.is_some().then_some(&stored_sizes),
+            };
+            scan_file(file_path, chunk_size, &scan, options)
+        })
+        .collect::<Result<Vec<_>, AppError>>()?;
+
+    let mut report = DedupReport {
+        chunk_size,
+        files: files.len() as u64,
+        total_chunks: 0,
+        unique_chunks: chunks.len() as u64,
+        total_bytes: 0,
+        unique_bytes: 0,
+        repeat_histogram: BTreeMap::new(),
+        by_type: Vec::with_capacity(types.len()),
+        by_file: Vec::new(),
+    };
+
+    for entry in chunks.iter() {
+        let (occurrences, length) = *entry.value();
+        report.total_chunks += occurrences;
+        report.total_bytes += occurrences * length;
+        report.unique_bytes += length;
+        *report.repeat_histogram.entry(occurrences).or_insert(0) += 1;
+    }
+
+    report.by_type = types
+        .into_iter()
+        .map(|(extension, totals)| TypeStats {
+            extension,
+            files: totals.files,
+            total_bytes: totals.total_bytes,
+            unique_bytes: totals.chunks.values().sum(),
+            sampled_bytes: totals.sampled_bytes,
+            sampled_compressed_bytes: totals.sampled_compressed_bytes,
+        })
+        .collect();
+    report.by_type.sort_by(|a, b| {
+        b.total_bytes
+            .cmp(&a.total_bytes)
+            .then_with(|| a.extension.cmp(&b.extension))
+    });
+
+    if let Some(limit) = options.per_file {
+        report.by_file = file_contributions(files, scanned, &stored_sizes, limit);
+    }
+
+    Ok(report)
+}
+
+/// Attributes each distinct chunk's stored size to the files containing it: wholly to its
+/// only owner, or split evenly between several.
+///
+/// # Returns
+/// The `limit` files with the most unique compressed bytes, largest first.
+fn file_contributions(
+    files: &[PathBuf],
+    scanned: Vec<ScannedFile>,
+    stored_sizes: &DashMap<ChunkHash, u64>,
+    limit: usize,
+) -> Vec<FileContribution> {
+    let distinct: Vec<HashSet<ChunkHash>> = scanned
+        .iter()
+        .map(|file| file.hashes.iter().copied().collect())
+        .collect();
+
+    let mut owners: HashMap<ChunkHash, u64> = HashMap::new();
+    for hashes in &distinct {
+        for hash in hashes {
+            *owners.entry(*hash).or_insert(0) += 1;
+        }
+    }
+
+    let mut contributions: Vec<FileContribution> = files
+        .iter()
+        .zip(scanned)
+        .zip(distinct)
+        .map(|((path, file), hashes)| {
+            let mut contribution = FileContribution {
+                path: path.clone(),
+                total_bytes: file.total_bytes,
+                unique_compressed_bytes: 0,
+                shared_compressed_bytes: 0,
+            };
+            for hash in hashes {
+                let size = stored_sizes.get(&hash).map_or(0, |size| *size);
+                match owners[&hash] {
+                    1 => contribution.unique_compressed_bytes += size,
+                    count => contribution.shared_compressed_bytes += size / count,
+                }
+            }
+            contribution
+        })
+        .collect();
+
+    contributions.sort_by(|a, b| {
+        b.unique_compressed_bytes
+            .cmp(&a.unique_compressed_bytes)
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    contributions.truncate(limit);
+    contributions
+}
+
+/// Returns the grouping key for a file's type: its lowercase extension.
+fn file_type(file_path: &Path) -> String {
+    file_path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_else(|| NO_EXTENSION.to_string())
+}
+
+/// Shared tallies every file is scanned into.
+struct ScanState<'a> {
+    chunks: &'a DashMap<ChunkHash, (u64, u64)>,
+    types: &'a DashMap<String, TypeAccumulator>,
+    /// Set when a per-file report needs every distinct chunk's stored size
+    stored_sizes: Option<&'a DashMap<ChunkHash, u64>>,
+}
+
+/// What scanning one file found, kept for the per-file report.
+struct ScannedFile {
+    total_bytes: u64,
+    /// Every chunk hash in file order; empty unless a per-file report was requested
+    hashes: Vec<ChunkHash>,
+}
+
+/// Reads a single file chunk by chunk, counting each chunk hash in `chunks` and in the totals
+/// for the file's type.
+fn scan_file(
+    file_path: &Path,
+    chunk_size: usize,
+    scan: &ScanState,
+    options: &AnalyzeOptions,
+) -> Result<ScannedFile, AppError> {
+    let ScanState {
+        chunks,
+        types,
+        stored_sizes,
+    } = scan;
+    let file = File::open(file_path).map_err(AppError::ReaderError)?;
+    let mut reader = BufReader::new(file);
+    let mut chunk_buf = vec![0u8; chunk_size];
+
+    let extension = file_type(file_path);
+    types.entry(extension.clone()).or_default().files += 1;
+
+    let mut scanned = ScannedFile {
+        total_bytes: 0,
+        hashes: Vec::new(),
+    };
+    loop {
+        let bytes_read = read_chunk(&mut reader, &mut chunk_buf).map_err(AppError::ReaderError)?;
+        if bytes_read == 0 {
+            break;
+        }
+
+        let chunk = &chunk_buf[..bytes_read];
+        let hash = hash_chunk(chunk);
+        chunks.entry(hash).or_insert((0, bytes_read as u64)).0 += 1;
+        scanned.total_bytes += bytes_read as u64;
+
+        // Measure each distinct chunk once, as packing would store it. Two files finding the
+        // same new chunk at once may both compress it, which only costs time
+        if let Some(stored_sizes) = stored_sizes {
+            scanned.hashes.push(hash);
+            if !stored_sizes.contains_key(&hash) {
+                let compressed =
+                    compress(chunk, COMPRESSION_LEVEL).map_err(|_| AppError::Compression)?;
+                stored_sizes.insert(hash, compressed.len().min(bytes_read) as u64);
+            }
+        }
+
+        // Claim a sample slot under the lock, but compress outside it
+        let sample = {
+            let mut totals = types.entry(extension.clone()).or_default();
+            totals.total_bytes += bytes_read as u64;
+            let is_new = totals.chunks.insert(hash, bytes_read as u64).is_none();
+
+            let sample = options.sample_compression
+                && is_new
+                && totals.sampled_chunks < COMPRESSION_SAMPLE_CHUNKS;
+            if sample {
+                totals.sampled_chunks += 1;
+            }
+            sample
+        };
+
+        if sample {
+            let compressed =
+                compress(chunk, COMPRESSION_LEVEL).map_err(|_| AppError::Compression)?;
+            let mut totals = types.entry(extension.clone()).or_default();
+            totals.sampled_bytes += bytes_read as u64;
+            totals.sampled_compressed_bytes += compressed.len() as u64;
+        }
+    }
+
+    Ok(scanned)
+}
@@ -1,7 +1,9 @@
+use std::io::{self, Read};
+
 use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
 use std::sync::Arc;
-use xxhash_rust::xxh3::xxh3_128;
+use xxhash_rust::xxh3::{xxh3_128, xxh3_128_with_seed, xxh3_64, xxh3_64_with_seed, Xxh3};
 use zstd::bulk::compress;
 
 use crate::util::errors::AppError;
@@ -9,20 +11,76 @@ use crate::util::errors::AppError;
 pub type ChunkHash = [u8; 16];
 
 pub const CHUNK_SIZE: usize = 2048 * 1024; // 2MB
-const COMPRESSION_LEVEL: i32 = 12;
+/// Largest original size a chunk table entry may declare; anything bigger means the table is
+/// corrupt, and decompressing it would try to allocate that much memory
+pub const MAX_CHUNK_SIZE: u64 = 1 << 30; // 1GiB
+/// zstd level chunks are compressed at unless `--level` says otherwise
+pub const COMPRESSION_LEVEL: i32 = 12;
+
+/// Compression must shrink a chunk by at least this percentage for the compressed form to be kept
+pub const DEFAULT_MIN_GAIN_PERCENT: f64 = 2.0;
+
+/// Set in a chunk entry's stored size when the chunk is stored uncompressed
+const RAW_CHUNK_FLAG: u64 = 1 << 63;
+
+/// How chunks are compressed when they are stored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Compress with zstd, falling back to raw storage for chunks that barely shrink
+    #[default]
+    Zstd,
+    /// Store every chunk raw without trying to compress it, for data that is already
+    /// compressed (video, images, zip files)
+    None,
+}
+
+/// Where files are split into chunks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Chunking {
+    /// Every chunk but a file's last is exactly the chunk size
+    #[default]
+    Fixed,
+    /// Boundaries are picked from the content with a FastCDC rolling hash, so inserting or
+    /// removing bytes only moves the boundaries around the edit and the rest of the file still
+    /// deduplicates against an earlier version. Chunks average the chunk size, between a
+    /// quarter of it and four times it
+    ContentDefined,
+}
+
+/// Random values the gear hash mixes in for each byte, generated with splitmix64 so every
+/// build cuts identical boundaries.
+const GEAR: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut state = 0x5175_6973_6852_5321u64;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
 
 pub struct InsertReturn {
     pub hash: ChunkHash,
     pub compressed_data: Option<Arc<Vec<u8>>>,
+    /// True when `compressed_data` holds the chunk uncompressed
+    pub stored_raw: bool,
 }
 
 #[derive(Clone)]
 pub struct ChunkStore {
     pub primary_store: PrimaryStore,
+    min_gain_percent: f64,
+    level: i32,
+    compression: Compression,
 }
 
 type PrimaryStore = Arc<DashMap<ChunkHash, ()>>;
-type ReturnInsertChunk = Result<InsertReturn, Box<dyn std::error::Error + Send + Sync>>;
+type ReturnInsertChunk = Result<InsertReturn, AppError>;
 
 /// Calculates the hash of a binary array
 ///
@@ -48,23 +106,435 @@ pub fn hash_chunk(chunk: &[u8]) -> ChunkHash {
     hash.to_le_bytes()
 }
 
+/// Calculates the hash of a chunk within a dedup scope
+///
+/// Identical chunks hashed under different scopes get unrelated hashes, so they are neither
+/// deduplicated against each other nor recognisable as equal from the archive. Without a scope
+/// this is [`hash_chunk`].
+///
+/// # examples
+///
+/// ```rust
+/// use squishrs::util::chunk::{hash_chunk, hash_chunk_scoped, scope_seed};
+///
+/// let a = hash_chunk_scoped(b"secret", Some(scope_seed("a.txt")));
+/// let b = hash_chunk_scoped(b"secret", Some(scope_seed("b.txt")));
+/// assert_ne!(a, b);
+/// assert_eq!(hash_chunk_scoped(b"secret", None), hash_chunk(b"secret"));
+/// ```
+pub fn hash_chunk_scoped(chunk: &[u8], scope: Option<u64>) -> ChunkHash {
+    match scope {
+        Some(seed) => xxh3_128_with_seed(chunk, seed).to_le_bytes(),
+        None => hash_chunk(chunk),
+    }
+}
+
+/// Derives the dedup scope of a file from its path inside the archive
+pub fn scope_seed(path: &str) -> u64 {
+    xxh3_64(path.as_bytes())
+}
+
+/// Seed chunks are hashed with in an archive packed with `hash_seed`, further scoped to `path`
+/// when deduplicating per file.
+///
+/// Each hash seed gives a disjoint hash space, so archives packed for different tenants never
+/// share chunk hashes. A seed of 0 keeps the unseeded hashes, and `scope_seed(path)` for a
+/// per-file scope, so existing archives are unaffected.
+///
+/// # examples
+///
+/// ```rust
+/// use squishrs::util::chunk::{chunk_seed, scope_seed};
+///
+/// assert_eq!(chunk_seed(0, None), None);
+/// assert_eq!(chunk_seed(7, None), Some(7));
+/// assert_eq!(chunk_seed(0, Some("a.txt")), Some(scope_seed("a.txt")));
+/// assert_ne!(chunk_seed(7, Some("a.txt")), Some(scope_seed("a.txt")));
+/// ```
+pub fn chunk_seed(hash_seed: u64, path: Option<&str>) -> Option<u64> {
+    match path {
+        Some(path) => Some(xxh3_64_with_seed(path.as_bytes(), hash_seed)),
+        None => (hash_seed != 0).then_some(hash_seed),
+    }
+}
+
+/// Formats a chunk hash as a lowercase hex string, as shown in diagnostics.
+///
+/// # Examples
+///
+/// ```rust
+/// use squishrs::util::chunk::hash_to_hex;
+///
+/// assert_eq!(hash_to_hex(&[0xab; 16]), "ab".repeat(16));
+/// ```
+pub fn hash_to_hex(hash: &ChunkHash) -> String {
+    hash.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Encodes the stored size field of a chunk entry, flagging chunks stored uncompressed.
+///
+/// # Examples
+///
+/// ```rust
+/// use squishrs::util::chunk::{decode_stored_size, encode_stored_size};
+///
+/// assert_eq!(decode_stored_size(encode_stored_size(512, true)), (512, true));
+/// assert_eq!(decode_stored_size(encode_stored_size(512, false)), (512, false));
+/// ```
+pub fn encode_stored_size(size: u64, stored_raw: bool) -> u64 {
+    if stored_raw {
+        size | RAW_CHUNK_FLAG
+    } else {
+        size
+    }
+}
+
+/// Splits the stored size field of a chunk entry into the byte length and the raw flag.
+pub fn decode_stored_size(field: u64) -> (u64, bool) {
+    (field & !RAW_CHUNK_FLAG, field & RAW_CHUNK_FLAG != 0)
+}
+
+/// Reads from `reader` until `buf` is full or the end of the input is reached.
+///
+/// A single `read` call may return fewer bytes than requested even when more data follows,
+/// which would make chunk boundaries depend on the operating system. Filling the buffer keeps
+/// every chunk except the last exactly `buf.len()` bytes long.
+///
+/// # Returns
+///
+/// The number of bytes read; `0` means the input is exhausted.
+///
+/// # Examples
+///
+/// ```rust
+/// use squishrs::util::chunk::read_chunk;
+///
+/// let mut input: &[u8] = b"abcdefg";
+/// let mut buf = [0u8; 4];
+/// assert_eq!(read_chunk(&mut input, &mut buf).unwrap(), 4);
+/// assert_eq!(read_chunk(&mut input, &mut buf).unwrap(), 3);
+/// assert_eq!(read_chunk(&mut input, &mut buf).unwrap(), 0);
+/// ```
+pub fn read_chunk<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Splits a stream into chunks the way packing does.
+///
+/// With [`Chunking::Fixed`] every chunk but the last is `chunk_size` bytes. With
+/// [`Chunking::ContentDefined`] boundaries fall where a gear hash of the preceding bytes has
+/// enough zero bits, FastCDC style: a stricter mask before `chunk_size` bytes and a looser one
+/// after it pulls chunk lengths towards `chunk_size`, within a quarter of it and four times it.
+///
+/// # Examples
+///
+/// ```rust
+/// use squishrs::util::chunk::{Chunker, Chunking};
+///
+/// let data = vec![7u8; 10_000];
+/// let mut chunker = Chunker::new(&data[..], 4096, Chunking::Fixed);
+/// let mut lengths = Vec::new();
+/// while let Some(chunk) = chunker.next_chunk().unwrap() {
+///     lengths.push(chunk.len());
+/// }
+/// assert_eq!(lengths, vec![4096, 4096, 1808]);
+/// ```
+pub struct Chunker<R> {
+    reader: R,
+    chunking: Chunking,
+    min_size: usize,
+    avg_size: usize,
+    /// Data read but not yet returned is `buf[start..end]`
+    buf: Vec<u8>,
+    start: usize,
+    end: usize,
+    eof: bool,
+}
+
+impl<R: Read> Chunker<R> {
+    /// Creates a chunker reading from `reader`, cutting chunks of `chunk_size` bytes, or
+    /// averaging `chunk_size` bytes when `chunking` is content-defined.
+    pub fn new(reader: R, chunk_size: usize, chunking: Chunking) -> Self {
+        let chunk_size = chunk_size.max(1);
+        let max_size = match chunking {
+            Chunking::Fixed => chunk_size,
+            Chunking::ContentDefined => chunk_size
+                .saturating_mul(4)
+                .min(MAX_CHUNK_SIZE as usize)
+                .max(chunk_size),
+        };
+        Self {
+            reader,
+            chunking,
+            min_size: chunk_size / 4,
+            avg_size: chunk_size,
+            buf: vec![0u8; max_size],
+            start: 0,
+            end: 0,
+            eof: false,
+        }
+    }
+
+    /// Returns the next chunk, or `None` once the input is exhausted.
+    pub fn next_chunk(&mut self) -> io::Result<Option<&[u8]>> {
+        // Keep a full window buffered so a cut point can be searched up to the largest size
+        if !self.eof && self.end - self.start < self.buf.len() {
+            self.buf.copy_within(self.start..self.end, 0);
+            self.end -= self.start;
+            self.start = 0;
+            let read = read_chunk(&mut self.reader, &mut self.buf[self.end..])?;
+            self.eof = self.end + read < self.buf.len();
+            self.end += read;
+        }
+        if self.start == self.end {
+            return Ok(None);
+        }
+
+        let pending = &self.buf[self.start..self.end];
+        let len = match self.chunking {
+            Chunking::Fixed => pending.len(),
+            Chunking::ContentDefined => cdc_cut(pending, self.min_size, self.avg_size),
+        };
+        let chunk = self.start..self.start + len;
+        self.start += len;
+        Ok(Some(&self.buf[chunk]))
+    }
+}
+
+/// Length of the first content-defined chunk of `data`, which holds at most one maximum-size
+/// chunk, or less at the end of the input.
+fn cdc_cut(data: &[u8], min_size: usize, avg_size: usize) -> usize {
+    if data.len() <= min_size {
+        return data.len();
+    }
+
+    // One more zero bit than the average length needs before it, one fewer after it
+    let bits = avg_size.ilog2();
+    let mask = |bits: u32| match bits {
+        0 => 0,
+        bits => u64::MAX << (64 - bits.min(64)),
+    };
+    let (strict, loose) = (mask(bits + 1), mask(bits.saturating_sub(1)));
+
+    let normal = avg_size.min(data.len());
+    let mut hash = 0u64;
+    for (i, &byte) in data.iter().enumerate().skip(min_size) {
+        hash = (hash << 1).wrapping_add(GEAR[byte as usize]);
+        let mask = if i < normal { strict } else { loose };
+        if hash & mask == 0 {
+            return i + 1;
+        }
+    }
+    data.len()
+}
+
+/// Splits everything readable from `reader` into `chunk_size` chunks and hashes each one.
+///
+/// Chunk boundaries are identical to those used when packing, so the result can be compared
+/// directly against the chunk hashes recorded in an archive's file table.
+///
+/// # Returns
+///
+/// The chunk hashes in file order; empty input yields an empty vector.
+///
+/// # Examples
+///
+/// ```rust
+/// use squishrs::util::chunk::{hash_chunk, hash_chunks};
+///
+/// let mut input: &[u8] = b"abcdefg";
+/// let hashes = hash_chunks(&mut input, 4).unwrap();
+/// assert_eq!(hashes, vec![hash_chunk(b"abcd"), hash_chunk(b"efg")]);
+/// ```
+pub fn hash_chunks<R: Read>(reader: &mut R, chunk_size: usize) -> io::Result<Vec<ChunkHash>> {
+    hash_chunks_scoped(reader, chunk_size, Chunking::Fixed, None)
+}
+
+/// Like [`hash_chunks`], splitting as `chunking` says and hashing every chunk within `scope`,
+/// see [`hash_chunk_scoped`].
+pub fn hash_chunks_scoped<R: Read>(
+    reader: &mut R,
+    chunk_size: usize,
+    chunking: Chunking,
+    scope: Option<u64>,
+) -> io::Result<Vec<ChunkHash>> {
+    let mut hashes = Vec::new();
+    let mut chunker = Chunker::new(reader, chunk_size, chunking);
+    while let Some(chunk) = chunker.next_chunk()? {
+        hashes.push(hash_chunk_scoped(chunk, scope));
+    }
+    Ok(hashes)
+}
+
+/// Hashes a file's whole content with xxh3-128 as it streams past, giving the hash the file
+/// table records for each file.
+///
+/// The result equals [`hash_chunk`] of the concatenated input, whatever pieces it arrives in.
+///
+/// # Examples
+///
+/// ```rust
+/// use squishrs::util::chunk::{hash_chunk, ContentHasher};
+///
+/// let mut hasher = ContentHasher::new();
+/// hasher.update(b"abcd");
+/// hasher.update(b"efg");
+/// assert_eq!(hasher.finish(), hash_chunk(b"abcdefg"));
+/// ```
+pub struct ContentHasher(Xxh3);
+
+impl ContentHasher {
+    pub fn new() -> Self {
+        Self(Xxh3::new())
+    }
+
+    /// Feeds the next bytes of the content.
+    pub fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    /// Returns the hash of everything fed so far.
+    pub fn finish(&self) -> ChunkHash {
+        self.0.digest128().to_le_bytes()
+    }
+}
+
+impl Default for ContentHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Hashes everything `reader` yields, see [`ContentHasher`].
+pub fn hash_content<R: Read>(reader: &mut R) -> io::Result<ChunkHash> {
+    let mut hasher = ContentHasher::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        match reader.read(&mut buffer)? {
+            0 => return Ok(hasher.finish()),
+            read => hasher.update(&buffer[..read]),
+        }
+    }
+}
+
+/// Checks that `size` is usable as the number of bytes per chunk: at least 1 byte and at most
+/// [`MAX_CHUNK_SIZE`], the most a reader accepts.
+///
+/// # Returns
+///
+/// `size` as a `usize`.
+///
+/// # Errors
+///
+/// Returns `AppError::InvalidChunkSize` otherwise.
+pub fn check_chunk_size(size: u64) -> Result<usize, AppError> {
+    if size == 0 || size > MAX_CHUNK_SIZE {
+        return Err(AppError::InvalidChunkSize(size));
+    }
+    Ok(size as usize)
+}
+
+/// Checks that zstd accepts compression `level`.
+///
+/// # Returns
+///
+/// `level` unchanged.
+///
+/// # Errors
+///
+/// Returns `AppError::InvalidCompressionLevel` naming the accepted range otherwise.
+pub fn check_compression_level(level: i32) -> Result<i32, AppError> {
+    let range = zstd::compression_level_range();
+    if range.contains(&level) {
+        Ok(level)
+    } else {
+        Err(AppError::InvalidCompressionLevel(
+            level,
+            *range.start(),
+            *range.end(),
+        ))
+    }
+}
+
 impl ChunkStore {
     pub fn new() -> Self {
+        Self::with_min_gain(DEFAULT_MIN_GAIN_PERCENT)
+    }
+
+    /// Creates a `ChunkStore` that keeps compressed chunks only when compression saves at least
+    /// `min_gain_percent` percent of the chunk's size; other chunks are stored raw.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use squishrs::util::chunk::ChunkStore;
+    ///
+    /// // Keep compressed data only when it saves at least 10%
+    /// let store = ChunkStore::with_min_gain(10.0);
+    /// assert!(store.is_empty());
+    /// ```
+    pub fn with_min_gain(min_gain_percent: f64) -> Self {
         ChunkStore {
             primary_store: Arc::new(DashMap::new()),
+            min_gain_percent,
+            level: COMPRESSION_LEVEL,
+            compression: Compression::Zstd,
         }
     }
 
+    /// Compresses chunks at zstd `level` instead of [`COMPRESSION_LEVEL`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::InvalidCompressionLevel` if zstd does not accept `level`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use squishrs::util::chunk::ChunkStore;
+    ///
+    /// let store = ChunkStore::new().with_level(19).unwrap();
+    /// assert!(ChunkStore::new().with_level(99).is_err());
+    /// ```
+    pub fn with_level(mut self, level: i32) -> Result<Self, AppError> {
+        self.level = check_compression_level(level)?;
+        Ok(self)
+    }
+
+    /// Sets how new chunks are compressed; with [`Compression::None`] every chunk is stored raw.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use squishrs::util::chunk::{ChunkStore, Compression};
+    ///
+    /// let store = ChunkStore::new().with_compression(Compression::None);
+    /// let inserted = store.insert(&[0u8; 4096]).unwrap();
+    /// assert!(inserted.stored_raw);
+    /// ```
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
     /// Inserts a chunk of data into the `ChunkStore`, performing deduplication and compression.
     ///
     /// This method first checks if the chunk's hash already exists in the primary store:
     /// - If found, it returns the existing compressed data clone (avoiding recompression).
     /// - Otherwise, it compresses the chunk using the configured compression encoder.
     ///
-    /// After compression, it performs secondary deduplication by storing the chunk and its compressed
-    /// version in a secondary store only if the compression was effective (compressed data is smaller).
-    ///
-    /// Finally, it inserts the compressed chunk and its original size into the primary store.
+    /// If compression does not save at least the configured minimum gain, the raw chunk is
+    /// returned instead and `stored_raw` is set, so restores skip decompressing data that barely
+    /// shrank.
     ///
     /// # Arguments
     ///
@@ -76,29 +546,81 @@ impl ChunkStore {
     ///
     /// # Errors
     ///
-    /// Returns an error if compression or writing to the encoder fails.
+    /// Returns `AppError::Compression` if compressing the chunk fails.
     pub fn insert(&self, chunk: &[u8]) -> ReturnInsertChunk {
-        let hash = hash_chunk(chunk);
+        self.insert_scoped(chunk, None)
+    }
+
+    /// Inserts a chunk like [`ChunkStore::insert`], but only deduplicates it against chunks
+    /// inserted with the same `scope`, see [`hash_chunk_scoped`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use squishrs::util::chunk::ChunkStore;
+    ///
+    /// let store = ChunkStore::new();
+    /// store.insert_scoped(b"shared", Some(1)).unwrap();
+    /// assert!(store.insert_scoped(b"shared", Some(2)).unwrap().compressed_data.is_some());
+    /// ```
+    pub fn insert_scoped(&self, chunk: &[u8], scope: Option<u64>) -> ReturnInsertChunk {
+        let hash = hash_chunk_scoped(chunk, scope);
 
         match self.primary_store.entry(hash) {
             Entry::Occupied(_) => Ok(InsertReturn {
                 hash,
                 compressed_data: None,
+                stored_raw: false,
             }),
             Entry::Vacant(entry) => {
-                let compressed =
-                    compress(chunk, COMPRESSION_LEVEL).map_err(|_| AppError::Compression)?;
+                if self.compression == Compression::None {
+                    entry.insert(());
+                    return Ok(InsertReturn {
+                        hash,
+                        compressed_data: Some(Arc::new(chunk.to_vec())),
+                        stored_raw: true,
+                    });
+                }
+
+                let compressed = compress(chunk, self.level).map_err(|_| AppError::Compression)?;
 
                 entry.insert(());
 
+                let max_kept_len = chunk.len() as f64 * (1.0 - self.min_gain_percent / 100.0);
+                let stored_raw = compressed.len() as f64 > max_kept_len;
+                let stored = if stored_raw {
+                    chunk.to_vec()
+                } else {
+                    compressed
+                };
+
                 Ok(InsertReturn {
                     hash,
-                    compressed_data: Some(Arc::new(compressed)),
+                    compressed_data: Some(Arc::new(stored)),
+                    stored_raw,
                 })
             }
         }
     }
 
+    /// Marks chunks as already stored elsewhere, so inserting them later deduplicates against
+    /// them instead of compressing and emitting them again.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use squishrs::util::chunk::{hash_chunk, ChunkStore};
+    ///
+    /// let store = ChunkStore::new();
+    /// store.seed([hash_chunk(b"shared")]);
+    /// assert!(store.insert(b"shared").unwrap().compressed_data.is_none());
+    /// ```
+    pub fn seed<I: IntoIterator<Item = ChunkHash>>(&self, hashes: I) {
+        for hash in hashes {
+            self.primary_store.insert(hash, ());
+        }
+    }
+
     /// Returns the number of entries currently stored in the `ChunkStore`.
     ///
     /// # Returns
@@ -0,0 +1,147 @@
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use argon2::{Algorithm, Argon2, Params, Version};
+
+use crate::util::errors::AppError;
+
+/// Bytes of random salt the key is derived with
+pub const SALT_LEN: usize = 16;
+
+/// Bytes of the random nonce stored in front of every sealed chunk and file table
+pub const NONCE_LEN: usize = 12;
+
+/// Bytes of the authentication tag stored after every sealed chunk and file table
+pub const TAG_LEN: usize = 16;
+
+/// Largest Argon2 memory cost an archive may ask for; anything bigger means the header is corrupt,
+/// and deriving the key would try to allocate that much memory
+const MAX_MEMORY_KIB: u32 = 4 * 1024 * 1024; // 4GiB
+
+/// Argon2id costs the key was derived with, recorded in the header so they can be raised later
+/// without breaking older archives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    /// Memory cost in KiB
+    pub memory_kib: u32,
+    /// Number of passes over the memory
+    pub iterations: u32,
+    /// Degree of parallelism
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    /// The costs the argon2 crate recommends: 19 MiB, two passes, one lane.
+    fn default() -> Self {
+        Self {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+/// How an encrypted archive's key is derived from its password, recorded in the header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Encryption {
+    /// Random salt, fresh for every archive so equal passwords give different keys
+    pub salt: [u8; SALT_LEN],
+    pub kdf: KdfParams,
+}
+
+impl Encryption {
+    /// Picks a fresh random salt for a new archive.
+    pub fn generate(kdf: KdfParams) -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Self { salt, kdf }
+    }
+
+    /// Derives the archive key from `password` with Argon2id.
+    ///
+    /// # Errors
+    /// Returns `AppError::Archive` if the recorded costs are out of range, which means the
+    /// header is corrupt.
+    pub fn derive_key(&self, password: &str) -> Result<ArchiveKey, AppError> {
+        let invalid = |e: argon2::Error| AppError::Archive(format!("invalid key derivation: {e}"));
+        if self.kdf.memory_kib > MAX_MEMORY_KIB {
+            return Err(AppError::Archive(format!(
+                "key derivation asks for {} KiB of memory, over the {MAX_MEMORY_KIB} KiB limit",
+                self.kdf.memory_kib
+            )));
+        }
+        let params = Params::new(
+            self.kdf.memory_kib,
+            self.kdf.iterations,
+            self.kdf.parallelism,
+            Some(32),
+        )
+        .map_err(invalid)?;
+
+        let mut key = [0u8; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(password.as_bytes(), &self.salt, &mut key)
+            .map_err(invalid)?;
+        let cipher = Aes256Gcm::new_from_slice(&key).map_err(|_| AppError::DecryptionFailed)?;
+        Ok(ArchiveKey { cipher })
+    }
+}
+
+/// AES-256-GCM key chunks and the file table are sealed with.
+#[derive(Clone)]
+pub struct ArchiveKey {
+    cipher: Aes256Gcm,
+}
+
+impl std::fmt::Debug for ArchiveKey {
+    /// Leaves the key itself out, so it never ends up in logs
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ArchiveKey(..)")
+    }
+}
+
+impl ArchiveKey {
+    /// Encrypts `plaintext` under a fresh random nonce, authenticating `aad` alongside it.
+    ///
+    /// # Returns
+    /// The nonce, the ciphertext and the tag, in that order; [`NONCE_LEN`] + [`TAG_LEN`] bytes
+    /// longer than `plaintext`.
+    pub fn seal(&self, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, AppError> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext,
+                    aad,
+                },
+            )
+            .map_err(|_| AppError::Other("encryption failed".into()))?;
+
+        let mut sealed = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    /// Decrypts the output of [`ArchiveKey::seal`], checking it against `aad`.
+    ///
+    /// # Errors
+    /// Returns `AppError::DecryptionFailed` if the key is wrong or the data was altered.
+    pub fn open(&self, sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>, AppError> {
+        if sealed.len() < NONCE_LEN + TAG_LEN {
+            return Err(AppError::DecryptionFailed);
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        self.cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad,
+                },
+            )
+            .map_err(|_| AppError::DecryptionFailed)
+    }
+}
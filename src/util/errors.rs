@@ -52,15 +52,79 @@ pub enum AppError {
     #[error("Missing Chunk for File: `{0}`")]
     MissingChunk(PathBuf),
 
+    #[error("File not found in squish: `{0}`")]
+    FileNotFoundInArchive(String),
+
+    #[error("File already exists in squish: `{0}`")]
+    FileExistsInArchive(String),
+
+    #[error("Invalid byte range {0}:{1} for `{2}`, which is {3} bytes")]
+    InvalidRange(u64, u64, String, u64),
+
+    #[error("Refusing to write outside the output directory: `{0}`")]
+    UnsafePath(PathBuf),
+
+    #[error("File `{0}` changed size while being packed: expected {1} bytes, read {2}")]
+    FileSizeChanged(PathBuf, u64, u64),
+
+    #[error(
+        "`{0}` rebuilt to {2} bytes, but the squish records {1}; the squish is probably corrupt"
+    )]
+    SizeMismatch(String, u64, u64),
+
+    #[error(
+        "`{0}` does not match the hash recorded when it was packed; the squish is probably corrupt"
+    )]
+    FileHashMismatch(String),
+
+    #[error("Chunk {1} (`{0}`) is corrupt: {2}")]
+    CorruptChunk(String, u64, String),
+
+    #[error("File `{0}` references chunk `{1}`, which the squish does not contain")]
+    UnknownChunk(String, String),
+
+    #[error("Verification failed: {0} file(s) differ between the squish and the source")]
+    VerificationFailed(usize),
+
+    #[error("unsupported: {0}")]
+    Unsupported(String),
+
+    #[error("Invalid output path `{0}`: {1}")]
+    InvalidOutput(PathBuf, String),
+
+    #[error("Compression level {0} is out of range; zstd accepts {1} to {2}")]
+    InvalidCompressionLevel(i32, i32, i32),
+
     #[error("Invalid chunk size: {0} bytes")]
     InvalidChunkSize(u64),
 
+    #[error("Chunk declares an original size of {0} bytes, over the {1} byte limit; the squish is probably corrupt")]
+    ChunkTooLarge(u64, u64),
+
     #[error("Unable to Cap Maximum Threads: {0}")]
     CapThreadsError(#[source] rayon::ThreadPoolBuildError),
 
     #[error("Invalid timestamp in squish: {0}")]
     InvalidTimeStamp(#[source] io::Error),
 
+    #[error("Wrong password, or the squish has been tampered with")]
+    DecryptionFailed,
+
+    #[error("The squish is encrypted; give its password with --password or --password-file")]
+    PasswordRequired,
+
+    #[error("Invalid key `{0}`: {1}")]
+    InvalidKey(PathBuf, String),
+
+    #[error("The squish is not signed")]
+    NotSigned,
+
+    #[error("Signature check failed: {0}")]
+    SignatureMismatch(String),
+
+    #[error("Interrupted; any incomplete archive was removed")]
+    Interrupted,
+
     #[error("Unknown error: {0}")]
     Other(String),
 }
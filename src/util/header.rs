@@ -1,18 +1,159 @@
 use std::io::ErrorKind;
 use std::io::{Error, Read, Seek, SeekFrom, Write};
+use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use chrono::{DateTime, Local, TimeZone};
+use chrono::{DateTime, TimeZone, Utc};
 
+use crate::util::chunk::Chunking;
+use crate::util::crypto::{Encryption, KdfParams, SALT_LEN};
 use crate::util::errors::AppError;
 use crate::VERSION;
 
 pub const PREFIX: &[u8] = b"squish";
 
+/// Magic prefix of a standalone chunk pool written by `export-chunks`
+pub const POOL_PREFIX: &[u8] = b"sqpool";
+
+/// Set in the timestamp field when a provenance block follows it
+const PROVENANCE_FLAG: u64 = 1 << 63;
+
+/// Set in the timestamp field when a `u64` hash seed follows it, after any provenance block
+const HASH_SEED_FLAG: u64 = 1 << 62;
+
+/// Set in the timestamp field when every file table entry carries a `u32` permission mode
+const FILE_MODES_FLAG: u64 = 1 << 61;
+
+/// Set in the timestamp field when every file table entry carries an entry type byte
+const ENTRY_TYPES_FLAG: u64 = 1 << 60;
+
+/// Set in the timestamp field when a `u32` chunk size follows it, after any hash seed
+const CHUNK_SIZE_FLAG: u64 = 1 << 59;
+
+/// Set in the timestamp field when chunk boundaries are content-defined; the chunk size is then
+/// their average
+const CONTENT_DEFINED_FLAG: u64 = 1 << 58;
+
+/// Set in the timestamp field when every file table entry carries an xxh3-128 content hash
+const FILE_HASHES_FLAG: u64 = 1 << 57;
+
+/// Set in the timestamp field when a `u64` total archive size follows it, after any chunk size
+const ARCHIVE_SIZE_FLAG: u64 = 1 << 56;
+
+/// Set in the timestamp field when a `u64` count of distinct top-level entries follows the chunk
+/// count
+const TOP_LEVEL_COUNT_FLAG: u64 = 1 << 55;
+
+/// Set in the timestamp field when chunks and the file table are encrypted; the key derivation
+/// salt and costs follow it, after any chunk size
+const ENCRYPTED_FLAG: u64 = 1 << 54;
+
+/// Set in the timestamp field when the archive ends with a signature footer, after any chunk
+/// index
+const SIGNED_FLAG: u64 = 1 << 53;
+
+/// Provenance feature bits: which length-prefixed strings follow the flag byte, in bit order
+const PROVENANCE_HOSTNAME: u8 = 1;
+const PROVENANCE_TOOL_VERSION: u8 = 1 << 1;
+
+/// Where and with what an archive was made, recorded only when packing with `--provenance`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Provenance {
+    /// Hostname of the machine that packed the archive
+    pub hostname: Option<String>,
+    /// Full squishrs version that packed the archive, e.g. `1.2.0`
+    pub tool_version: Option<String>,
+}
+
+impl Provenance {
+    /// Describes the running process: this machine's hostname, if it can be found, and this
+    /// build's version.
+    pub fn current() -> Self {
+        Self {
+            hostname: current_hostname(),
+            tool_version: Some(VERSION.to_string()),
+        }
+    }
+}
+
+/// Looks up the hostname without shelling out, returning `None` when it cannot be found
+fn current_hostname() -> Option<String> {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .map(|hostname| hostname.trim().to_string())
+        .filter(|hostname| !hostname.is_empty())
+}
+
+/// The timestamp field of an archive header together with the optional blocks it flags.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TimestampFields {
+    /// Seconds since the UNIX epoch
+    pub timestamp: u64,
+    pub provenance: Option<Provenance>,
+    /// xxh3 seed every chunk was hashed with; 0, the default, is not recorded
+    pub hash_seed: u64,
+    /// File table entries record each file's Unix permission mode
+    pub file_modes: bool,
+    /// File table entries record whether they are regular files or symlinks
+    pub entry_types: bool,
+    /// Bytes per chunk the archive was packed with; `None` for the default `CHUNK_SIZE`, which
+    /// is not recorded
+    pub chunk_size: Option<u32>,
+    /// How files were split into chunks; only sets a flag
+    pub chunking: Chunking,
+    /// File table entries record a hash of each regular file's whole content
+    pub file_hashes: bool,
+    /// Size of the whole archive in bytes, so a truncated copy is caught as soon as it is
+    /// opened. Written as a placeholder and patched once the archive is complete; `None` on
+    /// archives written without it
+    pub archive_size: Option<u64>,
+    /// The chunk count is followed by the number of distinct top-level entries; only sets a flag
+    pub top_level_count: bool,
+    /// How the key chunks and the file table are encrypted with is derived; `None` on
+    /// unencrypted archives
+    pub encryption: Option<Encryption>,
+    /// The archive ends with a signature footer; only sets a flag
+    pub signed: bool,
+}
+
+/// How an archive's format version relates to the version of this build
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionMatch {
+    /// Same major and minor version
+    Exact,
+    /// Same major version but a different minor version; the format is expected to be
+    /// compatible, so the archive is read after a warning
+    MinorMismatch,
+}
+
 pub fn magic_version() -> Vec<u8> {
     [PREFIX, VERSION.as_bytes()].concat()
 }
 
+/// Checks whether the file at `path` starts with the squish magic bytes.
+///
+/// Only the first few bytes are read, so this is cheap enough to run on every file before
+/// packing. Unreadable files are reported as not being archives.
+///
+/// # examples
+///
+/// ```no_run
+/// use squishrs::util::header::has_squish_magic;
+/// use std::path::Path;
+///
+/// if has_squish_magic(Path::new("backup.squish")) {
+///     println!("already an archive");
+/// }
+/// ```
+pub fn has_squish_magic(path: &Path) -> bool {
+    let mut magic = [0u8; PREFIX.len()];
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok_and(|_| magic == PREFIX)
+}
+
 /// Write the header to a archive file
 ///
 /// # arguments
@@ -47,34 +188,357 @@ pub fn write_header<W: Write>(writer: &mut W) -> std::io::Result<()> {
 ///
 /// # Errors
 ///
-/// Returns an `std::io::Error` if writing to the writer fails.
+/// Returns an `std::io::Error` if writing to the writer fails, or if the system clock is set
+/// before the UNIX epoch.
+pub fn write_timestamp<W: Write>(writer: &mut W) -> std::io::Result<()> {
+    write_timestamp_at(writer, unix_now()?)
+}
+
+/// Returns the current system time in seconds since the UNIX epoch.
 ///
-/// # Panics
+/// # Errors
 ///
-/// Panics if the system time is before the UNIX epoch (should not happen on normal systems).
-pub fn write_timestamp<W: Write>(writer: &mut W) -> std::io::Result<()> {
-    // Get current system time as seconds since UNIX epoch
-    let now = SystemTime::now()
+/// Returns an `std::io::Error` if the system clock is set before the UNIX epoch.
+pub fn unix_now() -> std::io::Result<u64> {
+    SystemTime::now()
         .duration_since(UNIX_EPOCH)
-        .expect("System time before UNIX");
-    let timestamp = now.as_secs();
+        .map(|elapsed| elapsed.as_secs())
+        .map_err(|_| Error::other("the system clock is set before the UNIX epoch"))
+}
+
+/// Writes the timestamp field followed by a provenance block.
+///
+/// The timestamp's top bit marks that the block follows, so archives without provenance keep
+/// their existing layout. The block is a feature flag byte, then each present field as a
+/// length-prefixed (`u32`, little-endian) UTF-8 string: hostname, then tool version.
+///
+/// # Examples
+///
+/// ```
+/// use squishrs::util::header::{read_timestamp, write_timestamp_with_provenance, Provenance};
+///
+/// let provenance = Provenance {
+///     hostname: Some("backup01".to_string()),
+///     tool_version: Some("1.2.0".to_string()),
+/// };
+/// let mut buffer = Vec::new();
+/// write_timestamp_with_provenance(&mut buffer, 1686890000, &provenance).unwrap();
+///
+/// let (timestamp, read_back) = read_timestamp(&mut buffer.as_slice()).unwrap();
+/// assert_eq!(timestamp, 1686890000);
+/// assert_eq!(read_back, Some(provenance));
+/// ```
+pub fn write_timestamp_with_provenance<W: Write>(
+    writer: &mut W,
+    timestamp: u64,
+    provenance: &Provenance,
+) -> std::io::Result<()> {
+    write_timestamp_fields(
+        writer,
+        &TimestampFields {
+            timestamp,
+            provenance: Some(provenance.clone()),
+            ..Default::default()
+        },
+    )
+}
+
+/// Writes the timestamp field followed by whichever optional blocks `fields` holds.
+///
+/// A flag bit in the timestamp marks each block that follows: the provenance block (see
+/// [`write_timestamp_with_provenance`]), then a little-endian `u64` hash seed when it is not 0,
+/// then a little-endian `u32` chunk size when one is set, then the encryption block (a 16-byte
+/// salt and little-endian `u32` memory cost, iterations and parallelism) when the archive is
+/// encrypted, then a little-endian `u64` archive size when one is set. With none, this writes
+/// the same 8 bytes as [`write_timestamp_at`]. `file_modes` only sets a flag, as do
+/// `entry_types`, `file_hashes` and `chunking`, since the modes, entry types and file hashes
+/// live in the file table, `top_level_count`, which follows the chunk count, and `signed`,
+/// since the signature is in the footer.
+///
+/// # Examples
+///
+/// ```
+/// use squishrs::util::header::{read_timestamp_fields, write_timestamp_fields, TimestampFields};
+///
+/// let fields = TimestampFields {
+///     timestamp: 1686890000,
+///     hash_seed: 7,
+///     ..Default::default()
+/// };
+/// let mut buffer = Vec::new();
+/// write_timestamp_fields(&mut buffer, &fields).unwrap();
+/// assert_eq!(read_timestamp_fields(&mut buffer.as_slice()).unwrap(), fields);
+/// ```
+pub fn write_timestamp_fields<W: Write>(
+    writer: &mut W,
+    fields: &TimestampFields,
+) -> std::io::Result<()> {
+    let mut field = fields.timestamp;
+    if fields.provenance.is_some() {
+        field |= PROVENANCE_FLAG;
+    }
+    if fields.hash_seed != 0 {
+        field |= HASH_SEED_FLAG;
+    }
+    if fields.file_modes {
+        field |= FILE_MODES_FLAG;
+    }
+    if fields.entry_types {
+        field |= ENTRY_TYPES_FLAG;
+    }
+    if fields.chunk_size.is_some() {
+        field |= CHUNK_SIZE_FLAG;
+    }
+    if fields.chunking == Chunking::ContentDefined {
+        field |= CONTENT_DEFINED_FLAG;
+    }
+    if fields.file_hashes {
+        field |= FILE_HASHES_FLAG;
+    }
+    if fields.archive_size.is_some() {
+        field |= ARCHIVE_SIZE_FLAG;
+    }
+    if fields.top_level_count {
+        field |= TOP_LEVEL_COUNT_FLAG;
+    }
+    if fields.encryption.is_some() {
+        field |= ENCRYPTED_FLAG;
+    }
+    if fields.signed {
+        field |= SIGNED_FLAG;
+    }
+    write_timestamp_at(writer, field)?;
+
+    if let Some(provenance) = &fields.provenance {
+        write_provenance_block(writer, provenance)?;
+    }
+    if fields.hash_seed != 0 {
+        writer.write_all(&fields.hash_seed.to_le_bytes())?;
+    }
+    if let Some(chunk_size) = fields.chunk_size {
+        writer.write_all(&chunk_size.to_le_bytes())?;
+    }
+    if let Some(encryption) = &fields.encryption {
+        writer.write_all(&encryption.salt)?;
+        writer.write_all(&encryption.kdf.memory_kib.to_le_bytes())?;
+        writer.write_all(&encryption.kdf.iterations.to_le_bytes())?;
+        writer.write_all(&encryption.kdf.parallelism.to_le_bytes())?;
+    }
+    if let Some(archive_size) = fields.archive_size {
+        writer.write_all(&archive_size.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Writes the feature flag byte and length-prefixed strings of a provenance block.
+fn write_provenance_block<W: Write>(
+    writer: &mut W,
+    provenance: &Provenance,
+) -> std::io::Result<()> {
+    let fields = [
+        (PROVENANCE_HOSTNAME, &provenance.hostname),
+        (PROVENANCE_TOOL_VERSION, &provenance.tool_version),
+    ];
+    let flags = fields
+        .iter()
+        .filter(|(_, value)| value.is_some())
+        .fold(0u8, |flags, (bit, _)| flags | bit);
+    writer.write_all(&[flags])?;
+
+    for value in fields.iter().filter_map(|(_, value)| value.as_ref()) {
+        writer.write_all(&(value.len() as u32).to_le_bytes())?;
+        writer.write_all(value.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Reads the timestamp field and, if flagged, the provenance block after it. A hash seed is
+/// read past too, leaving `reader` at the chunk count.
+///
+/// # Returns
+///
+/// The timestamp in seconds since the UNIX epoch, and the provenance if the archive has one.
+///
+/// # Errors
+///
+/// Returns an error if reading fails, a string is not UTF-8, or the block uses feature bits
+/// this build does not know, since their length cannot be skipped.
+pub fn read_timestamp<R: Read>(reader: &mut R) -> Result<(u64, Option<Provenance>), AppError> {
+    let fields = read_timestamp_fields(reader)?;
+    Ok((fields.timestamp, fields.provenance))
+}
+
+/// Reads the timestamp field and every optional block it flags, see [`write_timestamp_fields`].
+///
+/// # Errors
+///
+/// Returns an error if reading fails or the provenance block is malformed, see
+/// [`read_timestamp`], or `AppError::InvalidChunkSize` if the recorded chunk size is zero.
+pub fn read_timestamp_fields<R: Read>(reader: &mut R) -> Result<TimestampFields, AppError> {
+    let mut buf8 = [0u8; 8];
+    reader.read_exact(&mut buf8)?;
+    let field = u64::from_le_bytes(buf8);
+
+    let provenance = if field & PROVENANCE_FLAG != 0 {
+        Some(read_provenance_block(reader)?)
+    } else {
+        None
+    };
+
+    let hash_seed = if field & HASH_SEED_FLAG != 0 {
+        reader.read_exact(&mut buf8)?;
+        u64::from_le_bytes(buf8)
+    } else {
+        0
+    };
+
+    let chunk_size = if field & CHUNK_SIZE_FLAG != 0 {
+        let mut buf4 = [0u8; 4];
+        reader.read_exact(&mut buf4)?;
+        match u32::from_le_bytes(buf4) {
+            0 => return Err(AppError::InvalidChunkSize(0)),
+            chunk_size => Some(chunk_size),
+        }
+    } else {
+        None
+    };
+
+    let encryption = if field & ENCRYPTED_FLAG != 0 {
+        let mut salt = [0u8; SALT_LEN];
+        reader.read_exact(&mut salt)?;
+        let mut costs = [0u32; 3];
+        for cost in &mut costs {
+            let mut buf4 = [0u8; 4];
+            reader.read_exact(&mut buf4)?;
+            *cost = u32::from_le_bytes(buf4);
+        }
+        let [memory_kib, iterations, parallelism] = costs;
+        Some(Encryption {
+            salt,
+            kdf: KdfParams {
+                memory_kib,
+                iterations,
+                parallelism,
+            },
+        })
+    } else {
+        None
+    };
+
+    let archive_size = if field & ARCHIVE_SIZE_FLAG != 0 {
+        reader.read_exact(&mut buf8)?;
+        Some(u64::from_le_bytes(buf8))
+    } else {
+        None
+    };
+
+    let flags = PROVENANCE_FLAG
+        | HASH_SEED_FLAG
+        | FILE_MODES_FLAG
+        | ENTRY_TYPES_FLAG
+        | CHUNK_SIZE_FLAG
+        | CONTENT_DEFINED_FLAG
+        | FILE_HASHES_FLAG
+        | ARCHIVE_SIZE_FLAG
+        | TOP_LEVEL_COUNT_FLAG
+        | ENCRYPTED_FLAG
+        | SIGNED_FLAG;
+    Ok(TimestampFields {
+        timestamp: field & !flags,
+        provenance,
+        hash_seed,
+        file_modes: field & FILE_MODES_FLAG != 0,
+        entry_types: field & ENTRY_TYPES_FLAG != 0,
+        chunk_size,
+        chunking: if field & CONTENT_DEFINED_FLAG != 0 {
+            Chunking::ContentDefined
+        } else {
+            Chunking::Fixed
+        },
+        file_hashes: field & FILE_HASHES_FLAG != 0,
+        archive_size,
+        top_level_count: field & TOP_LEVEL_COUNT_FLAG != 0,
+        encryption,
+        signed: field & SIGNED_FLAG != 0,
+    })
+}
+
+/// Reads the provenance block that follows a flagged timestamp.
+fn read_provenance_block<R: Read>(reader: &mut R) -> Result<Provenance, AppError> {
+    let mut flags = [0u8; 1];
+    reader.read_exact(&mut flags)?;
+    let flags = flags[0];
+    if flags & !(PROVENANCE_HOSTNAME | PROVENANCE_TOOL_VERSION) != 0 {
+        return Err(AppError::Archive(format!(
+            "Unknown provenance fields in archive header: {flags:#04x}"
+        )));
+    }
+
+    let mut read_field = |bit: u8| -> Result<Option<String>, AppError> {
+        if flags & bit == 0 {
+            return Ok(None);
+        }
+        let mut buf4 = [0u8; 4];
+        reader.read_exact(&mut buf4)?;
+        let mut value = vec![0u8; u32::from_le_bytes(buf4) as usize];
+        reader.read_exact(&mut value)?;
+        String::from_utf8(value)
+            .map(Some)
+            .map_err(|_| AppError::Archive("Invalid UTF-8 in archive provenance".into()))
+    };
+
+    Ok(Provenance {
+        hostname: read_field(PROVENANCE_HOSTNAME)?,
+        tool_version: read_field(PROVENANCE_TOOL_VERSION)?,
+    })
+}
 
+/// Writes a fixed timestamp (seconds since the UNIX epoch) as a little-endian `u64`.
+///
+/// Used for reproducible archives, where the creation time must not vary between runs.
+///
+/// # Arguments
+///
+/// * `writer` - A mutable reference to a writer implementing the `Write` trait.
+/// * `timestamp` - Seconds since the UNIX epoch to record.
+///
+/// # Examples
+///
+/// ```
+/// use squishrs::util::header::write_timestamp_at;
+///
+/// let mut buffer = Vec::new();
+/// write_timestamp_at(&mut buffer, 0).expect("Failed to write timestamp");
+/// assert_eq!(buffer, vec![0u8; 8]);
+/// ```
+pub fn write_timestamp_at<W: Write>(writer: &mut W, timestamp: u64) -> std::io::Result<()> {
     writer.write_all(&timestamp.to_le_bytes())
 }
 
+/// Returns the timestamp to embed in reproducible archives.
+///
+/// Honours the `SOURCE_DATE_EPOCH` convention used by reproducible build tooling, falling back
+/// to the UNIX epoch when it is unset or malformed.
+pub fn source_date_epoch() -> u64 {
+    std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(0)
+}
+
 /// Converts a UNIX timestamp (seconds since epoch) into a formatted
-/// local date and time string.
+/// UTC date and time string.
 ///
-/// The returned string is formatted as result `"HH:MM DD/MM/YYYY"`.
+/// The returned string is formatted as result `"HH:MM DD/MM/YYYY"`, whatever the machine's
+/// time zone, so it matches the "(UTC)" label `list` and `info` print beside it.
 ///
 /// # Arguments
 ///
 /// * `timestamp_sec` - The timestamp in seconds since the UNIX epoch.
 ///
-/// # Panics
+/// # Errors
 ///
-/// Panics if the timestamp is invalid or cannot be converted to a single
-/// valid local datetime.
+/// Returns `AppError::InvalidTimeStamp` if the timestamp is out of range for a date.
 ///
 /// # Examples
 ///
@@ -82,10 +546,10 @@ pub fn write_timestamp<W: Write>(writer: &mut W) -> std::io::Result<()> {
 /// use squishrs::util::header::convert_timestamp_to_date;
 ///
 /// let formatted_date = convert_timestamp_to_date(1686890000).expect("Cannot convert to date");
-/// println!("{}", formatted_date); // e.g. "17:49 16/06/2025"
+/// assert_eq!(formatted_date, "04:33 16/06/2023");
 /// ```
 pub fn convert_timestamp_to_date(timestamp_sec: u64) -> Result<String, AppError> {
-    let datetime: DateTime<Local> = Local
+    let datetime: DateTime<Utc> = Utc
         .timestamp_opt(timestamp_sec as i64, 0)
         .single()
         .ok_or_else(|| {
@@ -96,6 +560,9 @@ pub fn convert_timestamp_to_date(timestamp_sec: u64) -> Result<String, AppError>
 
 /// Verify the header of an archive
 ///
+/// Archives from a different minor version of the same major version are accepted; use
+/// [`verify_header_strict`] to require an exact major.minor match.
+///
 /// # arguments
 ///
 /// * 'reader' - reader instance of the archive file
@@ -117,25 +584,49 @@ pub fn convert_timestamp_to_date(timestamp_sec: u64) -> Result<String, AppError>
 /// verify_header(&mut buffer).expect("Invalid Header");
 /// ```
 pub fn verify_header<R: Read>(reader: &mut R) -> Result<String, AppError> {
-    // Allocate buffer for prefix + version (prefix + 8 bytes for "00.01.01" format)
-    let expected_len = magic_version().len();
-    let mut header = vec![0u8; expected_len];
-    reader.read_exact(&mut header)?;
-
-    // Check prefix
-    if !header.starts_with(PREFIX) {
-        return Err(AppError::Archive(
-            "Invalid archive header: prefix mismatch".into(),
-        ));
-    }
+    verify_prefixed_header(reader, PREFIX)
+}
 
-    // Extract version bytes after prefix
-    let version_bytes = &header[PREFIX.len()..];
-    let version_str = std::str::from_utf8(version_bytes)
-        .map_err(|_| AppError::Archive("Invalid UTF-8 in version string".into()))?;
+/// Verify the header of an archive, also rejecting archives from a different minor version
+///
+/// # errors
+///
+/// Fails like [`verify_header`], and additionally when the minor versions differ.
+pub fn verify_header_strict<R: Read>(reader: &mut R) -> Result<String, AppError> {
+    let version = verify_header(reader)?;
+    check_version(&version, true)?;
+    Ok(version)
+}
 
+/// Compares an archive's format version against the version of this build
+///
+/// # Arguments
+///
+/// * `version` - Version string read from an archive header, e.g. `"1.2.0"`
+/// * `strict` - Treat a minor version difference as incompatible
+///
+/// # Returns
+///
+/// * `VersionMatch::MinorMismatch` when only the minor version differs and `strict` is off,
+///   otherwise `VersionMatch::Exact`
+///
+/// # Errors
+///
+/// Returns `AppError::Archive` if the major versions differ, the minor versions differ in
+/// strict mode, or the version string is malformed.
+///
+/// # Example
+///
+/// ```
+/// use squishrs::util::header::{check_version, VersionMatch};
+/// use squishrs::VERSION;
+///
+/// assert_eq!(check_version(VERSION, true).unwrap(), VersionMatch::Exact);
+/// assert!(check_version("99.0.0", false).is_err());
+/// ```
+pub fn check_version(version: &str, strict: bool) -> Result<VersionMatch, AppError> {
     // Parse major and minor from header version
-    let header_parts: Vec<&str> = version_str.split('.').collect();
+    let header_parts: Vec<&str> = version.split('.').collect();
     if header_parts.len() < 2 {
         return Err(AppError::Archive(
             "Invalid version format in archive header".into(),
@@ -152,13 +643,57 @@ pub fn verify_header<R: Read>(reader: &mut R) -> Result<String, AppError> {
     let current_major = current_parts[0];
     let current_minor = current_parts[1];
 
-    // Compare major and minor versions
-    if header_major != current_major || header_minor != current_minor {
+    // Only a major version change breaks the format; minor changes are rejected on request
+    if header_major != current_major || (strict && header_minor != current_minor) {
         return Err(AppError::Archive(format!(
             "Incompatible version: archive {header_major}.{header_minor} vs current {current_major}.{current_minor}"
         )));
     }
 
+    if header_minor != current_minor {
+        Ok(VersionMatch::MinorMismatch)
+    } else {
+        Ok(VersionMatch::Exact)
+    }
+}
+
+/// Write the header of a chunk pool file
+///
+/// Chunk pools share the archive's chunk serialization, so they carry the same version but a
+/// distinct prefix to avoid being mistaken for an archive.
+pub fn write_pool_header<W: Write>(writer: &mut W) -> std::io::Result<()> {
+    writer.write_all(&[POOL_PREFIX, VERSION.as_bytes()].concat())
+}
+
+/// Verify the header of a chunk pool file, returning its version
+///
+/// # errors
+///
+/// Fails like [`verify_header`] when the prefix is wrong or the version is incompatible.
+pub fn verify_pool_header<R: Read>(reader: &mut R) -> Result<String, AppError> {
+    verify_prefixed_header(reader, POOL_PREFIX)
+}
+
+fn verify_prefixed_header<R: Read>(reader: &mut R, prefix: &[u8]) -> Result<String, AppError> {
+    // Allocate buffer for prefix + version (prefix + 8 bytes for "00.01.01" format)
+    let expected_len = prefix.len() + VERSION.len();
+    let mut header = vec![0u8; expected_len];
+    reader.read_exact(&mut header)?;
+
+    // Check prefix
+    if !header.starts_with(prefix) {
+        return Err(AppError::Archive(
+            "Invalid archive header: prefix mismatch".into(),
+        ));
+    }
+
+    // Extract version bytes after prefix
+    let version_bytes = &header[prefix.len()..];
+    let version_str = std::str::from_utf8(version_bytes)
+        .map_err(|_| AppError::Archive("Invalid UTF-8 in version string".into()))?;
+
+    check_version(version_str, false)?;
+
     Ok(version_str.to_string())
 }
 
@@ -0,0 +1,57 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::util::errors::AppError;
+
+/// Raised by the Ctrl-C handler and polled by packing and unpacking.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Installs a SIGINT handler that asks long-running work to stop instead of killing the process.
+///
+/// Packing then fails with `AppError::Interrupted`, which removes the incomplete archive and
+/// joins the writer thread like any other error; unpacking stops starting new files. A second
+/// Ctrl-C exits immediately.
+///
+/// Only call this before work that polls [`check_interrupted`], which is packing, unpacking and
+/// repairing: anything else would swallow the first Ctrl-C. Other commands keep the default
+/// behaviour and end at once.
+///
+/// Linux only. On other platforms this does nothing, so Ctrl-C ends pack and unpack at once
+/// too; an interrupted pack still leaves any existing archive at the output path untouched, but
+/// may leave its temporary file behind.
+#[cfg(target_os = "linux")]
+pub fn install_interrupt_handler() {
+    extern "C" fn on_interrupt(_signal: libc::c_int) {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            // SAFETY: `_exit` is async-signal-safe
+            unsafe { libc::_exit(130) };
+        }
+    }
+
+    // SAFETY: the handler only touches an atomic and calls `_exit`, both async-signal-safe
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn install_interrupt_handler() {}
+
+/// Returns whether Ctrl-C has been pressed since the handler was installed.
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+/// Fails with `AppError::Interrupted` once Ctrl-C has been pressed.
+///
+/// # Errors
+///
+/// Returns `AppError::Interrupted` if an interrupt has been received.
+pub fn check_interrupted() -> Result<(), AppError> {
+    if is_interrupted() {
+        return Err(AppError::Interrupted);
+    }
+    Ok(())
+}
@@ -1,6 +1,10 @@
+pub mod analyze;
 pub mod chunk;
+pub mod crypto;
 pub mod errors;
 pub mod header;
+pub mod interrupt;
+pub mod signing;
 
 #[cfg(test)]
 mod tests;
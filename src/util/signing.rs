@@ -0,0 +1,194 @@
+use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use ed25519_dalek::pkcs8::{DecodePrivateKey, DecodePublicKey};
+use ed25519_dalek::{Signature, SigningKey, VerifyingKey, SIGNATURE_LENGTH};
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::util::errors::AppError;
+
+/// Marker closing a signed archive
+const FOOTER_MAGIC: [u8; 8] = *b"sqsign01";
+
+/// Context the Ed25519ph signature is made under, so a signature over an archive cannot be
+/// passed off as one over anything else signed with the same key
+const SIGNATURE_CONTEXT: &[u8] = b"squishrs archive";
+
+/// Bytes of the public key fingerprint recorded in the footer
+pub const FINGERPRINT_LEN: usize = 32;
+
+/// Size of the footer: the signed length, the key fingerprint, the signature and the magic
+/// marker.
+pub const FOOTER_SIZE: u64 = 8 + FINGERPRINT_LEN as u64 + SIGNATURE_LENGTH as u64 + 8;
+
+/// SHA-256 of an ed25519 public key, identifying which key signed an archive
+pub type KeyFingerprint = [u8; FINGERPRINT_LEN];
+
+/// The footer closing a signed archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureFooter {
+    /// Bytes from the start of the archive the signature covers, which is everything before
+    /// the footer
+    pub signed_length: u64,
+    /// Fingerprint of the public key that checks the signature
+    pub fingerprint: KeyFingerprint,
+    pub signature: Signature,
+}
+
+/// Reads an ed25519 private key from a PKCS#8 PEM file, as written by
+/// `openssl genpkey -algorithm ed25519`.
+///
+/// # Errors
+/// Returns `AppError::FileNotExist` if the file cannot be read, or `AppError::InvalidKey` if it
+/// does not hold an ed25519 private key.
+pub fn load_signing_key(path: &Path) -> Result<SigningKey, AppError> {
+    let pem = fs::read_to_string(path).map_err(|_| AppError::FileNotExist(path.to_path_buf()))?;
+    SigningKey::from_pkcs8_pem(&pem)
+        .map_err(|e| AppError::InvalidKey(path.to_path_buf(), e.to_string()))
+}
+
+/// Reads an ed25519 public key from a PEM file, as written by `openssl pkey -pubout`.
+///
+/// # Errors
+/// Returns `AppError::FileNotExist` if the file cannot be read, or `AppError::InvalidKey` if it
+/// does not hold an ed25519 public key.
+pub fn load_verifying_key(path: &Path) -> Result<VerifyingKey, AppError> {
+    let pem = fs::read_to_string(path).map_err(|_| AppError::FileNotExist(path.to_path_buf()))?;
+    VerifyingKey::from_public_key_pem(&pem)
+        .map_err(|e| AppError::InvalidKey(path.to_path_buf(), e.to_string()))
+}
+
+/// Returns the fingerprint of `key`, recorded in the footer of archives it signs.
+pub fn fingerprint(key: &VerifyingKey) -> KeyFingerprint {
+    Sha256::digest(key.as_bytes()).into()
+}
+
+/// Formats a fingerprint as lowercase hex, as `info` shows it.
+pub fn fingerprint_to_hex(fingerprint: &KeyFingerprint) -> String {
+    fingerprint
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Signs the first `signed_length` bytes of `reader` with `key`.
+///
+/// The bytes are hashed as they are read and signed with Ed25519ph, so the archive is read
+/// once and never held in memory.
+///
+/// # Errors
+/// Returns an error if `reader` holds fewer than `signed_length` bytes or cannot be read.
+pub fn sign<R: Read>(
+    reader: R,
+    signed_length: u64,
+    key: &SigningKey,
+) -> Result<SignatureFooter, AppError> {
+    let digest = hash_prefix(reader, signed_length)?;
+    let signature = key
+        .sign_prehashed(digest, Some(SIGNATURE_CONTEXT))
+        .map_err(|e| AppError::Other(format!("signing failed: {e}")))?;
+
+    Ok(SignatureFooter {
+        signed_length,
+        fingerprint: fingerprint(&key.verifying_key()),
+        signature,
+    })
+}
+
+/// Checks `footer` against the bytes it covers at the start of `reader`.
+///
+/// The fingerprint is compared first, so an archive signed by another key is told apart from
+/// one altered after signing without reading the archive.
+///
+/// # Errors
+/// Returns `AppError::SignatureMismatch` if `key` did not sign the archive or the signed bytes
+/// changed since, or an error if `reader` cannot be read.
+pub fn verify<R: Read>(
+    reader: R,
+    footer: &SignatureFooter,
+    key: &VerifyingKey,
+) -> Result<(), AppError> {
+    if footer.fingerprint != fingerprint(key) {
+        return Err(AppError::SignatureMismatch(format!(
+            "the squish was signed by key {}, not the given key",
+            fingerprint_to_hex(&footer.fingerprint)
+        )));
+    }
+
+    let digest = hash_prefix(reader, footer.signed_length)?;
+    key.verify_prehashed_strict(digest, Some(SIGNATURE_CONTEXT), &footer.signature)
+        .map_err(|_| {
+            AppError::SignatureMismatch("the squish was altered after it was signed".into())
+        })
+}
+
+/// Feeds the first `length` bytes of `reader` into a SHA-512 hasher.
+fn hash_prefix<R: Read>(reader: R, length: u64) -> Result<Sha512, AppError> {
+    let mut hasher = Sha512::new();
+    let hashed =
+        std::io::copy(&mut reader.take(length), &mut hasher).map_err(AppError::ReaderError)?;
+    if hashed != length {
+        return Err(AppError::Archive(format!(
+            "truncated archive: signature covers {length} bytes, found {hashed}"
+        )));
+    }
+    Ok(hasher)
+}
+
+/// Writes `footer` to `writer`: the signed length (`u64`, little-endian), the key
+/// fingerprint, the signature and the magic marker.
+pub fn write_footer<W: Write>(writer: &mut W, footer: &SignatureFooter) -> Result<(), AppError> {
+    writer
+        .write_all(&footer.signed_length.to_le_bytes())
+        .map_err(AppError::WriterError)?;
+    writer
+        .write_all(&footer.fingerprint)
+        .map_err(AppError::WriterError)?;
+    writer
+        .write_all(&footer.signature.to_bytes())
+        .map_err(AppError::WriterError)?;
+    writer
+        .write_all(&FOOTER_MAGIC)
+        .map_err(AppError::WriterError)
+}
+
+/// Reads the footer at the end of a signed archive of `archive_size` bytes.
+///
+/// # Errors
+/// Returns `AppError::Archive` if the footer is missing or does not cover everything before
+/// it, or an error if reading fails.
+pub fn read_footer<R: Read + Seek>(
+    reader: &mut R,
+    archive_size: u64,
+) -> Result<SignatureFooter, AppError> {
+    let missing = || AppError::Archive("signed squish has no signature footer".into());
+    let signed_length = archive_size.checked_sub(FOOTER_SIZE).ok_or_else(missing)?;
+
+    let mut footer = [0u8; FOOTER_SIZE as usize];
+    reader
+        .seek(SeekFrom::Start(signed_length))
+        .map_err(AppError::ReaderError)?;
+    reader
+        .read_exact(&mut footer)
+        .map_err(AppError::ReaderError)?;
+    if footer[FOOTER_SIZE as usize - FOOTER_MAGIC.len()..] != FOOTER_MAGIC {
+        return Err(missing());
+    }
+
+    let recorded_length = u64::from_le_bytes(footer[..8].try_into().unwrap());
+    if recorded_length != signed_length {
+        return Err(AppError::Archive(format!(
+            "signature footer covers {recorded_length} bytes, but {signed_length} precede it"
+        )));
+    }
+    let fingerprint_end = 8 + FINGERPRINT_LEN;
+    let signature_end = fingerprint_end + SIGNATURE_LENGTH;
+    Ok(SignatureFooter {
+        signed_length,
+        fingerprint: footer[8..fingerprint_end].try_into().unwrap(),
+        signature: Signature::from_bytes(
+            footer[fingerprint_end..signature_end].try_into().unwrap(),
+        ),
+    })
+}
@@ -1,11 +1,34 @@
 use std::io::{Cursor, Read, Seek};
 
-use crate::util::chunk::{hash_chunk, ChunkStore};
+use ed25519_dalek::SigningKey;
+
+use crate::util::analyze::{analyze_files, analyze_files_with_options, AnalyzeOptions};
+use crate::util::chunk::{
+    check_chunk_size, chunk_seed, hash_chunk, hash_chunk_scoped, hash_chunks_scoped, read_chunk,
+    ChunkStore, Chunker, Chunking, Compression, MAX_CHUNK_SIZE,
+};
+
+/// Deterministic bytes that zstd cannot meaningfully compress
+fn incompressible_bytes(len: usize) -> Vec<u8> {
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+use crate::util::crypto::{Encryption, KdfParams, NONCE_LEN, TAG_LEN};
 use crate::util::errors::AppError;
 use crate::util::header::{
-    convert_timestamp_to_date, magic_version, patch_u64, verify_header, write_header,
-    write_placeholder_u64, write_timestamp, PREFIX,
+    check_version, convert_timestamp_to_date, magic_version, patch_u64, read_timestamp,
+    read_timestamp_fields, verify_header, verify_header_strict, write_header,
+    write_placeholder_u64, write_timestamp, write_timestamp_at, write_timestamp_fields,
+    write_timestamp_with_provenance, Provenance, TimestampFields, VersionMatch, PREFIX,
 };
+use crate::util::signing::{fingerprint, read_footer, sign, verify, write_footer, FOOTER_SIZE};
 use crate::VERSION;
 
 #[test]
@@ -32,13 +55,203 @@ fn test_verify_header_invalid_prefix() {
     assert!(result.is_err());
 }
 
+/// Returns the current version with its minor part replaced by a different one of equal length
+fn other_minor_version() -> String {
+    let mut parts: Vec<String> = VERSION.split('.').map(str::to_string).collect();
+    parts[1] = if parts[1].starts_with('9') {
+        parts[1].replacen('9', "8", 1)
+    } else {
+        parts[1].replacen(|c: char| c.is_ascii_digit(), "9", 1)
+    };
+    parts.join(".")
+}
+
 #[test]
 fn test_verify_header_incompatible_version() {
-    // Forge header with different major.minor version
-    let fake_version = b"squish99.99.99";
-    let mut cursor = Cursor::new(fake_version.to_vec());
-    let result = verify_header(&mut cursor);
-    assert!(result.is_err());
+    // Forge header with a different major version, padded to the current version's length
+    let major_version = format!("{:9<width$}", "8.", width = VERSION.len());
+    let fake_header = format!("squish{major_version}");
+    let mut cursor = Cursor::new(fake_header.into_bytes());
+    assert!(verify_header(&mut cursor).is_err());
+    assert!(check_version(&major_version, false).is_err());
+
+    // A minor version difference is accepted unless strict
+    let minor_version = other_minor_version();
+    let fake_header = format!("squish{minor_version}");
+    let mut cursor = Cursor::new(fake_header.as_bytes().to_vec());
+    assert_eq!(verify_header(&mut cursor).unwrap(), minor_version);
+    assert_eq!(
+        check_version(&minor_version, false).unwrap(),
+        VersionMatch::MinorMismatch
+    );
+
+    let mut cursor = Cursor::new(fake_header.into_bytes());
+    assert!(verify_header_strict(&mut cursor).is_err());
+    assert!(check_version(&minor_version, true).is_err());
+    assert_eq!(check_version(VERSION, true).unwrap(), VersionMatch::Exact);
+}
+
+#[test]
+fn test_read_timestamp_provenance() {
+    // A plain timestamp carries no provenance
+    let mut buffer = Vec::new();
+    write_timestamp_at(&mut buffer, 42).unwrap();
+    assert_eq!(read_timestamp(&mut buffer.as_slice()).unwrap(), (42, None));
+
+    // Only the recorded fields are written
+    let provenance = Provenance {
+        hostname: None,
+        tool_version: Some("1.2.0".to_string()),
+    };
+    let mut buffer = Vec::new();
+    write_timestamp_with_provenance(&mut buffer, 42, &provenance).unwrap();
+    assert_eq!(buffer.len(), 8 + 1 + 4 + 5);
+    assert_eq!(
+        read_timestamp(&mut buffer.as_slice()).unwrap(),
+        (42, Some(provenance))
+    );
+
+    // Unknown feature bits cannot be skipped
+    buffer[8] |= 0x80;
+    assert!(read_timestamp(&mut buffer.as_slice()).is_err());
+}
+
+#[test]
+fn test_timestamp_fields_hash_seed() {
+    let fields = TimestampFields {
+        timestamp: 42,
+        provenance: Some(Provenance {
+            hostname: Some("tenant-a".to_string()),
+            tool_version: None,
+        }),
+        hash_seed: 0xfeed,
+        file_modes: true,
+        entry_types: true,
+        file_hashes: true,
+        chunk_size: None,
+        chunking: Chunking::ContentDefined,
+        archive_size: Some(4096),
+        top_level_count: true,
+        encryption: None,
+        signed: true,
+    };
+    let mut buffer = Vec::new();
+    write_timestamp_fields(&mut buffer, &fields).unwrap();
+    assert_eq!(buffer.len(), 8 + 1 + 4 + 8 + 8 + 8);
+
+    let mut reader = buffer.as_slice();
+    assert_eq!(read_timestamp_fields(&mut reader).unwrap(), fields);
+    assert!(reader.is_empty());
+
+    // Readers that ignore the seed still skip past it
+    let (timestamp, _) = read_timestamp(&mut buffer.as_slice()).unwrap();
+    assert_eq!(timestamp, 42);
+}
+
+#[test]
+fn test_timestamp_fields_chunk_size() {
+    let fields = TimestampFields {
+        timestamp: 42,
+        hash_seed: 7,
+        chunk_size: Some(64 * 1024),
+        ..Default::default()
+    };
+    let mut buffer = Vec::new();
+    write_timestamp_fields(&mut buffer, &fields).unwrap();
+    assert_eq!(buffer.len(), 8 + 8 + 4);
+    assert_eq!(
+        read_timestamp_fields(&mut buffer.as_slice()).unwrap(),
+        fields
+    );
+
+    // A recorded chunk size of zero is rejected
+    buffer[16..].copy_from_slice(&0u32.to_le_bytes());
+    assert!(matches!(
+        read_timestamp_fields(&mut buffer.as_slice()),
+        Err(AppError::InvalidChunkSize(0))
+    ));
+}
+
+#[test]
+fn test_timestamp_fields_encryption() {
+    let fields = TimestampFields {
+        timestamp: 42,
+        chunk_size: Some(64 * 1024),
+        archive_size: Some(4096),
+        encryption: Some(Encryption {
+            salt: [7; 16],
+            kdf: KdfParams {
+                memory_kib: 64,
+                iterations: 1,
+                parallelism: 1,
+            },
+        }),
+        ..Default::default()
+    };
+    let mut buffer = Vec::new();
+    write_timestamp_fields(&mut buffer, &fields).unwrap();
+    assert_eq!(buffer.len(), 8 + 4 + 16 + 12 + 8);
+
+    let mut reader = buffer.as_slice();
+    assert_eq!(read_timestamp_fields(&mut reader).unwrap(), fields);
+    assert!(reader.is_empty());
+}
+
+#[test]
+fn test_seal_and_open() {
+    let encryption = Encryption::generate(KdfParams {
+        memory_kib: 64,
+        iterations: 1,
+        parallelism: 1,
+    });
+    let key = encryption.derive_key("hunter2").unwrap();
+
+    let sealed = key.seal(b"secret chunk", b"hash").unwrap();
+    assert_eq!(sealed.len(), b"secret chunk".len() + NONCE_LEN + TAG_LEN);
+    assert_eq!(key.open(&sealed, b"hash").unwrap(), b"secret chunk");
+
+    // Sealing twice picks a new nonce
+    assert_ne!(key.seal(b"secret chunk", b"hash").unwrap(), sealed);
+
+    // The wrong password, the wrong associated data or a flipped bit are all rejected
+    let wrong_key = encryption.derive_key("hunter3").unwrap();
+    assert!(matches!(
+        wrong_key.open(&sealed, b"hash"),
+        Err(AppError::DecryptionFailed)
+    ));
+    assert!(matches!(
+        key.open(&sealed, b"other"),
+        Err(AppError::DecryptionFailed)
+    ));
+    let mut tampered = sealed.clone();
+    tampered[NONCE_LEN] ^= 1;
+    assert!(matches!(
+        key.open(&tampered, b"hash"),
+        Err(AppError::DecryptionFailed)
+    ));
+}
+
+#[test]
+fn test_check_chunk_size() {
+    assert_eq!(check_chunk_size(1).unwrap(), 1);
+    assert_eq!(
+        check_chunk_size(MAX_CHUNK_SIZE).unwrap(),
+        MAX_CHUNK_SIZE as usize
+    );
+    assert!(check_chunk_size(0).is_err());
+    assert!(check_chunk_size(MAX_CHUNK_SIZE + 1).is_err());
+}
+
+#[test]
+fn test_hash_seed_partitions_chunk_hashes() {
+    let chunk = b"shared between tenants";
+    let a = hash_chunk_scoped(chunk, chunk_seed(1, None));
+    let b = hash_chunk_scoped(chunk, chunk_seed(2, None));
+    assert_ne!(a, b);
+    assert_eq!(
+        hash_chunk_scoped(chunk, chunk_seed(0, None)),
+        hash_chunk(chunk)
+    );
 }
 
 #[test]
@@ -60,9 +273,17 @@ fn test_write_timestamp_and_convert() {
 
 #[test]
 fn test_convert_timestamp_to_date_known_value() {
-    let ts = 1686890000; // Mon, 16 Jun 2023 17:46:40 GMT
+    let ts = 1686890000; // Fri, 16 Jun 2023 04:33:20 UTC
     let result = convert_timestamp_to_date(ts).expect("Inavlid timestamp");
-    assert!(result.ends_with("/2023") || result.ends_with("/2025")); // Accept drift from TZ/localtime
+    assert_eq!(result, "04:33 16/06/2023");
+}
+
+#[test]
+fn test_convert_timestamp_to_date_out_of_range() {
+    assert!(matches!(
+        convert_timestamp_to_date(i64::MAX as u64),
+        Err(AppError::InvalidTimeStamp(_))
+    ));
 }
 
 #[test]
@@ -165,6 +386,71 @@ fn test_compressed_data_is_smaller_or_equal() {
     assert_eq!(decompressed, repetitive_data);
 }
 
+#[test]
+fn test_insert_stores_raw_below_min_gain() {
+    let store = ChunkStore::new();
+    let data = incompressible_bytes(4096);
+
+    let result = store.insert(&data).unwrap();
+    assert!(result.stored_raw);
+    assert_eq!(*result.compressed_data.unwrap(), data);
+
+    // With no threshold, any compressed output that is produced is kept
+    let store = ChunkStore::with_min_gain(0.0);
+    let result = store.insert(&vec![7u8; 4096]).unwrap();
+    assert!(!result.stored_raw);
+}
+
+#[test]
+fn test_insert_never_grows_incompressible_chunk() {
+    // Even with no minimum gain, zstd output larger than the input is not kept
+    let store = ChunkStore::with_min_gain(0.0);
+    let data = incompressible_bytes(4096);
+
+    let result = store.insert(&data).unwrap();
+    assert!(result.stored_raw);
+    assert!(result.compressed_data.unwrap().len() <= data.len());
+}
+
+#[test]
+fn test_compression_none_stores_raw() {
+    let store = ChunkStore::new().with_compression(Compression::None);
+    let data = vec![3u8; 64 * 1024];
+
+    let inserted = store.insert(&data).unwrap();
+    assert!(inserted.stored_raw);
+    assert_eq!(*inserted.compressed_data.unwrap(), data);
+
+    // Duplicates are still deduplicated
+    assert!(store.insert(&data).unwrap().compressed_data.is_none());
+}
+
+#[test]
+fn test_with_level_compresses_and_validates() {
+    let data = vec![3u8; 64 * 1024];
+    let fast = ChunkStore::new()
+        .with_level(1)
+        .unwrap()
+        .insert(&data)
+        .unwrap();
+    let slow = ChunkStore::new()
+        .with_level(19)
+        .unwrap()
+        .insert(&data)
+        .unwrap();
+
+    // Either level round-trips, since decompression ignores the level
+    for stored in [fast, slow] {
+        let stored = stored.compressed_data.unwrap();
+        assert_eq!(zstd::decode_all(Cursor::new(&*stored)).unwrap(), data);
+    }
+
+    assert!(matches!(
+        ChunkStore::new().with_level(23),
+        Err(AppError::InvalidCompressionLevel(23, _, 22))
+    ));
+}
+
 #[test]
 fn test_from_boxed_error() {
     use std::error::Error;
@@ -187,3 +473,235 @@ fn test_from_boxed_error() {
         _ => panic!("Expected AppError::Other variant"),
     }
 }
+
+fn chunk_lengths(data: &[u8], chunk_size: usize, chunking: Chunking) -> Vec<usize> {
+    let mut chunker = Chunker::new(data, chunk_size, chunking);
+    let mut lengths = Vec::new();
+    while let Some(chunk) = chunker.next_chunk().unwrap() {
+        lengths.push(chunk.len());
+    }
+    lengths
+}
+
+#[test]
+fn test_content_defined_chunk_lengths() {
+    let data = incompressible_bytes(256 * 1024);
+    let lengths = chunk_lengths(&data, 4096, Chunking::ContentDefined);
+
+    assert_eq!(lengths.iter().sum::<usize>(), data.len());
+    let (last, rest) = lengths.split_last().unwrap();
+    assert!(*last <= 4 * 4096);
+    assert!(rest.iter().all(|&len| (1024..=4 * 4096).contains(&len)));
+
+    // Lengths vary with the content but stay around the chunk size on average
+    assert!(rest.iter().any(|&len| len != rest[0]));
+    let average = data.len() / lengths.len();
+    assert!((2048..=8192).contains(&average), "average {average}");
+
+    assert_eq!(chunk_lengths(&data, 4096, Chunking::Fixed), vec![4096; 64]);
+}
+
+#[test]
+fn test_content_defined_chunks_survive_insertion() {
+    let original = incompressible_bytes(256 * 1024);
+    let mut edited = original.clone();
+    edited.insert(1000, 0xab);
+
+    let shared_chunks = |chunking| {
+        let before = hash_chunks_scoped(&mut &original[..], 4096, chunking, None).unwrap();
+        let after = hash_chunks_scoped(&mut &edited[..], 4096, chunking, None).unwrap();
+        (
+            after.iter().filter(|hash| before.contains(hash)).count(),
+            after.len(),
+        )
+    };
+
+    // Every fixed chunk shifts by the inserted byte; content-defined chunks resynchronise right
+    // after the edit
+    assert_eq!(shared_chunks(Chunking::Fixed).0, 0);
+    let (shared, total) = shared_chunks(Chunking::ContentDefined);
+    assert!(
+        shared + 3 >= total,
+        "only {shared} of {total} chunks still shared"
+    );
+}
+
+#[test]
+fn test_read_chunk_fills_buffer_across_short_reads() {
+    // A reader that only ever returns one byte per call
+    struct OneByte<'a>(&'a [u8]);
+    impl Read for OneByte<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.0.is_empty() || buf.is_empty() {
+                return Ok(0);
+            }
+            buf[0] = self.0[0];
+            self.0 = &self.0[1..];
+            Ok(1)
+        }
+    }
+
+    let mut reader = OneByte(b"abcdef");
+    let mut buf = [0u8; 4];
+    assert_eq!(read_chunk(&mut reader, &mut buf).unwrap(), 4);
+    assert_eq!(&buf, b"abcd");
+    assert_eq!(read_chunk(&mut reader, &mut buf).unwrap(), 2);
+    assert_eq!(read_chunk(&mut reader, &mut buf).unwrap(), 0);
+}
+
+#[test]
+fn test_analyze_files_counts_duplicates() {
+    let dir = tempfile::tempdir().unwrap();
+    let file1 = dir.path().join("a.bin");
+    let file2 = dir.path().join("b.bin");
+
+    // a.bin: chunks [A, B, A], b.bin: chunks [A, C]
+    std::fs::write(
+        &file1,
+        [vec![1u8; 16], vec![2u8; 16], vec![1u8; 16]].concat(),
+    )
+    .unwrap();
+    std::fs::write(&file2, [vec![1u8; 16], vec![3u8; 8]].concat()).unwrap();
+
+    let report = analyze_files(&[file1, file2], 16).unwrap();
+
+    assert_eq!(report.files, 2);
+    assert_eq!(report.total_chunks, 5);
+    assert_eq!(report.unique_chunks, 3);
+    assert_eq!(report.total_bytes, 72);
+    assert_eq!(report.unique_bytes, 40);
+    assert_eq!(report.duplicate_bytes(), 32);
+    assert_eq!(report.repeat_histogram.get(&3), Some(&1));
+    assert_eq!(report.repeat_histogram.get(&1), Some(&2));
+}
+
+#[test]
+fn test_analyze_files_groups_by_type() {
+    let dir = tempfile::tempdir().unwrap();
+    let log1 = dir.path().join("a.log");
+    let log2 = dir.path().join("b.LOG");
+    let blob = dir.path().join("c.bin");
+    let bare = dir.path().join("README");
+
+    // Both logs share their only chunk; the blob is a single unique chunk
+    std::fs::write(&log1, vec![b'x'; 16]).unwrap();
+    std::fs::write(&log2, vec![b'x'; 16]).unwrap();
+    std::fs::write(&blob, incompressible_bytes(8)).unwrap();
+    std::fs::write(&bare, b"readme").unwrap();
+
+    let options = AnalyzeOptions {
+        sample_compression: true,
+        ..Default::default()
+    };
+    let report = analyze_files_with_options(&[log1, log2, blob, bare], 16, &options).unwrap();
+
+    let types: Vec<&str> = report
+        .by_type
+        .iter()
+        .map(|stats| stats.extension.as_str())
+        .collect();
+    assert_eq!(types, vec!["log", "bin", "(none)"]);
+
+    let logs = &report.by_type[0];
+    assert_eq!(logs.files, 2);
+    assert_eq!(logs.total_bytes, 32);
+    assert_eq!(logs.unique_bytes, 16);
+    assert_eq!(logs.dedup_ratio(), 2.0);
+    assert_eq!(logs.sampled_bytes, 16);
+    assert!(logs.compression_ratio().is_some());
+
+    // Without sampling no compression estimate is made
+    let report = analyze_files(&[dir.path().join("c.bin")], 16).unwrap();
+    assert_eq!(report.by_type[0].compression_ratio(), None);
+}
+
+#[test]
+fn test_analyze_files_per_file_contributions() {
+    let dir = tempfile::tempdir().unwrap();
+    let solo = dir.path().join("solo.bin");
+    let copy1 = dir.path().join("copy1.bin");
+    let copy2 = dir.path().join("copy2.bin");
+    let mixed = dir.path().join("mixed.bin");
+
+    // solo owns two chunks; the copies share theirs with each other and with mixed, which also
+    // owns one chunk of its own
+    let shared = incompressible_bytes(16);
+    std::fs::write(&solo, &incompressible_bytes(48)[16..]).unwrap();
+    std::fs::write(&copy1, &shared).unwrap();
+    std::fs::write(&copy2, &shared).unwrap();
+    std::fs::write(&mixed, [shared.clone(), vec![7u8; 16]].concat()).unwrap();
+
+    let options = AnalyzeOptions {
+        per_file: Some(3),
+        ..Default::default()
+    };
+    let report = analyze_files_with_options(&[copy1, copy2, mixed, solo], 16, &options).unwrap();
+
+    let paths: Vec<String> = report
+        .by_file
+        .iter()
+        .map(|file| file.path.file_name().unwrap().to_string_lossy().to_string())
+        .collect();
+    assert_eq!(paths, vec!["solo.bin", "mixed.bin", "copy1.bin"]);
+
+    // Incompressible chunks are stored raw, at full size
+    let solo = &report.by_file[0];
+    assert_eq!(solo.unique_compressed_bytes, 32);
+    assert_eq!(solo.shared_compressed_bytes, 0);
+
+    let mixed = &report.by_file[1];
+    assert_eq!(mixed.total_bytes, 32);
+    assert!(mixed.unique_compressed_bytes > 0 && mixed.unique_compressed_bytes <= 16);
+    assert_eq!(mixed.shared_compressed_bytes, 16 / 3);
+
+    let copy = &report.by_file[2];
+    assert_eq!(copy.unique_compressed_bytes, 0);
+    assert_eq!(copy.shared_compressed_bytes, 16 / 3);
+
+    // Nothing is attributed unless asked for
+    let report = analyze_files(&[dir.path().join("solo.bin")], 16).unwrap();
+    assert!(report.by_file.is_empty());
+}
+
+#[test]
+fn test_analyze_files_rejects_zero_chunk_size() {
+    let result = analyze_files(&[], 0);
+    assert!(matches!(result, Err(AppError::InvalidChunkSize(0))));
+}
+
+#[test]
+fn test_sign_and_verify() {
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let body = b"header, chunk table, file table and chunk index".to_vec();
+
+    let footer = sign(body.as_slice(), body.len() as u64, &key).unwrap();
+    assert_eq!(footer.fingerprint, fingerprint(&key.verifying_key()));
+    verify(body.as_slice(), &footer, &key.verifying_key()).unwrap();
+
+    // The footer is read back from the end of the archive
+    let mut archive = body.clone();
+    write_footer(&mut archive, &footer).unwrap();
+    assert_eq!(archive.len() as u64, body.len() as u64 + FOOTER_SIZE);
+    let size = archive.len() as u64;
+    assert_eq!(
+        read_footer(&mut Cursor::new(&archive), size).unwrap(),
+        footer
+    );
+
+    // Another key, a changed byte or a missing footer are all caught
+    let other = SigningKey::from_bytes(&[8; 32]);
+    assert!(matches!(
+        verify(body.as_slice(), &footer, &other.verifying_key()),
+        Err(AppError::SignatureMismatch(_))
+    ));
+    let mut altered = body.clone();
+    altered[0] ^= 1;
+    assert!(matches!(
+        verify(altered.as_slice(), &footer, &key.verifying_key()),
+        Err(AppError::SignatureMismatch(_))
+    ));
+    assert!(matches!(
+        read_footer(&mut Cursor::new(&body), body.len() as u64),
+        Err(AppError::Archive(_))
+    ));
+}
@@ -1,4 +1,7 @@
 use assert_cmd::Command;
+use ed25519_dalek::pkcs8::spki::der::pem::LineEnding;
+use ed25519_dalek::pkcs8::{EncodePrivateKey, EncodePublicKey};
+use ed25519_dalek::SigningKey;
 use predicates::prelude::*;
 use std::fs::{self, File};
 use std::io::Write;
@@ -79,6 +82,121 @@ fn test_pack_empty_directory() {
         .stdout(predicate::str::contains("number_of_files: 0"));
 }
 
+#[test]
+fn test_pack_unpack_keeps_empty_subdirectory() {
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    let archive = temp.path().join("archive.squish");
+    let output = temp.path().join("output");
+
+    fs::create_dir_all(input.join("logs")).unwrap();
+    create_test_file(&input, "file1.txt", b"hello");
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "pack",
+            input.to_str().unwrap(),
+            "--output",
+            archive.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "unpack",
+            archive.to_str().unwrap(),
+            "--output",
+            output.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    assert!(output.join("logs").is_dir());
+    assert_eq!(fs::read_dir(output.join("logs")).unwrap().count(), 0);
+    assert_eq!(fs::read(output.join("file1.txt")).unwrap(), b"hello");
+}
+
+#[test]
+fn test_pack_include_exclude() {
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    let archive = temp.path().join("archive.squish");
+
+    fs::create_dir_all(input.join("target")).unwrap();
+    create_test_file(&input, "keep.txt", b"kept");
+    create_test_file(&input, "debug.log", b"noise");
+    create_test_file(&input, "target/out.txt", b"built");
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "pack",
+            input.to_str().unwrap(),
+            "--output",
+            archive.to_str().unwrap(),
+            "--include",
+            "*.txt",
+            "--exclude",
+            "target/**",
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args(["list", archive.to_str().unwrap(), "--simple"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("number_of_files: 1"))
+        .stdout(predicate::str::contains("keep.txt"));
+}
+
+#[test]
+fn test_remove_files() {
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    let archive = temp.path().join("archive.squish");
+
+    fs::create_dir_all(&input).unwrap();
+    create_test_file(&input, "keep.txt", b"kept");
+    create_test_file(&input, "drop.txt", b"dropped");
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "pack",
+            input.to_str().unwrap(),
+            "--output",
+            archive.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args(["remove", archive.to_str().unwrap(), "missing.txt"])
+        .assert()
+        .failure();
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args(["remove", archive.to_str().unwrap(), "drop.txt"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Removed 1 file(s)"));
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args(["list", archive.to_str().unwrap(), "--simple"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("number_of_files: 1"))
+        .stdout(predicate::str::contains("keep.txt"));
+}
+
 #[test]
 fn test_list_invalid_archive() {
     let temp = tempdir().unwrap();
@@ -157,3 +275,1103 @@ fn test_pack_nested_directories() {
         fs::read(output.join("subdir").join("file_sub.txt")).unwrap()
     );
 }
+
+#[test]
+fn test_list_simple_with_chunks() {
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    let archive = temp.path().join("archive.squish");
+
+    fs::create_dir(&input).unwrap();
+    create_test_file(&input, "file1.txt", b"hello");
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "pack",
+            input.to_str().unwrap(),
+            "--output",
+            archive.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args(["list", archive.to_str().unwrap(), "--simple", "--chunks"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Chunks"))
+        .stdout(predicate::str::contains("file1.txt"));
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args(["list", archive.to_str().unwrap(), "--files", "--limit", "1"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Largest files"))
+        .stdout(predicate::str::contains("file1.txt"));
+}
+
+#[test]
+fn test_list_simple_limit_shows_largest() {
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    let archive = temp.path().join("archive.squish");
+
+    fs::create_dir(&input).unwrap();
+    create_test_file(&input, "a_small.txt", b"tiny");
+    create_test_file(&input, "b_medium.txt", &[b'm'; 2048]);
+    create_test_file(&input, "c_large.txt", &[b'l'; 8192]);
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "pack",
+            input.to_str().unwrap(),
+            "--output",
+            archive.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    let output = Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "list",
+            archive.to_str().unwrap(),
+            "--simple",
+            "--files",
+            "--limit",
+            "2",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    // Largest first, like the table, and capped after sorting
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let large = stdout.find("c_large.txt").unwrap();
+    let medium = stdout.find("b_medium.txt").unwrap();
+    assert!(large < medium);
+    assert!(!stdout.contains("a_small.txt"));
+}
+
+#[test]
+fn test_list_json() {
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    let archive = temp.path().join("archive.squish");
+
+    fs::create_dir(&input).unwrap();
+    create_test_file(&input, "file1.txt", b"hello");
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "pack",
+            input.to_str().unwrap(),
+            "--output",
+            archive.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args(["list", archive.to_str().unwrap(), "--json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""path":"file1.txt""#))
+        .stdout(predicate::str::contains(r#""original_size":5"#))
+        .stdout(predicate::str::contains(r#""unique_chunks":1"#));
+}
+
+#[test]
+fn test_verify_against_source() {
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    let archive = temp.path().join("archive.squish");
+
+    fs::create_dir(&input).unwrap();
+    create_test_file(&input, "file1.txt", b"hello");
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "pack",
+            input.to_str().unwrap(),
+            "--output",
+            archive.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "verify",
+            archive.to_str().unwrap(),
+            "--against",
+            input.to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 matched"));
+
+    create_test_file(&input, "file1.txt", b"changed");
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "verify",
+            archive.to_str().unwrap(),
+            "--against",
+            input.to_str().unwrap(),
+        ])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Changed since packing"))
+        .stderr(predicate::str::contains("Verification failed"));
+}
+
+#[test]
+fn test_verify_integrity() {
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    let archive = temp.path().join("archive.squish");
+
+    fs::create_dir(&input).unwrap();
+    create_test_file(&input, "file1.txt", b"hello");
+    create_test_file(&input, "file2.txt", b"world");
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "pack",
+            input.to_str().unwrap(),
+            "--output",
+            archive.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args(["verify", archive.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Verified 2 chunks referenced by 2 files",
+        ))
+        .stdout(predicate::str::contains("Verification passed!"));
+}
+
+#[test]
+fn test_pack_unpack_single_thread() {
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    let output = temp.path().join("output");
+    let archive = temp.path().join("archive.squish");
+
+    fs::create_dir(&input).unwrap();
+    for i in 0..8 {
+        create_test_file(
+            &input,
+            &format!("file{i}.txt"),
+            format!("data {i}").as_bytes(),
+        );
+    }
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args(["-j", "1", "pack", input.to_str().unwrap()])
+        .args(["--output", archive.to_str().unwrap()])
+        .assert()
+        .success();
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args(["unpack", archive.to_str().unwrap(), "--max-threads", "1"])
+        .args(["--output", output.to_str().unwrap()])
+        .assert()
+        .success();
+
+    assert_eq!(fs::read(output.join("file7.txt")).unwrap(), b"data 7");
+}
+
+#[test]
+fn test_pack_max_files() {
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    let archive = temp.path().join("archive.squish");
+
+    fs::create_dir(&input).unwrap();
+    for i in 0..3 {
+        create_test_file(&input, &format!("file{i}.txt"), b"hello");
+    }
+
+    let pack = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("squishrs").unwrap();
+        cmd.args([
+            "pack",
+            input.to_str().unwrap(),
+            "--output",
+            archive.to_str().unwrap(),
+        ])
+        .args(args);
+        cmd
+    };
+
+    pack(&["--max-files", "2"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("file count limit exceeded"));
+    assert!(!archive.exists());
+
+    // Declining the prompt fails the same way, accepting it packs everything
+    pack(&["--max-files", "2", "--confirm-large"])
+        .write_stdin("n\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Continue anyway?"));
+    assert!(!archive.exists());
+
+    pack(&["--max-files", "2", "--confirm-large"])
+        .write_stdin("y\n")
+        .assert()
+        .success();
+    assert!(archive.exists());
+
+    pack(&["--max-files", "3"]).assert().success();
+}
+
+#[test]
+fn test_pack_skip_squished() {
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    let nested = temp.path().join("nested");
+    let archive = temp.path().join("archive.squish");
+
+    fs::create_dir(&input).unwrap();
+    fs::create_dir(&nested).unwrap();
+    create_test_file(&nested, "inner.txt", b"inner");
+    create_test_file(&input, "file1.txt", b"hello");
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "pack",
+            nested.to_str().unwrap(),
+            "--output",
+            input.join("old.squish").to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "pack",
+            input.to_str().unwrap(),
+            "--output",
+            archive.to_str().unwrap(),
+            "--skip-squished",
+        ])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("already squish archives"));
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args(["list", archive.to_str().unwrap(), "--simple"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("file1.txt"))
+        .stdout(predicate::str::contains("old.squish").not());
+}
+
+#[test]
+fn test_unpack_sequential_from_stdin() {
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    let output = temp.path().join("output");
+    let archive = temp.path().join("archive.squish");
+
+    fs::create_dir_all(input.join("subdir")).unwrap();
+    create_test_file(&input, "file1.txt", b"hello");
+    create_test_file(&input.join("subdir"), "file2.txt", b"world");
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "pack",
+            input.to_str().unwrap(),
+            "--output",
+            archive.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args(["unpack", "-", "--output", output.to_str().unwrap()])
+        .write_stdin(fs::read(&archive).unwrap())
+        .assert()
+        .success();
+
+    assert_eq!(fs::read(output.join("file1.txt")).unwrap(), b"hello");
+    assert_eq!(
+        fs::read(output.join("subdir").join("file2.txt")).unwrap(),
+        b"world"
+    );
+}
+
+#[test]
+fn test_pack_to_stdout_and_unpack_from_stdin() {
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    let output = temp.path().join("output");
+
+    fs::create_dir_all(input.join("subdir")).unwrap();
+    create_test_file(&input, "file1.txt", b"hello");
+    create_test_file(&input.join("subdir"), "file2.txt", b"world");
+
+    let packed = Command::cargo_bin("squishrs")
+        .unwrap()
+        .args(["pack", input.to_str().unwrap(), "--output", "-"])
+        .env("TMPDIR", temp.path())
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    assert!(packed.starts_with(b"squish"));
+
+    // The scratch archive is gone once streamed
+    let leftovers: Vec<_> = fs::read_dir(temp.path())
+        .unwrap()
+        .filter(|entry| entry.as_ref().unwrap().path().extension() == Some("squish".as_ref()))
+        .collect();
+    assert!(leftovers.is_empty());
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args(["unpack", "-", "--output", output.to_str().unwrap()])
+        .write_stdin(packed)
+        .assert()
+        .success();
+
+    assert_eq!(fs::read(output.join("file1.txt")).unwrap(), b"hello");
+    assert_eq!(
+        fs::read(output.join("subdir").join("file2.txt")).unwrap(),
+        b"world"
+    );
+}
+
+#[test]
+fn test_pack_dry_run_writes_nothing() {
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    let archive = temp.path().join("archive.squish");
+
+    fs::create_dir_all(&input).unwrap();
+    create_test_file(&input, "file1.txt", &b"hello ".repeat(1000));
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "pack",
+            input.to_str().unwrap(),
+            "--output",
+            archive.to_str().unwrap(),
+            "--dry-run",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Estimated archive size"))
+        .stdout(predicate::str::contains("Unique chunks: 1"));
+
+    assert!(!archive.exists());
+}
+
+#[test]
+fn test_pack_split_by_dir() {
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    let archives = temp.path().join("archives");
+    let output = temp.path().join("output");
+
+    fs::create_dir_all(input.join("alpha")).unwrap();
+    fs::create_dir_all(input.join("beta")).unwrap();
+    create_test_file(&input.join("alpha"), "a.txt", b"shared content");
+    create_test_file(&input.join("beta"), "b.txt", b"shared content");
+    create_test_file(&input, "loose.txt", b"loose");
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "pack",
+            input.to_str().unwrap(),
+            "--output",
+            archives.to_str().unwrap(),
+            "--split-by-dir",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Created 3 archives"))
+        .stdout(predicate::str::contains(
+            "Chunks stored in more than one archive: 1",
+        ));
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "unpack",
+            archives.join("beta.squish").to_str().unwrap(),
+            "--output",
+            output.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    assert_eq!(fs::read(output.join("b.txt")).unwrap(), b"shared content");
+    assert!(archives.join("alpha.squish").exists());
+    assert!(archives.join("_root.squish").exists());
+}
+
+#[test]
+fn test_pack_into_existing_directory() {
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    let out_dir = temp.path().join("backups");
+
+    fs::create_dir(&input).unwrap();
+    fs::create_dir(&out_dir).unwrap();
+    create_test_file(&input, "file1.txt", b"hello");
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "pack",
+            input.to_str().unwrap(),
+            "--output",
+            out_dir.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+    assert!(out_dir.join("input.squish").exists());
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "pack",
+            input.to_str().unwrap(),
+            "--output",
+            input.join("file1.txt/archive.squish").to_str().unwrap(),
+        ])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is a file, not a directory"));
+}
+
+#[test]
+fn test_verify_compat() {
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    let archive = temp.path().join("archive.squish");
+    let bogus = temp.path().join("bogus.squish");
+
+    fs::create_dir(&input).unwrap();
+    create_test_file(&input, "file1.txt", b"hello");
+    fs::write(&bogus, b"not an archive at all").unwrap();
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "pack",
+            input.to_str().unwrap(),
+            "--output",
+            archive.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args(["verify", archive.to_str().unwrap(), "--compat"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("extractable"));
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args(["verify", bogus.to_str().unwrap(), "--compat"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unsupported: "));
+}
+
+#[test]
+fn test_mv_renames_file_in_archive() {
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    let output = temp.path().join("output");
+    let archive = temp.path().join("archive.squish");
+
+    fs::create_dir(&input).unwrap();
+    create_test_file(&input, "draft.txt", b"final text");
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "pack",
+            input.to_str().unwrap(),
+            "--output",
+            archive.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "mv",
+            archive.to_str().unwrap(),
+            "draft.txt",
+            "final/text.txt",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Renamed 1 file(s)"));
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "unpack",
+            archive.to_str().unwrap(),
+            "--output",
+            output.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read(output.join("final/text.txt")).unwrap(),
+        b"final text"
+    );
+    assert!(!output.join("draft.txt").exists());
+}
+
+#[test]
+fn test_pack_output_inside_input_is_not_packed() {
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    fs::create_dir(&input).unwrap();
+    create_test_file(&input, "file1.txt", b"hello");
+    let archive = input.join("self.squish");
+
+    // The second pack finds the first archive at its own output path
+    for _ in 0..2 {
+        Command::cargo_bin("squishrs")
+            .unwrap()
+            .args([
+                "pack",
+                input.to_str().unwrap(),
+                "--output",
+                archive.to_str().unwrap(),
+            ])
+            .assert()
+            .success()
+            .stderr(predicate::str::contains("already squish archives").not());
+    }
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args(["list", archive.to_str().unwrap(), "--simple"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("file1.txt"))
+        .stdout(predicate::str::contains("self.squish").not());
+}
+
+#[test]
+fn test_unpack_single_file() {
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    let output = temp.path().join("output");
+    let archive = temp.path().join("archive.squish");
+
+    fs::create_dir_all(input.join("nested")).unwrap();
+    create_test_file(&input.join("nested"), "wanted.txt", b"wanted");
+    create_test_file(&input, "other.txt", b"other");
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args(["pack", input.to_str().unwrap()])
+        .args(["--output", archive.to_str().unwrap()])
+        .assert()
+        .success();
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "unpack",
+            archive.to_str().unwrap(),
+            "--file",
+            "nested/wanted.txt",
+        ])
+        .args(["--output", output.to_str().unwrap()])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read(output.join("nested/wanted.txt")).unwrap(),
+        b"wanted"
+    );
+    assert!(!output.join("other.txt").exists());
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args(["unpack", archive.to_str().unwrap(), "--file", "nope.txt"])
+        .args(["--output", output.to_str().unwrap()])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "File not found in squish: `nope.txt`",
+        ));
+}
+
+#[test]
+fn test_unpack_check() {
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    let archive = temp.path().join("archive.squish");
+    fs::create_dir(&input).unwrap();
+    create_test_file(&input, "file1.txt", b"hello");
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "pack",
+            input.to_str().unwrap(),
+            "--output",
+            archive.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .current_dir(temp.path())
+        .args(["unpack", archive.to_str().unwrap(), "--check"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("All 1 files"));
+    assert!(!temp.path().join("archive").exists());
+}
+
+#[test]
+fn test_info_prints_header_details() {
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    let archive = temp.path().join("archive.squish");
+
+    fs::create_dir_all(&input).unwrap();
+    create_test_file(&input, "a.txt", b"first");
+    create_test_file(&input, "b.txt", b"second");
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "pack",
+            input.to_str().unwrap(),
+            "--output",
+            archive.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args(["info", archive.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Squish Version"))
+        .stdout(predicate::str::contains("Creation Date (UTC)"))
+        .stdout(predicate::str::contains("Number of files"))
+        .stdout(predicate::str::contains("Number of chunks"));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_interrupted_pack_removes_partial_output() {
+    use std::process::{Command as StdCommand, Stdio};
+    use std::time::{Duration, Instant};
+
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    let out_dir = temp.path().join("out");
+    let archive = out_dir.join("archive.squish");
+    fs::create_dir_all(&input).unwrap();
+    fs::create_dir_all(&out_dir).unwrap();
+
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    let data: Vec<u8> = (0..4 * 1024 * 1024)
+        .map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+            (state >> 33) as u8
+        })
+        .collect();
+    create_test_file(&input, "big.bin", &data);
+
+    // Throttled so the pack is still running when the signal arrives
+    let mut child = StdCommand::new(assert_cmd::cargo::cargo_bin("squishrs"))
+        .args([
+            "pack",
+            input.to_str().unwrap(),
+            "--output",
+            archive.to_str().unwrap(),
+            "--compression",
+            "none",
+            "--chunk-size",
+            "16KiB",
+            "--rate-limit",
+            "512KB",
+        ])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(700));
+    let status = StdCommand::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        assert!(Instant::now() < deadline, "pack did not stop after Ctrl-C");
+        std::thread::sleep(Duration::from_millis(50));
+    };
+    assert_eq!(status.code(), Some(130));
+    assert_eq!(fs::read_dir(&out_dir).unwrap().count(), 0);
+}
+
+#[test]
+fn test_cat_writes_exact_bytes() {
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    let archive = temp.path().join("archive.squish");
+
+    fs::create_dir_all(&input).unwrap();
+    let contents: Vec<u8> = (0..=255u8).chain([0, b'\n', 0]).collect();
+    create_test_file(&input, "blob.bin", &contents);
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "pack",
+            input.to_str().unwrap(),
+            "--output",
+            archive.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    let output = Command::cargo_bin("squishrs")
+        .unwrap()
+        .args(["cat", archive.to_str().unwrap(), "blob.bin"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, contents);
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args(["cat", archive.to_str().unwrap(), "missing.bin"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "File not found in squish: `missing.bin`",
+        ));
+}
+
+#[test]
+fn test_quiet_keeps_summary_unless_asked() {
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    let archive = temp.path().join("archive.squish");
+    fs::create_dir(&input).unwrap();
+    create_test_file(&input, "file.txt", b"quiet please");
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "pack",
+            input.to_str().unwrap(),
+            "-o",
+            archive.to_str().unwrap(),
+        ])
+        .arg("--quiet")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Packing complete!"));
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args(["unpack", archive.to_str().unwrap(), "-o"])
+        .arg(temp.path().join("output"))
+        .args(["--quiet", "--no-summary"])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::is_empty());
+    assert_eq!(
+        fs::read(temp.path().join("output/file.txt")).unwrap(),
+        b"quiet please"
+    );
+
+    // --no-summary only applies to quiet runs
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args(["info", archive.to_str().unwrap(), "--no-summary"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_progress_json_lines() {
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    let archive = temp.path().join("archive.squish");
+    fs::create_dir(&input).unwrap();
+    create_test_file(&input, "file.txt", b"progress");
+
+    // Lines only appear once a second, but nothing else may be drawn to stderr
+    let output = Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "pack",
+            input.to_str().unwrap(),
+            "-o",
+            archive.to_str().unwrap(),
+        ])
+        .args(["--progress", "json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    for line in String::from_utf8(output.stderr).unwrap().lines() {
+        let progress: serde_json::Value = serde_json::from_str(line).unwrap();
+        assert!(progress["processed"].is_u64(), "{line}");
+    }
+}
+
+#[test]
+fn test_repair_writes_report() {
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    let archive = temp.path().join("archive.squish");
+    let report = temp.path().join("report.json");
+    fs::create_dir(&input).unwrap();
+    create_test_file(&input, "file.txt", b"nothing to repair");
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args([
+            "pack",
+            input.to_str().unwrap(),
+            "-o",
+            archive.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args(["repair", archive.to_str().unwrap(), "-o"])
+        .arg(temp.path().join("output"))
+        .arg("--report")
+        .arg(&report)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Recovered 1 of 1 files"));
+
+    let report: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+    assert_eq!(report["recovered"], serde_json::json!(["file.txt"]));
+    assert_eq!(report["lost"], serde_json::json!([]));
+    assert_eq!(
+        fs::read(temp.path().join("output/file.txt")).unwrap(),
+        b"nothing to repair"
+    );
+}
+
+#[test]
+fn test_encrypted_pack_needs_password() {
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    let output = temp.path().join("output");
+    let archive = temp.path().join("archive.squish");
+    let password_file = temp.path().join("password.txt");
+
+    fs::create_dir(&input).unwrap();
+    create_test_file(&input, "secret.txt", b"hello");
+    fs::write(&password_file, "hunter2\n").unwrap();
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args(["pack", input.to_str().unwrap(), "--output"])
+        .arg(&archive)
+        .arg("--password-file")
+        .arg(&password_file)
+        .assert()
+        .success();
+
+    // Without a terminal to prompt on, a missing password is an error of its own
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .arg("list")
+        .arg(&archive)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("encrypted"));
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .arg("unpack")
+        .arg(&archive)
+        .args(["--password", "hunter3", "--output"])
+        .arg(&output)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Wrong password"));
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .arg("unpack")
+        .arg(&archive)
+        .args(["--password", "hunter2", "--output"])
+        .arg(&output)
+        .assert()
+        .success();
+    assert_eq!(fs::read(output.join("secret.txt")).unwrap(), b"hello");
+}
+
+#[test]
+fn test_signed_pack_verifies() {
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    let archive = temp.path().join("archive.squish");
+    let private_key = temp.path().join("key.pem");
+    let public_key = temp.path().join("key.pub.pem");
+    let other_public_key = temp.path().join("other.pub.pem");
+
+    fs::create_dir(&input).unwrap();
+    create_test_file(&input, "release.txt", b"v2");
+    let key = SigningKey::from_bytes(&[7; 32]);
+    fs::write(&private_key, key.to_pkcs8_pem(LineEnding::LF).unwrap()).unwrap();
+    let write_public = |path: &std::path::Path, key: &SigningKey| {
+        let pem = key.verifying_key().to_public_key_pem(LineEnding::LF);
+        fs::write(path, pem.unwrap()).unwrap();
+    };
+    write_public(&public_key, &key);
+    write_public(&other_public_key, &SigningKey::from_bytes(&[8; 32]));
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args(["pack", input.to_str().unwrap(), "--output"])
+        .arg(&archive)
+        .arg("--sign")
+        .arg(&private_key)
+        .assert()
+        .success();
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .arg("verify-signature")
+        .arg(&archive)
+        .arg("--key")
+        .arg(&public_key)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Signature verified"));
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .arg("verify-signature")
+        .arg(&archive)
+        .arg("--key")
+        .arg(&other_public_key)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Signature check failed"));
+
+    // A private key is not a public key
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .arg("verify-signature")
+        .arg(&archive)
+        .arg("--key")
+        .arg(&private_key)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid key"));
+}
+
+#[test]
+fn test_reencrypt_changes_password() {
+    let temp = tempdir().unwrap();
+    let input = temp.path().join("input");
+    let output = temp.path().join("output");
+    let archive = temp.path().join("archive.squish");
+    let rekeyed = temp.path().join("rekeyed.squish");
+
+    fs::create_dir(&input).unwrap();
+    create_test_file(&input, "secret.txt", b"hello");
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .args(["pack", input.to_str().unwrap(), "--output"])
+        .arg(&archive)
+        .args(["--password", "hunter2"])
+        .assert()
+        .success();
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .arg("reencrypt")
+        .arg(&archive)
+        .arg(&rekeyed)
+        .args([
+            "--old-password",
+            "hunter2",
+            "--new-password",
+            "correct horse",
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .arg("unpack")
+        .arg(&rekeyed)
+        .args(["--password", "hunter2", "--output"])
+        .arg(&output)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Wrong password"));
+
+    Command::cargo_bin("squishrs")
+        .unwrap()
+        .arg("unpack")
+        .arg(&rekeyed)
+        .args(["--password", "correct horse", "--output"])
+        .arg(&output)
+        .assert()
+        .success();
+    assert_eq!(fs::read(output.join("secret.txt")).unwrap(), b"hello");
+}